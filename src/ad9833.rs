@@ -0,0 +1,161 @@
+//! Register writes for the AD9833 and AD9837 DDS chips, low cost
+//! oscillators for low power WSPR beacons.
+//!
+//! Both chips produce `word * mclk / 2^28` Hz from a 28-bit frequency
+//! register, and have two of them, FREQ0 and FREQ1, with a bit of the
+//! control register choosing which one drives the output. Changing tone by
+//! rewriting the register in use can glitch the output while its two
+//! halves are written, so [`updates`] writes each symbol's tone to the
+//! register not in use, alternating between them, then switches to it,
+//! which keeps the phase continuous.
+//!
+//! Each write is a 16-bit word, shifted out most significant bit first
+//! with the SPI clock idling high. [`START`] resets the chip before the
+//! first symbol, and [`RESET`] stops the output after the last.
+
+use crate::Error;
+
+// The control register's bits.
+const B28: u16 = 0x2000;
+const FSELECT: u16 = 0x0800;
+const RESET_BIT: u16 = 0x0100;
+
+// The address bits of FREQ0, FREQ1, and PHASE0 writes.
+const FREQ0: u16 = 0x4000;
+const FREQ1: u16 = 0x8000;
+const PHASE0: u16 = 0xC000;
+
+/// The writes that reset the chip and clear its phase register, to send
+/// before the first symbol's writes.
+pub const START: [u16; 2] = [B28 | RESET_BIT, PHASE0];
+
+/// The write that stops the output, holding it at midscale.
+pub const RESET: u16 = B28 | RESET_BIT;
+
+/// One of the two frequency registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum Register {
+    /// FREQ0, driving the output when FSELECT is clear.
+    Freq0,
+    /// FREQ1, driving the output when FSELECT is set.
+    Freq1,
+}
+
+/// The 28-bit frequency word for `frequency` millihertz from a master
+/// clock of `mclk_hz`, 25 MHz at most on an AD9833 and 16 MHz on an
+/// AD9837.
+///
+/// Returns [`Error::InvalidFrequency`] unless the frequency is below half
+/// the master clock.
+pub fn frequency_word(frequency: u64, mclk_hz: u32) -> Result<u32, Error> {
+    let mclk = mclk_hz as u128 * 1000;
+    if frequency as u128 * 2 >= mclk {
+        return Err(Error::InvalidFrequency);
+    }
+    let word = ((frequency as u128) << 28) + mclk / 2;
+    Ok((word / mclk) as u32)
+}
+
+/// The two writes that load `word` into `register`, its lower 14 bits then
+/// its upper 14 bits.
+pub fn frequency_writes(register: Register, word: u32) -> [u16; 2] {
+    let address = match register {
+        Register::Freq0 => FREQ0,
+        Register::Freq1 => FREQ1,
+    };
+    [
+        address | (word & 0x3FFF) as u16,
+        address | ((word >> 14) & 0x3FFF) as u16,
+    ]
+}
+
+/// The control write that drives the output from `register`, with both
+/// halves of a frequency register written in consecutive writes.
+pub fn select(register: Register) -> u16 {
+    match register {
+        Register::Freq0 => B28,
+        Register::Freq1 => B28 | FSELECT,
+    }
+}
+
+/// The three writes for each of the 162 symbols' `frequencies` in
+/// millihertz, from a master clock of `mclk_hz`: the symbol's frequency
+/// into FREQ0 for even symbols and FREQ1 for odd, then the control write
+/// switching to it.
+///
+/// Returns [`Error::InvalidFrequency`] unless every frequency is below half
+/// the master clock.
+pub fn updates(
+    frequencies: &[u64; 162],
+    mclk_hz: u32,
+) -> Result<[[u16; 3]; 162], Error> {
+    let mut updates = [[0; 3]; 162];
+    for (i, (update, &frequency)) in
+        updates.iter_mut().zip(frequencies).enumerate()
+    {
+        let register = if i % 2 == 0 {
+            Register::Freq0
+        } else {
+            Register::Freq1
+        };
+        let [low, high] =
+            frequency_writes(register, frequency_word(frequency, mclk_hz)?);
+        *update = [low, high, select(register)];
+    }
+    Ok(updates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulate::rf_frequencies;
+
+    #[test]
+    fn test_frequency_word() {
+        // The AD9833 datasheet's example of 400 Hz from 25 MHz.
+        assert_eq!(frequency_word(400_000, 25_000_000), Ok(0x10C7));
+        assert_eq!(frequency_writes(Register::Freq0, 0x10C7), [0x50C7, 0x4000]);
+        assert_eq!(frequency_word(6_250_000_000, 25_000_000), Ok(1 << 26));
+        assert_eq!(
+            frequency_word(12_500_000_000, 25_000_000),
+            Err(Error::InvalidFrequency)
+        );
+        assert_eq!(
+            frequency_writes(Register::Freq1, 0x0FFF_FFFF),
+            [0xBFFF, 0xBFFF]
+        );
+        assert_eq!(select(Register::Freq0), 0x2000);
+        assert_eq!(select(Register::Freq1), 0x2800);
+        assert_eq!(START, [0x2100, 0xC000]);
+    }
+
+    #[test]
+    fn test_updates() {
+        // 30 m from a 25 MHz clock, about 0.093 Hz a word.
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let frequencies = rf_frequencies(10_138_700, 1500, &symbols);
+        let writes = updates(&frequencies, 25_000_000).unwrap();
+        for (i, (update, &frequency)) in
+            writes.iter().zip(&frequencies).enumerate()
+        {
+            // Each symbol alternates registers, and switches to the one it
+            // wrote.
+            let address = if i % 2 == 0 { FREQ0 } else { FREQ1 };
+            assert_eq!(update[0] & 0xC000, address);
+            assert_eq!(update[1] & 0xC000, address);
+            assert_eq!(update[2] & FSELECT != 0, i % 2 == 1);
+
+            let word = (update[0] & 0x3FFF) as u64
+                | ((update[1] & 0x3FFF) as u64) << 14;
+            let actual = (word * 25_000_000_000 + (1 << 27)) >> 28;
+            assert!(actual.abs_diff(frequency) <= 47);
+        }
+
+        let frequencies = rf_frequencies(14_095_600, 1500, &symbols);
+        assert_eq!(
+            updates(&frequencies, 16_000_000).map(|_| ()),
+            Err(Error::InvalidFrequency)
+        );
+    }
+}
@@ -0,0 +1,118 @@
+//! Tuning words for the AD9850 and AD9851 DDS chips, the most common
+//! oscillators of homebrew WSPR transmitters.
+//!
+//! The DDS adds a 32-bit tuning word to its phase each reference clock
+//! cycle, so it produces `word * reference / 2^32` Hz. With a 125 MHz
+//! reference, a word is about 0.029 Hz, some 50 words to a tone.
+//! [`tuning_words`] turns the frequency of each symbol, from
+//! [`rf_frequencies`](crate::modulate::rf_frequencies), into the word to load
+//! as the symbol begins.
+//!
+//! Both chips load serially a 40-bit word, least significant bit first: the
+//! tuning word, then a control byte. [`serial_word`] packs them into the five
+//! bytes to shift out, each least significant bit first.
+
+use crate::Error;
+
+/// The AD9851's control bit that multiplies its reference clock by 6, such
+/// as a 30 MHz crystal up to 180 MHz. It must be clear on an AD9850.
+pub const REFCLK_MULTIPLIER: u8 = 0x01;
+
+/// The tuning word for `frequency` millihertz from a reference clock of
+/// `reference_hz`, after the AD9851's multiplier if it's used.
+///
+/// Returns [`Error::InvalidFrequency`] unless the frequency is below half
+/// the reference clock.
+pub fn tuning_word(frequency: u64, reference_hz: u32) -> Result<u32, Error> {
+    let reference = reference_hz as u128 * 1000;
+    if frequency as u128 * 2 >= reference {
+        return Err(Error::InvalidFrequency);
+    }
+    let word = ((frequency as u128) << 32) + reference / 2;
+    Ok((word / reference) as u32)
+}
+
+/// The tuning word for each of the 162 symbols' `frequencies` in
+/// millihertz, from a reference clock of `reference_hz`.
+///
+/// Returns [`Error::InvalidFrequency`] unless every frequency is below half
+/// the reference clock.
+pub fn tuning_words(
+    frequencies: &[u64; 162],
+    reference_hz: u32,
+) -> Result<[u32; 162], Error> {
+    let mut words = [0; 162];
+    for (word, &frequency) in words.iter_mut().zip(frequencies) {
+        *word = tuning_word(frequency, reference_hz)?;
+    }
+    Ok(words)
+}
+
+/// The frequency in millihertz tuning word `word` produces from a reference
+/// clock of `reference_hz`.
+pub fn frequency(word: u32, reference_hz: u32) -> u64 {
+    let frequency = word as u128 * reference_hz as u128 * 1000;
+    ((frequency + (1 << 31)) >> 32) as u64
+}
+
+/// The five bytes of a serial load of tuning word `word` and control byte
+/// `control`, in the order to shift them out, each least significant bit
+/// first. `control` is 0 on an AD9850, or [`REFCLK_MULTIPLIER`] on an
+/// AD9851 using its multiplier; the phase and power down bits are left to
+/// the caller.
+pub fn serial_word(word: u32, control: u8) -> [u8; 5] {
+    let [a, b, c, d] = word.to_le_bytes();
+    [a, b, c, d, control]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulate::rf_frequencies;
+
+    #[test]
+    fn test_tuning_word() {
+        // Half the reference clock is out of range, and a quarter of it is
+        // exact.
+        assert_eq!(
+            tuning_word(62_500_000_000, 125_000_000),
+            Err(Error::InvalidFrequency)
+        );
+        assert_eq!(tuning_word(31_250_000_000, 125_000_000), Ok(0x4000_0000));
+        assert_eq!(tuning_word(0, 125_000_000), Ok(0));
+        assert_eq!(tuning_word(1, 0), Err(Error::InvalidFrequency));
+        // 10 MHz from 125 MHz, and from an AD9851's 30 MHz times 6.
+        assert_eq!(tuning_word(10_000_000_000, 125_000_000), Ok(0x147A_E148));
+        assert_eq!(tuning_word(10_000_000_000, 180_000_000), Ok(0x0E38_E38E));
+        assert_eq!(frequency(0x147A_E148, 125_000_000), 10_000_000_009);
+    }
+
+    #[test]
+    fn test_tuning_words() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let frequencies = rf_frequencies(14_095_600, 1500, &symbols);
+        let words = tuning_words(&frequencies, 125_000_000).unwrap();
+        for (&word, &expected) in words.iter().zip(&frequencies) {
+            // Each word is within half a word, 15 mHz, of its tone.
+            let actual = frequency(word, 125_000_000);
+            assert!(actual.abs_diff(expected) <= 15);
+        }
+        // Tones are about 50 words apart.
+        let lowest = *words.iter().min().unwrap();
+        let highest = *words.iter().max().unwrap();
+        assert_eq!(highest - lowest, 151);
+
+        let frequencies = rf_frequencies(144_489_000, 1500, &symbols);
+        assert_eq!(
+            tuning_words(&frequencies, 125_000_000),
+            Err(Error::InvalidFrequency)
+        );
+        assert!(tuning_words(&frequencies, 300_000_000).is_ok());
+    }
+
+    #[test]
+    fn test_serial_word() {
+        assert_eq!(serial_word(0x147A_E148, 0), [0x48, 0xE1, 0x7A, 0x14, 0]);
+        assert_eq!(serial_word(1, REFCLK_MULTIPLIER), [1, 0, 0, 0, 1]);
+    }
+}
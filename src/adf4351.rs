@@ -0,0 +1,294 @@
+//! Frequency plans for the ADF4351 and ADF4350 fractional-N synthesizers,
+//! to drive 2 m and 70 cm beacons directly.
+//!
+//! The synthesizer divides its reference by the R counter down to the
+//! phase frequency detector (PFD), multiplies that up to a VCO of 2.2 to
+//! 4.4 GHz by `INT + FRAC / MOD`, and divides the VCO down by a power of
+//! two from 1 to 64. With a PFD of several MHz, even the finest fraction
+//! is hundreds of Hz, far coarser than WSPR's 1.46 Hz tones, so a [`Plan`]
+//! runs the PFD nearly as low as INT allows, some tens of kHz, and gives
+//! each tone its own MOD, the fraction with the closest a 12-bit MOD can
+//! get. The tones then land within a millihertz or two of where they should.
+//!
+//! Each tone is set by writing its R1, holding MOD, then its R0, holding
+//! INT and FRAC, which updates the output. The R counter goes in bits 14
+//! to 23 of R2, and the RF divider's setting in bits 20 to 22 of R4, along
+//! with the rest of the configuration.
+
+use crate::modulate::TONE_SPACING_MILLIHERTZ;
+use crate::si5351::approximate;
+use crate::Error;
+
+// The range of the VCO in Hz.
+const VCO_MIN: u64 = 2_200_000_000;
+const VCO_MAX: u64 = 4_400_000_000;
+
+// The range of INT with the 8/9 prescaler.
+const INT_MIN: u128 = 75;
+const INT_MAX: u128 = 65_535;
+
+// The largest MOD and R counter.
+const MAX_MODULUS: u128 = 4095;
+const MAX_R: u64 = 1023;
+
+// The number of R counters to try.
+const CANDIDATES: u64 = 32;
+
+/// A multiplier of `int + frac / modulus`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Fraction {
+    /// INT, from 75 to 65535.
+    pub int: u16,
+    /// FRAC, less than MOD.
+    pub frac: u16,
+    /// MOD, from 2 to 4095.
+    pub modulus: u16,
+}
+
+impl Fraction {
+    /// The values of R1 and R0 for the fraction, in the order to write
+    /// them, with the 8/9 prescaler and a phase value of 1.
+    pub fn registers(&self) -> [u32; 2] {
+        let r1 = 1 << 27 | 1 << 15 | (self.modulus as u32) << 3 | 1;
+        let r0 = (self.int as u32) << 15 | (self.frac as u32) << 3;
+        [r1, r0]
+    }
+}
+
+/// The settings of an ADF4351 for each of the four tones of a transmission.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Plan {
+    reference: u32,
+    r: u16,
+    // The RF divider, as a power of two.
+    divider: u8,
+    tones: [Fraction; 4],
+}
+
+impl Plan {
+    /// Plans the tones of a transmission centered `audio_offset_hz` above
+    /// `dial_hz`, as [`rf_frequencies`](crate::modulate::rf_frequencies)
+    /// places them, from a reference of `reference_hz`, such as 25 MHz.
+    ///
+    /// Returns [`Error::InvalidFrequency`] if the tones are beyond the
+    /// synthesizer's range, from 35 MHz to 4.4 GHz, or the reference is
+    /// too low to keep INT within its range.
+    pub fn new(
+        dial_hz: u64,
+        audio_offset_hz: u32,
+        reference_hz: u32,
+    ) -> Result<Self, Error> {
+        let center = dial_hz + audio_offset_hz as u64;
+        if reference_hz == 0 || center == 0 || center + 3 > VCO_MAX {
+            return Err(Error::InvalidFrequency);
+        }
+
+        // The RF divider brings the VCO into its range, and the R counter
+        // can be no larger than keeps INT in range at the highest tone.
+        let mut divider = 0;
+        while center << divider < VCO_MIN && divider < 6 {
+            divider += 1;
+        }
+        let highest = (center + 3) << divider;
+        let largest =
+            (INT_MAX as u64 * reference_hz as u64 / highest).min(MAX_R);
+
+        // How close the tones land depends on how well their fractions
+        // happen to fit a 12-bit MOD, so the R counters just below the
+        // largest are tried too, for the closest fit.
+        let mut best: Option<(u128, u64, [Fraction; 4])> = None;
+        for r in (largest.saturating_sub(CANDIDATES - 1)..=largest).rev() {
+            if let Some((error, tones)) =
+                fractions(center, divider, r, reference_hz)
+            {
+                if best.map_or(true, |(least, _, _)| error < least) {
+                    best = Some((error, r, tones));
+                }
+            }
+        }
+        let Some((_, r, tones)) = best else {
+            return Err(Error::InvalidFrequency);
+        };
+
+        Ok(Self {
+            reference: reference_hz,
+            r: r as u16,
+            divider,
+            tones,
+        })
+    }
+
+    /// The fraction for tone `tone`, from 0 to 3.
+    pub fn tone(&self, tone: u8) -> Fraction {
+        self.tones[tone as usize & 3]
+    }
+
+    /// The values of R1 and R0 for tone `tone`, from 0 to 3, in the order
+    /// to write them.
+    pub fn tone_registers(&self, tone: u8) -> [u32; 2] {
+        self.tone(tone).registers()
+    }
+
+    /// The R counter, from 1 to 1023.
+    pub fn r_counter(&self) -> u16 {
+        self.r
+    }
+
+    /// The RF divider's setting, the power of two it divides by, from 0 to
+    /// 6.
+    pub fn rf_divider(&self) -> u8 {
+        self.divider
+    }
+
+    /// The PFD's frequency in millihertz.
+    pub fn pfd(&self) -> u64 {
+        let reference = self.reference as u64 * 1000;
+        (reference + self.r as u64 / 2) / self.r as u64
+    }
+
+    /// The frequency of tone `tone`, from 0 to 3, in millihertz, as the
+    /// synthesizer will produce it from an exact reference.
+    pub fn frequency(&self, tone: u8) -> u64 {
+        let fraction = self.tone(tone);
+        let m = fraction.modulus as u128;
+        let numerator = self.reference as u128
+            * 1000
+            * (fraction.int as u128 * m + fraction.frac as u128);
+        let denominator = (m * self.r as u128) << self.divider;
+        ((numerator + denominator / 2) / denominator) as u64
+    }
+}
+
+// The fractions for each tone around `center` Hz with an R counter of `r`,
+// and the largest error between them in 512ths of a millihertz, if they're
+// all in range.
+fn fractions(
+    center: u64,
+    divider: u8,
+    r: u64,
+    reference_hz: u32,
+) -> Option<(u128, [Fraction; 4])> {
+    if r == 0 {
+        return None;
+    }
+    let mut error = 0;
+    let mut tones = [Fraction {
+        int: 0,
+        frac: 0,
+        modulus: 2,
+    }; 4];
+    for (tone, fraction) in tones.iter_mut().enumerate() {
+        // In 512ths of a millihertz, where the tones are exact: tone 0 is
+        // 1.5 tones below the center.
+        let (numerator, denominator) = TONE_SPACING_MILLIHERTZ;
+        let (numerator, unit) = (numerator as u128, 2 * denominator as u128);
+        let frequency = center as u128 * 1000 * unit
+            + (2 * tone as u128) * numerator
+            - 3 * numerator;
+        let vco = (frequency << divider) * r as u128;
+        let pfd = reference_hz as u128 * 1000 * unit;
+
+        let (frac, modulus) = approximate(vco % pfd, pfd, MAX_MODULUS);
+        let int = vco / pfd + (frac == modulus) as u128;
+        if !(INT_MIN..=INT_MAX).contains(&int) {
+            return None;
+        }
+        // MOD is at least 2, even when FRAC is 0.
+        let (frac, modulus) = match (frac, modulus) {
+            (_, 1) => (0, 2),
+            (frac, modulus) => (frac, modulus),
+        };
+        let multiplier = int * modulus + frac;
+        let vco = reference_hz as u128 * multiplier / (modulus * r as u128);
+        if !(VCO_MIN as u128..=VCO_MAX as u128).contains(&vco) {
+            return None;
+        }
+
+        let actual = pfd * multiplier / ((modulus * r as u128) << divider);
+        error = error.max(actual.abs_diff(frequency));
+        *fraction = Fraction {
+            int: int as u16,
+            frac: frac as u16,
+            modulus: modulus as u16,
+        };
+    }
+    Some((error, tones))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulate::tone_millihertz;
+
+    #[test]
+    fn test_registers() {
+        // The datasheet's R0 of 0x002C8018 and R1 of 0x08008011 for an INT
+        // of 89, FRAC of 3, and MOD of 2, with the 8/9 prescaler and a phase
+        // of 1.
+        let fraction = Fraction {
+            int: 89,
+            frac: 3,
+            modulus: 2,
+        };
+        let [r1, r0] = fraction.registers();
+        assert_eq!(r0, 0x002C_8018);
+        assert_eq!(r1 & 0x7FFF, 0x0011);
+        assert_eq!(r1 >> 27 & 1, 1);
+        assert_eq!(r1 >> 15 & 0xFFF, 1);
+    }
+
+    #[test]
+    fn test_plan() {
+        assert_eq!(
+            Plan::new(144_489_000, 1500, 0),
+            Err(Error::InvalidFrequency)
+        );
+        assert_eq!(
+            Plan::new(10_138_700, 1500, 25_000_000),
+            Err(Error::InvalidFrequency)
+        );
+        assert_eq!(
+            Plan::new(5_760_000_000, 1500, 25_000_000),
+            Err(Error::InvalidFrequency)
+        );
+
+        for (dial, reference) in [
+            (50_293_000, 25_000_000),
+            (70_091_000, 25_000_000),
+            (144_489_000, 25_000_000),
+            (144_489_000, 10_000_000),
+            (432_300_000, 25_000_000),
+            (1_296_500_000, 26_000_000),
+            (1_296_500_000, 25_000_000),
+            (2_320_000_000, 25_000_000),
+        ] {
+            let plan = Plan::new(dial, 1500, reference).unwrap();
+            let base = (dial + 1500) * 1000 - 2197;
+            for tone in 0..4 {
+                // Each tone is within 2 mHz of where it should be.
+                let expected = tone_millihertz(base, tone);
+                let actual = plan.frequency(tone);
+                assert!(actual.abs_diff(expected) <= 2, "{}", dial);
+
+                let fraction = plan.tone(tone);
+                assert!(fraction.int >= 75);
+                assert!(fraction.frac < fraction.modulus);
+                assert!((2..=4095).contains(&fraction.modulus));
+                let vco = plan.frequency(tone) << plan.rf_divider();
+                assert!((2_200_000_000_000..=4_400_000_000_000).contains(&vco));
+            }
+        }
+
+        // 2 m from 25 MHz divides a 2.31 GHz VCO by 16, from a PFD of 35 to
+        // 37 kHz.
+        let plan = Plan::new(144_489_000, 1500, 25_000_000).unwrap();
+        assert_eq!(plan.rf_divider(), 4);
+        assert!((677..=708).contains(&plan.r_counter()));
+        assert!(plan.pfd() > 35_000_000 && plan.pfd() < 37_000_000);
+        let [r1, r0] = plan.tone_registers(0);
+        assert_eq!(r0 & 7, 0);
+        assert_eq!(r1 & 7, 1);
+        assert_eq!(r0 >> 15, plan.tone(0).int as u32);
+    }
+}
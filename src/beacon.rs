@@ -0,0 +1,117 @@
+//! Scheduling the messages a beacon transmits.
+//!
+//! Beacons and trackers often have more than one message to send, such as a
+//! regular message followed by telemetry. A [`Rotation`] cycles through
+//! them in order, one per transmit slot.
+
+// The length of a cycle in seconds.
+const CYCLE: u64 = 120;
+
+/// A list of encoded messages, sent one after another in a fixed order.
+///
+/// The next message is tracked by a position that can be saved and
+/// restored across resets with [`Rotation::position`] and
+/// [`Rotation::with_position`]. Alternatively [`Rotation::at`] picks the
+/// message from the time of the cycle alone, so a beacon with a clock keeps
+/// its order without saving anything.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rotation<'a> {
+    messages: &'a [[u8; 162]],
+    position: usize,
+}
+
+impl<'a> Rotation<'a> {
+    /// Creates a rotation through `messages`, starting with the first.
+    pub fn new(messages: &'a [[u8; 162]]) -> Self {
+        Self {
+            messages,
+            position: 0,
+        }
+    }
+
+    /// Starts the rotation at `position`, as returned by
+    /// [`Rotation::position`] before a reset.
+    pub fn with_position(self, position: usize) -> Self {
+        let position = position.checked_rem(self.messages.len()).unwrap_or(0);
+        Self { position, ..self }
+    }
+
+    /// The position of the next message.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// The messages rotated through.
+    pub fn messages(&self) -> &'a [[u8; 162]] {
+        self.messages
+    }
+
+    /// The message to send in the cycle starting at `timestamp`, in seconds
+    /// since the Unix epoch, counting cycles from the epoch, or `None` if
+    /// there are no messages. The position is moved to the message after it.
+    pub fn at(&mut self, timestamp: u64) -> Option<&'a [u8; 162]> {
+        let len = self.messages.len() as u64;
+        let position = (timestamp / CYCLE).checked_rem(len)?;
+        self.position = position as usize;
+        self.next()
+    }
+}
+
+/// Yields the messages in order, starting over after the last, forever
+/// unless there are no messages.
+impl<'a> Iterator for Rotation<'a> {
+    type Item = &'a [u8; 162];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let message = self.messages.get(self.position)?;
+        self.position = (self.position + 1) % self.messages.len();
+        Some(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rotation() {
+        let messages = [
+            crate::encode("K1ABC", "FN42", 37).unwrap(),
+            crate::encode("K1ABC", "FN42AX", 37).unwrap(),
+            crate::telemetry::u4b::Telemetry {
+                subsquare: *b"AX",
+                altitude: 12060,
+                temperature: -25,
+                voltage: 4.1,
+                speed: 36,
+                gps_valid: true,
+            }
+            .encode(0)
+            .unwrap(),
+        ];
+
+        let mut rotation = Rotation::new(&messages);
+        for i in 0..7 {
+            assert_eq!(rotation.next(), Some(&messages[i % 3]));
+        }
+        assert_eq!(rotation.position(), 1);
+
+        // The position survives a reset.
+        let mut restored =
+            Rotation::new(&messages).with_position(rotation.position());
+        assert_eq!(restored.next(), Some(&messages[1]));
+        let mut restored = Rotation::new(&messages).with_position(5);
+        assert_eq!(restored.next(), Some(&messages[2]));
+
+        // Or is found from the time of the cycle.
+        let start = 1_700_000_280;
+        assert_eq!(rotation.at(start), Some(&messages[0]));
+        assert_eq!(rotation.next(), Some(&messages[1]));
+        assert_eq!(rotation.at(start + 5 * 120), Some(&messages[2]));
+        assert_eq!(rotation.position(), 0);
+
+        let mut empty = Rotation::new(&[]).with_position(3);
+        assert_eq!(empty.next(), None);
+        assert_eq!(empty.at(start), None);
+    }
+}
@@ -0,0 +1,252 @@
+//! Reading the `.c2` files written by wsprd and wsprdaemon, and decoding the
+//! WSPR transmissions within them.
+//!
+//! A `.c2` file holds a 2 minute cycle as a 375 Hz complex baseband centered
+//! 1500 Hz above the dial frequency, which is exactly what the
+//! [`search`](crate::search) works on. The file begins with a 26 byte
+//! header: a 14 byte file name, conventionally `YYMMDD_HHMM.c2`, the length
+//! of the cycle in minutes as an `i32`, and the dial frequency in MHz as an
+//! `f64`. Pairs of `f32`s follow, the in-phase and negated quadrature parts
+//! of each sample. All values are little endian.
+//!
+//! [`write`] writes `.c2` files, such as of cycles generated by
+//! [`simulate`](crate::channel::simulate), for wsprd to decode.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+use std::string::String;
+use std::vec::Vec;
+
+use crate::dsp::Complex;
+use crate::spot::Spot;
+use crate::wav::{decode_baseband, invalid};
+
+// The offset of the center of the baseband above the dial frequency, in Hz.
+const CENTER: f64 = 1500.0;
+
+/// The contents of a `.c2` file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct C2 {
+    /// The file name stored in the header, conventionally `YYMMDD_HHMM.c2`
+    /// giving the start of the cycle.
+    pub name: String,
+    /// The length of the cycle in minutes, 2 for WSPR.
+    pub period: i32,
+    /// The dial frequency in Hz. The baseband is centered 1500 Hz above it.
+    pub dial: f64,
+    /// The 375 Hz complex baseband.
+    pub samples: Vec<Complex>,
+}
+
+impl C2 {
+    /// The start of the cycle in seconds since the Unix epoch (UTC), parsed
+    /// from [`C2::name`], or `None` if the name isn't of the form
+    /// `YYMMDD_HHMM`.
+    pub fn timestamp(&self) -> Option<u64> {
+        let name = self.name.as_bytes();
+        if name.len() < 11 || name[6] != b'_' {
+            return None;
+        }
+        let field = |i: usize| -> Option<u64> {
+            let (tens, ones) = (name[i], name[i + 1]);
+            if !tens.is_ascii_digit() || !ones.is_ascii_digit() {
+                return None;
+            }
+            Some(((tens - b'0') * 10 + (ones - b'0')) as u64)
+        };
+
+        let (year, month, day) = (2000 + field(0)?, field(2)?, field(4)?);
+        let (hour, minute) = (field(7)?, field(9)?);
+        if !(1..=12).contains(&month)
+            || !(1..=31).contains(&day)
+            || hour > 23
+            || minute > 59
+        {
+            return None;
+        }
+
+        let days = days_from_civil(year, month, day);
+        Some(((days * 24 + hour) * 60 + minute) * 60)
+    }
+}
+
+/// Reads a `.c2` file.
+pub fn read<R: Read>(mut reader: R) -> io::Result<C2> {
+    let mut header = [0u8; 26];
+    reader.read_exact(&mut header)?;
+
+    let name = &header[..14];
+    let name = &name[..name.iter().position(|&b| b == 0).unwrap_or(14)];
+    let name = String::from_utf8_lossy(name).trim().into();
+    let period =
+        i32::from_le_bytes([header[14], header[15], header[16], header[17]]);
+    let mut dial = [0u8; 8];
+    dial.copy_from_slice(&header[18..26]);
+    let dial = f64::from_le_bytes(dial) * 1e6;
+
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data)?;
+    let samples = data
+        .chunks_exact(8)
+        .map(|b| {
+            let i = f32::from_le_bytes([b[0], b[1], b[2], b[3]]);
+            let q = f32::from_le_bytes([b[4], b[5], b[6], b[7]]);
+            Complex::new(i, -q)
+        })
+        .collect();
+
+    Ok(C2 {
+        name,
+        period,
+        dial,
+        samples,
+    })
+}
+
+/// Writes a `.c2` file. The name is cut short at 14 bytes.
+pub fn write<W: Write>(mut writer: W, c2: &C2) -> io::Result<()> {
+    let mut name = [0u8; 14];
+    let length = c2.name.len().min(14);
+    name[..length].copy_from_slice(&c2.name.as_bytes()[..length]);
+    writer.write_all(&name)?;
+    writer.write_all(&c2.period.to_le_bytes())?;
+    writer.write_all(&(c2.dial / 1e6).to_le_bytes())?;
+    for sample in &c2.samples {
+        writer.write_all(&sample.re.to_le_bytes())?;
+        writer.write_all(&(-sample.im).to_le_bytes())?;
+    }
+    writer.flush()
+}
+
+/// Decodes every WSPR transmission found in a `.c2` file. See
+/// [`decode_reader`].
+pub fn decode_c2<P: AsRef<Path>>(path: P) -> io::Result<Vec<Spot>> {
+    decode_reader(BufReader::new(File::open(path)?))
+}
+
+/// Decodes every WSPR transmission found in a `.c2` file. The frequency of
+/// each spot is an RF frequency, and the timestamp is parsed from the name
+/// in the header, or left unknown if it can't be.
+///
+/// The 200 Hz passband is searched, for transmissions beginning up to 1
+/// second early or 3 seconds late.
+pub fn decode_reader<R: Read>(reader: R) -> io::Result<Vec<Spot>> {
+    let mut c2 = read(reader)?;
+    if c2.period != 2 {
+        return Err(invalid("cycle must be 2 minutes long"));
+    }
+
+    let timestamp = c2.timestamp().unwrap_or(0);
+    let mut spots = decode_baseband(&mut c2.samples)?;
+    for spot in spots.iter_mut() {
+        spot.frequency += c2.dial + CENTER;
+        spot.timestamp = timestamp;
+    }
+    Ok(spots)
+}
+
+// Returns the number of days since 1970-01-01 of a date. See
+// http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let month = (month + 9) % 12;
+    let day_of_year = (153 * month + 2) / 5 + day - 1;
+    let day_of_era =
+        year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::tests::{noise, synthesize};
+
+    // Builds a `.c2` file in memory.
+    fn c2(name: &str, period: i32, dial: f64, samples: &[Complex]) -> Vec<u8> {
+        let mut c2 = Vec::new();
+        let mut field = [0u8; 14];
+        field[..name.len()].copy_from_slice(name.as_bytes());
+        c2.extend_from_slice(&field);
+        c2.extend_from_slice(&period.to_le_bytes());
+        c2.extend_from_slice(&dial.to_le_bytes());
+        for sample in samples {
+            c2.extend_from_slice(&sample.re.to_le_bytes());
+            c2.extend_from_slice(&(-sample.im).to_le_bytes());
+        }
+        c2
+    }
+
+    #[test]
+    fn test_write() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let simulation = crate::channel::Simulation::new()
+            .with_frequency(-20.0)
+            .with_snr(-22.0);
+        let mut samples = std::vec![Complex::default(); simulation.total()];
+        crate::channel::simulate(&symbols, &simulation, &mut samples).unwrap();
+        let c2 = C2 {
+            name: "240315_1802.c2".into(),
+            period: 2,
+            dial: 10_138_700.0,
+            samples,
+        };
+
+        let mut file = Vec::new();
+        write(&mut file, &c2).unwrap();
+        assert_eq!(file.len(), 26 + 8 * 45000);
+        assert_eq!(read(&file[..]).unwrap(), c2);
+
+        let spots = decode_reader(&file[..]).unwrap();
+        assert_eq!(spots.len(), 1);
+        assert_eq!(spots[0].message.callsign(), "K1ABC");
+        assert!((spots[0].frequency - 10_140_180.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn test_days_from_civil() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 2, 29), 11_016);
+        assert_eq!(days_from_civil(2024, 12, 31), 20_088);
+    }
+
+    #[test]
+    fn test_read() {
+        let samples = [Complex::new(0.5, 0.25), Complex::new(-1.0, 0.0)];
+        let file = c2("240315_1802.c2", 2, 14.0956, &samples);
+        let c2 = read(&file[..]).unwrap();
+        assert_eq!(c2.name, "240315_1802.c2");
+        assert_eq!(c2.period, 2);
+        assert!((c2.dial - 14_095_600.0).abs() < 1e-3);
+        assert_eq!(c2.samples, samples);
+        assert_eq!(c2.timestamp(), Some(1_710_525_720));
+
+        let c2 = C2 {
+            name: "capture.c2".into(),
+            ..c2
+        };
+        assert_eq!(c2.timestamp(), None);
+
+        assert!(read(&file[..20]).is_err());
+    }
+
+    #[test]
+    fn test_decode_reader() {
+        let mut samples = noise(45000);
+        let amplitude = (10f32.powf(-2.0) * 2500.0 / 375.0).sqrt();
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        synthesize(&symbols, 30.0, 0.0, 375, amplitude, &mut samples);
+
+        let file = c2("240315_1802.c2", 2, 14.0956, &samples);
+        let spots = decode_reader(&file[..]).unwrap();
+        assert_eq!(spots.len(), 1);
+        assert_eq!(spots[0].message.callsign(), "K1ABC");
+        assert!((spots[0].frequency - 14_097_130.0).abs() < 1.0);
+        assert_eq!(spots[0].timestamp, 1_710_525_720);
+
+        let file = c2("240315_1802.c2", 15, 14.0956, &samples);
+        assert!(decode_reader(&file[..]).is_err());
+    }
+}
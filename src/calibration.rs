@@ -0,0 +1,204 @@
+//! Calibrating a receiver's frequency from decodes of beacons whose
+//! transmit frequencies are known.
+//!
+//! The frequency error of an RTL-SDR dongle or a sound card is set by its
+//! clock, so is proportional to frequency and best expressed in parts per
+//! million. Each decode of a known beacon gives a measurement of it, and
+//! [`Calibration`] tracks how it changes over time as the clock warms up
+//! or the room temperature changes.
+
+use crate::spot::Spot;
+
+/// A transmitter whose frequency is known, such as a GPS disciplined
+/// beacon.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Beacon<'a> {
+    /// The beacon's callsign, as it appears in its messages.
+    pub callsign: &'a str,
+    /// The frequency of the center of the beacon's signal, midway between
+    /// tones 1 and 2, in the same units as the spots it's compared against:
+    /// an RF frequency in Hz for spots with RF frequencies, or an audio
+    /// frequency to calibrate a sound card's clock.
+    pub frequency: f64,
+}
+
+/// A single measurement of the receiver's frequency error.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Measurement {
+    /// The time of the cycle the beacon was decoded in, in seconds since the
+    /// Unix epoch.
+    pub timestamp: u64,
+    /// How far above its true frequency the beacon was received, in Hz.
+    pub error: f64,
+    /// The error in parts per million.
+    pub ppm: f64,
+}
+
+/// A running calibration of a receiver's frequency, fitting a straight line
+/// to the measured error over time.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Calibration {
+    count: u32,
+    // The time of the first measurement, which the others are relative to.
+    start: u64,
+    // Sums for the least squares fit of ppm against time in hours.
+    t: f64,
+    tt: f64,
+    ppm: f64,
+    tppm: f64,
+    last: u64,
+}
+
+impl Calibration {
+    /// Creates a calibration with no measurements.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of measurements added.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Adds a measurement if `spot` is a decode of one of `beacons`,
+    /// returning it.
+    pub fn add(
+        &mut self,
+        spot: &Spot,
+        beacons: &[Beacon],
+    ) -> Option<Measurement> {
+        let beacon = beacons
+            .iter()
+            .find(|beacon| beacon.callsign == spot.message.callsign())?;
+        if beacon.frequency == 0.0 {
+            return None;
+        }
+
+        let error = spot.frequency - beacon.frequency;
+        let measurement = Measurement {
+            timestamp: spot.timestamp,
+            error,
+            ppm: error / beacon.frequency * 1e6,
+        };
+        self.push(&measurement);
+        Some(measurement)
+    }
+
+    /// Adds a measurement made some other way.
+    pub fn push(&mut self, measurement: &Measurement) {
+        if self.count == 0 {
+            self.start = measurement.timestamp;
+        }
+        let t = hours(measurement.timestamp, self.start);
+        self.count += 1;
+        self.t += t;
+        self.tt += t * t;
+        self.ppm += measurement.ppm;
+        self.tppm += t * measurement.ppm;
+        self.last = self.last.max(measurement.timestamp);
+    }
+
+    /// The rate at which the error is changing, in ppm per hour, or `None`
+    /// until there are measurements from at least two different times.
+    pub fn drift(&self) -> Option<f64> {
+        let n = self.count as f64;
+        let denominator = n * self.tt - self.t * self.t;
+        if self.count < 2 || denominator <= 1e-12 {
+            return None;
+        }
+        Some((n * self.tppm - self.t * self.ppm) / denominator)
+    }
+
+    /// The estimated error in ppm at `timestamp`, following the drift if
+    /// it's known, or `None` without any measurements.
+    pub fn ppm(&self, timestamp: u64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let n = self.count as f64;
+        let Some(drift) = self.drift() else {
+            return Some(self.ppm / n);
+        };
+        let mean_t = self.t / n;
+        let t = hours(timestamp, self.start);
+        Some(self.ppm / n + drift * (t - mean_t))
+    }
+
+    /// The estimated error in ppm as of the latest measurement.
+    pub fn latest_ppm(&self) -> Option<f64> {
+        self.ppm(self.last)
+    }
+
+    /// Corrects a frequency received at `timestamp`, returning the true
+    /// frequency. The frequency is returned as is without any measurements.
+    pub fn correct(&self, frequency: f64, timestamp: u64) -> f64 {
+        match self.ppm(timestamp) {
+            Some(ppm) => frequency / (1.0 + ppm / 1e6),
+            None => frequency,
+        }
+    }
+}
+
+// The time from `start` to `timestamp`, in hours.
+fn hours(timestamp: u64, start: u64) -> f64 {
+    (timestamp as f64 - start as f64) / 3600.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_calibration() {
+        let beacons = [
+            Beacon {
+                callsign: "K1ABC",
+                frequency: 14_097_050.0,
+            },
+            Beacon {
+                callsign: "G1ABC",
+                frequency: 14_097_100.0,
+            },
+        ];
+        let spot = |callsign, grid, frequency, timestamp| {
+            let symbols = crate::encode(callsign, grid, 37).unwrap();
+            Spot {
+                message: crate::decode(&symbols).unwrap(),
+                frequency,
+                timestamp,
+                ..Spot::default()
+            }
+        };
+
+        let mut calibration = Calibration::new();
+        assert_eq!(calibration.latest_ppm(), None);
+        assert_eq!(calibration.correct(14e6, 0), 14e6);
+
+        // Another station isn't a measurement.
+        let other = spot("N6AB", "CM87", 14_097_080.0, 0);
+        assert_eq!(calibration.add(&other, &beacons), None);
+
+        // A receiver 1 ppm high, drifting up by 0.5 ppm an hour.
+        for hour in 0..4u64 {
+            let ppm = 1.0 + 0.5 * hour as f64;
+            for beacon in &beacons {
+                let grid = if beacon.callsign == "K1ABC" {
+                    "FN42"
+                } else {
+                    "IO83"
+                };
+                let received = beacon.frequency * (1.0 + ppm / 1e6);
+                let spot = spot(beacon.callsign, grid, received, 3600 * hour);
+                let measurement = calibration.add(&spot, &beacons).unwrap();
+                assert!((measurement.ppm - ppm).abs() < 1e-6);
+            }
+        }
+        assert_eq!(calibration.count(), 8);
+        assert!((calibration.drift().unwrap() - 0.5).abs() < 1e-6);
+        assert!((calibration.latest_ppm().unwrap() - 2.5).abs() < 1e-6);
+
+        let received = 10e6 * (1.0 + 2.5 / 1e6);
+        let corrected = calibration.correct(received, 3 * 3600);
+        assert!((corrected - 10e6).abs() < 1e-3);
+    }
+}
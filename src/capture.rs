@@ -0,0 +1,259 @@
+//! Recording 2 minute cycles from a sound card with `cpal`, and decoding the
+//! WSPR transmissions within them.
+//!
+//! With a receiver's audio output connected to a line or microphone input,
+//! [`Capture::decode_next_cycle`] called in a loop is a complete WSPR
+//! monitor:
+//!
+//! ```no_run
+//! let capture = wspr::capture::Capture::new()?.with_dial(14_095_600.0);
+//! loop {
+//!     for spot in capture.decode_next_cycle()? {
+//!         let mut line = String::new();
+//!         spot.write_line(&mut line).unwrap();
+//!         println!("{line}");
+//!     }
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use std::io;
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::vec::Vec;
+
+use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use cpal::{FromSample, SampleFormat, SizedSample, StreamConfig};
+
+use crate::dsp::{Complex, Decimator};
+use crate::spot::Spot;
+use crate::wav::{decode_audio, invalid};
+
+// The sample rate the audio is decoded at.
+const SAMPLE_RATE: u32 = 12000;
+
+// The length of a cycle, and of the audio recorded from the start of each
+// cycle, in seconds.
+const CYCLE: u64 = 120;
+const RECORDING: usize = 114;
+
+// The cutoff of the filter ahead of decimation, in Hz. Its transition is
+// at most about 4 kHz wide even from 192000 Hz, so the passband stays flat
+// past 2 kHz, and anything that would alias below 2 kHz, from 10 kHz up, is
+// at least 70 dB down.
+const CUTOFF: f32 = 5000.0;
+
+fn other(error: cpal::Error) -> io::Error {
+    io::Error::other(error)
+}
+
+/// Records and decodes 2 minute cycles from an audio input device.
+///
+/// The device must support a sample rate that is a multiple of 12000 Hz,
+/// such as 12000 Hz or 48000 Hz. Only the first channel is used. Cycles are
+/// timed from the system clock, which should be kept accurate to within a
+/// second or so, such as with NTP.
+pub struct Capture {
+    device: cpal::Device,
+    config: StreamConfig,
+    format: SampleFormat,
+    dial: f64,
+}
+
+impl Capture {
+    /// Creates a capture from the default input device of the default host.
+    pub fn new() -> io::Result<Self> {
+        let device =
+            cpal::default_host().default_input_device().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, "no input device")
+            })?;
+        Self::with_device(device)
+    }
+
+    /// Creates a capture from `device`, choosing the lowest supported sample
+    /// rate that is a multiple of 12000 Hz.
+    pub fn with_device(device: cpal::Device) -> io::Result<Self> {
+        let mut best: Option<cpal::SupportedStreamConfig> = None;
+        for range in device.supported_input_configs().map_err(other)? {
+            let format = range.sample_format();
+            if !matches!(
+                format,
+                SampleFormat::I16 | SampleFormat::I32 | SampleFormat::F32
+            ) {
+                continue;
+            }
+            let rate = (1..=16)
+                .map(|n| n * SAMPLE_RATE)
+                .find(|&rate| range.contains_rate(rate));
+            if let Some(rate) = rate {
+                if best.as_ref().map_or(true, |best| rate < best.sample_rate())
+                {
+                    best = Some(range.with_sample_rate(rate));
+                }
+            }
+        }
+
+        let best = best
+            .ok_or(invalid("no sample rate that is a multiple of 12000 Hz"))?;
+        Ok(Self {
+            device,
+            format: best.sample_format(),
+            config: best.config(),
+            dial: 0.0,
+        })
+    }
+
+    /// Reports spot frequencies as RF frequencies, for a receiver tuned to
+    /// `dial` Hz in USB. Otherwise they are audio frequencies.
+    pub fn with_dial(self, dial: f64) -> Self {
+        Self { dial, ..self }
+    }
+
+    /// Waits for the next cycle to begin, at the start of an even minute,
+    /// records it, and decodes every WSPR transmission found. The spots'
+    /// timestamps are the start of the cycle.
+    pub fn decode_next_cycle(&self) -> io::Result<Vec<Spot>> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let start = (now.as_secs() / CYCLE + 1) * CYCLE;
+        let samples = self.record(UNIX_EPOCH + Duration::from_secs(start))?;
+
+        let mut spots = decode_audio(&samples)?;
+        for spot in spots.iter_mut() {
+            spot.frequency += self.dial;
+            spot.timestamp = start;
+        }
+        Ok(spots)
+    }
+
+    /// Records 114 seconds of audio beginning at `start`, returned as 12000
+    /// Hz samples. Blocks until the recording is complete.
+    pub fn record(&self, start: SystemTime) -> io::Result<Vec<f32>> {
+        // Leave a second for the stream to start before it's needed.
+        let lead = Duration::from_secs(1);
+        if let Ok(wait) = start.duration_since(SystemTime::now() + lead) {
+            thread::sleep(wait);
+        }
+
+        let (sender, receiver) = mpsc::channel();
+        let stream = match self.format {
+            SampleFormat::I16 => self.stream::<i16>(sender)?,
+            SampleFormat::I32 => self.stream::<i32>(sender)?,
+            _ => self.stream::<f32>(sender)?,
+        };
+        stream.play().map_err(other)?;
+
+        let len = RECORDING * SAMPLE_RATE as usize;
+        let mut downsampler =
+            Downsampler::new(self.config.sample_rate / SAMPLE_RATE);
+        let mut samples = Vec::with_capacity(len);
+        while samples.len() < len {
+            let block = receiver.recv_timeout(Duration::from_secs(5)).map_err(
+                |_| io::Error::new(io::ErrorKind::TimedOut, "input stopped"),
+            )?;
+            // Blocks that arrive before the start of the recording are
+            // dropped.
+            if SystemTime::now() >= start {
+                downsampler.push(&block, &mut samples);
+            }
+        }
+        samples.truncate(len);
+        Ok(samples)
+    }
+
+    // Builds an input stream sending the first channel of each block it
+    // receives to `sender`.
+    fn stream<T>(
+        &self,
+        sender: mpsc::Sender<Vec<f32>>,
+    ) -> io::Result<cpal::Stream>
+    where
+        T: SizedSample,
+        f32: FromSample<T>,
+    {
+        let channels = self.config.channels.max(1) as usize;
+        self.device
+            .build_input_stream(
+                self.config,
+                move |data: &[T], _: &cpal::InputCallbackInfo| {
+                    let block = data
+                        .iter()
+                        .step_by(channels)
+                        .map(|&sample| f32::from_sample_(sample))
+                        .collect();
+                    // The receiver only goes away once recording is complete.
+                    let _ = sender.send(block);
+                },
+                |_| {},
+                None,
+            )
+            .map_err(other)
+    }
+}
+
+// Decimates audio by an integer factor, low pass filtering it first so
+// noise above the new Nyquist frequency doesn't alias into the WSPR band.
+struct Downsampler {
+    decimator: Option<Decimator<256>>,
+}
+
+impl Downsampler {
+    fn new(factor: u32) -> Self {
+        let rate = (factor * SAMPLE_RATE) as f32;
+        Self {
+            decimator: (factor > 1)
+                .then(|| Decimator::new(factor as usize, CUTOFF / rate)),
+        }
+    }
+
+    fn push(&mut self, samples: &[f32], out: &mut Vec<f32>) {
+        let Some(decimator) = self.decimator.as_mut() else {
+            out.extend_from_slice(samples);
+            return;
+        };
+        for &sample in samples {
+            if let Some(sample) = decimator.push(Complex::new(sample, 0.0)) {
+                out.push(sample.re);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::f64::consts::TAU;
+    use std::vec;
+
+    // The power of a second of a tone at `frequency` Hz after decimating
+    // it from 48000 Hz, once the filter has settled.
+    fn power(frequency: f64) -> f32 {
+        let tone: Vec<f32> = (0..48000)
+            .map(|n| (TAU * frequency * n as f64 / 48000.0).sin() as f32)
+            .collect();
+        let mut downsampler = Downsampler::new(4);
+        let mut out = Vec::new();
+        downsampler.push(&tone[..3], &mut out);
+        assert!(out.is_empty());
+        downsampler.push(&tone[3..], &mut out);
+        assert_eq!(out.len(), 12000);
+        let settled = &out[100..];
+        settled.iter().map(|s| s * s).sum::<f32>() / settled.len() as f32
+    }
+
+    #[test]
+    fn test_downsampler() {
+        // The WSPR band passes, and a tone that would alias onto it is
+        // removed.
+        assert!((power(1500.0) - 0.5).abs() < 0.005);
+        assert!(power(10500.0) < 0.5e-7);
+
+        // At 12000 Hz the samples are kept as they are.
+        let mut downsampler = Downsampler::new(1);
+        let mut out = Vec::new();
+        downsampler.push(&[1.0, 2.0, 3.0], &mut out);
+        assert_eq!(out, vec![1.0, 2.0, 3.0]);
+    }
+}
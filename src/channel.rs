@@ -0,0 +1,634 @@
+//! Simulating the HF path a transmission takes, to characterize the
+//! decoder against the drift, timing errors, and fading of real signals.
+//!
+//! A [`Channel`] shifts the frequency of complex baseband samples, such as
+//! those of an [`IqModulator`](crate::modulate::IqModulator), by a linear
+//! and a sinusoidal drift, fades them, and delays them. The real part of
+//! the result is the audio a receiver would hear, if the modulator was
+//! offset to an audio frequency.
+//!
+//! [`simulate`] generates a whole cycle as received, with noise, as the
+//! wsprsim tool does, so the crate can build its own decoder test corpus.
+
+use core::f64::consts::TAU;
+
+use crate::dsp::Complex;
+use crate::modulate::IqModulator;
+use crate::{math, Error, SYMBOL_LENGTH};
+
+// The number of paths summed for Rayleigh fading.
+const PATHS: usize = 16;
+
+/// A simulated propagation channel, applied to samples one at a time as
+/// time passes.
+#[derive(Debug, Clone)]
+pub struct Channel {
+    sample_rate: u32,
+    drift: f32,
+    wobble: f32,
+    period: f32,
+    delay: f32,
+    spread: f32,
+    seed: u32,
+    // The index of the next sample and the phase of the drift.
+    index: usize,
+    phase: f64,
+    fading: Option<Fading>,
+}
+
+// The gain of a Rayleigh fading path, the sum of equal paths at random
+// Doppler shifts and phases (Clarke's model).
+#[derive(Debug, Clone)]
+struct Fading {
+    phases: [f64; PATHS],
+    steps: [f64; PATHS],
+}
+
+impl Channel {
+    /// Creates a channel for samples at `sample_rate` Hz, which passes them
+    /// through unchanged until configured.
+    ///
+    /// Returns [`Error::InvalidSampleRate`] for a sample rate of 0.
+    pub fn new(sample_rate: u32) -> Result<Self, Error> {
+        if sample_rate == 0 {
+            return Err(Error::InvalidSampleRate);
+        }
+        Ok(Self {
+            sample_rate,
+            drift: 0.0,
+            wobble: 0.0,
+            period: 0.0,
+            delay: 0.0,
+            spread: 0.0,
+            seed: 0x9E37_79B9,
+            index: 0,
+            phase: 0.0,
+            fading: None,
+        })
+    }
+
+    /// Drifts the frequency linearly by `drift` Hz over the 162 symbols of a
+    /// transmission, as [`drift_offset`](crate::demod::drift_offset) does,
+    /// with no offset at the middle.
+    pub fn with_drift(self, drift: f32) -> Self {
+        Self { drift, ..self }
+    }
+
+    /// Swings the frequency sinusoidally by up to `amplitude` Hz either
+    /// way, once every `period` seconds, as an oscillator might with a
+    /// cycling heater or a swaying antenna.
+    pub fn with_wobble(self, amplitude: f32, period: f32) -> Self {
+        Self {
+            wobble: amplitude,
+            period: period.max(0.0),
+            ..self
+        }
+    }
+
+    /// Delays the samples by `seconds`, which may be negative to drop the
+    /// first of them, as for a transmitter whose clock is off.
+    pub fn with_delay(self, seconds: f32) -> Self {
+        Self {
+            delay: seconds,
+            ..self
+        }
+    }
+
+    /// Fades the samples as Rayleigh fading does, with a Doppler spread of
+    /// `spread` Hz. The mean power is unchanged. HF paths spread by 0.1 Hz
+    /// or less on a quiet day, and by several Hz over the poles.
+    pub fn with_fading(self, spread: f32) -> Self {
+        let mut channel = Self {
+            spread: spread.max(0.0),
+            ..self
+        };
+        channel.reset();
+        channel
+    }
+
+    /// Seeds the random paths of the fading, so different runs fade
+    /// differently.
+    pub fn with_seed(self, seed: u32) -> Self {
+        let mut channel = Self {
+            seed: seed.max(1),
+            ..self
+        };
+        channel.reset();
+        channel
+    }
+
+    /// The delay in samples, negative for samples dropped.
+    pub fn delay(&self) -> isize {
+        let delay = self.delay as f64 * self.sample_rate as f64;
+        math::round(delay) as isize
+    }
+
+    /// The frequency offset in Hz of the drift at the next sample.
+    pub fn offset(&self) -> f32 {
+        let time = self.index as f64 / self.sample_rate as f64;
+        let symbol = time * 12000.0 / SYMBOL_LENGTH as f64;
+        let mut offset = self.drift as f64 * (symbol - 81.0) / 162.0;
+        if self.period > 0.0 {
+            let x = TAU * time / self.period as f64;
+            offset += self.wobble as f64 * math::sin_cos(x).0;
+        }
+        offset as f32
+    }
+
+    /// Passes the next sample through the channel.
+    pub fn process(&mut self, sample: Complex) -> Complex {
+        let mut gain = Complex::from_phase(self.phase);
+        if let Some(fading) = self.fading.as_mut() {
+            gain = gain * fading.next();
+        }
+        let offset = self.offset() as f64;
+        self.phase =
+            (self.phase + TAU * offset / self.sample_rate as f64) % TAU;
+        self.index += 1;
+        sample * gain
+    }
+
+    /// Passes `samples` through the channel in place, in time order. The
+    /// delay isn't applied; see [`Channel::transmit`].
+    pub fn apply(&mut self, samples: &mut [Complex]) {
+        for sample in samples {
+            *sample = self.process(*sample);
+        }
+    }
+
+    /// Passes `signal` through the channel, delayed by the channel's delay:
+    /// preceded by silence, or with its first samples dropped.
+    pub fn transmit<I: Iterator<Item = Complex>>(
+        self,
+        signal: I,
+    ) -> Received<I> {
+        let delay = self.delay();
+        let mut signal = signal;
+        if delay < 0 {
+            signal.by_ref().take(delay.unsigned_abs()).for_each(drop);
+        }
+        Received {
+            channel: self,
+            signal,
+            silence: delay.max(0) as usize,
+        }
+    }
+
+    // Starts the fading over, with paths drawn from the seed.
+    fn reset(&mut self) {
+        if self.spread == 0.0 {
+            self.fading = None;
+            return;
+        }
+        let mut random = Random(self.seed);
+        let mut phases = [0.0; PATHS];
+        let mut steps = [0.0; PATHS];
+        for (phase, step) in phases.iter_mut().zip(&mut steps) {
+            let angle = TAU * random.uniform();
+            let doppler = self.spread as f64 * math::sin_cos(angle).1;
+            *phase = TAU * random.uniform();
+            *step = TAU * doppler / self.sample_rate as f64;
+        }
+        self.fading = Some(Fading { phases, steps });
+    }
+}
+
+impl Fading {
+    // The gain at the next sample.
+    fn next(&mut self) -> Complex {
+        let mut gain = Complex::default();
+        for (phase, &step) in self.phases.iter_mut().zip(&self.steps) {
+            gain += Complex::from_phase(*phase);
+            *phase = (*phase + step) % TAU;
+        }
+        // The paths' powers sum to PATHS, so scaling by 1 / √PATHS makes
+        // the mean power 1.
+        gain * (1.0 / math::sqrt(PATHS as f32))
+    }
+}
+
+/// The samples of a signal after passing through a [`Channel`].
+#[derive(Debug, Clone)]
+pub struct Received<I> {
+    channel: Channel,
+    signal: I,
+    silence: usize,
+}
+
+impl<I: Iterator<Item = Complex>> Iterator for Received<I> {
+    type Item = Complex;
+
+    fn next(&mut self) -> Option<Complex> {
+        if self.silence > 0 {
+            self.silence -= 1;
+            return Some(Complex::default());
+        }
+        let sample = self.signal.next()?;
+        Some(self.channel.process(sample))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (low, high) = self.signal.size_hint();
+        (
+            low.saturating_add(self.silence),
+            high.and_then(|high| high.checked_add(self.silence)),
+        )
+    }
+}
+
+/// The conditions of a simulated cycle, see [`simulate`].
+///
+/// By default the cycle is 375 Hz complex baseband, as wsprd works on, and
+/// the transmission is centered at 0 Hz without noise, drift, or fading,
+/// beginning 1 second in as a transmitter following the protocol does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Simulation {
+    sample_rate: u32,
+    frequency: f32,
+    snr: Option<f32>,
+    drift: f32,
+    dt: f32,
+    spread: f32,
+    seed: u32,
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Simulation {
+    /// Creates the default conditions.
+    pub const fn new() -> Self {
+        Self {
+            sample_rate: crate::search::SAMPLE_RATE,
+            frequency: 0.0,
+            snr: None,
+            drift: 0.0,
+            dt: 0.0,
+            spread: 0.0,
+            seed: 0x2545_F491,
+        }
+    }
+
+    /// Generates samples at `sample_rate` Hz, such as 12000 Hz for audio.
+    pub fn with_sample_rate(self, sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            ..self
+        }
+    }
+
+    /// Centers the signal, midway between tones 1 and 2, at `frequency` Hz,
+    /// which may be negative for complex samples.
+    pub fn with_frequency(self, frequency: f32) -> Self {
+        Self { frequency, ..self }
+    }
+
+    /// Adds white Gaussian noise, with the signal `snr` dB above the noise
+    /// in a 2500 Hz bandwidth, as WSPR reports SNR.
+    pub fn with_snr(self, snr: f32) -> Self {
+        Self {
+            snr: Some(snr),
+            ..self
+        }
+    }
+
+    /// Drifts the signal by `drift` Hz over the transmission, see
+    /// [`Channel::with_drift`].
+    pub fn with_drift(self, drift: f32) -> Self {
+        Self { drift, ..self }
+    }
+
+    /// Begins the transmission `dt` seconds after its nominal start, as
+    /// wsprd reports DT.
+    pub fn with_dt(self, dt: f32) -> Self {
+        Self { dt, ..self }
+    }
+
+    /// Fades the signal with a Doppler spread of `spread` Hz, see
+    /// [`Channel::with_fading`].
+    pub fn with_fading(self, spread: f32) -> Self {
+        Self { spread, ..self }
+    }
+
+    /// Seeds the noise and fading, so different runs differ.
+    pub fn with_seed(self, seed: u32) -> Self {
+        Self {
+            seed: seed.max(1),
+            ..self
+        }
+    }
+
+    /// The sample rate in Hz.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// The number of samples in a whole cycle, 2 minutes.
+    pub fn total(&self) -> usize {
+        120 * self.sample_rate as usize
+    }
+
+    /// The samples of a cycle holding a transmission of `symbols`, one at a
+    /// time, see [`simulate`].
+    pub fn cycle<'a>(
+        &self,
+        symbols: &'a [u8; 162],
+    ) -> Result<Cycle<'a>, Error> {
+        let modulator =
+            IqModulator::new(symbols, self.sample_rate, self.frequency)?;
+        let channel = Channel::new(self.sample_rate)?
+            .with_drift(self.drift)
+            .with_delay(1.0 + self.dt)
+            .with_fading(self.spread)
+            .with_seed(self.seed);
+        Ok(Cycle {
+            signal: channel.transmit(modulator),
+            amplitude: self.amplitude(),
+            noise: self.snr.map(|_| Random(self.seed ^ 0x5DEE_CE66)),
+            remaining: self.total(),
+        })
+    }
+
+    // The amplitude of the signal for complex noise of power 1 per sample.
+    fn amplitude(&self) -> f32 {
+        match self.snr {
+            Some(snr) => {
+                let bandwidth = 2500.0 / self.sample_rate as f32;
+                math::sqrt(math::exp10(snr / 10.0) * bandwidth)
+            }
+            None => 1.0,
+        }
+    }
+}
+
+/// Generates the complex samples of a cycle holding a transmission of
+/// `symbols` under the conditions of `simulation` into `samples`, normally
+/// [`Simulation::total`] long. The noise, if any, has a power of 1.0 per
+/// sample.
+///
+/// Fails as [`IqModulator::new`] does if the sample rate is 0 or a tone
+/// lies outside the baseband.
+pub fn simulate(
+    symbols: &[u8; 162],
+    simulation: &Simulation,
+    samples: &mut [Complex],
+) -> Result<(), Error> {
+    let cycle = simulation.cycle(symbols)?;
+    for (sample, value) in samples.iter_mut().zip(cycle) {
+        *sample = value;
+    }
+    Ok(())
+}
+
+/// Generates the audio samples of a cycle as [`simulate`] does, from the
+/// real part of the signal at the same SNR. The noise, if any, has an RMS
+/// of 0.1; otherwise the signal peaks at 0.5.
+///
+/// Returns the errors [`simulate`] does, and [`Error::InvalidFrequency`]
+/// if any tone would be at or below 0 Hz.
+pub fn simulate_audio(
+    symbols: &[u8; 162],
+    simulation: &Simulation,
+    samples: &mut [f32],
+) -> Result<(), Error> {
+    let lowest = simulation.frequency - 1.5 * crate::TONE_SPACING;
+    if lowest <= 0.0 {
+        return Err(Error::InvalidFrequency);
+    }
+
+    // The real part of complex noise holds half its power, and of a complex
+    // tone half its power too, but the noise is spread over half the
+    // bandwidth, so the signal is raised by 3 dB to keep the SNR.
+    let (cycle, scale) = match simulation.snr {
+        Some(snr) => {
+            let simulation = simulation.with_snr(snr + 3.0103);
+            (simulation.cycle(symbols)?, 0.1 * core::f32::consts::SQRT_2)
+        }
+        None => (simulation.cycle(symbols)?, 0.5),
+    };
+    for (sample, value) in samples.iter_mut().zip(cycle) {
+        *sample = value.re * scale;
+    }
+    Ok(())
+}
+
+/// The samples of a simulated cycle, see [`Simulation::cycle`].
+#[derive(Debug, Clone)]
+pub struct Cycle<'a> {
+    signal: Received<IqModulator<'a>>,
+    amplitude: f32,
+    noise: Option<Random>,
+    remaining: usize,
+}
+
+impl Iterator for Cycle<'_> {
+    type Item = Complex;
+
+    fn next(&mut self) -> Option<Complex> {
+        self.remaining = self.remaining.checked_sub(1)?;
+        let mut sample =
+            self.signal.next().unwrap_or_default() * self.amplitude;
+        if let Some(noise) = self.noise.as_mut() {
+            sample += noise.gaussian();
+        }
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for Cycle<'_> {}
+
+// Uniform random numbers by xorshift, so that simulations are repeatable.
+#[derive(Debug, Clone)]
+pub(crate) struct Random(pub(crate) u32);
+
+impl Random {
+    // A random number from 0.0 up to 1.0.
+    pub(crate) fn uniform(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as f64 / 4294967296.0
+    }
+
+    // Complex Gaussian noise with a power of 1, by the Box-Muller transform.
+    pub(crate) fn gaussian(&mut self) -> Complex {
+        let u = 1.0 - self.uniform() as f32;
+        let radius = math::sqrt(-math::log10(u) / core::f32::consts::LOG10_E);
+        Complex::from_phase(TAU * self.uniform()) * radius
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demod::drift_offset;
+    use crate::modulate::IqModulator;
+    use crate::TONE_SPACING;
+    use std::vec::Vec;
+
+    // The frequency in Hz of each step between `samples` at 375 Hz.
+    fn frequencies(samples: &[Complex]) -> Vec<f32> {
+        samples
+            .windows(2)
+            .map(|pair| {
+                let step = pair[1] * pair[0].conj();
+                step.im.atan2(step.re) * 375.0 / TAU as f32
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_drift() {
+        assert_eq!(Channel::new(0).err(), Some(Error::InvalidSampleRate));
+
+        // A steady tone drifts like the search's model of drift.
+        let tone = (0..162 * 256).map(|_| Complex::new(1.0, 0.0));
+        let samples: Vec<Complex> = Channel::new(375)
+            .unwrap()
+            .with_drift(4.0)
+            .transmit(tone)
+            .collect();
+        let measured = frequencies(&samples);
+        for symbol in 0..162 {
+            let frequency = measured[symbol * 256];
+            assert!((frequency - drift_offset(4.0, symbol)).abs() < 1e-3);
+        }
+
+        // The wobble peaks a quarter of a period in.
+        let tone = (0..3750).map(|_| Complex::new(1.0, 0.0));
+        let samples: Vec<Complex> = Channel::new(375)
+            .unwrap()
+            .with_wobble(0.5, 4.0)
+            .transmit(tone)
+            .collect();
+        let measured = frequencies(&samples);
+        assert!((measured[375] - 0.5).abs() < 1e-3);
+        assert!((measured[1125] + 0.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_delay() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let modulator = IqModulator::new(&symbols, 375, 0.0).unwrap();
+        let total = modulator.total();
+
+        let channel = Channel::new(375).unwrap().with_delay(2.0);
+        assert_eq!(channel.delay(), 750);
+        let samples: Vec<Complex> =
+            channel.transmit(modulator.clone()).collect();
+        assert_eq!(samples.len(), total + 750);
+        assert!(samples[..750].iter().all(|&s| s == Complex::default()));
+        assert_eq!(samples[750], Complex::new(1.0, 0.0));
+
+        // A negative delay drops the start of the first symbol.
+        let channel = Channel::new(375).unwrap().with_delay(-0.5);
+        let received = channel.transmit(modulator);
+        assert_eq!(received.size_hint(), (total - 188, Some(total - 188)));
+        let samples: Vec<Complex> = received.collect();
+        let frequency = frequencies(&samples[..2])[0];
+        let tone = (symbols[0] as f32 - 1.5) * TONE_SPACING;
+        assert!((frequency - tone).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_simulate() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let simulation = Simulation::new()
+            .with_frequency(30.0)
+            .with_snr(-20.0)
+            .with_drift(1.0)
+            .with_dt(0.5);
+        let mut samples = std::vec![Complex::default(); simulation.total()];
+        simulate(&symbols, &simulation, &mut samples).unwrap();
+        assert_eq!(samples.len(), 45000);
+
+        // The noise before the transmission has a power of 1.
+        let noise = samples[..500].iter().map(|s| s.norm_sqr()).sum::<f32>();
+        assert!((noise / 500.0 - 1.0).abs() < 0.15, "{}", noise / 500.0);
+
+        // It decodes with the conditions it was simulated with.
+        let mut workspace =
+            std::vec![0f32; crate::search::workspace_len(samples.len())];
+        let mut spots = [crate::spot::Spot::default(); 4];
+        let count = crate::search::decode(
+            &mut samples,
+            &mut crate::dsp::Radix2::new(),
+            &mut workspace,
+            &mut spots,
+        )
+        .unwrap();
+        assert_eq!(count, 1);
+        let spot = &spots[0];
+        assert_eq!(spot.message.callsign(), "K1ABC");
+        assert!((spot.frequency - 30.0).abs() < 0.5, "{}", spot.frequency);
+        assert!((spot.dt - 0.5).abs() < 0.2, "{}", spot.dt);
+        assert!((spot.snr + 20.0).abs() < 2.0, "{}", spot.snr);
+        assert!((spot.drift - 1.0).abs() < 1.0, "{}", spot.drift);
+
+        // The same seed gives the same cycle, and another a different one.
+        let mut again = std::vec![Complex::default(); 1000];
+        simulate(&symbols, &simulation, &mut again).unwrap();
+        let mut other = again.clone();
+        simulate(&symbols, &simulation.with_seed(7), &mut other).unwrap();
+        assert_ne!(again, other);
+        simulate(&symbols, &simulation, &mut other).unwrap();
+        assert_eq!(again, other);
+    }
+
+    #[test]
+    fn test_simulate_audio() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let simulation = Simulation::new().with_sample_rate(8000);
+        let mut samples = std::vec![0f32; 16000];
+        assert_eq!(
+            simulate_audio(&symbols, &simulation, &mut samples),
+            Err(Error::InvalidFrequency)
+        );
+
+        // Without noise the signal peaks at 0.5, a second in.
+        let simulation = simulation.with_frequency(1500.0);
+        simulate_audio(&symbols, &simulation, &mut samples).unwrap();
+        assert!(samples[..8000].iter().all(|&sample| sample == 0.0));
+        let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!((peak - 0.5).abs() < 1e-3);
+
+        // With noise, its RMS is 0.1.
+        let simulation = simulation.with_snr(-10.0);
+        simulate_audio(&symbols, &simulation, &mut samples).unwrap();
+        let power = samples[..8000].iter().map(|s| s * s).sum::<f32>() / 8000.0;
+        assert!((power.sqrt() - 0.1).abs() < 0.005, "{}", power.sqrt());
+    }
+
+    #[test]
+    fn test_fading() {
+        // The gain varies, with a mean power of about 1 and the deep fades
+        // of a Rayleigh distribution, about 10% of the time 10 dB down.
+        let mut channel = Channel::new(375).unwrap().with_fading(1.0);
+        let powers: Vec<f32> = (0..375 * 600)
+            .map(|_| channel.process(Complex::new(1.0, 0.0)).norm_sqr())
+            .collect();
+        let mean = powers.iter().sum::<f32>() / powers.len() as f32;
+        assert!((mean - 1.0).abs() < 0.2, "{}", mean);
+        let faded = powers.iter().filter(|&&power| power < 0.1).count();
+        let fraction = faded as f32 / powers.len() as f32;
+        assert!(fraction > 0.05 && fraction < 0.15, "{}", fraction);
+
+        // Another seed fades differently, and the same seed the same.
+        let fade = |seed| {
+            let mut channel =
+                Channel::new(375).unwrap().with_fading(1.0).with_seed(seed);
+            (0..1000)
+                .map(|_| channel.process(Complex::new(1.0, 0.0)))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(fade(1), fade(1));
+        assert_ne!(fade(1), fade(2));
+    }
+}
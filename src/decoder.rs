@@ -0,0 +1,390 @@
+//! Channel decoding of the K=32, r=1/2 convolutional code used by WSPR.
+//!
+//! The decoders in this module operate on the 162 deinterleaved code bits of
+//! a message (that is, with the sync vector removed). Each bit is a soft
+//! value in the range 0-255 where 0 is a confident 0, 255 is a confident 1,
+//! and values near 128 carry little information.
+
+use crate::{parity, Error, POLY0, POLY1};
+
+mod osd;
+#[cfg(feature = "viterbi")]
+mod viterbi;
+
+pub use osd::{Osd, OsdScratch};
+
+#[cfg(feature = "viterbi")]
+pub use viterbi::{ListViterbi, ListViterbiScratch};
+
+// The number of bits fed through the encoder: 50 message bits followed by 31
+// zero tail bits.
+const NBITS: usize = 81;
+
+// The first node at which the encoder is fed the tail.
+const TAIL: usize = 50;
+
+// The default metric for a 0 bit, derived from a Gaussian channel model with
+// a bias of 0.45 and scaled by 10, in the same manner as wsprd. The metric
+// for a 1 bit is the mirror image.
+const DEFAULT_METRIC: [i16; 256] = [
+    5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+    5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5,
+    5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 5, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4, 4,
+    4, 4, 4, 4, 4, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 3, 2, 2, 2, 2, 2, 2, 2, 2, 1,
+    1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, -1, -1, -1, -1, -1, -2, -2, -2, -2, -2,
+    -3, -3, -3, -4, -4, -4, -4, -5, -5, -5, -6, -6, -6, -6, -7, -7, -7, -8, -8,
+    -9, -9, -9, -10, -10, -10, -11, -11, -12, -12, -12, -13, -13, -14, -14,
+    -15, -15, -15, -16, -16, -17, -17, -18, -18, -19, -19, -20, -20, -20, -21,
+    -21, -22, -22, -23, -23, -24, -24, -25, -25, -26, -26, -27, -27, -28, -29,
+    -29, -30, -30, -31, -31, -32, -32, -33, -33, -34, -34, -35, -35, -36, -37,
+    -37, -38, -38, -39, -39, -40, -40, -41, -42, -42, -43, -43, -44, -44, -45,
+    -45, -46, -47, -47, -48, -48, -49, -49, -50, -50, -51, -52, -52, -53, -53,
+    -54, -54, -55, -56, -56, -57, -57, -58, -58, -59, -60, -60, -61, -61, -62,
+    -62, -63, -64, -64, -65, -65, -66, -66, -67, -68, -68,
+];
+
+/// The branch metrics used by the sequential decoder, indexed by soft bit
+/// value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetricTable {
+    /// The metric added when the soft value is hypothesized to be a 0.
+    pub zero: [i16; 256],
+    /// The metric added when the soft value is hypothesized to be a 1.
+    pub one: [i16; 256],
+}
+
+impl MetricTable {
+    /// Builds a table for a channel that treats 0s and 1s alike, where the
+    /// metric for a 1 at soft value `i` is the metric for a 0 at `255 - i`.
+    pub fn symmetric(zero: [i16; 256]) -> Self {
+        let mut one = [0i16; 256];
+        for (i, metric) in one.iter_mut().enumerate() {
+            *metric = zero[255 - i];
+        }
+        Self { zero, one }
+    }
+}
+
+impl Default for MetricTable {
+    fn default() -> Self {
+        Self::symmetric(DEFAULT_METRIC)
+    }
+}
+
+/// The result of a successful sequential decode.
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decoded {
+    /// The 50 message bits, with the first bit transmitted in bit 49.
+    pub data: u64,
+    /// The accumulated path metric of the decoded message.
+    pub metric: i32,
+    /// The number of cycles the decoder required.
+    pub cycles: u32,
+}
+
+/// A decoder for the K=32, r=1/2 convolutional code, allowing the time and
+/// memory trade-off of decoding to be chosen by the caller.
+pub trait ChannelDecoder {
+    /// The working memory the decoder needs.
+    type Scratch: Default;
+
+    /// Decodes 162 deinterleaved soft bits into the 50 message bits, using
+    /// `scratch` as working memory.
+    fn decode_in(
+        &self,
+        bits: &[u8; 162],
+        scratch: &mut Self::Scratch,
+    ) -> Result<Decoded, Error>;
+
+    /// Decodes 162 deinterleaved soft bits into the 50 message bits, with
+    /// the working memory on the stack.
+    fn decode(&self, bits: &[u8; 162]) -> Result<Decoded, Error> {
+        self.decode_in(bits, &mut Self::Scratch::default())
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Node {
+    // The encoder state after the bit at this node has been shifted in.
+    state: u32,
+    // The path metric up to this node.
+    gamma: i32,
+    // The branch metrics for each of the four possible symbol pairs.
+    metrics: [i32; 4],
+    // The sorted metrics for the best and second best branches.
+    branches: [i32; 2],
+    // The branch currently being explored.
+    branch: usize,
+}
+
+impl Node {
+    const EMPTY: Node = Node {
+        state: 0,
+        gamma: 0,
+        metrics: [0; 4],
+        branches: [0; 2],
+        branch: 0,
+    };
+
+    // Computes and sorts the branch metrics leaving this node. The state
+    // must have a 0 in its least significant bit on entry, and will have the
+    // better branch's bit on exit.
+    fn sort(&mut self, tail: bool) {
+        let symbols = encode(self.state);
+        if tail {
+            // The tail must be all zeroes, the 1 branch is never explored.
+            self.branches[0] = self.metrics[symbols];
+        } else {
+            // Both polynomials are odd, so the 1 branch produces the
+            // complement of the 0 branch's symbols.
+            let m0 = self.metrics[symbols];
+            let m1 = self.metrics[3 ^ symbols];
+            if m0 > m1 {
+                self.branches = [m0, m1];
+            } else {
+                self.branches = [m1, m0];
+                self.state |= 1;
+            }
+        }
+        self.branch = 0;
+    }
+}
+
+/// Working memory for a [`Fano`] decoder, about 3.3KB.
+///
+/// [`Fano::decode`] keeps this on the stack. Holding it elsewhere, such as
+/// in a `static`, and decoding with [`Fano::decode_in`] makes the decoder's
+/// memory use explicit.
+#[derive(Clone)]
+pub struct FanoScratch {
+    nodes: [Node; NBITS + 1],
+}
+
+impl FanoScratch {
+    /// Creates zeroed working memory.
+    pub const fn new() -> Self {
+        Self {
+            nodes: [Node::EMPTY; NBITS + 1],
+        }
+    }
+}
+
+impl Default for FanoScratch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Returns the pair of code bits produced by the encoder in `state`.
+fn encode(state: u32) -> usize {
+    ((parity(state & POLY0) << 1) | parity(state & POLY1)) as usize
+}
+
+/// A Fano sequential decoder, as used by wsprd, for the K=32, r=1/2
+/// convolutional code.
+///
+/// The decoder walks the code tree, backing up whenever the path metric
+/// falls below a running threshold. The amount of work it does grows quickly
+/// as the signal degrades, so the search is abandoned once `max_cycles` per
+/// bit have been spent.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fano {
+    /// The branch metrics to use.
+    pub metric: MetricTable,
+    /// The amount by which the threshold is raised or lowered.
+    pub delta: i32,
+    /// The maximum number of cycles, per decoded bit, before giving up.
+    pub max_cycles: u32,
+}
+
+impl Default for Fano {
+    fn default() -> Self {
+        Self {
+            metric: MetricTable::default(),
+            delta: 60,
+            max_cycles: 10_000,
+        }
+    }
+}
+
+impl Fano {
+    /// Decodes 162 deinterleaved soft bits into the 50 message bits,
+    /// returning [`Error::DecodeFailed`] if the cycle limit is reached.
+    pub fn decode(&self, bits: &[u8; 162]) -> Result<Decoded, Error> {
+        self.decode_in(bits, &mut FanoScratch::new())
+    }
+
+    /// Decodes 162 deinterleaved soft bits as [`Fano::decode`] does, using
+    /// `scratch` as working memory.
+    pub fn decode_in(
+        &self,
+        bits: &[u8; 162],
+        scratch: &mut FanoScratch,
+    ) -> Result<Decoded, Error> {
+        let nodes = &mut scratch.nodes;
+        nodes.fill(Node::EMPTY);
+
+        // Compute the branch metrics for every possible symbol pair up
+        // front, this is the only place the received bits are examined.
+        let (zero, one) = (&self.metric.zero, &self.metric.one);
+        for (node, pair) in nodes.iter_mut().zip(bits.chunks_exact(2)) {
+            let (a, b) = (pair[0] as usize, pair[1] as usize);
+            node.metrics = [
+                (zero[a] + zero[b]) as i32,
+                (zero[a] + one[b]) as i32,
+                (one[a] + zero[b]) as i32,
+                (one[a] + one[b]) as i32,
+            ];
+        }
+
+        nodes[0].sort(false);
+
+        let max_cycles = self.max_cycles.saturating_mul(NBITS as u32);
+        let mut threshold = 0;
+        let mut n = 0;
+        let mut cycles = 0;
+
+        while n < NBITS {
+            cycles += 1;
+            if cycles > max_cycles {
+                return Err(Error::DecodeFailed);
+            }
+
+            // Look forward.
+            let node = nodes[n];
+            let gamma = node.gamma + node.branches[node.branch];
+            if gamma >= threshold {
+                // On the first visit to a node, tighten the threshold.
+                if node.gamma < threshold + self.delta {
+                    while gamma >= threshold + self.delta {
+                        threshold += self.delta;
+                    }
+                }
+
+                n += 1;
+                nodes[n].gamma = gamma;
+                nodes[n].state = node.state << 1;
+                if n < NBITS {
+                    nodes[n].sort(n >= TAIL);
+                }
+                continue;
+            }
+
+            // The threshold has been violated, look backward.
+            loop {
+                if n == 0 || nodes[n - 1].gamma < threshold {
+                    // Can't back up either, so relax the threshold and look
+                    // forward again from the best branch.
+                    threshold -= self.delta;
+                    if nodes[n].branch != 0 {
+                        nodes[n].branch = 0;
+                        nodes[n].state ^= 1;
+                    }
+                    break;
+                }
+
+                // Back up and try the next best branch, if there is one.
+                n -= 1;
+                if n < TAIL && nodes[n].branch != 1 {
+                    nodes[n].branch += 1;
+                    nodes[n].state ^= 1;
+                    break;
+                }
+            }
+        }
+
+        // Each node's state holds the last 32 bits decided, so the 50
+        // message bits are spread over two of them.
+        let high = (nodes[17].state & 0x3FFFF) as u64;
+        let low = nodes[TAIL - 1].state as u64;
+
+        Ok(Decoded {
+            data: (high << 32) | low,
+            metric: nodes[NBITS].gamma,
+            cycles,
+        })
+    }
+}
+
+impl ChannelDecoder for Fano {
+    type Scratch = FanoScratch;
+
+    fn decode_in(
+        &self,
+        bits: &[u8; 162],
+        scratch: &mut FanoScratch,
+    ) -> Result<Decoded, Error> {
+        Fano::decode_in(self, bits, scratch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Runs the encoder over 50 message bits, returning the code bits as hard
+    // soft values.
+    pub(super) fn encode_bits(data: u64) -> [u8; 162] {
+        let mut bits = [0u8; 162];
+        let mut state = 0u32;
+        for i in 0..NBITS {
+            let bit = if i < TAIL {
+                (data >> (49 - i)) & 0x01
+            } else {
+                0
+            };
+            state = (state << 1) | bit as u32;
+            bits[2 * i] = parity(state & POLY0) * 255;
+            bits[2 * i + 1] = parity(state & POLY1) * 255;
+        }
+        bits
+    }
+
+    #[test]
+    fn test_metric_table() {
+        let metric = MetricTable::default();
+        assert_eq!(metric.zero[0], metric.one[255]);
+        assert_eq!(metric.zero[255], metric.one[0]);
+        assert!(metric.zero[0] > 0 && metric.zero[255] < 0);
+    }
+
+    #[test]
+    fn test_fano() {
+        let fano = Fano::default();
+        for data in [0, 0x3_FFFF_FFFF_FFFF, 0x2_4924_9249_2492, 0x1_2345_6789] {
+            let decoded = fano.decode(&encode_bits(data)).unwrap();
+            assert_eq!(decoded.data, data);
+        }
+    }
+
+    #[test]
+    fn test_fano_corrects_errors() {
+        let fano = Fano::default();
+        let mut bits = encode_bits(0x1_2345_6789);
+        for i in [3, 40, 41, 90, 150] {
+            bits[i] ^= 0xFF;
+        }
+        assert_eq!(fano.decode(&bits).unwrap().data, 0x1_2345_6789);
+
+        // Erasures carry no information, but don't hurt either.
+        let mut bits = encode_bits(0x1_2345_6789);
+        for bit in bits.iter_mut().step_by(7) {
+            *bit = 128;
+        }
+        assert_eq!(fano.decode(&bits).unwrap().data, 0x1_2345_6789);
+    }
+
+    #[test]
+    fn test_fano_cycle_limit() {
+        let fano = Fano {
+            max_cycles: 1,
+            ..Fano::default()
+        };
+        let mut bits = encode_bits(0x1_2345_6789);
+        for bit in bits.iter_mut().step_by(3) {
+            *bit ^= 0xFF;
+        }
+        assert_eq!(fano.decode(&bits), Err(Error::DecodeFailed));
+    }
+}
@@ -0,0 +1,331 @@
+use super::{ChannelDecoder, Decoded, MetricTable, NBITS, TAIL};
+use crate::{parity, Error, POLY0, POLY1};
+
+// A codeword of 162 bits, bit `i` of the codeword in bit `i % 64` of word
+// `i / 64`.
+type Codeword = [u64; 3];
+
+#[derive(Clone, Copy, Default)]
+struct Row {
+    // The codeword this row of the generator matrix produces.
+    codeword: Codeword,
+    // The message bits that produce it.
+    data: u64,
+}
+
+impl Row {
+    const EMPTY: Row = Row {
+        codeword: [0; 3],
+        data: 0,
+    };
+
+    fn get(&self, bit: usize) -> bool {
+        self.codeword[bit / 64] >> (bit % 64) & 1 == 1
+    }
+
+    fn xor(&mut self, other: &Row) {
+        for (word, other) in self.codeword.iter_mut().zip(&other.codeword) {
+            *word ^= other;
+        }
+        self.data ^= other.data;
+    }
+}
+
+/// Working memory for an [`Osd`] decoder, about 1.8KB.
+///
+/// [`Osd::decode`] keeps this on the stack. Holding it elsewhere, such as in
+/// a `static`, and decoding with [`Osd::decode_in`] makes the decoder's
+/// memory use explicit.
+#[derive(Clone)]
+pub struct OsdScratch {
+    rows: [Row; TAIL],
+    order: [u8; 162],
+}
+
+impl OsdScratch {
+    /// Creates zeroed working memory.
+    pub const fn new() -> Self {
+        Self {
+            rows: [Row::EMPTY; TAIL],
+            order: [0; 162],
+        }
+    }
+}
+
+impl Default for OsdScratch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An ordered statistics decoder for the K=32, r=1/2 convolutional code,
+/// treating it as a (162, 50) block code.
+///
+/// The generator matrix is reduced so that the message is determined by
+/// the 50 most reliable independent code bits. Their hard decisions give one
+/// codeword, and flipping up to `order` of them gives the rest of the
+/// codewords tested; the one that best matches the soft bits is returned.
+/// An order of 2 tests 1276 codewords.
+///
+/// Unlike [`Fano`](super::Fano), the running time doesn't depend on the
+/// signal quality, and it doesn't give up on a weak signal, which makes it
+/// a useful second attempt once the sequential decoder has failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Osd {
+    /// The branch metrics used to score each codeword.
+    pub metric: MetricTable,
+    /// The most reliable bits flipped at once, from 0 to 2.
+    pub order: u32,
+    /// The lowest metric accepted. The best of so many codewords always
+    /// matches noise somewhat, so the default of -140 is set to reject noise
+    /// alone for soft symbols scaled as [`crate::soft_symbols`] scales them.
+    pub min_metric: i32,
+}
+
+impl Default for Osd {
+    fn default() -> Self {
+        Self {
+            metric: MetricTable::default(),
+            order: 2,
+            min_metric: -140,
+        }
+    }
+}
+
+impl Osd {
+    /// Decodes 162 deinterleaved soft bits into the 50 message bits. The
+    /// codeword that best matches the soft bits is returned, unless its
+    /// metric is below `min_metric`, in which case [`Error::DecodeFailed`]
+    /// is returned.
+    pub fn decode(&self, bits: &[u8; 162]) -> Result<Decoded, Error> {
+        self.decode_in(bits, &mut OsdScratch::new())
+    }
+
+    /// Decodes 162 deinterleaved soft bits as [`Osd::decode`] does, using
+    /// `scratch` as working memory.
+    pub fn decode_in(
+        &self,
+        bits: &[u8; 162],
+        scratch: &mut OsdScratch,
+    ) -> Result<Decoded, Error> {
+        let OsdScratch { rows, order } = scratch;
+
+        // The generator matrix, one row per message bit.
+        for (i, row) in rows.iter_mut().enumerate() {
+            *row = generator(i);
+        }
+
+        // The hard decisions, and the code bits from most to least reliable.
+        let mut hard = [0u64; 3];
+        for (i, &bit) in bits.iter().enumerate() {
+            hard[i / 64] |= ((bit >= 128) as u64) << (i % 64);
+        }
+        for (i, position) in order.iter_mut().enumerate() {
+            *position = i as u8;
+        }
+        order.sort_unstable_by_key(|&i| {
+            core::cmp::Reverse(reliability(bits[i as usize]))
+        });
+
+        // Reduce the matrix to have a single 1 in each of the 50 most
+        // reliable independent columns, so that each row flips exactly one
+        // of those bits.
+        let mut pivots = [0u8; TAIL];
+        let mut rank = 0;
+        for &column in order.iter() {
+            if rank == TAIL {
+                break;
+            }
+            let column = column as usize;
+            let Some(pivot) = (rank..TAIL).find(|&r| rows[r].get(column))
+            else {
+                continue;
+            };
+            rows.swap(rank, pivot);
+            let pivot = rows[rank];
+            for (r, row) in rows.iter_mut().enumerate() {
+                if r != rank && row.get(column) {
+                    row.xor(&pivot);
+                }
+            }
+            pivots[rank] = column as u8;
+            rank += 1;
+        }
+        if rank < TAIL {
+            return Err(Error::DecodeFailed);
+        }
+
+        // The codeword matching the hard decisions of the pivot bits.
+        let mut base = Row::EMPTY;
+        for (row, &pivot) in rows.iter().zip(&pivots) {
+            if hard[pivot as usize / 64] >> (pivot % 64) & 1 == 1 {
+                base.xor(row);
+            }
+        }
+
+        let score = |row: &Row| discrepancy(bits, &hard, &row.codeword);
+        let mut best = (score(&base), base);
+        let mut cycles = 1;
+        let mut test = |row: Row| {
+            cycles += 1;
+            let discrepancy = score(&row);
+            if discrepancy < best.0 {
+                best = (discrepancy, row);
+            }
+        };
+        if self.order >= 1 {
+            for (i, first) in rows.iter().enumerate() {
+                let mut flipped = base;
+                flipped.xor(first);
+                test(flipped);
+                if self.order >= 2 {
+                    for second in &rows[i + 1..] {
+                        let mut flipped = flipped;
+                        flipped.xor(second);
+                        test(flipped);
+                    }
+                }
+            }
+        }
+
+        let (_, best) = best;
+        let metric = bits
+            .iter()
+            .enumerate()
+            .map(|(i, &bit)| {
+                let table = if best.get(i) {
+                    &self.metric.one
+                } else {
+                    &self.metric.zero
+                };
+                table[bit as usize] as i32
+            })
+            .sum();
+        if metric < self.min_metric {
+            return Err(Error::DecodeFailed);
+        }
+
+        Ok(Decoded {
+            data: best.data,
+            metric,
+            cycles,
+        })
+    }
+}
+
+impl ChannelDecoder for Osd {
+    type Scratch = OsdScratch;
+
+    fn decode_in(
+        &self,
+        bits: &[u8; 162],
+        scratch: &mut OsdScratch,
+    ) -> Result<Decoded, Error> {
+        Osd::decode_in(self, bits, scratch)
+    }
+}
+
+// The row of the generator matrix for message bit `i`, counting from the
+// first bit transmitted.
+fn generator(i: usize) -> Row {
+    let mut row = Row {
+        codeword: [0; 3],
+        data: 1 << (TAIL - 1 - i),
+    };
+    let mut state = 0u32;
+    for n in i..NBITS {
+        state = (state << 1) | (n == i) as u32;
+        if state == 0 {
+            break;
+        }
+        for (bit, poly) in [(2 * n, POLY0), (2 * n + 1, POLY1)] {
+            row.codeword[bit / 64] |=
+                (parity(state & poly) as u64) << (bit % 64);
+        }
+    }
+    row
+}
+
+// How confident a soft bit is, from 0 to 127.
+fn reliability(bit: u8) -> u8 {
+    (bit as i16 - 128).unsigned_abs().min(127) as u8
+}
+
+// The total reliability of the soft bits whose hard decisions disagree with
+// `codeword`.
+fn discrepancy(bits: &[u8; 162], hard: &Codeword, codeword: &Codeword) -> u32 {
+    let mut total = 0;
+    for (w, (&hard, &word)) in hard.iter().zip(codeword).enumerate() {
+        let mut differences = hard ^ word;
+        while differences != 0 {
+            let bit = differences.trailing_zeros() as usize;
+            total += reliability(bits[64 * w + bit]) as u32;
+            differences &= differences - 1;
+        }
+    }
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::encode_bits;
+    use super::super::Fano;
+    use super::*;
+    use crate::demod::tests::Noise;
+
+    #[test]
+    fn test_osd() {
+        let osd = Osd::default();
+        let mut scratch = OsdScratch::new();
+        for data in [0, 0x3_FFFF_FFFF_FFFF, 0x1_2345_6789] {
+            let decoded = osd.decode(&encode_bits(data)).unwrap();
+            assert_eq!(decoded.data, data);
+            let decoded =
+                osd.decode_in(&encode_bits(data), &mut scratch).unwrap();
+            assert_eq!(decoded.data, data);
+            assert_eq!(decoded.cycles, 1276);
+        }
+    }
+
+    #[test]
+    fn test_osd_fallback() {
+        let fano = Fano::default();
+        let osd = Osd::default();
+        let data = 0x1_2345_6789;
+        let expected = encode_bits(data);
+        let mut noise = Noise::new();
+
+        // Weak soft bits in heavy noise, which the sequential decoder often
+        // can't decode. Ordered statistics recovers some of those it misses.
+        let (mut fano_decodes, mut osd_decodes) = (0, 0);
+        for _ in 0..40 {
+            let mut bits = [0u8; 162];
+            for (bit, &expected) in bits.iter_mut().zip(&expected) {
+                let sign = if expected == 255 { 1.0 } else { -1.0 };
+                let noise: f32 =
+                    (0..12).map(|_| noise.next()).sum::<f32>() / 2.0;
+                let soft = 128.0 + 40.0 * (0.8 * sign + noise);
+                *bit = soft.clamp(0.0, 255.0) as u8;
+            }
+            if fano.decode(&bits).is_ok_and(|d| d.data == data) {
+                fano_decodes += 1;
+            } else if let Ok(decoded) = osd.decode(&bits) {
+                assert_eq!(decoded.data, data);
+                osd_decodes += 1;
+            }
+        }
+        assert!(fano_decodes < 40);
+        assert!(osd_decodes > 0);
+
+        // Noise alone is rejected.
+        for _ in 0..20 {
+            let mut bits = [0u8; 162];
+            for bit in bits.iter_mut() {
+                let noise: f32 =
+                    (0..12).map(|_| noise.next()).sum::<f32>() / 2.0;
+                *bit = (128.0 + 40.0 * noise).clamp(0.0, 255.0) as u8;
+            }
+            assert_eq!(osd.decode(&bits), Err(Error::DecodeFailed));
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use super::{encode, ChannelDecoder, Decoded, MetricTable, TAIL};
+use crate::Error;
+
+#[derive(Clone, Copy, Default)]
+struct Path {
+    // The last 32 bits decided along this path.
+    state: u32,
+    // The accumulated path metric.
+    metric: i32,
+    // The message bits decided so far.
+    data: u64,
+}
+
+impl Path {
+    const EMPTY: Path = Path {
+        state: 0,
+        metric: 0,
+        data: 0,
+    };
+}
+
+/// Working memory for a [`ListViterbi`] decoder keeping `L` paths, 48 × `L`
+/// bytes.
+///
+/// [`ListViterbi::decode`] keeps this on the stack, which is impractical for
+/// large lists. Holding it elsewhere, such as in a `static`, and decoding
+/// with [`ListViterbi::decode_in`] makes the decoder's memory use explicit.
+#[derive(Clone)]
+pub struct ListViterbiScratch<const L: usize> {
+    paths: [Path; L],
+    candidates: [[Path; L]; 2],
+}
+
+impl<const L: usize> ListViterbiScratch<L> {
+    /// Creates zeroed working memory.
+    pub const fn new() -> Self {
+        Self {
+            paths: [Path::EMPTY; L],
+            candidates: [[Path::EMPTY; L]; 2],
+        }
+    }
+}
+
+impl<const L: usize> Default for ListViterbiScratch<L> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A list Viterbi decoder for the K=32, r=1/2 convolutional code.
+///
+/// A full Viterbi decoder would need to track 2^31 states, so instead only
+/// the `L` best paths are kept at each step, with paths that arrive at the
+/// same state merged as usual. Larger lists are slower but less likely to
+/// drop the correct path while it is temporarily behind.
+///
+/// Unlike [`Fano`](super::Fano), the running time doesn't depend on the
+/// signal quality: every decode extends `L` paths for each of the 81 bits.
+/// The working memory, a [`ListViterbiScratch`], is 48 × `L` bytes, so an
+/// `L` of 64 needs 3KB and an `L` of 1024 needs 48KB.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListViterbi<const L: usize> {
+    /// The branch metrics to use.
+    pub metric: MetricTable,
+}
+
+impl<const L: usize> Default for ListViterbi<L> {
+    fn default() -> Self {
+        Self {
+            metric: MetricTable::default(),
+        }
+    }
+}
+
+impl<const L: usize> ListViterbi<L> {
+    /// Decodes 162 deinterleaved soft bits into the 50 message bits. The
+    /// best surviving path is returned, unless its metric is negative which
+    /// indicates it is no better than chance, in which case
+    /// [`Error::DecodeFailed`] is returned.
+    pub fn decode(&self, bits: &[u8; 162]) -> Result<Decoded, Error> {
+        self.decode_in(bits, &mut ListViterbiScratch::new())
+    }
+
+    /// Decodes 162 deinterleaved soft bits as [`ListViterbi::decode`] does,
+    /// using `scratch` as working memory.
+    pub fn decode_in(
+        &self,
+        bits: &[u8; 162],
+        scratch: &mut ListViterbiScratch<L>,
+    ) -> Result<Decoded, Error> {
+        const { assert!(L > 0, "the list must hold at least one path") };
+
+        let ListViterbiScratch { paths, candidates } = scratch;
+        paths[0] = Path::EMPTY;
+        let mut count = 1;
+        let mut extended = 0;
+
+        let (zero, one) = (&self.metric.zero, &self.metric.one);
+        for (n, pair) in bits.chunks_exact(2).enumerate() {
+            let (a, b) = (pair[0] as usize, pair[1] as usize);
+            let metrics = [
+                (zero[a] + zero[b]) as i32,
+                (zero[a] + one[b]) as i32,
+                (one[a] + zero[b]) as i32,
+                (one[a] + one[b]) as i32,
+            ];
+
+            // Extend every path by a 0 and, outside the tail, by a 1.
+            let candidates = candidates.as_flattened_mut();
+            let mut total = 0;
+            for path in &paths[..count] {
+                let state = path.state << 1;
+                let symbols = encode(state);
+                for bit in 0..if n < TAIL { 2 } else { 1 } {
+                    candidates[total] = Path {
+                        state: state | bit,
+                        metric: path.metric
+                            + metrics[symbols ^ (3 * bit as usize)],
+                        data: if n < TAIL {
+                            (path.data << 1) | bit as u64
+                        } else {
+                            path.data
+                        },
+                    };
+                    total += 1;
+                }
+            }
+            extended += total as u32;
+
+            // Merge paths that have arrived at the same state, keeping the
+            // better of the two.
+            let candidates = &mut candidates[..total];
+            candidates.sort_unstable_by(|a, b| {
+                a.state.cmp(&b.state).then(b.metric.cmp(&a.metric))
+            });
+            let mut unique = 0;
+            for i in 0..total {
+                if i == 0 || candidates[i].state != candidates[unique - 1].state
+                {
+                    candidates[unique] = candidates[i];
+                    unique += 1;
+                }
+            }
+
+            // Keep the best L.
+            let candidates = &mut candidates[..unique];
+            count = unique.min(L);
+            if unique > L {
+                candidates.select_nth_unstable_by(L - 1, |a, b| {
+                    b.metric.cmp(&a.metric)
+                });
+            }
+            paths[..count].copy_from_slice(&candidates[..count]);
+        }
+
+        let best = paths[..count]
+            .iter()
+            .max_by_key(|path| path.metric)
+            .ok_or(Error::DecodeFailed)?;
+
+        if best.metric < 0 {
+            return Err(Error::DecodeFailed);
+        }
+
+        Ok(Decoded {
+            data: best.data,
+            metric: best.metric,
+            cycles: extended,
+        })
+    }
+}
+
+impl<const L: usize> ChannelDecoder for ListViterbi<L> {
+    type Scratch = ListViterbiScratch<L>;
+
+    fn decode_in(
+        &self,
+        bits: &[u8; 162],
+        scratch: &mut ListViterbiScratch<L>,
+    ) -> Result<Decoded, Error> {
+        ListViterbi::decode_in(self, bits, scratch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::tests::encode_bits;
+    use super::*;
+
+    #[test]
+    fn test_list_viterbi() {
+        let viterbi = ListViterbi::<64>::default();
+        let mut scratch = ListViterbiScratch::new();
+        for data in [0, 0x3_FFFF_FFFF_FFFF, 0x1_2345_6789] {
+            let decoded = viterbi.decode(&encode_bits(data)).unwrap();
+            assert_eq!(decoded.data, data);
+            let decoded =
+                viterbi.decode_in(&encode_bits(data), &mut scratch).unwrap();
+            assert_eq!(decoded.data, data);
+        }
+    }
+
+    #[test]
+    fn test_list_viterbi_corrects_errors() {
+        let viterbi = ListViterbi::<64>::default();
+        let mut bits = encode_bits(0x1_2345_6789);
+        for i in [3, 40, 41, 90, 150] {
+            bits[i] ^= 0xFF;
+        }
+        assert_eq!(viterbi.decode(&bits).unwrap().data, 0x1_2345_6789);
+
+        let mut bits = encode_bits(0x1_2345_6789);
+        for bit in bits.iter_mut().step_by(2) {
+            *bit ^= 0xFF;
+        }
+        assert_eq!(viterbi.decode(&bits), Err(Error::DecodeFailed));
+    }
+}
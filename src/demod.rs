@@ -0,0 +1,685 @@
+//! Demodulation of the 4 tone FSK signal into symbols.
+//!
+//! The demodulators in this module assume the frequency and timing of the
+//! signal are already known. Each symbol period is correlated against the
+//! four tones, producing the magnitude of each tone from which hard or soft
+//! symbols are derived.
+//!
+//! Both real (audio) samples and complex (I/Q) baseband samples are
+//! accepted, see [`Sample`].
+
+use crate::dsp::{Complex, Sample};
+use crate::{Error, SYMBOL_LENGTH, SYNC, TONE_SPACING};
+
+/// A demodulator for real (audio) or complex (I/Q) samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Demodulator {
+    sample_rate: u32,
+    frequency: f32,
+    drift: f32,
+}
+
+// The index of the first sample of symbol `i`, relative to the start of the
+// transmission. A symbol need not be a whole number of samples long, so the
+// boundaries are rounded down individually to avoid accumulating any error.
+pub(crate) fn boundary(sample_rate: u32, i: usize) -> usize {
+    (i as u64 * SYMBOL_LENGTH as u64 * sample_rate as u64 / 12000) as usize
+}
+
+/// The offset in Hz of symbol `symbol` from the frequency at the middle of a
+/// transmission, for a signal that drifts linearly by `drift` Hz over the
+/// length of the transmission.
+pub fn drift_offset(drift: f32, symbol: usize) -> f32 {
+    drift * (symbol as f32 - 81.0) / 162.0
+}
+
+impl Demodulator {
+    /// Creates a demodulator for audio sampled at `sample_rate` Hz, where the
+    /// lowest of the four tones (symbol 0) is at `frequency` Hz. The highest
+    /// tone must lie below the Nyquist frequency.
+    pub fn new(sample_rate: u32, frequency: f32) -> Result<Self, Error> {
+        let highest = frequency + 3.0 * TONE_SPACING;
+        if frequency <= 0.0 || highest >= sample_rate as f32 / 2.0 {
+            return Err(Error::InvalidFrequency);
+        }
+
+        Ok(Self {
+            sample_rate,
+            frequency,
+            drift: 0.0,
+        })
+    }
+
+    /// Creates a demodulator for complex baseband samples at `sample_rate`
+    /// Hz, where the lowest of the four tones (symbol 0) is at `frequency`
+    /// Hz relative to the center of the baseband, which may be negative. All
+    /// four tones must lie within ±`sample_rate / 2`.
+    ///
+    /// A demodulator created this way must only be given complex samples.
+    pub fn new_iq(sample_rate: u32, frequency: f32) -> Result<Self, Error> {
+        let nyquist = sample_rate as f32 / 2.0;
+        let highest = frequency + 3.0 * TONE_SPACING;
+        if frequency <= -nyquist || highest >= nyquist {
+            return Err(Error::InvalidFrequency);
+        }
+
+        Ok(Self {
+            sample_rate,
+            frequency,
+            drift: 0.0,
+        })
+    }
+
+    /// Tracks a signal whose frequency drifts linearly by `drift` Hz over the
+    /// length of the transmission (110.6s). The frequency given when the
+    /// demodulator was created is then the frequency at the middle of the
+    /// transmission, see [`drift_offset`].
+    pub fn with_drift(self, drift: f32) -> Self {
+        Self { drift, ..self }
+    }
+
+    /// The number of samples needed to hold a transmission, 110.6s at the
+    /// configured sample rate.
+    pub fn samples_needed(&self) -> usize {
+        self.boundary(162)
+    }
+
+    fn boundary(&self, i: usize) -> usize {
+        boundary(self.sample_rate, i)
+    }
+
+    /// Returns the magnitude of each of the four tones in every symbol
+    /// period, for a transmission beginning at sample `start`. Returns
+    /// [`Error::TooFewSamples`] if the transmission would run past the end of
+    /// `samples`.
+    pub fn magnitudes<S: Sample>(
+        &self,
+        samples: &[S],
+        start: usize,
+    ) -> Result<[[f32; 4]; 162], Error> {
+        let samples = samples
+            .get(start..)
+            .and_then(|samples| samples.get(..self.samples_needed()))
+            .ok_or(Error::TooFewSamples)?;
+
+        // Each tone is mixed down to DC with a rotating phasor, and the
+        // result summed over the symbol period.
+        let mut magnitudes = [[0f32; 4]; 162];
+        for (i, magnitudes) in magnitudes.iter_mut().enumerate() {
+            let lowest = self.frequency + drift_offset(self.drift, i);
+            let mut steps = [Complex::default(); 4];
+            for (tone, step) in steps.iter_mut().enumerate() {
+                let frequency = lowest + tone as f32 * TONE_SPACING;
+                let phase = core::f64::consts::TAU * frequency as f64
+                    / self.sample_rate as f64;
+                *step = Complex::from_phase(-phase);
+            }
+
+            let symbol = &samples[self.boundary(i)..self.boundary(i + 1)];
+            for (magnitude, &step) in magnitudes.iter_mut().zip(&steps) {
+                let mut phasor = Complex::new(1.0, 0.0);
+                let mut sum = Complex::default();
+                for &sample in symbol {
+                    sum += sample.to_complex() * phasor;
+                    phasor = phasor * step;
+                }
+                *magnitude = sum.norm();
+            }
+        }
+
+        Ok(magnitudes)
+    }
+
+    /// Demodulates a transmission beginning at sample `start` into hard
+    /// symbols, picking the strongest tone in each symbol period.
+    pub fn hard_symbols<S: Sample>(
+        &self,
+        samples: &[S],
+        start: usize,
+    ) -> Result<[u8; 162], Error> {
+        Ok(crate::hard_symbols(&self.magnitudes(samples, start)?))
+    }
+
+    /// Demodulates a transmission beginning at sample `start` into soft
+    /// symbols suitable for [`decode_soft`](crate::decode_soft).
+    pub fn soft_symbols<S: Sample>(
+        &self,
+        samples: &[S],
+        start: usize,
+    ) -> Result<[i8; 162], Error> {
+        Ok(crate::soft_symbols(&self.magnitudes(samples, start)?))
+    }
+}
+
+/// A demodulator for real (audio) samples that processes one sample at a
+/// time using a bank of four Goertzel filters, one per tone.
+///
+/// Only a few dozen bytes of state are kept, making this suitable for
+/// microcontrollers monitoring a single, known, frequency with known timing,
+/// such as when checking a transmitter in loopback.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Goertzel {
+    sample_rate: u32,
+    // 2cos(ω) for each tone.
+    coefficients: [f32; 4],
+    // The two most recent outputs of each filter.
+    state: [(f32, f32); 4],
+    // The symbol being received, and the index of the next sample within
+    // the transmission.
+    symbol: usize,
+    sample: usize,
+}
+
+impl Goertzel {
+    /// Creates a demodulator for audio sampled at `sample_rate` Hz, where the
+    /// lowest of the four tones (symbol 0) is at `frequency` Hz. The highest
+    /// tone must lie below the Nyquist frequency.
+    pub fn new(sample_rate: u32, frequency: f32) -> Result<Self, Error> {
+        Demodulator::new(sample_rate, frequency)?;
+
+        let mut coefficients = [0f32; 4];
+        for (tone, coefficient) in coefficients.iter_mut().enumerate() {
+            let frequency = frequency + tone as f32 * TONE_SPACING;
+            let omega =
+                core::f64::consts::TAU * frequency as f64 / sample_rate as f64;
+            *coefficient = 2.0 * crate::math::sin_cos(omega).1 as f32;
+        }
+
+        Ok(Self {
+            sample_rate,
+            coefficients,
+            state: [(0.0, 0.0); 4],
+            symbol: 0,
+            sample: 0,
+        })
+    }
+
+    /// The index of the symbol currently being received, 162 once the
+    /// transmission is complete.
+    pub fn symbol(&self) -> usize {
+        self.symbol
+    }
+
+    /// Returns true once all 162 symbols have been received.
+    pub fn is_complete(&self) -> bool {
+        self.symbol == 162
+    }
+
+    /// Prepares to receive another transmission.
+    pub fn reset(&mut self) {
+        self.state = [(0.0, 0.0); 4];
+        self.symbol = 0;
+        self.sample = 0;
+    }
+
+    /// Processes the next sample of the transmission. When the sample
+    /// completes a symbol, the magnitudes of the four tones over that symbol
+    /// are returned. Samples pushed after the transmission is complete are
+    /// ignored.
+    pub fn push(&mut self, sample: f32) -> Option<[f32; 4]> {
+        if self.is_complete() {
+            return None;
+        }
+
+        for ((s1, s2), &coefficient) in
+            self.state.iter_mut().zip(&self.coefficients)
+        {
+            let s0 = sample + coefficient * *s1 - *s2;
+            (*s1, *s2) = (s0, *s1);
+        }
+
+        self.sample += 1;
+        if self.sample < boundary(self.sample_rate, self.symbol + 1) {
+            return None;
+        }
+
+        let mut magnitudes = [0f32; 4];
+        for ((magnitude, (s1, s2)), &coefficient) in magnitudes
+            .iter_mut()
+            .zip(&mut self.state)
+            .zip(&self.coefficients)
+        {
+            let power = *s1 * *s1 + *s2 * *s2 - coefficient * *s1 * *s2;
+            *magnitude = crate::math::sqrt(power);
+            (*s1, *s2) = (0.0, 0.0);
+        }
+
+        self.symbol += 1;
+        Some(magnitudes)
+    }
+}
+
+// The maximum sample rate of a `FixedDemodulator`, so that the sums over a
+// symbol period can't overflow, as explained in `FixedDemodulator::push`.
+const MAX_FIXED_RATE: u32 = 192_000;
+
+/// A demodulator for 16 bit audio samples that uses only integer
+/// arithmetic, for microcontrollers without a floating point unit.
+///
+/// Like [`Goertzel`], it processes one sample at a time and keeps only a
+/// few dozen bytes of state. Each tone is mixed down to DC with a phase
+/// accumulator and a table of sines, and the result summed over the symbol
+/// period in 32 bits. Floating point is only used once, when the
+/// demodulator is created.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FixedDemodulator {
+    sample_rate: u32,
+    // The phase step of each tone per sample, in units of 2^-32 cycles.
+    steps: [u32; 4],
+    phases: [u32; 4],
+    // The real and imaginary sums of each tone over the symbol so far.
+    sums: [(i32, i32); 4],
+    // The symbol being received, and the index of the next sample within
+    // the transmission.
+    symbol: usize,
+    sample: usize,
+}
+
+impl FixedDemodulator {
+    /// Creates a demodulator for audio sampled at `sample_rate` Hz, where the
+    /// lowest of the four tones (symbol 0) is at `frequency` Hz. The highest
+    /// tone must lie below the Nyquist frequency, and the sample rate may be
+    /// at most 192000 Hz.
+    pub fn new(sample_rate: u32, frequency: f32) -> Result<Self, Error> {
+        if sample_rate > MAX_FIXED_RATE {
+            return Err(Error::InvalidSampleRate);
+        }
+        Demodulator::new(sample_rate, frequency)?;
+
+        let mut steps = [0u32; 4];
+        for (tone, step) in steps.iter_mut().enumerate() {
+            let frequency =
+                frequency as f64 + tone as f64 * TONE_SPACING as f64;
+            let cycles = frequency / sample_rate as f64;
+            *step = crate::math::round(cycles * (1u64 << 32) as f64) as u32;
+        }
+
+        Ok(Self {
+            sample_rate,
+            steps,
+            phases: [0; 4],
+            sums: [(0, 0); 4],
+            symbol: 0,
+            sample: 0,
+        })
+    }
+
+    /// The index of the symbol currently being received, 162 once the
+    /// transmission is complete.
+    pub fn symbol(&self) -> usize {
+        self.symbol
+    }
+
+    /// Returns true once all 162 symbols have been received.
+    pub fn is_complete(&self) -> bool {
+        self.symbol == 162
+    }
+
+    /// Prepares to receive another transmission.
+    pub fn reset(&mut self) {
+        self.phases = [0; 4];
+        self.sums = [(0, 0); 4];
+        self.symbol = 0;
+        self.sample = 0;
+    }
+
+    /// Processes the next sample of the transmission. When the sample
+    /// completes a symbol, the magnitudes of the four tones over that symbol
+    /// are returned, see [`fixed_soft_symbols`]. Samples pushed after the
+    /// transmission is complete are ignored.
+    pub fn push(&mut self, sample: i16) -> Option<[u32; 4]> {
+        if self.is_complete() {
+            return None;
+        }
+
+        let sample = sample as i32;
+        for ((phase, &step), (re, im)) in
+            self.phases.iter_mut().zip(&self.steps).zip(&mut self.sums)
+        {
+            let (sin, cos) = crate::nco::sin_cos(*phase);
+            let (sin, cos) = (sin as i32, cos as i32);
+            // Each product is scaled back to at most 2^14, and a symbol is
+            // at most 2^17 samples, at 192000 Hz. The sums can't reach
+            // 2^31 even so, as the mixer's sines average 2/π of full
+            // scale, so even a full scale square wave in step with a tone
+            // sums to at most 2/π of 2^31.
+            *re += (sample * cos) >> 16;
+            *im -= (sample * sin) >> 16;
+            *phase = phase.wrapping_add(step);
+        }
+
+        self.sample += 1;
+        if self.sample < boundary(self.sample_rate, self.symbol + 1) {
+            return None;
+        }
+
+        let mut magnitudes = [0u32; 4];
+        for (magnitude, (re, im)) in magnitudes.iter_mut().zip(&mut self.sums) {
+            let (x, y) = (*re as i64, *im as i64);
+            *magnitude = isqrt((x * x + y * y) as u64);
+            (*re, *im) = (0, 0);
+        }
+
+        self.symbol += 1;
+        Some(magnitudes)
+    }
+}
+
+// The square root of `x`, rounded down, a bit of the root at a time.
+fn isqrt(x: u64) -> u32 {
+    let mut x = x;
+    let mut root = 0;
+    let mut bit = 1u64 << 62;
+    while bit > x {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if x >= root + bit {
+            x -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+    root as u32
+}
+
+/// Converts the integer magnitudes of the four tones in each symbol period
+/// from a [`FixedDemodulator`] into soft symbols, as
+/// [`soft_symbols`](crate::soft_symbols) does, without floating point.
+pub fn fixed_soft_symbols(magnitudes: &[[u32; 4]; 162]) -> [i8; 162] {
+    let mut soft = [0i64; 162];
+    let mut total = 0;
+    for ((soft, tones), &sync) in soft.iter_mut().zip(magnitudes).zip(&SYNC) {
+        let sync = sync as usize;
+        *soft = tones[sync + 2] as i64 - tones[sync] as i64;
+        total += soft.abs();
+    }
+
+    let mut llrs = [0i8; 162];
+    if total > 0 {
+        for (llr, soft) in llrs.iter_mut().zip(soft) {
+            *llr = (soft * 40 * 162 / total).clamp(-127, 127) as i8;
+        }
+    }
+    llrs
+}
+
+/// Converts the integer magnitudes of the four tones in each symbol period
+/// into hard symbols, by picking the strongest tone.
+pub fn fixed_hard_symbols(magnitudes: &[[u32; 4]; 162]) -> [u8; 162] {
+    let mut symbols = [0u8; 162];
+    for (symbol, tones) in symbols.iter_mut().zip(magnitudes) {
+        for (tone, &magnitude) in tones.iter().enumerate() {
+            if magnitude > tones[*symbol as usize] {
+                *symbol = tone as u8;
+            }
+        }
+    }
+    symbols
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::vec;
+    use std::vec::Vec;
+
+    // A simple linear congruential generator producing uniform noise in the
+    // range [-1, 1), so tests are repeatable.
+    pub(crate) struct Noise(u32);
+
+    impl Noise {
+        pub(crate) fn new() -> Self {
+            Self(0x1234_5678)
+        }
+
+        pub(crate) fn next(&mut self) -> f32 {
+            self.0 = self.0.wrapping_mul(1664525).wrapping_add(1013904223);
+            (self.0 >> 8) as f32 / (1 << 23) as f32 - 1.0
+        }
+    }
+
+    // Synthesizes a transmission of `symbols` at `sample_rate`, with the
+    // lowest tone at `frequency`, preceded by `delay` samples of silence.
+    pub(crate) fn synthesize(
+        symbols: &[u8; 162],
+        sample_rate: u32,
+        frequency: f32,
+        delay: usize,
+        samples: &mut [f32],
+    ) {
+        let demodulator = Demodulator::new(sample_rate, frequency).unwrap();
+        let mut phase = 0f64;
+        for (i, &symbol) in symbols.iter().enumerate() {
+            let tone = frequency + symbol as f32 * TONE_SPACING;
+            let step =
+                core::f64::consts::TAU * tone as f64 / sample_rate as f64;
+            let range = demodulator.boundary(i)..demodulator.boundary(i + 1);
+            for sample in &mut samples[delay..][range] {
+                *sample += crate::math::sin_cos(phase).0 as f32;
+                phase += step;
+            }
+        }
+    }
+
+    #[test]
+    fn test_demodulator() {
+        assert_eq!(Demodulator::new(3200, 0.0), Err(Error::InvalidFrequency));
+        assert_eq!(
+            Demodulator::new(3200, 1600.0),
+            Err(Error::InvalidFrequency)
+        );
+
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let demodulator = Demodulator::new(3200, 1000.0).unwrap();
+        assert_eq!(demodulator.samples_needed(), 353894);
+
+        let mut samples = vec![0f32; 360000];
+        synthesize(&symbols, 3200, 1000.0, 1000, &mut samples);
+        assert_eq!(demodulator.hard_symbols(&samples, 1000), Ok(symbols));
+        assert_eq!(
+            demodulator.hard_symbols(&samples, 10000),
+            Err(Error::TooFewSamples)
+        );
+    }
+
+    #[test]
+    fn test_goertzel() {
+        assert_eq!(Goertzel::new(3200, 1600.0), Err(Error::InvalidFrequency));
+
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let mut samples = vec![0f32; 360000];
+        let mut noise = Noise::new();
+        for sample in samples.iter_mut() {
+            *sample = noise.next();
+        }
+        synthesize(&symbols, 3200, 1000.0, 0, &mut samples);
+
+        let mut goertzel = Goertzel::new(3200, 1000.0).unwrap();
+        let mut magnitudes = [[0f32; 4]; 162];
+        for &sample in &samples {
+            let symbol = goertzel.symbol();
+            if let Some(tones) = goertzel.push(sample) {
+                magnitudes[symbol] = tones;
+            }
+        }
+        assert!(goertzel.is_complete());
+        assert_eq!(crate::hard_symbols(&magnitudes), symbols);
+
+        // The magnitudes match those of the block demodulator.
+        let demodulator = Demodulator::new(3200, 1000.0).unwrap();
+        let expected = demodulator.magnitudes(&samples, 0).unwrap();
+        for (tones, expected) in magnitudes.iter().zip(&expected) {
+            for (magnitude, expected) in tones.iter().zip(expected) {
+                assert!((magnitude - expected).abs() < 0.5);
+            }
+        }
+
+        goertzel.reset();
+        assert_eq!(goertzel.symbol(), 0);
+    }
+
+    #[test]
+    fn test_fixed_demodulator() {
+        assert_eq!(
+            FixedDemodulator::new(3200, 1600.0),
+            Err(Error::InvalidFrequency)
+        );
+        assert_eq!(
+            FixedDemodulator::new(384_000, 1500.0),
+            Err(Error::InvalidSampleRate)
+        );
+
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let mut samples = vec![0f32; 360000];
+        let mut noise = Noise::new();
+        for sample in samples.iter_mut() {
+            *sample = 4.0 * noise.next();
+        }
+        synthesize(&symbols, 3200, 1000.0, 0, &mut samples);
+        let fixed: Vec<i16> = samples
+            .iter()
+            .map(|&sample| (sample * 5000.0) as i16)
+            .collect();
+
+        let mut demodulator = FixedDemodulator::new(3200, 1000.0).unwrap();
+        let mut magnitudes = [[0u32; 4]; 162];
+        for &sample in &fixed {
+            let symbol = demodulator.symbol();
+            if let Some(tones) = demodulator.push(sample) {
+                magnitudes[symbol] = tones;
+            }
+        }
+        assert!(demodulator.is_complete());
+
+        let llrs = fixed_soft_symbols(&magnitudes);
+        let message = crate::decode_soft(&llrs).unwrap();
+        assert_eq!(message.callsign(), "K1ABC");
+        assert_eq!(message.grid(), "FN42");
+
+        // The magnitudes are in proportion to those of the floating point
+        // demodulator, and give the same symbols.
+        let expected = Demodulator::new(3200, 1000.0)
+            .unwrap()
+            .magnitudes(&samples, 0)
+            .unwrap();
+        let scale = 5000.0 * 32767.0 / 65536.0;
+        for (tones, expected) in magnitudes.iter().zip(&expected) {
+            for (&magnitude, &expected) in tones.iter().zip(expected) {
+                let magnitude = magnitude as f32 / scale;
+                assert!((magnitude - expected).abs() < 0.01 * expected + 1.0);
+            }
+        }
+        assert_eq!(
+            fixed_hard_symbols(&magnitudes),
+            crate::hard_symbols(&expected)
+        );
+
+        demodulator.reset();
+        assert_eq!(demodulator.symbol(), 0);
+    }
+
+    #[test]
+    fn test_isqrt() {
+        for x in [0u64, 1, 2, 3, 4, 15, 16, 17, 99, 100, 1 << 40] {
+            assert_eq!(isqrt(x) as u64, (x as f64).sqrt() as u64, "{}", x);
+        }
+        let root = u32::MAX as u64;
+        assert_eq!(isqrt(root * root), u32::MAX);
+        assert_eq!(isqrt(root * root - 1), u32::MAX - 1);
+        assert_eq!(isqrt(u64::MAX), u32::MAX);
+    }
+
+    #[test]
+    fn test_demodulator_noise() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let demodulator = Demodulator::new(3200, 1000.0).unwrap();
+
+        let mut samples = vec![0f32; 360000];
+        let mut noise = Noise::new();
+        for sample in samples.iter_mut() {
+            *sample = 4.0 * noise.next();
+        }
+        synthesize(&symbols, 3200, 1000.0, 0, &mut samples);
+
+        let llrs = demodulator.soft_symbols(&samples, 0).unwrap();
+        let message = crate::decode_soft(&llrs).unwrap();
+        assert_eq!(message.callsign(), "K1ABC");
+        assert_eq!(message.grid(), "FN42");
+        assert_eq!(message.power(), 37);
+    }
+
+    #[test]
+    fn test_demodulator_iq() {
+        assert_eq!(
+            Demodulator::new_iq(375, -187.5),
+            Err(Error::InvalidFrequency)
+        );
+        assert_eq!(
+            Demodulator::new_iq(375, 185.0),
+            Err(Error::InvalidFrequency)
+        );
+
+        // A signal centered on the baseband, sampled at 375 Hz with noise.
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let frequency = -1.5 * TONE_SPACING;
+        let demodulator = Demodulator::new_iq(375, frequency).unwrap();
+
+        let mut noise = Noise::new();
+        let mut samples =
+            vec![Complex::default(); demodulator.samples_needed()];
+        let mut phase = 0f64;
+        for (i, &symbol) in symbols.iter().enumerate() {
+            let tone = frequency + symbol as f32 * TONE_SPACING;
+            let step = core::f64::consts::TAU * tone as f64 / 375.0;
+            let range = demodulator.boundary(i)..demodulator.boundary(i + 1);
+            for sample in &mut samples[range] {
+                let noise = Complex::new(noise.next(), noise.next());
+                *sample = Complex::from_phase(phase) + noise * 2.0;
+                phase += step;
+            }
+        }
+
+        let llrs = demodulator.soft_symbols(&samples, 0).unwrap();
+        assert_eq!(crate::decode_soft(&llrs).unwrap().callsign(), "K1ABC");
+
+        // The same samples, interleaved.
+        let mut interleaved = vec![0f32; 2 * samples.len()];
+        for (pair, sample) in interleaved.chunks_exact_mut(2).zip(&samples) {
+            pair.copy_from_slice(&[sample.re, sample.im]);
+        }
+        let pairs: Vec<[f32; 2]> = interleaved
+            .chunks_exact(2)
+            .map(|pair| [pair[0], pair[1]])
+            .collect();
+        assert_eq!(demodulator.soft_symbols(&pairs, 0), Ok(llrs));
+    }
+
+    #[test]
+    fn test_demodulator_drift() {
+        // A signal drifting by 4 Hz, almost 3 tones, over the transmission.
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let frequency = -1.5 * TONE_SPACING;
+        let demodulator = Demodulator::new_iq(375, frequency).unwrap();
+
+        let mut samples =
+            vec![Complex::default(); demodulator.samples_needed()];
+        let mut phase = 0f64;
+        for (i, &symbol) in symbols.iter().enumerate() {
+            let tone =
+                frequency + drift_offset(4.0, i) + symbol as f32 * TONE_SPACING;
+            let step = core::f64::consts::TAU * tone as f64 / 375.0;
+            let range = demodulator.boundary(i)..demodulator.boundary(i + 1);
+            for sample in &mut samples[range] {
+                *sample = Complex::from_phase(phase);
+                phase += step;
+            }
+        }
+
+        assert_ne!(demodulator.hard_symbols(&samples, 0), Ok(symbols));
+        let demodulator = demodulator.with_drift(4.0);
+        assert_eq!(demodulator.hard_symbols(&samples, 0), Ok(symbols));
+    }
+}
@@ -0,0 +1,513 @@
+//! Signal processing building blocks shared by the receive and transmit
+//! paths.
+
+use core::ops::{Add, AddAssign, Mul, Sub};
+
+mod decimate;
+mod spectrum;
+
+pub use decimate::{Decimator, Downconverter, Mixer, Resampler};
+pub use spectrum::Spectrum;
+
+/// A complex number, used for I/Q samples.
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Complex {
+    /// The real (in-phase) part.
+    pub re: f32,
+    /// The imaginary (quadrature) part.
+    pub im: f32,
+}
+
+impl Complex {
+    /// Creates a complex number from its real and imaginary parts.
+    pub const fn new(re: f32, im: f32) -> Self {
+        Self { re, im }
+    }
+
+    /// The complex conjugate.
+    pub fn conj(self) -> Self {
+        Self::new(self.re, -self.im)
+    }
+
+    /// The squared magnitude, or power.
+    pub fn norm_sqr(self) -> f32 {
+        self.re * self.re + self.im * self.im
+    }
+
+    /// The magnitude.
+    pub fn norm(self) -> f32 {
+        crate::math::sqrt(self.norm_sqr())
+    }
+
+    // Returns e^(jθ).
+    pub(crate) fn from_phase(theta: f64) -> Self {
+        let (sin, cos) = crate::math::sin_cos(theta);
+        Self::new(cos as f32, sin as f32)
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::new(self.re + rhs.re, self.im + rhs.im)
+    }
+}
+
+impl AddAssign for Complex {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::new(self.re - rhs.re, self.im - rhs.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self::new(
+            self.re * rhs.re - self.im * rhs.im,
+            self.re * rhs.im + self.im * rhs.re,
+        )
+    }
+}
+
+impl Mul<f32> for Complex {
+    type Output = Self;
+
+    fn mul(self, rhs: f32) -> Self {
+        Self::new(self.re * rhs, self.im * rhs)
+    }
+}
+
+/// A sample accepted by the receive path: either a real audio sample, or a
+/// complex I/Q sample given as a [`Complex`] or an `[i, q]` pair. Interleaved
+/// I/Q buffers may be split into pairs with [`slice::chunks_exact`].
+pub trait Sample: Copy {
+    /// Converts the sample to a complex number.
+    fn to_complex(self) -> Complex;
+}
+
+impl Sample for f32 {
+    fn to_complex(self) -> Complex {
+        Complex::new(self, 0.0)
+    }
+}
+
+impl Sample for i16 {
+    fn to_complex(self) -> Complex {
+        Complex::new(self as f32 / 32768.0, 0.0)
+    }
+}
+
+impl Sample for Complex {
+    fn to_complex(self) -> Complex {
+        self
+    }
+}
+
+impl Sample for [f32; 2] {
+    fn to_complex(self) -> Complex {
+        Complex::new(self[0], self[1])
+    }
+}
+
+/// An impulse noise blanker, run over samples ahead of the demodulator to
+/// remove the clicks of lightning static and switching power supplies.
+///
+/// A running average of the sample power is kept, and any sample whose
+/// power exceeds it by more than the threshold is treated as part of an
+/// impulse. The impulse, along with a few samples either side of it, is
+/// blanked (set to zero) or, if clipping is enabled, scaled down to the
+/// threshold. Impulses don't contribute to the running average.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoiseBlanker {
+    // The threshold as a ratio of powers.
+    threshold: f32,
+    clip: bool,
+    hold: usize,
+    average: f32,
+    // The number of samples still to be blanked after the last impulse.
+    remaining: usize,
+}
+
+// The weight given to each new sample in the running average of the power.
+// The average has a time constant of about 1000 samples.
+const BLANKER_ALPHA: f32 = 1.0 / 1024.0;
+
+impl NoiseBlanker {
+    /// Creates a blanker for samples whose magnitude exceeds the average
+    /// magnitude by more than `threshold`, as a ratio of amplitudes. A
+    /// threshold around 5-10 catches impulses without touching the noise.
+    pub fn new(threshold: f32) -> Self {
+        Self {
+            threshold: threshold * threshold,
+            clip: false,
+            hold: 4,
+            average: 0.0,
+            remaining: 0,
+        }
+    }
+
+    /// Sets the number of samples blanked after each impulse, to catch the
+    /// ringing of the receiver's filters. The default is 4.
+    pub fn with_hold(self, hold: usize) -> Self {
+        Self { hold, ..self }
+    }
+
+    /// Scales impulses down to the threshold, rather than blanking them.
+    pub fn with_clipping(self) -> Self {
+        Self { clip: true, ..self }
+    }
+
+    /// Blanks the impulses in `samples` in place, returning the number of
+    /// samples changed. Consecutive blocks of samples may be passed in
+    /// turn.
+    pub fn process<S>(&mut self, samples: &mut [S]) -> usize
+    where
+        S: Sample + Default + Mul<f32, Output = S>,
+    {
+        let mut changed = 0;
+        for sample in samples.iter_mut() {
+            let power = sample.to_complex().norm_sqr();
+            if self.average == 0.0 {
+                self.average = power;
+            }
+
+            let limit = self.threshold * self.average;
+            if power > limit {
+                self.remaining = self.hold + 1;
+            } else {
+                self.average += BLANKER_ALPHA * (power - self.average);
+            }
+            if self.remaining == 0 {
+                continue;
+            }
+
+            self.remaining -= 1;
+            if !self.clip {
+                *sample = S::default();
+                changed += 1;
+            } else if power > limit {
+                *sample = *sample * crate::math::sqrt(limit / power);
+                changed += 1;
+            }
+        }
+        changed
+    }
+}
+
+/// A correction stage for the I/Q samples of a direct conversion receiver,
+/// removing the DC offset and the gain and phase imbalance between the I
+/// and Q channels.
+///
+/// Left uncorrected, the DC offset appears as a spike at the tuned
+/// frequency and the imbalance as images of each signal mirrored about it,
+/// either of which can produce false candidates in the search. All three
+/// are estimated blindly from running averages, assuming the signals
+/// received are spread evenly enough that I and Q should be uncorrelated
+/// and of equal power.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IqCorrector {
+    alpha: f32,
+    dc: Complex,
+    // Running averages of I², Q², and IQ.
+    ii: f32,
+    qq: f32,
+    iq: f32,
+}
+
+impl IqCorrector {
+    /// Creates a corrector whose estimates average over roughly `samples`
+    /// samples, typically a few tenths of a second.
+    pub fn new(samples: u32) -> Self {
+        Self {
+            alpha: 1.0 / samples.max(1) as f32,
+            dc: Complex::default(),
+            ii: 0.0,
+            qq: 0.0,
+            iq: 0.0,
+        }
+    }
+
+    /// The estimated DC offset.
+    pub fn dc(&self) -> Complex {
+        self.dc
+    }
+
+    /// The estimated ratio of the gain of the Q channel to the I channel.
+    pub fn gain(&self) -> f32 {
+        if self.ii > 0.0 {
+            crate::math::sqrt(self.qq / self.ii)
+        } else {
+            1.0
+        }
+    }
+
+    /// The estimated sine of the phase error of the Q channel.
+    pub fn phase(&self) -> f32 {
+        let power = crate::math::sqrt(self.ii * self.qq);
+        if power > 0.0 {
+            (self.iq / power).clamp(-0.99, 0.99)
+        } else {
+            0.0
+        }
+    }
+
+    /// Corrects a single sample, updating the estimates.
+    pub fn correct(&mut self, sample: Complex) -> Complex {
+        self.dc += (sample - self.dc) * self.alpha;
+        let Complex { re: i, im: q } = sample - self.dc;
+
+        self.ii += self.alpha * (i * i - self.ii);
+        self.qq += self.alpha * (q * q - self.qq);
+        self.iq += self.alpha * (i * q - self.iq);
+
+        // Remove the part of Q correlated with I, and rescale it to match.
+        let sin = self.phase();
+        let cos = crate::math::sqrt(1.0 - sin * sin);
+        Complex::new(i, (q / self.gain() - i * sin) / cos)
+    }
+
+    /// Corrects `samples` in place.
+    pub fn process(&mut self, samples: &mut [Complex]) {
+        for sample in samples.iter_mut() {
+            *sample = self.correct(*sample);
+        }
+    }
+}
+
+/// A forward fast Fourier transform of `N` points, computed in place.
+///
+/// The receive path is written against this trait so the transform can be
+/// swapped for one better suited to the target, such as one using hardware
+/// acceleration. [`Radix2`] is a portable implementation.
+pub trait Fft<const N: usize> {
+    /// Replaces `buffer` with its discrete Fourier transform,
+    /// X\[k\] = Σ x\[n\] e^(-j2πkn/N), without any scaling.
+    fn forward(&mut self, buffer: &mut [Complex; N]);
+}
+
+/// A simple iterative radix-2 FFT, for any power of two `N`. Twiddle factors
+/// are computed as they're needed, so no memory is needed beyond the buffer
+/// being transformed.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Radix2<const N: usize>;
+
+impl<const N: usize> Radix2<N> {
+    /// Creates the transform, failing to compile if `N` isn't a power of two.
+    pub fn new() -> Self {
+        const { assert!(N.is_power_of_two(), "N must be a power of two") };
+        Self
+    }
+}
+
+impl<const N: usize> Fft<N> for Radix2<N> {
+    fn forward(&mut self, buffer: &mut [Complex; N]) {
+        // Reorder the input into bit-reversed order.
+        let bits = N.trailing_zeros();
+        for i in 0..N {
+            let j = i
+                .reverse_bits()
+                .checked_shr(usize::BITS - bits)
+                .unwrap_or(0);
+            if j > i {
+                buffer.swap(i, j);
+            }
+        }
+
+        // Combine pairs of transforms of length `half` into transforms of
+        // length `2 * half`.
+        let mut half = 1;
+        while half < N {
+            let (sin, cos) =
+                crate::math::sin_cos(-core::f64::consts::PI / half as f64);
+            let mut twiddle = (1f64, 0f64);
+            for j in 0..half {
+                let w = Complex::new(twiddle.0 as f32, twiddle.1 as f32);
+                for start in (0..N).step_by(2 * half) {
+                    let a = buffer[start + j];
+                    let b = buffer[start + j + half] * w;
+                    buffer[start + j] = a + b;
+                    buffer[start + j + half] = a - b;
+                }
+                twiddle = (
+                    twiddle.0 * cos - twiddle.1 * sin,
+                    twiddle.0 * sin + twiddle.1 * cos,
+                );
+            }
+            half *= 2;
+        }
+    }
+}
+
+/// An [`Fft`] backed by the `microfft` crate, which uses precomputed twiddle
+/// factors and is considerably faster than [`Radix2`] on most targets.
+/// Sizes from 4 to 512 points are supported.
+#[cfg(feature = "microfft")]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MicroFft;
+
+#[cfg(feature = "microfft")]
+macro_rules! microfft_impls {
+    ($($n:literal => $cfft:ident),* $(,)?) => {
+        $(
+            impl Fft<$n> for MicroFft {
+                fn forward(&mut self, buffer: &mut [Complex; $n]) {
+                    let mut input = [microfft::Complex32::new(0.0, 0.0); $n];
+                    for (input, x) in input.iter_mut().zip(buffer.iter()) {
+                        *input = microfft::Complex32::new(x.re, x.im);
+                    }
+                    let output = microfft::complex::$cfft(&mut input);
+                    for (x, output) in buffer.iter_mut().zip(output.iter()) {
+                        *x = Complex::new(output.re, output.im);
+                    }
+                }
+            }
+        )*
+    };
+}
+
+#[cfg(feature = "microfft")]
+microfft_impls! {
+    4 => cfft_4,
+    8 => cfft_8,
+    16 => cfft_16,
+    32 => cfft_32,
+    64 => cfft_64,
+    128 => cfft_128,
+    256 => cfft_256,
+    512 => cfft_512,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_complex() {
+        let a = Complex::new(1.0, 2.0);
+        let b = Complex::new(3.0, -1.0);
+        assert_eq!(a + b, Complex::new(4.0, 1.0));
+        assert_eq!(a - b, Complex::new(-2.0, 3.0));
+        assert_eq!(a * b, Complex::new(5.0, 5.0));
+        assert_eq!(a * 2.0, Complex::new(2.0, 4.0));
+        assert_eq!(a * a.conj(), Complex::new(5.0, 0.0));
+        assert_eq!(Complex::new(3.0, 4.0).norm(), 5.0);
+    }
+
+    #[test]
+    fn test_radix2() {
+        // Compare against a direct DFT.
+        let mut buffer = [Complex::default(); 64];
+        for (n, x) in buffer.iter_mut().enumerate() {
+            *x = Complex::new((n as f32 * 0.3).sin(), (n % 7) as f32);
+        }
+        let input = buffer;
+        Radix2::<64>::new().forward(&mut buffer);
+
+        for (k, &actual) in buffer.iter().enumerate() {
+            let mut expected = Complex::default();
+            for (n, &x) in input.iter().enumerate() {
+                let phase = -core::f64::consts::TAU * (k * n) as f64 / 64.0;
+                expected += x * Complex::from_phase(phase);
+            }
+            assert!((actual - expected).norm() < 1e-3, "bin {}", k);
+        }
+
+        let mut single = [Complex::new(2.0, 1.0)];
+        Radix2::<1>::new().forward(&mut single);
+        assert_eq!(single, [Complex::new(2.0, 1.0)]);
+    }
+
+    #[cfg(feature = "microfft")]
+    #[test]
+    fn test_microfft() {
+        let mut buffer = [Complex::default(); 512];
+        for (n, x) in buffer.iter_mut().enumerate() {
+            *x = Complex::new((n as f32 * 0.3).sin(), (n % 7) as f32);
+        }
+        let mut expected = buffer;
+        Radix2::<512>::new().forward(&mut expected);
+        MicroFft.forward(&mut buffer);
+
+        for (actual, expected) in buffer.iter().zip(&expected) {
+            assert!((*actual - *expected).norm() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn test_noise_blanker() {
+        let mut samples = [0f32; 4000];
+        for (n, sample) in samples.iter_mut().enumerate() {
+            *sample = (n as f32 * 0.3).sin();
+        }
+        let clean = samples;
+        samples[2000] = 100.0;
+        samples[3000] = -50.0;
+
+        let mut blanker = NoiseBlanker::new(8.0).with_hold(2);
+        assert_eq!(blanker.process(&mut samples), 6);
+        assert_eq!(samples[2000..2003], [0.0; 3]);
+        assert_eq!(samples[3000..3003], [0.0; 3]);
+        assert_eq!(samples[..2000], clean[..2000]);
+        assert_eq!(samples[2003..3000], clean[2003..3000]);
+
+        // Clipping leaves a pulse at the threshold, and only touches the
+        // hold samples that were also over it.
+        let mut samples = [Complex::new(1.0, 0.0); 100];
+        samples[50] = Complex::new(0.0, 40.0);
+        let mut blanker = NoiseBlanker::new(5.0).with_clipping();
+        assert_eq!(blanker.process(&mut samples), 1);
+        assert!((samples[50].norm() - 5.0).abs() < 1e-3);
+        assert_eq!(samples[51], Complex::new(1.0, 0.0));
+    }
+
+    #[test]
+    fn test_iq_corrector() {
+        // A tone with a DC offset, 20% too much gain in Q, and a phase
+        // error of 0.1 radians.
+        let step = core::f64::consts::TAU / 37.0;
+        let mut samples: [Complex; 20000] = core::array::from_fn(|n| {
+            let phase = step * n as f64;
+            let i = crate::math::sin_cos(phase).1 as f32;
+            let q = 1.2 * crate::math::sin_cos(phase + 0.1).0 as f32;
+            Complex::new(i + 0.3, q - 0.2)
+        });
+
+        // The power of the image relative to the tone over the final 3700
+        // samples, a whole number of cycles.
+        let image = |samples: &[Complex]| {
+            let (mut tone, mut image) =
+                (Complex::default(), Complex::default());
+            for (n, &sample) in samples[16300..].iter().enumerate() {
+                let phasor = Complex::from_phase(step * n as f64);
+                tone += sample * phasor.conj();
+                image += sample * phasor;
+            }
+            image.norm_sqr() / tone.norm_sqr()
+        };
+        assert!(image(&samples) > 1e-3);
+
+        let mut corrector = IqCorrector::new(2000);
+        corrector.process(&mut samples);
+        assert!(image(&samples) < 1e-5);
+        assert!((corrector.dc() - Complex::new(0.3, -0.2)).norm() < 0.01);
+        assert!((corrector.gain() - 1.2).abs() < 0.01);
+        assert!((corrector.phase() - 0.1f32.sin()).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_sample() {
+        assert_eq!(1.5f32.to_complex(), Complex::new(1.5, 0.0));
+        assert_eq!([1.0f32, -1.0].to_complex(), Complex::new(1.0, -1.0));
+    }
+}
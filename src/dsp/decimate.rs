@@ -0,0 +1,398 @@
+use super::{Complex, Sample};
+use crate::search::SAMPLE_RATE;
+use crate::Error;
+
+/// A numerically controlled oscillator, shifting samples down in frequency.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mixer {
+    step: Complex,
+    phasor: Complex,
+    count: u32,
+}
+
+impl Mixer {
+    /// Creates a mixer for samples at `sample_rate` Hz, moving `frequency`
+    /// Hz down to DC.
+    pub fn new(sample_rate: u32, frequency: f32) -> Self {
+        let phase =
+            -core::f64::consts::TAU * frequency as f64 / sample_rate as f64;
+        Self {
+            step: Complex::from_phase(phase),
+            phasor: Complex::new(1.0, 0.0),
+            count: 0,
+        }
+    }
+
+    /// Mixes the next sample.
+    pub fn mix(&mut self, sample: Complex) -> Complex {
+        let mixed = sample * self.phasor;
+        self.phasor = self.phasor * self.step;
+
+        // Rounding errors slowly change the magnitude of the phasor, so it's
+        // renormalized every so often.
+        self.count += 1;
+        if self.count == 1024 {
+            self.phasor = self.phasor * (1.0 / self.phasor.norm());
+            self.count = 0;
+        }
+        mixed
+    }
+}
+
+/// A low pass FIR filter of `N` taps followed by decimation by an integer
+/// factor, only computing the samples that are kept.
+///
+/// The filter is a Blackman windowed sinc, whose transition from passband
+/// to stopband is about 5.5 / `N` of the input sample rate wide, centered
+/// on the cutoff. The stopband is at least 70 dB down.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decimator<const N: usize> {
+    taps: [f32; N],
+    history: [Complex; N],
+    // The index in `history` of the oldest sample.
+    index: usize,
+    factor: usize,
+    count: usize,
+}
+
+impl<const N: usize> Decimator<N> {
+    /// Creates a decimator by `factor`, with a cutoff of `cutoff` times the
+    /// input sample rate. A cutoff of 0.5 / `factor` puts it at the output
+    /// Nyquist frequency. A factor of 1 gives a plain low pass filter.
+    pub fn new(factor: usize, cutoff: f32) -> Self {
+        const { assert!(N > 1, "the filter must have at least two taps") };
+
+        let mut taps = [0f32; N];
+        let middle = (N - 1) as f64 / 2.0;
+        let cutoff = cutoff as f64;
+        let mut sum = 0.0;
+        for (n, tap) in taps.iter_mut().enumerate() {
+            let t = n as f64 - middle;
+            let sinc = if t == 0.0 {
+                2.0 * cutoff
+            } else {
+                crate::math::sin_cos(core::f64::consts::TAU * cutoff * t).0
+                    / (core::f64::consts::PI * t)
+            };
+            let x = core::f64::consts::TAU * n as f64 / (N - 1) as f64;
+            let window = 0.42 - 0.5 * crate::math::sin_cos(x).1
+                + 0.08 * crate::math::sin_cos(2.0 * x).1;
+            *tap = (sinc * window) as f32;
+            sum += *tap as f64;
+        }
+        // Normalize to unity gain at DC.
+        for tap in taps.iter_mut() {
+            *tap = (*tap as f64 / sum) as f32;
+        }
+
+        Self {
+            taps,
+            history: [Complex::default(); N],
+            index: 0,
+            factor: factor.max(1),
+            count: 0,
+        }
+    }
+
+    /// The decimation factor.
+    pub fn factor(&self) -> usize {
+        self.factor
+    }
+
+    /// Filters the next sample, returning an output sample for every
+    /// `factor` input samples.
+    pub fn push(&mut self, sample: Complex) -> Option<Complex> {
+        self.history[self.index] = sample;
+        self.index = (self.index + 1) % N;
+        self.count += 1;
+        if self.count < self.factor {
+            return None;
+        }
+        self.count = 0;
+
+        let (newer, older) = self.history.split_at(self.index);
+        let mut sum = Complex::default();
+        for (&tap, &sample) in self.taps.iter().zip(older.iter().chain(newer)) {
+            sum += sample * tap;
+        }
+        Some(sum)
+    }
+}
+
+/// A sample rate converter reducing the rate by any ratio of at least 1,
+/// by linear interpolation.
+///
+/// Linear interpolation is only accurate for signals well below the
+/// Nyquist frequency, so the input should be oversampled several times and
+/// already filtered to the band of interest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resampler {
+    // Input samples per output sample.
+    step: f64,
+    // The time of the next output sample, in input samples after `previous`.
+    phase: f64,
+    previous: Complex,
+}
+
+impl Resampler {
+    /// Creates a converter from `input` Hz to `output` Hz, where `input` is
+    /// at least `output`.
+    pub fn new(input: f64, output: f64) -> Result<Self, Error> {
+        if !(output > 0.0 && input >= output) {
+            return Err(Error::InvalidSampleRate);
+        }
+        Ok(Self {
+            step: input / output,
+            phase: 1.0,
+            previous: Complex::default(),
+        })
+    }
+
+    /// Takes the next input sample, returning an output sample if one falls
+    /// between it and the previous input sample.
+    pub fn push(&mut self, sample: Complex) -> Option<Complex> {
+        let output = (self.phase <= 1.0).then(|| {
+            let output =
+                self.previous + (sample - self.previous) * self.phase as f32;
+            self.phase += self.step;
+            output
+        });
+        self.phase -= 1.0;
+        self.previous = sample;
+        output
+    }
+}
+
+// The rate the first stages bring the signal down to, before it's resampled
+// to a multiple of the baseband rate.
+const INTERMEDIATE_RATE: u32 = 3000;
+
+// The most stages of decimation by up to 4 before the intermediate rate.
+const MAX_STAGES: usize = 8;
+
+// The widest signal kept, in Hz either side of the passband's center.
+const BANDWIDTH: f32 = 150.0;
+
+/// A filter chain taking samples at any rate of at least 3000 Hz to the 375
+/// Hz complex baseband the [`search`](crate::search) works on.
+///
+/// The passband is mixed down to DC with a [`Mixer`], decimated by up to 4
+/// at a time with [`Decimator`]s until the rate is below 12000 Hz, filtered
+/// to ±1200 Hz, resampled to exactly 3000 Hz with a [`Resampler`], and
+/// finally decimated by 8 with a sharp filter. The ±150 Hz around the
+/// center of the passband is kept, with everything beyond 225 Hz removed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Downconverter {
+    sample_rate: u32,
+    mixer: Mixer,
+    stages: [Option<Decimator<32>>; MAX_STAGES],
+    band: Decimator<128>,
+    resampler: Resampler,
+    last: Decimator<256>,
+    delay: f64,
+}
+
+impl Downconverter {
+    /// Creates a chain for samples at `sample_rate` Hz, real or complex,
+    /// where the center of the WSPR passband is at `frequency` Hz, such as
+    /// 1500 Hz for audio. Returns [`Error::InvalidSampleRate`] if the rate
+    /// is below 3000 Hz, or [`Error::InvalidFrequency`] if the passband
+    /// isn't within the sampled bandwidth.
+    pub fn new(sample_rate: u32, frequency: f32) -> Result<Self, Error> {
+        if sample_rate < INTERMEDIATE_RATE {
+            return Err(Error::InvalidSampleRate);
+        }
+        if frequency.abs() + BANDWIDTH >= sample_rate as f32 / 2.0 {
+            return Err(Error::InvalidFrequency);
+        }
+
+        // The rate is tracked exactly, as any error would shift the
+        // frequency of the output.
+        let limit = 4.0 * 4.0 * INTERMEDIATE_RATE as f64;
+        let mut rate = sample_rate as f64;
+        let mut stages = [const { None }; MAX_STAGES];
+        let mut delay = 0.0;
+        for stage in stages.iter_mut() {
+            if rate < limit {
+                break;
+            }
+            *stage = Some(Decimator::new(4, 0.125));
+            delay += group_delay(32, 4, rate);
+            rate /= 4.0;
+        }
+        if rate >= limit {
+            return Err(Error::InvalidSampleRate);
+        }
+
+        let factor = ((rate / INTERMEDIATE_RATE as f64) as usize).min(4);
+        let band = Decimator::new(factor, (1200.0 / rate) as f32);
+        delay += group_delay(128, factor, rate);
+        let rate = rate / factor as f64;
+        let resampler = Resampler::new(rate, INTERMEDIATE_RATE as f64)?;
+        let factor = INTERMEDIATE_RATE / SAMPLE_RATE;
+        let cutoff = 190.0 / INTERMEDIATE_RATE as f32;
+        delay += group_delay(256, factor as usize, INTERMEDIATE_RATE as f64);
+
+        Ok(Self {
+            sample_rate,
+            mixer: Mixer::new(sample_rate, frequency),
+            stages,
+            band,
+            resampler,
+            last: Decimator::new(factor as usize, cutoff),
+            delay,
+        })
+    }
+
+    /// How far the baseband lags the input, in seconds. The first output
+    /// sample is of the signal this long before the first input sample, so
+    /// it should be subtracted from times measured in the baseband.
+    pub fn delay(&self) -> f64 {
+        self.delay
+    }
+
+    /// The most output samples [`Downconverter::process`] may produce from
+    /// `len` input samples. Each stage may complete a sample earlier or
+    /// later than the overall ratio suggests, so this allows a few extra.
+    pub fn output_len(&self, len: usize) -> usize {
+        (len as u64 * SAMPLE_RATE as u64 / self.sample_rate as u64) as usize + 4
+    }
+
+    /// Takes the next input sample, returning a baseband sample once one is
+    /// complete.
+    pub fn push<S: Sample>(&mut self, sample: S) -> Option<Complex> {
+        let mut sample = self.mixer.mix(sample.to_complex());
+        for stage in self.stages.iter_mut().flatten() {
+            sample = stage.push(sample)?;
+        }
+        let sample = self.band.push(sample)?;
+        let sample = self.resampler.push(sample)?;
+        self.last.push(sample)
+    }
+
+    /// Converts a block of input samples, writing the baseband samples it
+    /// completes to `out` and returning how many were written.
+    ///
+    /// `out` must hold at least [`Downconverter::output_len`] samples,
+    /// otherwise [`Error::BufferTooSmall`] is returned and the block is
+    /// ignored.
+    pub fn process<S: Sample>(
+        &mut self,
+        input: &[S],
+        out: &mut [Complex],
+    ) -> Result<usize, Error> {
+        if out.len() < self.output_len(input.len()) {
+            return Err(Error::BufferTooSmall);
+        }
+
+        let mut written = 0;
+        for &sample in input {
+            if let Some(sample) = self.push(sample) {
+                out[written] = sample;
+                written += 1;
+            }
+        }
+        Ok(written)
+    }
+}
+
+// The delay of a decimator of `taps` taps by `factor` at `rate` Hz, in
+// seconds. Each output is centered on the middle of the taps, and the first
+// is produced after `factor` input samples.
+fn group_delay(taps: usize, factor: usize, rate: f64) -> f64 {
+    ((taps - 1) as f64 / 2.0 - (factor - 1) as f64) / rate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec;
+    use std::vec::Vec;
+
+    // The magnitude and frequency, in cycles per sample, of a complex tone.
+    fn measure(samples: &[Complex]) -> (f32, f64) {
+        let magnitude = samples.iter().map(|s| s.norm()).sum::<f32>()
+            / samples.len() as f32;
+        let mut rotation = Complex::default();
+        for pair in samples.windows(2) {
+            rotation += pair[1] * pair[0].conj();
+        }
+        let step = rotation * (1.0 / rotation.norm());
+        let frequency = (step.im as f64).asin() / core::f64::consts::TAU;
+        (magnitude, frequency)
+    }
+
+    #[test]
+    fn test_decimator() {
+        // Unity gain at DC.
+        let mut decimator = Decimator::<32>::new(4, 0.125);
+        let out: Vec<_> = (0..400)
+            .filter_map(|_| decimator.push(Complex::new(1.0, 0.0)))
+            .collect();
+        assert_eq!(out.len(), 100);
+        assert!((out[99].re - 1.0).abs() < 1e-4);
+
+        // A tone in the stopband is removed rather than aliased.
+        let mut decimator = Decimator::<32>::new(4, 0.125);
+        let out: Vec<_> = (0..4000)
+            .filter_map(|n| {
+                let phase = core::f64::consts::TAU * 0.4 * n as f64;
+                decimator.push(Complex::from_phase(phase))
+            })
+            .collect();
+        assert!(measure(&out[10..]).0 < 1e-3);
+    }
+
+    #[test]
+    fn test_resampler() {
+        assert!(Resampler::new(100.0, 200.0).is_err());
+
+        let mut resampler = Resampler::new(3.0, 2.0).unwrap();
+        let out: Vec<_> = (0..7)
+            .filter_map(|n| resampler.push(Complex::new(n as f32, 0.0)))
+            .collect();
+        let out: Vec<_> = out.iter().map(|s| s.re).collect();
+        assert_eq!(out, [0.0, 1.5, 3.0, 4.5, 6.0]);
+    }
+
+    #[test]
+    fn test_downconverter() {
+        assert_eq!(
+            Downconverter::new(2000, 0.0),
+            Err(Error::InvalidSampleRate)
+        );
+        assert_eq!(
+            Downconverter::new(8000, 3900.0),
+            Err(Error::InvalidFrequency)
+        );
+
+        // A tone 20 Hz above the center of the passband, and a stronger one
+        // 400 Hz above, in audio at a rate that isn't a multiple of 375 Hz.
+        for sample_rate in [8000, 44100, 2_048_001] {
+            let len = sample_rate as usize * 2;
+            let input: Vec<f32> = (0..len)
+                .map(|n| {
+                    let t = n as f64 / sample_rate as f64;
+                    let tone = |f: f64| {
+                        crate::math::sin_cos(core::f64::consts::TAU * f * t).1
+                    };
+                    (tone(1520.0) + 10.0 * tone(1900.0)) as f32
+                })
+                .collect();
+
+            let mut downconverter =
+                Downconverter::new(sample_rate, 1500.0).unwrap();
+            let mut out = vec![Complex::default(); 754];
+            assert_eq!(
+                downconverter.process(&input, &mut out[..753]),
+                Err(Error::BufferTooSmall)
+            );
+            let written = downconverter.process(&input, &mut out).unwrap();
+            assert!((748..=750).contains(&written), "{sample_rate}");
+
+            let (magnitude, frequency) = measure(&out[200..written]);
+            assert!((magnitude - 0.5).abs() < 0.01, "{sample_rate}");
+            assert!((frequency * 375.0 - 20.0).abs() < 0.01, "{sample_rate}");
+        }
+    }
+}
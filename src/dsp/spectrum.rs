@@ -0,0 +1,194 @@
+use super::{Complex, Fft, Radix2, Sample};
+use crate::math;
+
+/// The power spectral density of a signal, averaged over Hann windowed
+/// `N`-point FFTs overlapping by half (Welch's method), for checking how
+/// much bandwidth a generated signal occupies.
+///
+/// The power of each bin is a fraction of the mean power of the samples,
+/// so the bins sum to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spectrum<const N: usize> {
+    sample_rate: u32,
+    // The frequency of the first bin, as an index into the FFT's bins.
+    first: isize,
+    bins: usize,
+    power: [f32; N],
+}
+
+impl<const N: usize> Spectrum<N> {
+    /// Computes the one-sided spectrum of real `samples` at `sample_rate`
+    /// Hz, with N / 2 + 1 bins from 0 Hz to the Nyquist frequency, the
+    /// power at negative frequencies folded onto the positive.
+    pub fn new<S: Sample>(samples: &[S], sample_rate: u32) -> Self {
+        let mut spectrum = Self::new_iq(samples, sample_rate);
+        let mut power = [0.0; N];
+        power[0] = spectrum.power[N / 2];
+        for (k, power) in power.iter_mut().enumerate().take(N / 2).skip(1) {
+            *power = spectrum.power[N / 2 + k] + spectrum.power[N / 2 - k];
+        }
+        power[N / 2] = spectrum.power[0];
+        spectrum.power = power;
+        spectrum.first = 0;
+        spectrum.bins = N / 2 + 1;
+        spectrum
+    }
+
+    /// Computes the spectrum of complex `samples` at `sample_rate` Hz, with
+    /// N bins from -`sample_rate` / 2 up to `sample_rate` / 2.
+    pub fn new_iq<S: Sample>(samples: &[S], sample_rate: u32) -> Self {
+        let mut window = [0.0f32; N];
+        for (i, w) in window.iter_mut().enumerate() {
+            let x = core::f64::consts::TAU * i as f64 / N as f64;
+            *w = (0.5 - 0.5 * math::sin_cos(x).1) as f32;
+        }
+        let energy: f32 = window.iter().map(|w| w * w).sum();
+
+        // Segments start every N / 2 samples; a final partial segment is
+        // padded with zeros only if there's no whole segment at all.
+        let step = (N / 2).max(1);
+        let segments = match samples.len() {
+            length if length < N => 1,
+            length => (length - N) / step + 1,
+        };
+        let mut fft = Radix2::<N>::new();
+        let mut sums = [0.0f32; N];
+        for segment in 0..segments {
+            let mut buffer = [Complex::default(); N];
+            let start = segment * step;
+            for ((value, &sample), &w) in
+                buffer.iter_mut().zip(&samples[start..]).zip(&window)
+            {
+                *value = sample.to_complex() * w;
+            }
+            fft.forward(&mut buffer);
+            for (sum, bin) in sums.iter_mut().zip(&buffer) {
+                *sum += bin.norm_sqr();
+            }
+        }
+
+        // Rotate the bins by half, so they run from the lowest frequency to
+        // the highest.
+        let scale = 1.0 / (segments as f32 * N as f32 * energy);
+        let mut power = [0.0; N];
+        for (k, power) in power.iter_mut().enumerate() {
+            *power = sums[(k + N / 2) % N] * scale;
+        }
+        Self {
+            sample_rate,
+            first: -(N as isize / 2),
+            bins: N,
+            power,
+        }
+    }
+
+    /// The power of each bin, from the lowest frequency to the highest.
+    pub fn power(&self) -> &[f32] {
+        &self.power[..self.bins]
+    }
+
+    /// The frequency of the center of bin `bin` in Hz.
+    pub fn frequency(&self, bin: usize) -> f32 {
+        (self.first + bin as isize) as f32 * self.resolution()
+    }
+
+    /// The width of each bin in Hz.
+    pub fn resolution(&self) -> f32 {
+        self.sample_rate as f32 / N as f32
+    }
+
+    /// The total power, the mean power of the samples.
+    pub fn total(&self) -> f32 {
+        self.power().iter().sum()
+    }
+
+    /// The lowest and highest frequencies in Hz of the band holding
+    /// `fraction` of the power, such as 0.99, with the rest split evenly
+    /// above and below it.
+    pub fn occupied_band(&self, fraction: f32) -> (f32, f32) {
+        let outside = (1.0 - fraction.clamp(0.0, 1.0)) / 2.0 * self.total();
+        let low = self.edge(self.power().iter(), outside);
+        let high = self.edge(self.power().iter().rev(), outside);
+        let resolution = self.resolution();
+        let start = self.frequency(0) - resolution / 2.0;
+        let end = self.frequency(self.bins - 1) + resolution / 2.0;
+        (start + low * resolution, end - high * resolution)
+    }
+
+    /// The width in Hz of the band holding `fraction` of the power, such as
+    /// 0.99 for the 99% occupied bandwidth.
+    pub fn occupied_bandwidth(&self, fraction: f32) -> f32 {
+        let (low, high) = self.occupied_band(fraction);
+        (high - low).max(0.0)
+    }
+
+    // How many bins, with a fraction of the last, from the first of `bins`
+    // hold `power` between them, spreading each bin's power evenly across
+    // it.
+    fn edge<'a>(
+        &self,
+        bins: impl Iterator<Item = &'a f32>,
+        mut power: f32,
+    ) -> f32 {
+        let mut count = 0.0;
+        for &bin in bins {
+            if bin >= power {
+                return count + if bin > 0.0 { power / bin } else { 0.0 };
+            }
+            power -= bin;
+            count += 1.0;
+        }
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulate::{Modulator, Shaping};
+    use std::vec::Vec;
+
+    #[test]
+    fn test_spectrum() {
+        // A real tone's power is all in its bin, half the square of its
+        // amplitude.
+        let samples: Vec<f32> = (0..8192)
+            .map(|i| 0.5 * (i as f32 * core::f32::consts::TAU / 8.0).sin())
+            .collect();
+        let spectrum = Spectrum::<256>::new(&samples, 8000);
+        assert_eq!(spectrum.power().len(), 129);
+        assert_eq!(spectrum.resolution(), 31.25);
+        assert!((spectrum.total() - 0.125).abs() < 1e-3);
+        assert!(
+            (spectrum.power()[32] / spectrum.total() - 2.0 / 3.0).abs() < 1e-3
+        );
+        let (low, high) = spectrum.occupied_band(0.99);
+        assert!(low > 950.0 && high < 1050.0, "{} {}", low, high);
+
+        let iq: Vec<Complex> = samples
+            .iter()
+            .enumerate()
+            .map(|(i, &s)| Complex::new(s, 0.0) * ((i % 2) as f32 * 2.0 - 1.0))
+            .collect();
+        let spectrum = Spectrum::<256>::new_iq(&iq, 8000);
+        assert_eq!(spectrum.power().len(), 256);
+        assert_eq!(spectrum.frequency(0), -4000.0);
+        assert!((spectrum.total() - 0.125).abs() < 1e-3);
+
+        // WSPR stays within about 6 Hz, and shaping the steps between tones
+        // narrows it further.
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let modulator = Modulator::new(&symbols, 375, 100.0).unwrap();
+        let samples: Vec<f32> = modulator.clone().collect();
+        let spectrum = Spectrum::<4096>::new(&samples, 375);
+        let bandwidth = spectrum.occupied_bandwidth(0.99);
+        assert!(bandwidth > 5.5 && bandwidth < 6.5, "{}", bandwidth);
+        let (low, high) = spectrum.occupied_band(0.99);
+        assert!(low < 97.8 && high > 102.2, "{} {}", low, high);
+
+        let shaped: Vec<f32> =
+            modulator.with_shaping(Shaping::Gaussian(1.0)).collect();
+        let shaped = Spectrum::<4096>::new(&shaped, 375);
+        assert!(shaped.occupied_bandwidth(0.99) < bandwidth);
+    }
+}
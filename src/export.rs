@@ -0,0 +1,108 @@
+//! Rendering tables of symbols, tone frequencies, or DDS tuning words as
+//! source for other toolchains, so a PIC, AVR, or FPGA beacon can have its
+//! tables generated by this crate.
+//!
+//! [`write_c_array`] writes a C array for a header file, and
+//! [`write_memh`] a memory file for Verilog's `$readmemh`. Both write to a
+//! [`core::fmt::Write`], such as a `String` or a fixed size buffer.
+//!
+//! ```
+//! use wspr::export::write_c_array;
+//!
+//! let symbols = wspr::encode("K1ABC", "FN42", 37).unwrap();
+//! let mut header = String::new();
+//! write_c_array(&mut header, "uint8_t", "wspr_symbols", &symbols).unwrap();
+//! assert!(header.starts_with("const uint8_t wspr_symbols[162] = {\n    3, 3,"));
+//! ```
+
+use core::fmt::{self, Display, UpperHex, Write};
+
+// The number of values on each line of a C array.
+const PER_LINE: usize = 16;
+
+/// Writes `values` as a C array of `ctype` named `name`, such as
+/// `const uint8_t wspr_symbols[162] = { ... };`, 16 values to a line.
+pub fn write_c_array<W: Write, T: Display>(
+    writer: &mut W,
+    ctype: &str,
+    name: &str,
+    values: &[T],
+) -> fmt::Result {
+    writeln!(writer, "const {} {}[{}] = {{", ctype, name, values.len())?;
+    for (i, line) in values.chunks(PER_LINE).enumerate() {
+        writer.write_str("    ")?;
+        for (j, value) in line.iter().enumerate() {
+            if j > 0 {
+                writer.write_str(" ")?;
+            }
+            let last = i * PER_LINE + j + 1 == values.len();
+            write!(writer, "{}{}", value, if last { "" } else { "," })?;
+        }
+        writer.write_str("\n")?;
+    }
+    writer.write_str("};\n")
+}
+
+/// Writes `values` as a Verilog memory file for `$readmemh`, one value to a
+/// line in hexadecimal, zero padded to `digits` digits.
+pub fn write_memh<W: Write, T: UpperHex>(
+    writer: &mut W,
+    digits: usize,
+    values: &[T],
+) -> fmt::Result {
+    for value in values {
+        writeln!(writer, "{:01$X}", value, digits)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulate::tone_millihertz;
+    use std::string::String;
+
+    #[test]
+    fn test_write_c_array() {
+        let mut text = String::new();
+        write_c_array(&mut text, "uint8_t", "symbols", &[3u8, 1, 2]).unwrap();
+        assert_eq!(text, "const uint8_t symbols[3] = {\n    3, 1, 2\n};\n");
+
+        // The four tones in millihertz, all on one line.
+        let tones: [u64; 4] =
+            core::array::from_fn(|i| tone_millihertz(14_097_098_535, i as u8));
+        let mut text = String::new();
+        write_c_array(&mut text, "uint64_t", "tones", &tones).unwrap();
+        assert_eq!(
+            text,
+            "const uint64_t tones[4] = {\n    14097098535, 14097100000, \
+             14097101465, 14097102930\n};\n"
+        );
+
+        // The symbols wrap after 16 values, leaving 2 on the last line.
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let mut text = String::new();
+        write_c_array(&mut text, "uint8_t", "wspr", &symbols).unwrap();
+        let lines: std::vec::Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2 + 11);
+        assert!(lines[1].ends_with(','));
+        assert!(!lines[11].ends_with(','));
+        assert_eq!(lines[11].split(", ").count(), 2);
+        assert_eq!(lines[12], "};");
+    }
+
+    #[test]
+    fn test_write_memh() {
+        let mut text = String::new();
+        write_memh(&mut text, 8, &[0x1234_ABCDu32, 0x0F]).unwrap();
+        assert_eq!(text, "1234ABCD\n0000000F\n");
+
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let mut text = String::new();
+        write_memh(&mut text, 1, &symbols).unwrap();
+        assert_eq!(text.len(), 2 * 162);
+        for (line, symbol) in text.lines().zip(symbols) {
+            assert_eq!(line, std::format!("{}", symbol));
+        }
+    }
+}
@@ -0,0 +1,203 @@
+//! Callsign hashes, and tables of previously heard callsigns.
+//!
+//! Type 3 messages carry a 15-bit hash of the callsign in place of the
+//! callsign itself. Receivers recover the callsign by remembering the
+//! callsigns heard in type 1 and type 2 messages, keyed by their hash. See
+//! [`Message::resolve`](crate::Message::resolve).
+
+#[cfg(feature = "std")]
+use std::{collections::HashMap, string::String};
+
+// The `initval` WSJT uses when hashing callsigns.
+const SEED: u32 = 146;
+
+// Bob Jenkins' lookup3 `hashlittle`, as used by WSJT's `nhash`. See
+// http://burtleburtle.net/bob/c/lookup3.c
+fn lookup3(key: &[u8], seed: u32) -> u32 {
+    let mut a = 0xdeadbeef_u32
+        .wrapping_add(key.len() as u32)
+        .wrapping_add(seed);
+    let mut b = a;
+    let mut c = a;
+
+    let word = |bytes: &[u8]| {
+        bytes
+            .iter()
+            .enumerate()
+            .fold(0u32, |word, (i, &byte)| word | (byte as u32) << (8 * i))
+    };
+
+    let mut key = key;
+    while key.len() > 12 {
+        a = a.wrapping_add(word(&key[0..4]));
+        b = b.wrapping_add(word(&key[4..8]));
+        c = c.wrapping_add(word(&key[8..12]));
+
+        a = a.wrapping_sub(c) ^ c.rotate_left(4);
+        c = c.wrapping_add(b);
+        b = b.wrapping_sub(a) ^ a.rotate_left(6);
+        a = a.wrapping_add(c);
+        c = c.wrapping_sub(b) ^ b.rotate_left(8);
+        b = b.wrapping_add(a);
+        a = a.wrapping_sub(c) ^ c.rotate_left(16);
+        c = c.wrapping_add(b);
+        b = b.wrapping_sub(a) ^ a.rotate_left(19);
+        a = a.wrapping_add(c);
+        c = c.wrapping_sub(b) ^ b.rotate_left(4);
+        b = b.wrapping_add(a);
+
+        key = &key[12..];
+    }
+
+    if key.is_empty() {
+        return c;
+    }
+    a = a.wrapping_add(word(&key[..key.len().min(4)]));
+    b = b.wrapping_add(word(key.get(4..key.len().min(8)).unwrap_or(&[])));
+    c = c.wrapping_add(word(key.get(8..).unwrap_or(&[])));
+
+    c = (c ^ b).wrapping_sub(b.rotate_left(14));
+    a = (a ^ c).wrapping_sub(c.rotate_left(11));
+    b = (b ^ a).wrapping_sub(a.rotate_left(25));
+    c = (c ^ b).wrapping_sub(b.rotate_left(16));
+    a = (a ^ c).wrapping_sub(c.rotate_left(4));
+    b = (b ^ a).wrapping_sub(a.rotate_left(14));
+    (c ^ b).wrapping_sub(b.rotate_left(24))
+}
+
+/// The 15-bit hash of a callsign carried by type 3 messages, WSJT's `nhash`
+/// of the callsign masked to 15 bits.
+///
+/// The callsign is hashed exactly as given, so it should be upper case with
+/// any prefix or suffix included, such as `PJ4/K1ABC`, as it's transmitted
+/// in a type 1 or type 2 message.
+pub fn hash(callsign: &str) -> u16 {
+    (lookup3(callsign.as_bytes(), SEED) & 0x7FFF) as u16
+}
+
+/// A table of previously heard callsigns, keyed by their 15-bit hash.
+pub trait CallsignTable {
+    /// Returns the callsign with the given hash, if one has been heard.
+    fn get(&self, hash: u16) -> Option<&str>;
+
+    /// Records that `callsign`, with the given hash, has been heard.
+    fn insert(&mut self, hash: u16, callsign: &str);
+}
+
+/// A fixed size [`CallsignTable`] holding up to `N` callsigns. Once full,
+/// the oldest callsign is forgotten to make room for each new one.
+#[derive(Debug, Clone)]
+pub struct Callsigns<const N: usize> {
+    entries: [(u16, [u8; 10]); N],
+    len: usize,
+    next: usize,
+}
+
+impl<const N: usize> Callsigns<N> {
+    /// Creates an empty table.
+    pub const fn new() -> Self {
+        Callsigns {
+            entries: [(0, [b' '; 10]); N],
+            len: 0,
+            next: 0,
+        }
+    }
+}
+
+impl<const N: usize> Default for Callsigns<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> CallsignTable for Callsigns<N> {
+    fn get(&self, hash: u16) -> Option<&str> {
+        self.entries[..self.len]
+            .iter()
+            .find(|(h, _)| *h == hash)
+            .map(|(_, callsign)| crate::ascii(callsign).trim_end())
+    }
+
+    fn insert(&mut self, hash: u16, callsign: &str) {
+        let Some(padded) = crate::pad(&[callsign.as_bytes()]) else {
+            return;
+        };
+        if N == 0 {
+            return;
+        }
+
+        // Callsigns already in the table are refreshed in place.
+        let entry = match self.entries[..self.len]
+            .iter()
+            .position(|(h, _)| *h == hash)
+        {
+            Some(i) => &mut self.entries[i],
+            None => {
+                let i = self.next;
+                self.next = (self.next + 1) % N;
+                self.len = (self.len + 1).min(N);
+                &mut self.entries[i]
+            }
+        };
+        *entry = (hash, padded);
+    }
+}
+
+#[cfg(feature = "std")]
+impl CallsignTable for HashMap<u16, String> {
+    fn get(&self, hash: u16) -> Option<&str> {
+        HashMap::get(self, &hash).map(String::as_str)
+    }
+
+    fn insert(&mut self, hash: u16, callsign: &str) {
+        HashMap::insert(self, hash, callsign.into());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup3() {
+        // Test vectors from lookup3.c
+        assert_eq!(lookup3(b"", 0), 0xdeadbeef);
+        assert_eq!(lookup3(b"", 0xdeadbeef), 0xbd5b7dde);
+        let key = b"Four score and seven years ago";
+        assert_eq!(lookup3(key, 0), 0x17770551);
+        assert_eq!(lookup3(key, 1), 0xcd628161);
+    }
+
+    #[test]
+    fn test_hash() {
+        // WSJT's `nhash(callsign, strlen(callsign), 146) & 32767`, from the
+        // `hashlittle` of lookup3.c that it wraps.
+        assert_eq!(hash("K1ABC"), 6521);
+        assert_eq!(hash("PJ4/K1ABC"), 19735);
+        assert_eq!(hash("K1ABC/7"), 5722);
+        assert_eq!(hash("G1ABC"), 5580);
+        assert_eq!(hash("W1AW"), 5970);
+        assert_ne!(hash("K1ABC"), hash("PJ4/K1ABC"));
+
+        // The hash a type 3 message carries.
+        let symbols = crate::encode("PJ4/K1ABC", "FK52UD", 37).unwrap();
+        let message = crate::decode(&symbols).unwrap();
+        assert_eq!(message.hash(), Some(hash("PJ4/K1ABC")));
+    }
+
+    #[test]
+    fn test_callsigns() {
+        let mut table = Callsigns::<2>::new();
+        assert_eq!(table.get(hash("K1ABC")), None);
+
+        table.insert(hash("K1ABC"), "K1ABC");
+        table.insert(hash("PJ4/K1ABC"), "PJ4/K1ABC");
+        assert_eq!(table.get(hash("K1ABC")), Some("K1ABC"));
+        assert_eq!(table.get(hash("PJ4/K1ABC")), Some("PJ4/K1ABC"));
+
+        // The oldest entry makes way for new ones.
+        table.insert(hash("G1ABC"), "G1ABC");
+        assert_eq!(table.get(hash("K1ABC")), None);
+        assert_eq!(table.get(hash("G1ABC")), Some("G1ABC"));
+    }
+}
@@ -2,8 +2,43 @@
 
 #![no_std]
 
-#[cfg(feature = "defmt-03")]
-use defmt;
+#[cfg(any(test, feature = "std"))]
+extern crate std;
+
+pub mod ad9833;
+pub mod ad9850;
+pub mod adf4351;
+pub mod beacon;
+#[cfg(feature = "std")]
+pub mod c2;
+pub mod calibration;
+#[cfg(feature = "cpal")]
+pub mod capture;
+pub mod channel;
+pub mod decoder;
+pub mod demod;
+pub mod dsp;
+pub mod export;
+pub mod hash;
+mod math;
+pub mod modulate;
+pub mod morse;
+mod nco;
+pub mod output;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+pub mod rtlsdr;
+pub mod search;
+pub mod si5351;
+#[cfg(feature = "std")]
+pub mod spectrogram;
+pub mod spot;
+pub mod stack;
+pub mod stream;
+pub mod telemetry;
+pub mod transmitter;
+#[cfg(feature = "std")]
+pub mod wav;
 
 #[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
 #[derive(Debug, PartialEq)]
@@ -11,6 +46,42 @@ pub enum Error {
     InvalidPower,
     InvalidGrid,
     InvalidCallsign,
+    InvalidSymbol,
+    DecodeFailed,
+    InvalidFrequency,
+    TooFewSamples,
+    BufferTooSmall,
+    InvalidSampleRate,
+    InvalidPrefix,
+    MissingNumeral,
+    InvalidSuffix,
+}
+
+/// The length of each symbol in samples at 12000 Hz, about 683ms.
+pub const SYMBOL_LENGTH: u32 = 8192;
+
+/// The spacing between adjacent tones in Hz, about 1.46Hz.
+pub const TONE_SPACING: f32 = 12000.0 / SYMBOL_LENGTH as f32;
+
+// Generator polynomials of the K=32, r=1/2 convolutional code.
+const POLY0: u32 = 0xF2D05351;
+const POLY1: u32 = 0xE4613C47;
+
+// The pseudo-random sync vector, one bit per symbol, carried in the least
+// significant bit of each transmitted symbol.
+const SYNC: [u8; 162] = [
+    1, 1, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1, 0, 1, 1,
+    1, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0, 1, 0, 1, 1,
+    0, 0, 1, 1, 0, 1, 0, 0, 0, 1, 1, 0, 1, 0, 0, 0, 0, 1, 1, 0, 1, 0, 1, 0, 1,
+    0, 1, 0, 0, 1, 0, 0, 1, 0, 1, 1, 0, 0, 0, 1, 1, 0, 1, 0, 1, 0, 0, 0, 1, 0,
+    0, 0, 0, 0, 1, 0, 0, 1, 0, 0, 1, 1, 1, 0, 1, 1, 0, 0, 1, 1, 0, 1, 0, 0, 0,
+    1, 1, 1, 0, 0, 0, 0, 0, 1, 0, 1, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 1, 1, 0,
+    1, 0, 1, 1, 0, 0, 0, 1, 1, 0, 0, 0,
+];
+
+// Returns 1 if an odd number of bits are set in `value`, 0 otherwise.
+fn parity(value: u32) -> u8 {
+    (value.count_ones() & 0x01) as u8
 }
 
 // A 32-bit shift register that shifts bits into the least significant bit,
@@ -31,9 +102,7 @@ impl ShiftRegister {
 
     fn shift(&mut self, bit: u32) -> u8 {
         self.value = (self.value << 1) | bit;
-        let ones = (self.value & self.and_const).count_ones();
-        let parity = ones & 0x01; // is parity odd
-        parity as u8
+        parity(self.value & self.and_const)
     }
 }
 
@@ -58,42 +127,125 @@ impl Buffer {
     }
 
     fn interleave(&mut self) {
-        let mut interleaved = [0u8; 162];
-        let mut p = 0;
-        for i in 0u8..255 {
-            let j = i.reverse_bits() as usize;
-            if j < 162 {
-                interleaved[j] = self.buffer[p];
-                p += 1;
-                if p == 162 {
-                    break;
-                }
-            }
+        self.buffer = interleave(&self.buffer);
+    }
+
+    fn sync(&mut self) {
+        self.buffer = apply_sync(&self.buffer);
+    }
+
+    fn release(self) -> [u8; 162] {
+        self.buffer
+    }
+
+    // The inverse of `release`, loads a set of received symbols so they may
+    // be unwound by `desync` and `deinterleave`.
+    fn load(symbols: &[u8; 162]) -> Self {
+        Self {
+            buffer: *symbols,
+            index: 0,
         }
+    }
 
-        self.buffer = interleaved;
+    // Loads symbols packed by `pack_symbols`, keeping only the data bit of
+    // each as a soft value of 0 or 255, as `desync` does.
+    fn load_packed(packed: &[u8; 41]) -> Self {
+        let mut buffer = [0u8; 162];
+        for (i, bit) in buffer.iter_mut().enumerate() {
+            let symbol = packed[i / 4] >> (6 - 2 * (i % 4));
+            *bit = (symbol >> 1 & 0x01) * 255;
+        }
+        Self { buffer, index: 0 }
     }
 
-    fn sync(&mut self) {
-        const SYNC: [u8; 162] = [
-            1, 1, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1,
-            0, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 1, 0, 1, 0, 0, 0, 0,
-            0, 0, 1, 0, 1, 1, 0, 0, 1, 1, 0, 1, 0, 0, 0, 1, 1, 0, 1, 0, 0, 0,
-            0, 1, 1, 0, 1, 0, 1, 0, 1, 0, 1, 0, 0, 1, 0, 0, 1, 0, 1, 1, 0, 0,
-            0, 1, 1, 0, 1, 0, 1, 0, 0, 0, 1, 0, 0, 0, 0, 0, 1, 0, 0, 1, 0, 0,
-            1, 1, 1, 0, 1, 1, 0, 0, 1, 1, 0, 1, 0, 0, 0, 1, 1, 1, 0, 0, 0, 0,
-            0, 1, 0, 1, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 1, 1, 0, 1, 0, 1, 1,
-            0, 0, 0, 1, 1, 0, 0, 0,
-        ];
+    fn deinterleave(&mut self) {
+        self.buffer = deinterleave(&self.buffer);
+    }
 
-        for i in 0..162 {
-            self.buffer[i] = SYNC[i] + 2 * self.buffer[i];
+    // The inverse of `sync`, strips the sync bit from each symbol leaving the
+    // data bit as a soft value of 0 or 255.
+    fn desync(&mut self) -> Result<(), Error> {
+        self.buffer = remove_sync(&self.buffer)?;
+        for bit in self.buffer.iter_mut() {
+            *bit *= 255;
         }
+        Ok(())
     }
+}
 
-    fn release(self) -> [u8; 162] {
-        self.buffer
+// Yields pairs of (position before interleaving, position after
+// interleaving). Each position is moved to the bit reversal of a counter,
+// skipping any reversals that fall outside the 162 positions.
+fn interleave_positions() -> impl Iterator<Item = (usize, usize)> {
+    (0u8..=255)
+        .map(|i| i.reverse_bits() as usize)
+        .filter(|&j| j < 162)
+        .enumerate()
+}
+
+/// Interleaves 162 values using the bit-reversal permutation applied by
+/// [`encode`] to the output of the convolutional code, spreading adjacent
+/// bits across the transmission so a burst of errors is scattered.
+pub fn interleave<T: Copy + Default>(values: &[T; 162]) -> [T; 162] {
+    let mut interleaved = [T::default(); 162];
+    for (p, j) in interleave_positions() {
+        interleaved[j] = values[p];
+    }
+    interleaved
+}
+
+/// The inverse of [`interleave`], restoring 162 values to the order they
+/// were produced by the convolutional code. The values may be hard bits or
+/// any kind of soft value.
+pub fn deinterleave<T: Copy + Default>(values: &[T; 162]) -> [T; 162] {
+    let mut deinterleaved = [T::default(); 162];
+    for (p, j) in interleave_positions() {
+        deinterleaved[p] = values[j];
     }
+    deinterleaved
+}
+
+/// Combines 162 data bits (each 0 or 1) with the sync vector, producing
+/// symbols in the range 0-3. The data bit becomes the most significant bit
+/// of each symbol and the sync bit the least significant.
+pub fn apply_sync(bits: &[u8; 162]) -> [u8; 162] {
+    let mut symbols = [0u8; 162];
+    for ((symbol, bit), sync) in symbols.iter_mut().zip(bits).zip(&SYNC) {
+        *symbol = sync + 2 * (bit & 0x01);
+    }
+    symbols
+}
+
+/// The inverse of [`apply_sync`], returning the data bit (0 or 1) of each
+/// symbol. Symbols outside the range 0-3 are rejected with
+/// [`Error::InvalidSymbol`].
+pub fn remove_sync(symbols: &[u8; 162]) -> Result<[u8; 162], Error> {
+    let mut bits = [0u8; 162];
+    for (bit, &symbol) in bits.iter_mut().zip(symbols) {
+        if symbol > 3 {
+            return Err(Error::InvalidSymbol);
+        }
+        *bit = symbol >> 1;
+    }
+    Ok(bits)
+}
+
+/// Checks that 162 symbols are plausibly a WSPR transmission, returning the
+/// correlation between the least significant bit of each symbol and the
+/// sync vector. The correlation ranges from -1.0 to 1.0, where 1.0 is a
+/// perfect match (as produced by [`encode`]) and values near 0.0 are no
+/// better than chance.
+///
+/// Symbols outside the range 0-3 are rejected with [`Error::InvalidSymbol`].
+pub fn validate_symbols(symbols: &[u8; 162]) -> Result<f32, Error> {
+    let mut correlation = 0i32;
+    for (&symbol, &sync) in symbols.iter().zip(&SYNC) {
+        if symbol > 3 {
+            return Err(Error::InvalidSymbol);
+        }
+        correlation += if symbol & 0x01 == sync { 1 } else { -1 };
+    }
+    Ok(correlation as f32 / 162.0)
 }
 
 // Return the base-36 value (0-35) for a single character '0-9A-Z', 36 for
@@ -105,7 +257,7 @@ fn encode_callsign_char(c: u8) -> Result<u32, Error> {
     } else {
         match c.to_digit(36) {
             Some(d) => Ok(d),
-            None => return Err(Error::InvalidCallsign),
+            None => Err(Error::InvalidCallsign),
         }
     }
 }
@@ -131,20 +283,9 @@ fn encode_callsign(callsign: &str) -> Result<u32, Error> {
 
     // Determine the starting index of the first non-space character.
     let start = match length {
-        0..=4 => {
-            if (callsign[2] as char).is_digit(10) {
-                0
-            } else {
-                1
-            }
-        }
-        5 => {
-            if (callsign[1] as char).is_digit(10) {
-                1
-            } else {
-                0
-            }
-        }
+        0..=4 if callsign[2].is_ascii_digit() => 0,
+        0..=4 => 1,
+        5 if callsign[1].is_ascii_digit() => 1,
         _ => 0,
     };
 
@@ -152,11 +293,11 @@ fn encode_callsign(callsign: &str) -> Result<u32, Error> {
     // offset.
     let stop = start + length;
     let mut padded = [b' '; 6];
-    padded[start..stop].copy_from_slice(&callsign);
+    padded[start..stop].copy_from_slice(callsign);
     let callsign = padded;
 
     // Ensure the 3rd character in the padded callsign is a digit.
-    if !(callsign[2] as char).is_digit(10) {
+    if !callsign[2].is_ascii_digit() {
         return Err(Error::InvalidCallsign);
     }
 
@@ -176,19 +317,19 @@ fn encode_callsign(callsign: &str) -> Result<u32, Error> {
 
     let mut n = 0;
     for (index, &c) in callsign.iter().enumerate() {
-        n = n * scalars[index] + encode_callsign_char(c)? as u32
-            - subtracts[index];
+        n = n * scalars[index] + encode_callsign_char(c)? - subtracts[index];
     }
 
     Ok(n)
 }
 
 fn encode_grid_char(c: u8) -> Result<u8, Error> {
-    return match (c as char).to_ascii_uppercase() {
-        'A'..='R' => Ok((c as u8) - b'A'),
-        '0'..='9' => Ok((c as u8) - b'0'),
+    let c = c.to_ascii_uppercase();
+    match c {
+        b'A'..=b'R' => Ok(c - b'A'),
+        b'0'..=b'9' => Ok(c - b'0'),
         _ => Err(Error::InvalidGrid),
-    };
+    }
 }
 
 fn encode_grid(grid: &str) -> Result<u16, Error> {
@@ -222,22 +363,733 @@ fn encode_power(power: u8) -> Result<u8, Error> {
     }
 }
 
+// The inverse of `encode_callsign_char`, returns the ASCII character for a
+// base-37 value (0-36).
+fn decode_callsign_char(value: u32) -> u8 {
+    match value {
+        0..=9 => b'0' + value as u8,
+        10..=35 => b'A' + (value - 10) as u8,
+        _ => b' ',
+    }
+}
+
+// Unwind the packing performed by `encode_callsign`, returning the space
+// padded 6 character callsign.
+fn decode_callsign(n: u32) -> Result<[u8; 6], Error> {
+    let scalars = [37u32, 36, 10, 27, 27, 27];
+    let adds = [0u32, 0, 0, 10, 10, 10];
+
+    let mut n = n;
+    let mut callsign = [b' '; 6];
+    for index in (0..6).rev() {
+        let value = n % scalars[index] + adds[index];
+        n /= scalars[index];
+        callsign[index] = decode_callsign_char(value);
+    }
+
+    // Anything left over doesn't fit a type 1 callsign.
+    if n != 0 {
+        return Err(Error::InvalidCallsign);
+    }
+
+    Ok(callsign)
+}
+
+// Unwind the packing performed by `encode_grid`.
+fn decode_grid(m: u16) -> Result<[u8; 4], Error> {
+    if m >= 180 * 180 {
+        return Err(Error::InvalidGrid);
+    }
+
+    let (high, low) = (179 - m / 180, m % 180);
+    let first = high / 10;
+    let third = high % 10;
+    let second = low / 10;
+    let fourth = low % 10;
+
+    if first > 17 || second > 17 {
+        return Err(Error::InvalidGrid);
+    }
+
+    Ok([
+        b'A' + first as u8,
+        b'A' + second as u8,
+        b'0' + third as u8,
+        b'0' + fourth as u8,
+    ])
+}
+
+// Unwind the offset applied by `encode_power`, rejecting values that
+// `encode_power` would not have produced.
+fn decode_power(p: u8) -> Result<u8, Error> {
+    let power = p.checked_sub(64).ok_or(Error::InvalidPower)?;
+    encode_power(power)?;
+    Ok(power)
+}
+
+/// Unpacks `n`, the 28 bit packed callsign of a type 1 or type 2 message,
+/// into `buffer`, returning the callsign with its padding removed. This is
+/// the inverse of the packing [`encode_raw`] expects.
+///
+/// Returns [`Error::InvalidCallsign`] if `n` isn't a packed callsign.
+pub fn unpack_callsign(n: u32, buffer: &mut [u8; 6]) -> Result<&str, Error> {
+    *buffer = decode_callsign(n)?;
+    Ok(ascii(buffer).trim())
+}
+
+/// Unpacks `m`, the 22 bit packed grid and power of a type 1 message, into
+/// the grid square, written to `buffer`, and the power level in dBm. This is
+/// the inverse of the packing [`encode_raw`] expects.
+///
+/// Returns [`Error::InvalidGrid`] or [`Error::InvalidPower`] if either
+/// field isn't a packed grid or power level.
+pub fn unpack_grid_power(
+    m: u32,
+    buffer: &mut [u8; 4],
+) -> Result<(&str, u8), Error> {
+    let grid = (m >> 7) as u16;
+    if grid >= 0x8000 {
+        return Err(Error::InvalidGrid);
+    }
+    let power = decode_power(m as u8 & 0x7F)?;
+    *buffer = decode_grid(grid)?;
+    Ok((ascii(buffer), power))
+}
+
+// Unwind the packing of the prefix or suffix of a type 2 message, adding it
+// to `callsign`. Values below 60000 are a prefix of up to 3 base-37
+// characters, values above a suffix of a single character or two digits.
+fn decode_prefix(n: u32, callsign: &str) -> Result<[u8; 12], Error> {
+    let callsign = callsign.as_bytes();
+
+    if n < 60000 {
+        let mut prefix = [b' '; 3];
+        let mut n = n;
+        for c in prefix.iter_mut().rev() {
+            *c = decode_callsign_char(n % 37);
+            n /= 37;
+        }
+        let prefix = ascii(&prefix).trim().as_bytes();
+        if prefix.is_empty() || prefix.contains(&b' ') {
+            return Err(Error::InvalidCallsign);
+        }
+        return pad(&[prefix, b"/", callsign]).ok_or(Error::InvalidCallsign);
+    }
+
+    let suffix = match n - 60000 {
+        n @ 0..=35 => [decode_callsign_char(n), b' '],
+        n @ 36..=125 => {
+            [b'0' + ((n - 26) / 10) as u8, b'0' + ((n - 26) % 10) as u8]
+        }
+        _ => return Err(Error::InvalidCallsign),
+    };
+    let suffix = ascii(&suffix).trim_end().as_bytes();
+    pad(&[callsign, b"/", suffix]).ok_or(Error::InvalidCallsign)
+}
+
+// Packs the prefix or suffix of a compound callsign into the value
+// `decode_prefix` unpacks, returning it along with the standard callsign.
+fn encode_prefix(callsign: &str) -> Result<(u32, &str), Error> {
+    validate_compound(callsign)?;
+    let (before, after) =
+        callsign.split_once('/').ok_or(Error::InvalidCallsign)?;
+
+    if encode_callsign(after).is_ok() && before.len() <= 3 {
+        let mut n = 0;
+        for _ in before.len()..3 {
+            n = n * 37 + 36;
+        }
+        for c in before.bytes() {
+            n = n * 37 + encode_callsign_char(c)?;
+        }
+        return Ok((n, after));
+    }
+
+    let n = match *after.as_bytes() {
+        [c] => encode_callsign_char(c)?,
+        [tens, units] => 26 + (tens - b'0') as u32 * 10 + (units - b'0') as u32,
+        _ => return Err(Error::InvalidCallsign),
+    };
+    Ok((60000 + n, before))
+}
+
+// Checks that `locator` is a six character Maidenhead locator.
+fn validate_locator(locator: &[u8; 6]) -> Result<(), Error> {
+    let valid = matches!(locator[0], b'A'..=b'R')
+        && matches!(locator[1], b'A'..=b'R')
+        && locator[2].is_ascii_digit()
+        && locator[3].is_ascii_digit()
+        && matches!(locator[4], b'A'..=b'X')
+        && matches!(locator[5], b'A'..=b'X');
+
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::InvalidGrid)
+    }
+}
+
+// Checks that `callsign` is a standard callsign with a prefix or suffix
+// that a type 2 message can carry.
+fn validate_compound(callsign: &str) -> Result<(), Error> {
+    let alphanumeric = |s: &str| {
+        s.bytes()
+            .all(|c| c.is_ascii_digit() || c.is_ascii_uppercase())
+    };
+    let digits = |s: &str| s.bytes().all(|c| c.is_ascii_digit());
+
+    let (before, after) =
+        callsign.split_once('/').ok_or(Error::InvalidCallsign)?;
+    let prefix = (1..=3).contains(&before.len())
+        && alphanumeric(before)
+        && encode_callsign(after).is_ok();
+    let suffix = encode_callsign(before).is_ok()
+        && ((after.len() == 1 && alphanumeric(after))
+            || (after.len() == 2 && digits(after) && !after.starts_with('0')));
+
+    if prefix || suffix {
+        Ok(())
+    } else {
+        Err(Error::InvalidCallsign)
+    }
+}
+
+/// Checks that `callsign` has the structure of a real callsign, stricter
+/// than [`encode`], which only needs a digit in the third position and so
+/// accepts strings such as `111` or `1A1`.
+///
+/// A callsign is a prefix of one to three letters and digits including at
+/// least one letter, such as `K`, `9A`, or `3DA`, then a single numeral,
+/// then a suffix of one to three letters. Each part that doesn't fit is
+/// reported with its own error: [`Error::InvalidPrefix`],
+/// [`Error::MissingNumeral`], or [`Error::InvalidSuffix`]. The callsign of a
+/// compound callsign, such as `PJ4/K1ABC`, is checked the same way, and
+/// [`Error::InvalidCallsign`] is returned if it's otherwise too long for a
+/// message.
+pub fn validate_callsign(callsign: &str) -> Result<(), Error> {
+    if callsign.contains('/') {
+        validate_compound(callsign)?;
+        let (before, after) =
+            callsign.split_once('/').ok_or(Error::InvalidCallsign)?;
+        let base = if encode_callsign(after).is_ok() && before.len() <= 3 {
+            after
+        } else {
+            before
+        };
+        return validate_callsign(base);
+    }
+
+    let bytes = callsign.as_bytes();
+    let numeral = bytes
+        .iter()
+        .rposition(u8::is_ascii_digit)
+        .ok_or(Error::MissingNumeral)?;
+    let (prefix, suffix) = (&bytes[..numeral], &bytes[numeral + 1..]);
+
+    if !(1..=3).contains(&prefix.len())
+        || !prefix.iter().any(u8::is_ascii_uppercase)
+        || !prefix
+            .iter()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit())
+    {
+        return Err(Error::InvalidPrefix);
+    }
+    if !(1..=3).contains(&suffix.len())
+        || !suffix.iter().all(u8::is_ascii_uppercase)
+    {
+        return Err(Error::InvalidSuffix);
+    }
+    encode_callsign(callsign).map(|_| ())
+}
+
+// Suffixes operators add to show how they're operating rather than where,
+// which a type 2 message can't carry in full.
+const OPERATING_SUFFIXES: [&str; 7] =
+    ["P", "M", "A", "MM", "AM", "QRP", "QRPP"];
+
+/// Normalizes a callsign as a user might enter it, writing it to `buffer`:
+/// surrounding whitespace is trimmed, letters are made upper case, and a
+/// suffix showing how the station is operating that a message can't carry,
+/// such as `/QRP` or `/MM`, is removed.
+///
+/// Single character suffixes such as `/P` are kept, so the callsign is sent
+/// as a type 2 message; see [`strip_suffix`] to send the callsign alone.
+/// Returns [`Error::InvalidCallsign`] if the result can't be encoded.
+pub fn normalize_callsign<'a>(
+    callsign: &str,
+    buffer: &'a mut [u8; 12],
+) -> Result<&'a str, Error> {
+    let trimmed = callsign.trim();
+    *buffer = pad(&[trimmed.as_bytes()]).ok_or(Error::InvalidCallsign)?;
+    buffer.make_ascii_uppercase();
+    let mut callsign = ascii(buffer).trim_end();
+
+    if let Some((base, suffix)) = callsign.rsplit_once('/') {
+        if suffix.len() > 1 && OPERATING_SUFFIXES.contains(&suffix) {
+            callsign = base;
+        }
+    }
+    if callsign.contains('/') {
+        validate_compound(callsign)?;
+    } else {
+        encode_callsign(callsign)?;
+    }
+    Ok(callsign)
+}
+
+/// Removes a suffix showing how the station is operating, such as `/P`,
+/// `/M`, or `/QRP`, from a callsign, so it can be sent in a type 1 message.
+/// Other suffixes, such as a call area like `/7`, and prefixes are kept.
+pub fn strip_suffix(callsign: &str) -> &str {
+    match callsign.rsplit_once('/') {
+        Some((base, suffix)) if OPERATING_SUFFIXES.contains(&suffix) => base,
+        _ => callsign,
+    }
+}
+
+/// Normalizes a grid square or locator as a user might enter it, such as
+/// ` fn42ax`, writing it to `buffer`: surrounding whitespace is trimmed and
+/// letters are made upper case. Returns [`Error::InvalidGrid`] if the result
+/// isn't a four character grid square or a six character locator.
+pub fn normalize_grid<'a>(
+    grid: &str,
+    buffer: &'a mut [u8; 6],
+) -> Result<&'a str, Error> {
+    let trimmed = grid.trim();
+    *buffer = pad(&[trimmed.as_bytes()]).ok_or(Error::InvalidGrid)?;
+    buffer.make_ascii_uppercase();
+    let grid = ascii(buffer).trim_end();
+    match grid.len() {
+        4 => encode_grid(grid).map(|_| grid),
+        6 => {
+            validate_locator(buffer)?;
+            Ok(ascii(buffer))
+        }
+        _ => Err(Error::InvalidGrid),
+    }
+}
+
+/// A callsign that can be encoded, standard or compound, checked when it's
+/// created so it can be kept in configuration and encoded without errors
+/// later.
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Callsign([u8; 12]);
+
+impl Callsign {
+    /// Creates a callsign from text, normalized as [`normalize_callsign`]
+    /// does.
+    pub fn new(callsign: &str) -> Result<Self, Error> {
+        let mut buffer = [0; 12];
+        let callsign = normalize_callsign(callsign, &mut buffer)?;
+        pad(&[callsign.as_bytes()])
+            .map(Callsign)
+            .ok_or(Error::InvalidCallsign)
+    }
+
+    /// The callsign.
+    pub fn as_str(&self) -> &str {
+        ascii(&self.0).trim_end()
+    }
+
+    /// Whether the callsign has a prefix or suffix, and so is sent in a type
+    /// 2 message.
+    pub fn is_compound(&self) -> bool {
+        self.0.contains(&b'/')
+    }
+
+    /// Encodes the messages to send from `grid` at `power`, see
+    /// [`encode_auto`].
+    pub fn encode(
+        &self,
+        grid: &Grid,
+        power: Power,
+    ) -> Result<([u8; 162], Option<[u8; 162]>), Error> {
+        encode_auto(self.as_str(), grid.as_str(), power.dbm())
+    }
+}
+
+/// A four character grid square or six character locator, checked when
+/// it's created.
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Grid([u8; 6]);
+
+impl Grid {
+    /// Creates a grid from text, normalized as [`normalize_grid`] does.
+    pub fn new(grid: &str) -> Result<Self, Error> {
+        let mut buffer = [0; 6];
+        let grid = normalize_grid(grid, &mut buffer)?;
+        pad(&[grid.as_bytes()]).map(Grid).ok_or(Error::InvalidGrid)
+    }
+
+    /// The grid square or locator.
+    pub fn as_str(&self) -> &str {
+        ascii(&self.0).trim_end()
+    }
+
+    /// Whether this is a six character locator, sent in a type 3 message.
+    pub fn is_locator(&self) -> bool {
+        self.0[4] != b' '
+    }
+
+    /// The four character grid square containing this one.
+    pub fn square(&self) -> &str {
+        ascii(&self.0[..4])
+    }
+}
+
+/// A power level in dBm that can be encoded: 0 to 60, ending in 0, 3, or 7.
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Power(u8);
+
+impl Power {
+    /// Creates a power level of `dbm` dBm.
+    pub fn new(dbm: u8) -> Result<Self, Error> {
+        encode_power(dbm)?;
+        Ok(Power(dbm))
+    }
+
+    /// The power level in dBm.
+    pub fn dbm(self) -> u8 {
+        self.0
+    }
+}
+
+impl core::str::FromStr for Callsign {
+    type Err = Error;
+
+    fn from_str(callsign: &str) -> Result<Self, Error> {
+        Callsign::new(callsign)
+    }
+}
+
+impl core::str::FromStr for Grid {
+    type Err = Error;
+
+    fn from_str(grid: &str) -> Result<Self, Error> {
+        Grid::new(grid)
+    }
+}
+
+impl core::str::FromStr for Power {
+    type Err = Error;
+
+    fn from_str(dbm: &str) -> Result<Self, Error> {
+        Power::new(dbm.trim().parse().map_err(|_| Error::InvalidPower)?)
+    }
+}
+
+impl core::fmt::Display for Callsign {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl core::fmt::Display for Grid {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// Callsigns and grids are serialized as strings, and power levels as
+// numbers, checked as they're deserialized.
+#[cfg(feature = "serde")]
+mod config_serde {
+    use super::{Callsign, Grid, Power};
+    use serde::de::Error as _;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    impl Serialize for Callsign {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_str(self.as_str())
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Callsign {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            let callsign = <&str>::deserialize(d)?;
+            Callsign::new(callsign)
+                .map_err(|_| D::Error::custom("invalid callsign"))
+        }
+    }
+
+    impl Serialize for Grid {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_str(self.as_str())
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Grid {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            let grid = <&str>::deserialize(d)?;
+            Grid::new(grid).map_err(|_| D::Error::custom("invalid grid"))
+        }
+    }
+
+    impl Serialize for Power {
+        fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_u8(self.0)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Power {
+        fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            let dbm = u8::deserialize(d)?;
+            Power::new(dbm).map_err(|_| D::Error::custom("invalid power"))
+        }
+    }
+}
+
 /// Encodes a callsign, a four character Maidenhead grid square, and a power
 /// level (in dBm) into 162 symbols each with a range of 0-3. These symbols
 /// may then be transmitting using 4 tone frequency shift keying. Each tone
 /// is separated by 1.46Hz and is transmitted for 0.683s at a time, for a total
 /// transmission time of 110.64s.
+///
+/// A compound callsign, with a prefix of up to three characters or a suffix
+/// of a single character or two digits such as `PJ4/K1ABC` or `K1ABC/7`, is
+/// sent as a type 2 message. There's no room for a grid square in a type 2
+/// message, so `grid` must be empty, otherwise [`Error::InvalidGrid`] is
+/// returned.
+///
+/// A six character locator, such as `FN42AX`, is sent as a type 3 message
+/// with the 15-bit hash of the callsign, standard or compound, in place of
+/// the callsign itself. Receivers show the callsign once they've heard it
+/// in a type 1 or type 2 message, see [`Message::resolve`].
 pub fn encode(
     callsign: &str,
     grid: &str,
     power: u8,
 ) -> Result<[u8; 162], Error> {
-    let callsign = encode_callsign(callsign)?;
-    let grid = encode_grid(grid)?;
-    let power = encode_power(power)?;
+    let transmission = if grid.len() == 6 {
+        Transmission::Type3 {
+            callsign,
+            locator: grid,
+            power,
+        }
+    } else if callsign.contains('/') {
+        if !grid.is_empty() {
+            return Err(Error::InvalidGrid);
+        }
+        Transmission::Type2 { callsign, power }
+    } else {
+        Transmission::Type1 {
+            callsign,
+            grid,
+            power,
+        }
+    };
+    transmission.encode()
+}
+
+/// Encodes the messages WSJT-X would send for a callsign, grid, and power
+/// level, choosing their types from the callsign and grid: the first message
+/// is always returned, and the second if the two should be alternated.
+///
+/// A standard callsign with a four character grid is a single type 1
+/// message, and a compound callsign without a grid a single type 2 message.
+/// A six character locator, or a compound callsign with a grid, is sent as
+/// the pair of messages [`encode_pair`] returns. A compound callsign with a
+/// four character grid is sent with the center of the grid square, the
+/// subsquare `LL`.
+pub fn encode_auto(
+    callsign: &str,
+    grid: &str,
+    power: u8,
+) -> Result<([u8; 162], Option<[u8; 162]>), Error> {
+    let compound = callsign.contains('/');
+    match grid.len() {
+        0 if compound => Ok((encode(callsign, "", power)?, None)),
+        4 if compound => {
+            let locator: [u8; 6] =
+                pad(&[grid.as_bytes(), b"LL"]).ok_or(Error::InvalidGrid)?;
+            let (first, second) =
+                encode_pair(callsign, ascii(&locator), power)?;
+            Ok((first, Some(second)))
+        }
+        6 => {
+            let (first, second) = encode_pair(callsign, grid, power)?;
+            Ok((first, Some(second)))
+        }
+        _ => Ok((encode(callsign, grid, power)?, None)),
+    }
+}
+
+/// A message to be transmitted, with the fields of each message type.
+///
+/// [`encode`] chooses the type from the callsign and grid it's given;
+/// building a `Transmission` instead states the type outright, so a
+/// callsign or grid that doesn't fit it is an error rather than a message
+/// of another type.
+///
+/// It's separate from [`Message`], which is what's decoded, holding the
+/// fields as received with the callsign of a type 3 message only as a
+/// hash. A `Transmission` borrows its fields as text, which is only checked
+/// when it's encoded, so [`Transmission::encode`] returns a `Result` rather
+/// than the symbols themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transmission<'a> {
+    /// A standard callsign, a four character grid, and a power level.
+    Type1 {
+        callsign: &'a str,
+        grid: &'a str,
+        power: u8,
+    },
+    /// A compound callsign, such as `PJ4/K1ABC` or `K1ABC/7`, and a power
+    /// level.
+    Type2 { callsign: &'a str, power: u8 },
+    /// The hash of a standard or compound callsign, a six character
+    /// locator, and a power level.
+    Type3 {
+        callsign: &'a str,
+        locator: &'a str,
+        power: u8,
+    },
+}
+
+impl Transmission<'_> {
+    /// Encodes the message into 162 symbols, see [`encode`].
+    pub fn encode(&self) -> Result<[u8; 162], Error> {
+        match *self {
+            Transmission::Type1 {
+                callsign,
+                grid,
+                power,
+            } => {
+                let callsign = encode_callsign(callsign)?;
+                let grid = encode_grid(grid)?;
+                let power = encode_power(power)?;
+                Ok(encode_fields(callsign, grid as u32, power as u32))
+            }
+            Transmission::Type2 { callsign, power } => {
+                let (prefix, callsign) = encode_prefix(callsign)?;
+                let power = encode_power(power)?;
+
+                // The prefix takes the grid's 15 bits, and its top bit is
+                // carried by the power, offset by 1 or 2 so it no longer
+                // ends in 0, 3, or 7.
+                let offset = prefix / 32768 + 1;
+                Ok(encode_fields(
+                    encode_callsign(callsign)?,
+                    prefix % 32768,
+                    power as u32 + offset,
+                ))
+            }
+            Transmission::Type3 {
+                callsign,
+                locator,
+                power,
+            } => encode_type3(callsign, locator, power),
+        }
+    }
+}
+
+/// Encodes the pair of messages WSJT-X alternates between to send a
+/// callsign along with a six character locator, such as `PJ4/K1ABC` in
+/// `FK52UD`: first the callsign, then the type 3 message with the locator.
+///
+/// A compound callsign is sent in a type 2 message and a standard callsign
+/// in a type 1 message with the first four characters of the locator.
+/// Transmitting the first before the second lets receivers resolve the
+/// hashed callsign of the type 3 message.
+pub fn encode_pair(
+    callsign: &str,
+    locator: &str,
+    power: u8,
+) -> Result<([u8; 162], [u8; 162]), Error> {
+    if locator.len() != 6 {
+        return Err(Error::InvalidGrid);
+    }
+    let second = encode(callsign, locator, power)?;
+    let first = if callsign.contains('/') {
+        encode(callsign, "", power)?
+    } else {
+        encode(callsign, &locator[..4], power)?
+    };
+    Ok((first, second))
+}
+
+// Encodes a type 3 message for `callsign`, checking that it's a standard
+// or compound callsign first.
+fn encode_type3(
+    callsign: &str,
+    locator: &str,
+    power: u8,
+) -> Result<[u8; 162], Error> {
+    let upper = uppercase::<12>(callsign).ok_or(Error::InvalidCallsign)?;
+    let callsign = ascii(&upper).trim_end();
+    if !callsign.contains('/') {
+        encode_callsign(callsign)?;
+    } else {
+        validate_compound(callsign)?;
+    }
+    encode_locator(hash::hash(callsign), locator, power)
+}
+
+// Encodes a type 3 message: the locator, rotated so its third character
+// lands where a callsign's digit does, in the callsign field, the hash of
+// the callsign in the grid field, and the power negated.
+fn encode_locator(
+    hash: u16,
+    locator: &str,
+    power: u8,
+) -> Result<[u8; 162], Error> {
+    let mut locator: [u8; 6] = uppercase(locator)
+        .filter(|_| locator.len() == 6)
+        .ok_or(Error::InvalidGrid)?;
+    validate_locator(&locator)?;
+    locator.rotate_left(1);
+
+    encode_power(power)?;
+    Ok(encode_fields(
+        encode_callsign(ascii(&locator))?,
+        hash as u32 & 0x7FFF,
+        63 - power as u32,
+    ))
+}
+
+// Copies `text` into an array padded with spaces and converted to upper
+// case, or returns `None` if it doesn't fit.
+fn uppercase<const N: usize>(text: &str) -> Option<[u8; N]> {
+    let mut upper: [u8; N] = pad(&[text.as_bytes()])?;
+    upper.make_ascii_uppercase();
+    Some(upper)
+}
+
+/// Encodes an arbitrary 50 bit payload into 162 symbols, applying only the
+/// convolutional code, interleaving, and sync vector of [`encode`]. Bits
+/// above the lowest 50 of `payload` are ignored.
+///
+/// The payload is transmitted from its most significant bit, so a standard
+/// message is the 28 bit callsign field, then the 15 bit grid field, then
+/// the 7 bit power field. Experimental and telemetry protocols can fill the
+/// bits however they like, though receivers will only show a message if
+/// the payload also unpacks as one.
+pub fn encode_payload(payload: u64) -> [u8; 162] {
+    encode_fields(
+        (payload >> 22) as u32 & 0xFFF_FFFF,
+        (payload >> 7) as u32 & 0x7FFF,
+        payload as u32 & 0x7F,
+    )
+}
 
-    let mut reg0 = ShiftRegister::new(0xF2D05351);
-    let mut reg1 = ShiftRegister::new(0xE4613C47);
+/// Encodes the packed fields of a message into 162 symbols: `n`, the 28 bit
+/// packed callsign, and `m`, the 22 bit packed grid and power, the grid
+/// times 128 plus the power plus 64. These are the `n` and `m` of the WSPR
+/// coding process, which telemetry schemes often compute themselves.
+///
+/// Neither field is checked, and only their lowest 28 and 22 bits are used.
+pub fn encode_raw(n: u32, m: u32) -> [u8; 162] {
+    encode_payload((n as u64 & 0xFFF_FFFF) << 22 | (m as u64 & 0x3F_FFFF))
+}
+
+// Encodes the 28 bit callsign field, 15 bit grid field, and 7 bit power
+// field of a message into symbols.
+fn encode_fields(callsign: u32, grid: u32, power: u32) -> [u8; 162] {
+    let mut reg0 = ShiftRegister::new(POLY0);
+    let mut reg1 = ShiftRegister::new(POLY1);
 
     let mut buffer = Buffer::new();
 
@@ -248,13 +1100,13 @@ pub fn encode(
     }
 
     for i in (0..15).rev() {
-        let bit = (grid as u32 >> i) & 0x01;
+        let bit = (grid >> i) & 0x01;
         buffer.push(reg0.shift(bit));
         buffer.push(reg1.shift(bit));
     }
 
     for i in (0..7).rev() {
-        let bit = (power as u32 >> i) & 0x01;
+        let bit = (power >> i) & 0x01;
         buffer.push(reg0.shift(bit));
         buffer.push(reg1.shift(bit));
     }
@@ -267,12 +1119,620 @@ pub fn encode(
 
     buffer.interleave();
     buffer.sync();
-    Ok(buffer.release())
+    buffer.release()
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// A decoded WSPR message consisting of a callsign, a Maidenhead grid
+/// square, and a power level (in dBm).
+///
+/// Type 1 messages carry a standard callsign and a four character grid.
+/// Type 2 messages carry a compound callsign, with a prefix or suffix such as
+/// `PJ4/K1ABC` or `K1ABC/7`, and no grid. Type 3 messages carry a six
+/// character grid and a hash of the callsign, which is shown as `<...>` until
+/// it's resolved with [`Message::resolve`].
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Message {
+    callsign: [u8; 12],
+    grid: [u8; 6],
+    power: u8,
+    hash: Option<u16>,
+}
+
+impl Message {
+    // Creates a message from its text, validating it the way the decoder
+    // would have: a message with a hash must be a type 3 message, and a
+    // message without a grid must be a type 2 message.
+    fn from_parts(
+        callsign: &str,
+        grid: &str,
+        power: u8,
+        hash: Option<u16>,
+    ) -> Result<Self, Error> {
+        encode_power(power)?;
+        if let Some(hash) = hash {
+            let grid =
+                grid.as_bytes().try_into().map_err(|_| Error::InvalidGrid)?;
+            validate_locator(grid)?;
+            let bracketed =
+                callsign.starts_with('<') && callsign.ends_with('>');
+            return Ok(Message {
+                callsign: pad(&[callsign.as_bytes()])
+                    .filter(|_| bracketed && hash < 0x8000)
+                    .ok_or(Error::InvalidCallsign)?,
+                grid: *grid,
+                power,
+                hash: Some(hash),
+            });
+        }
+
+        if grid.is_empty() {
+            validate_compound(callsign)?;
+            return Ok(Message {
+                callsign: pad(&[callsign.as_bytes()])
+                    .ok_or(Error::InvalidCallsign)?,
+                grid: [b' '; 6],
+                power,
+                hash: None,
+            });
+        }
+
+        let callsign = decode_callsign(encode_callsign(callsign)?)?;
+        let grid = decode_grid(encode_grid(grid)?)?;
+        Ok(Message {
+            callsign: pad(&[ascii(&callsign).trim().as_bytes()])
+                .ok_or(Error::InvalidCallsign)?,
+            grid: pad(&[&grid]).ok_or(Error::InvalidGrid)?,
+            power,
+            hash: None,
+        })
+    }
+
+    /// The callsign, with any padding removed.
+    pub fn callsign(&self) -> &str {
+        ascii(&self.callsign).trim()
+    }
+
+    /// The Maidenhead grid square: four characters for a type 1 message, six
+    /// for a type 3 message, or an empty string for a type 2 message.
+    pub fn grid(&self) -> &str {
+        ascii(&self.grid).trim()
+    }
+
+    /// The power level in dBm.
+    pub fn power(&self) -> u8 {
+        self.power
+    }
+
+    /// The 15-bit hash of the callsign carried by a type 3 message, or
+    /// `None` for other types.
+    pub fn hash(&self) -> Option<u16> {
+        self.hash
+    }
+
+    /// Connects type 3 messages with the callsigns they were sent by.
+    ///
+    /// The callsign of a type 1 or type 2 message is recorded in `table`.
+    /// If the hash of a type 3 message is found in `table`, the message's
+    /// callsign becomes the callsign found, in angle brackets, such as
+    /// `<PJ4/K1ABC>`. This mirrors wsprd, which keeps a table of every
+    /// callsign it has decoded.
+    pub fn resolve<T: hash::CallsignTable + ?Sized>(&mut self, table: &mut T) {
+        match self.hash {
+            None => table.insert(hash::hash(self.callsign()), self.callsign()),
+            Some(hash) => {
+                if let Some(callsign) = table
+                    .get(hash)
+                    .and_then(|c| pad(&[b"<", c.as_bytes(), b">"]))
+                {
+                    self.callsign = callsign;
+                }
+            }
+        }
+    }
+
+    /// Encodes the message into 162 symbols, see [`encode`]. A type 3
+    /// message is encoded with the hash it carries, so a decoded message
+    /// encodes to the symbols it was decoded from.
+    pub fn encode(&self) -> Result<[u8; 162], Error> {
+        match self.hash {
+            Some(hash) => encode_locator(hash, self.grid(), self.power),
+            None => encode(self.callsign(), self.grid(), self.power),
+        }
+    }
+}
+
+/// Parses a message from its text: a callsign, a grid, and a power level
+/// separated by whitespace, such as `K1ABC FN42 37`, or a compound callsign
+/// and a power level, such as `PJ4/K1ABC 37`, in upper or lower case.
+///
+/// A callsign with a six character locator is parsed as a type 3 message,
+/// carrying the callsign's hash, with the callsign shown in angle brackets.
+impl core::str::FromStr for Message {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self, Error> {
+        let mut fields = text.split_whitespace();
+        let callsign = fields.next().ok_or(Error::InvalidCallsign)?;
+        let mut grid = fields.next().ok_or(Error::InvalidPower)?;
+        let power = match fields.next() {
+            Some(power) => power,
+            None => core::mem::take(&mut grid),
+        };
+        if fields.next().is_some() {
+            return Err(Error::InvalidPower);
+        }
+        let power = power.parse().map_err(|_| Error::InvalidPower)?;
+
+        let callsign =
+            uppercase::<12>(callsign).ok_or(Error::InvalidCallsign)?;
+        let callsign = ascii(&callsign).trim_end();
+        let grid = uppercase::<6>(grid).ok_or(Error::InvalidGrid)?;
+        let grid = ascii(&grid).trim_end();
+        if grid.len() < 6 {
+            return Message::from_parts(callsign, grid, power, None);
+        }
+
+        if !callsign.contains('/') {
+            encode_callsign(callsign)?;
+        } else {
+            validate_compound(callsign)?;
+        }
+        let bracketed: [u8; 12] = pad(&[b"<", callsign.as_bytes(), b">"])
+            .ok_or(Error::InvalidCallsign)?;
+        Message::from_parts(
+            ascii(&bracketed).trim_end(),
+            grid,
+            power,
+            Some(hash::hash(callsign)),
+        )
+    }
+}
+
+// Messages only ever hold ASCII, so the conversion can't fail.
+fn ascii(bytes: &[u8]) -> &str {
+    core::str::from_utf8(bytes).unwrap_or_default()
+}
+
+// Concatenates `parts` into an array padded with spaces, or returns `None`
+// if they don't fit.
+fn pad<const N: usize>(parts: &[&[u8]]) -> Option<[u8; N]> {
+    let mut padded = [b' '; N];
+    let mut length = 0;
+    for part in parts {
+        padded
+            .get_mut(length..length + part.len())?
+            .copy_from_slice(part);
+        length += part.len();
+    }
+    Some(padded)
+}
+
+// Writes the next items of `iter` to the start of `out`, returning how many
+// were written, fewer than `out.len()` only if `iter` ran out.
+fn fill<I: Iterator>(iter: I, out: &mut [I::Item]) -> usize {
+    let mut count = 0;
+    for (slot, item) in out.iter_mut().zip(iter) {
+        *slot = item;
+        count += 1;
+    }
+    count
+}
+
+// Messages are serialized with their callsign and grid as strings, rather
+// than the padded arrays they're stored as.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "Message")]
+struct MessageFields<'a> {
+    callsign: &'a str,
+    grid: &'a str,
+    power: u8,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hash: Option<u16>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Message {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        MessageFields {
+            callsign: self.callsign(),
+            grid: self.grid(),
+            power: self.power,
+            hash: self.hash,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Message {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        use serde::de::Error as _;
+
+        let fields = MessageFields::deserialize(deserializer)?;
+        Message::from_parts(
+            fields.callsign,
+            fields.grid,
+            fields.power,
+            fields.hash,
+        )
+        .map_err(|error| match error {
+            Error::InvalidGrid => D::Error::custom("invalid grid"),
+            Error::InvalidPower => D::Error::custom("invalid power"),
+            _ => D::Error::custom("invalid callsign"),
+        })
+    }
+}
+
+/// Decodes 162 symbols, each with a range of 0-3, back into the callsign,
+/// grid square, and power level they were encoded from. This is the inverse
+/// of [`encode`]: the sync vector is removed, the symbols are deinterleaved,
+/// the convolutional code is decoded, and the resulting 50 bits are
+/// unpacked.
+///
+/// The convolutional code is decoded with a [`decoder::Fano`] decoder, so a
+/// handful of corrupted symbols can be tolerated. If the decoder gives up,
+/// [`Error::DecodeFailed`] is returned.
+pub fn decode(symbols: &[u8; 162]) -> Result<Message, Error> {
+    decode_with(symbols, &decoder::Fano::default())
+}
+
+/// Decodes 162 symbols as [`decode`] does, using the given decoder for the
+/// convolutional code.
+pub fn decode_with<D: decoder::ChannelDecoder>(
+    symbols: &[u8; 162],
+    decoder: &D,
+) -> Result<Message, Error> {
+    let mut buffer = Buffer::load(symbols);
+    buffer.desync()?;
+    decode_buffer(buffer, decoder, &mut D::Scratch::default())
+        .map(|(message, _)| message)
+}
+
+/// Checks that `symbols` are exactly the symbols [`encode`] produces for the
+/// given callsign, grid square, and power level, so firmware can confirm
+/// what it's about to transmit hasn't been corrupted. An error is returned
+/// if the message itself can't be encoded.
+pub fn verify(
+    callsign: &str,
+    grid: &str,
+    power: u8,
+    symbols: &[u8; 162],
+) -> Result<bool, Error> {
+    Ok(encode(callsign, grid, power)? == *symbols)
+}
+
+/// Checks that `symbols` decode, with [`decode`], to the given callsign,
+/// grid square, and power level. Unlike [`verify`], this checks the symbols
+/// against an independent implementation of the message format: the
+/// decoder. An error is returned if the message itself isn't valid.
+pub fn verify_decode(
+    callsign: &str,
+    grid: &str,
+    power: u8,
+    symbols: &[u8; 162],
+) -> Result<bool, Error> {
+    let expected = Message::from_parts(callsign, grid, power, None)?;
+    Ok(decode(symbols).is_ok_and(|message| message == expected))
+}
+
+/// Packs 162 symbols, each with a range of 0-3, into 41 bytes for compact
+/// storage. Each byte holds four symbols, the first in the two most
+/// significant bits; the final byte holds only two.
+pub fn pack_symbols(symbols: &[u8; 162]) -> Result<[u8; 41], Error> {
+    let mut packed = [0u8; 41];
+    for (i, &symbol) in symbols.iter().enumerate() {
+        if symbol > 3 {
+            return Err(Error::InvalidSymbol);
+        }
+        packed[i / 4] |= symbol << (6 - 2 * (i % 4));
+    }
+    Ok(packed)
+}
+
+/// Decodes symbols packed by [`pack_symbols`], as [`decode`] does, without
+/// unpacking them first.
+pub fn decode_packed(packed: &[u8; 41]) -> Result<Message, Error> {
+    decode_packed_with(packed, &decoder::Fano::default())
+}
+
+/// Decodes packed symbols as [`decode_packed`] does, using the given
+/// decoder for the convolutional code.
+pub fn decode_packed_with<D: decoder::ChannelDecoder>(
+    packed: &[u8; 41],
+    decoder: &D,
+) -> Result<Message, Error> {
+    decode_buffer(
+        Buffer::load_packed(packed),
+        decoder,
+        &mut D::Scratch::default(),
+    )
+    .map(|(message, _)| message)
+}
+
+/// Decodes 162 soft symbols into a message. Each soft symbol is the
+/// log-likelihood that the data bit of the corresponding symbol (the most
+/// significant bit of the 0-3 symbol value) is a 1: large positive values
+/// are a confident 1, large negative values a confident 0, and values near
+/// zero carry little information.
+///
+/// Soft symbols typically allow messages to be decoded at several dB lower
+/// signal to noise ratios than hard symbols.
+pub fn decode_soft(llrs: &[i8; 162]) -> Result<Message, Error> {
+    decode_soft_with(llrs, &decoder::Fano::default())
+}
+
+/// Decodes 162 soft symbols as [`decode_soft`] does, using the given decoder
+/// for the convolutional code.
+pub fn decode_soft_with<D: decoder::ChannelDecoder>(
+    llrs: &[i8; 162],
+    decoder: &D,
+) -> Result<Message, Error> {
+    decode_soft_in(llrs, decoder, &mut D::Scratch::default())
+}
+
+/// Decodes 162 soft symbols as [`decode_soft_with`] does, using `scratch` as
+/// the decoder's working memory. Besides `scratch`, decoding needs only a few
+/// hundred bytes of stack.
+pub fn decode_soft_in<D: decoder::ChannelDecoder>(
+    llrs: &[i8; 162],
+    decoder: &D,
+    scratch: &mut D::Scratch,
+) -> Result<Message, Error> {
+    decode_buffer(load_soft(llrs), decoder, scratch).map(|(message, _)| message)
+}
+
+/// Decodes 162 soft symbols as [`decode_soft`] does, also returning the
+/// result of the sequential decoder, whose path metric measures how well the
+/// message matched the soft symbols.
+pub fn decode_soft_metric(
+    llrs: &[i8; 162],
+) -> Result<(Message, decoder::Decoded), Error> {
+    decode_soft_metric_with(llrs, &decoder::Fano::default())
+}
+
+/// Decodes 162 soft symbols as [`decode_soft_metric`] does, using the given
+/// decoder for the convolutional code.
+pub fn decode_soft_metric_with<D: decoder::ChannelDecoder>(
+    llrs: &[i8; 162],
+    decoder: &D,
+) -> Result<(Message, decoder::Decoded), Error> {
+    decode_buffer(load_soft(llrs), decoder, &mut D::Scratch::default())
+}
+
+// Loads soft symbols into a buffer, shifting the log-likelihoods into the
+// 0-255 range expected by the decoders.
+fn load_soft(llrs: &[i8; 162]) -> Buffer {
+    let mut bits = [0u8; 162];
+    for (bit, &llr) in bits.iter_mut().zip(llrs.iter()) {
+        *bit = (llr as u8) ^ 0x80;
+    }
+    Buffer::load(&bits)
+}
+
+/// Counts the soft symbols (see [`decode_soft`]) whose sign disagrees with
+/// the data bit of the corresponding symbol of `symbols`, usually the
+/// symbols of the decoded message. These are the errors a decoder working
+/// from hard decisions would have had to correct.
+pub fn hard_errors(llrs: &[i8; 162], symbols: &[u8; 162]) -> u32 {
+    llrs.iter()
+        .zip(symbols)
+        .filter(|&(&llr, &symbol)| (llr > 0) != (symbol >> 1 == 1))
+        .count() as u32
+}
+
+// The lowest score `decode_deep` accepts. For soft symbols of noise alone,
+// scores have a standard deviation of about 0.1, so thousands of hypotheses
+// can be tested with little chance of any reaching this.
+const DEEP_THRESHOLD: f32 = 0.55;
+
+/// Decodes 162 soft symbols (see [`decode_soft`]) by testing them against
+/// messages built from a list of known callsigns and grids, instead of
+/// decoding the convolutional code. This mirrors the deep search of wsprd.
+///
+/// Each callsign and grid in `known` is tried with every power level, and
+/// the message whose symbols best match the soft symbols is returned. If no
+/// message matches well enough, [`Error::DecodeFailed`] is returned.
+///
+/// Because the whole message is tested at once, a deep search can find
+/// transmissions a few dB weaker than [`decode_soft`], though only from
+/// stations in `known`. It's intended as a second attempt once decoding has
+/// failed; the more hypotheses there are, the greater the chance of a false
+/// decode.
+pub fn decode_deep(
+    llrs: &[i8; 162],
+    known: &[(&str, &str)],
+) -> Result<Message, Error> {
+    let total: i32 = llrs.iter().map(|&llr| (llr as i32).abs()).sum();
+    if total == 0 {
+        return Err(Error::DecodeFailed);
+    }
+
+    let mut best = None;
+    let mut best_score = DEEP_THRESHOLD;
+    for &(callsign, grid) in known {
+        for power in (0..=60).filter(|&p| encode_power(p).is_ok()) {
+            let Ok(symbols) = encode(callsign, grid, power) else {
+                break;
+            };
+            let agreement: i32 = symbols
+                .iter()
+                .zip(llrs)
+                .map(|(&symbol, &llr)| {
+                    if symbol >> 1 == 1 {
+                        llr as i32
+                    } else {
+                        -(llr as i32)
+                    }
+                })
+                .sum();
+
+            let score = agreement as f32 / total as f32;
+            if score >= best_score {
+                best_score = score;
+                best = Some((callsign, grid, power));
+            }
+        }
+    }
+
+    let (callsign, grid, power) = best.ok_or(Error::DecodeFailed)?;
+    Message::from_parts(callsign, grid, power, None)
+}
+
+/// Converts the magnitudes (or powers) of the four tones in each symbol
+/// period into soft symbols suitable for [`decode_soft`].
+///
+/// The sync bit of each symbol is known, leaving a choice between two tones:
+/// the soft symbol is the difference between them, normalized so that a
+/// typical soft symbol has a magnitude of around 40.
+pub fn soft_symbols(magnitudes: &[[f32; 4]; 162]) -> [i8; 162] {
+    let mut soft = [0f32; 162];
+    let mut total = 0.0;
+    for ((soft, tones), &sync) in soft.iter_mut().zip(magnitudes).zip(&SYNC) {
+        let sync = sync as usize;
+        *soft = tones[sync + 2] - tones[sync];
+        total += soft.abs();
+    }
+
+    let mut llrs = [0i8; 162];
+    if total > 0.0 {
+        let scale = 40.0 * 162.0 / total;
+        for (llr, soft) in llrs.iter_mut().zip(soft) {
+            *llr = (soft * scale).clamp(-127.0, 127.0) as i8;
+        }
+    }
+    llrs
+}
+
+/// Converts the magnitudes (or powers) of the four tones in each symbol
+/// period into hard symbols, by picking the strongest tone.
+pub fn hard_symbols(magnitudes: &[[f32; 4]; 162]) -> [u8; 162] {
+    let mut symbols = [0u8; 162];
+    for (symbol, tones) in symbols.iter_mut().zip(magnitudes) {
+        for (tone, &magnitude) in tones.iter().enumerate() {
+            if magnitude > tones[*symbol as usize] {
+                *symbol = tone as u8;
+            }
+        }
+    }
+    symbols
+}
+
+// Deinterleaves and decodes a buffer of soft bits.
+fn decode_buffer<D: decoder::ChannelDecoder>(
+    mut buffer: Buffer,
+    decoder: &D,
+    scratch: &mut D::Scratch,
+) -> Result<(Message, decoder::Decoded), Error> {
+    buffer.deinterleave();
+    let decoded = decoder.decode_in(&buffer.release(), scratch)?;
+    Ok((unpack(decoded.data)?, decoded))
+}
+
+// Unpacks the 50 message bits into a message.
+//
+// The 7 bit power field also identifies the type of message. Powers ending
+// in 0, 3, or 7 are type 1 messages. Other values up to 62 are type 2
+// messages, whose power is offset by 1-3 to extend the 15 bit grid field
+// to the 17 bits needed for a prefix or suffix. Negative values are type 3
+// messages, which carry a six character locator in the callsign field and
+// the hash of the callsign in the grid field.
+fn unpack(data: u64) -> Result<Message, Error> {
+    let n = decode_callsign((data >> 22) as u32)?;
+    let callsign = ascii(&n).trim();
+    let m = (data >> 7) as u32 & 0x7FFF;
+    let p = data as u8 & 0x7F;
+
+    let Some(power) = p.checked_sub(64) else {
+        // The locator is rotated so its third character, always a digit,
+        // lands where a callsign's digit does.
+        let locator = [n[5], n[0], n[1], n[2], n[3], n[4]];
+        validate_locator(&locator)?;
+        return Ok(Message {
+            callsign: pad(&[b"<...>"]).ok_or(Error::InvalidCallsign)?,
+            grid: locator,
+            power: decode_power(63 - p + 64)?,
+            hash: Some(m as u16),
+        });
+    };
+    if power > 62 {
+        return Err(Error::InvalidPower);
+    }
+
+    match power % 10 {
+        0 | 3 | 7 => Ok(Message {
+            callsign: pad(&[callsign.as_bytes()])
+                .ok_or(Error::InvalidCallsign)?,
+            grid: pad(&[&decode_grid(m as u16)?]).ok_or(Error::InvalidGrid)?,
+            power: decode_power(p)?,
+            hash: None,
+        }),
+        rem => {
+            let offset = match rem {
+                1 | 4 | 8 => 1,
+                2 | 5 | 9 => 2,
+                _ => 3,
+            };
+            Ok(Message {
+                callsign: decode_prefix(m + 32768 * (offset - 1), callsign)?,
+                grid: [b' '; 6],
+                power: decode_power(p - offset as u8)?,
+                hash: None,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interleave() {
+        let mut values = [0u8; 162];
+        for (i, value) in values.iter_mut().enumerate() {
+            *value = i as u8;
+        }
+
+        let deinterleaved = deinterleave(&values);
+        assert_eq!(&deinterleaved[..4], &[0, 128, 64, 32]);
+        assert_eq!(interleave(&deinterleaved), values);
+        assert_eq!(deinterleave(&interleave(&values)), values);
+    }
+
+    #[test]
+    fn test_sync() {
+        let symbols = encode("K1A", "FN34", 33).unwrap();
+        let bits = remove_sync(&symbols).unwrap();
+        assert!(bits.iter().all(|&bit| bit <= 1));
+        assert_eq!(apply_sync(&bits), symbols);
+        assert_eq!(apply_sync(&[0; 162]), SYNC);
+
+        assert_eq!(remove_sync(&[4; 162]), Err(Error::InvalidSymbol));
+    }
+
+    #[test]
+    fn test_validate_symbols() {
+        let mut symbols = encode("K1A", "FN34", 33).unwrap();
+        assert_eq!(validate_symbols(&symbols), Ok(1.0));
+
+        symbols[0] ^= 0x01;
+        assert_eq!(validate_symbols(&symbols), Ok(160.0 / 162.0));
+
+        for symbol in symbols.iter_mut() {
+            *symbol ^= 0x01;
+        }
+        assert_eq!(validate_symbols(&symbols), Ok(-160.0 / 162.0));
+
+        symbols[0] = 4;
+        assert_eq!(validate_symbols(&symbols), Err(Error::InvalidSymbol));
+    }
 
     #[test]
     fn test_encode_callsign() {
@@ -307,6 +1767,637 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_decode_callsign() {
+        assert_eq!(
+            decode_callsign(encode_callsign("K1A").unwrap()),
+            Ok(*b" K1A  ")
+        );
+        assert_eq!(decode_callsign(143706369), Ok(*b"KA1BCD"));
+        assert_eq!(decode_callsign(1 << 28), Err(Error::InvalidCallsign));
+    }
+
+    #[test]
+    fn test_decode_grid() {
+        assert_eq!(decode_grid(32220), Ok(*b"AA00"));
+        assert_eq!(decode_grid(179), Ok(*b"RR99"));
+        assert_eq!(decode_grid(32400), Err(Error::InvalidGrid));
+    }
+
+    #[test]
+    fn test_decode_prefix() {
+        assert_eq!(decode_prefix(34932, "K1ABC"), Ok(*b"PJ4/K1ABC   "));
+        assert_eq!(decode_prefix(50652, "K1ABC"), Err(Error::InvalidCallsign));
+        assert_eq!(decode_prefix(60007, "K1ABC"), Ok(*b"K1ABC/7     "));
+        assert_eq!(decode_prefix(60025, "K1ABC"), Ok(*b"K1ABC/P     "));
+        assert_eq!(decode_prefix(60038, "K1ABC"), Ok(*b"K1ABC/12    "));
+        assert_eq!(decode_prefix(60126, "K1ABC"), Err(Error::InvalidCallsign));
+    }
+
+    #[test]
+    fn test_encode_prefix() {
+        assert_eq!(encode_prefix("PJ4/K1ABC"), Ok((34932, "K1ABC")));
+        assert_eq!(encode_prefix("K1ABC/7"), Ok((60007, "K1ABC")));
+        assert_eq!(encode_prefix("K1ABC/P"), Ok((60025, "K1ABC")));
+        assert_eq!(encode_prefix("K1ABC/12"), Ok((60038, "K1ABC")));
+        assert_eq!(encode_prefix("K1ABC/05"), Err(Error::InvalidCallsign));
+        assert_eq!(encode_prefix("K1ABC/PJ4"), Err(Error::InvalidCallsign));
+        assert_eq!(encode_prefix("K1ABC"), Err(Error::InvalidCallsign));
+    }
+
+    #[test]
+    fn test_encode_type2() {
+        for callsign in
+            ["PJ4/K1ABC", "F/G1ABC", "K1ABC/7", "K1ABC/P", "K1ABC/12"]
+        {
+            for power in [0, 37, 60] {
+                let symbols = encode(callsign, "", power).unwrap();
+                let message = decode(&symbols).unwrap();
+                assert_eq!(message.callsign(), callsign);
+                assert_eq!(message.grid(), "");
+                assert_eq!(message.power(), power);
+            }
+        }
+
+        assert_eq!(encode("PJ4/K1ABC", "FK52", 37), Err(Error::InvalidGrid));
+        assert_eq!(encode("PJ4/K1ABC", "", 38), Err(Error::InvalidPower));
+        assert_eq!(encode("PJ4/K1ABC/P", "", 37), Err(Error::InvalidCallsign));
+    }
+
+    #[test]
+    fn test_encode_type3() {
+        for callsign in ["K1ABC", "PJ4/K1ABC", "K1ABC/7"] {
+            for power in [0, 37, 60] {
+                let symbols = encode(callsign, "FK52UD", power).unwrap();
+                let message = decode(&symbols).unwrap();
+                assert_eq!(message.callsign(), "<...>");
+                assert_eq!(message.grid(), "FK52UD");
+                assert_eq!(message.power(), power);
+                assert_eq!(message.hash(), Some(hash::hash(callsign)));
+            }
+        }
+        assert_eq!(
+            encode("k1abc", "fn42ax", 37),
+            encode("K1ABC", "FN42AX", 37)
+        );
+
+        assert_eq!(encode("K1ABC", "FN42AZ", 37), Err(Error::InvalidGrid));
+        assert_eq!(encode("K1ABC", "FN42A", 37), Err(Error::InvalidGrid));
+        assert_eq!(encode("K1ABC", "FN42AX", 38), Err(Error::InvalidPower));
+        assert_eq!(
+            encode("K1ABC/PJ4", "FN42AX", 37),
+            Err(Error::InvalidCallsign)
+        );
+        assert_eq!(encode("ABCDEF", "FN42AX", 37), Err(Error::InvalidCallsign));
+    }
+
+    #[test]
+    fn test_transmission() {
+        let type1 = Transmission::Type1 {
+            callsign: "K1ABC",
+            grid: "FN42",
+            power: 37,
+        };
+        assert_eq!(type1.encode(), encode("K1ABC", "FN42", 37));
+        let type2 = Transmission::Type2 {
+            callsign: "PJ4/K1ABC",
+            power: 37,
+        };
+        assert_eq!(type2.encode(), encode("PJ4/K1ABC", "", 37));
+        let type3 = Transmission::Type3 {
+            callsign: "PJ4/K1ABC",
+            locator: "FK52UD",
+            power: 37,
+        };
+        assert_eq!(type3.encode(), encode("PJ4/K1ABC", "FK52UD", 37));
+
+        // Fields that don't fit the type are errors.
+        let type1 = Transmission::Type1 {
+            callsign: "PJ4/K1ABC",
+            grid: "FK52",
+            power: 37,
+        };
+        assert_eq!(type1.encode(), Err(Error::InvalidCallsign));
+        let type1 = Transmission::Type1 {
+            callsign: "K1ABC",
+            grid: "FK52UD",
+            power: 37,
+        };
+        assert_eq!(type1.encode(), Err(Error::InvalidGrid));
+        let type2 = Transmission::Type2 {
+            callsign: "K1ABC",
+            power: 37,
+        };
+        assert_eq!(type2.encode(), Err(Error::InvalidCallsign));
+        let type3 = Transmission::Type3 {
+            callsign: "K1ABC",
+            locator: "FK52",
+            power: 37,
+        };
+        assert_eq!(type3.encode(), Err(Error::InvalidGrid));
+    }
+
+    #[test]
+    fn test_encode_auto() {
+        assert_eq!(
+            encode_auto("K1ABC", "FN42", 37),
+            Ok((encode("K1ABC", "FN42", 37).unwrap(), None))
+        );
+        assert_eq!(
+            encode_auto("PJ4/K1ABC", "", 37),
+            Ok((encode("PJ4/K1ABC", "", 37).unwrap(), None))
+        );
+        for (callsign, grid, locator) in [
+            ("K1ABC", "FN42AX", "FN42AX"),
+            ("PJ4/K1ABC", "FK52UD", "FK52UD"),
+            ("PJ4/K1ABC", "FK52", "FK52LL"),
+        ] {
+            let (first, second) = encode_pair(callsign, locator, 37).unwrap();
+            assert_eq!(
+                encode_auto(callsign, grid, 37),
+                Ok((first, Some(second)))
+            );
+        }
+
+        assert_eq!(encode_auto("K1ABC", "", 37), Err(Error::InvalidGrid));
+        assert_eq!(encode_auto("K1ABC", "FN4", 37), Err(Error::InvalidGrid));
+        assert_eq!(
+            encode_auto("PJ4/K1ABC", "FK5", 37),
+            Err(Error::InvalidGrid)
+        );
+        assert_eq!(
+            encode_auto("PJ4/K1ABC", "FK52", 38),
+            Err(Error::InvalidPower)
+        );
+    }
+
+    #[test]
+    fn test_message_from_str() {
+        let message: Message = "K1ABC FN42 37".parse().unwrap();
+        assert_eq!(message.callsign(), "K1ABC");
+        assert_eq!(message.grid(), "FN42");
+        assert_eq!(message.power(), 37);
+        assert_eq!(message.encode(), encode("K1ABC", "FN42", 37));
+        assert_eq!("  k1abc\tfn42   37\r\n".parse(), Ok(message));
+
+        let message: Message = "PJ4/K1ABC 37".parse().unwrap();
+        assert_eq!(message.callsign(), "PJ4/K1ABC");
+        assert_eq!(message.grid(), "");
+        assert_eq!(message.encode(), encode("PJ4/K1ABC", "", 37));
+
+        let message: Message = "PJ4/K1ABC FK52UD 37".parse().unwrap();
+        assert_eq!(message.callsign(), "<PJ4/K1ABC>");
+        assert_eq!(message.grid(), "FK52UD");
+        assert_eq!(message.hash(), Some(hash::hash("PJ4/K1ABC")));
+        assert_eq!(message.encode(), encode("PJ4/K1ABC", "FK52UD", 37));
+
+        // Decoded messages encode to the symbols they were decoded from,
+        // even with an unresolved hash.
+        for (callsign, grid) in [("G1ABC", "IO83"), ("K1ABC", "FN42AX")] {
+            let symbols = encode(callsign, grid, 30).unwrap();
+            assert_eq!(decode(&symbols).unwrap().encode(), Ok(symbols));
+        }
+
+        assert_eq!("".parse::<Message>(), Err(Error::InvalidCallsign));
+        assert_eq!("K1ABC".parse::<Message>(), Err(Error::InvalidPower));
+        assert_eq!("K1ABC FN42".parse::<Message>(), Err(Error::InvalidPower));
+        assert_eq!(
+            "K1ABC FN42 37 1".parse::<Message>(),
+            Err(Error::InvalidPower)
+        );
+        assert_eq!("K1ABC 37".parse::<Message>(), Err(Error::InvalidCallsign));
+        assert_eq!("K1ABC FN4Z 37".parse::<Message>(), Err(Error::InvalidGrid));
+        assert_eq!(
+            "K1ABC/PJ4 FK52UD 37".parse::<Message>(),
+            Err(Error::InvalidCallsign)
+        );
+    }
+
+    #[test]
+    fn test_encode_payload() {
+        let callsign = encode_callsign("K1ABC").unwrap() as u64;
+        let grid = encode_grid("FN42").unwrap() as u64;
+        let payload = callsign << 22 | grid << 7 | (37 + 64);
+        assert_eq!(Ok(encode_payload(payload)), encode("K1ABC", "FN42", 37));
+        assert_eq!(
+            encode_payload(payload | 0xF << 50),
+            encode_payload(payload)
+        );
+
+        // Payloads that aren't messages still decode to the same bits.
+        for payload in [0, 0x3_FFFF_FFFF_FFFF, 0x1_2345_6789_ABCD] {
+            let mut buffer = Buffer::load(&encode_payload(payload));
+            buffer.desync().unwrap();
+            buffer.deinterleave();
+            let decoded =
+                decoder::Fano::default().decode(&buffer.release()).unwrap();
+            assert_eq!(decoded.data, payload);
+        }
+    }
+
+    #[test]
+    fn test_encode_raw() {
+        let n = encode_callsign("K1ABC").unwrap();
+        let m = encode_grid("FN42").unwrap() as u32 * 128 + 37 + 64;
+        assert_eq!(Ok(encode_raw(n, m)), encode("K1ABC", "FN42", 37));
+        assert_eq!(encode_raw(n | 0xF << 28, m | 0xF << 22), encode_raw(n, m));
+        assert_eq!(
+            encode_raw(n, m),
+            encode_payload((n as u64) << 22 | m as u64)
+        );
+    }
+
+    #[test]
+    fn test_unpack_fields() {
+        let mut callsign = [0; 6];
+        let mut grid = [0; 4];
+        for (expected, locator, power) in [
+            ("K1ABC", "FN42", 37),
+            ("G1ABC", "IO83", 0),
+            ("KA1BCD", "AA00", 60),
+        ] {
+            let n = encode_callsign(expected).unwrap();
+            let m =
+                encode_grid(locator).unwrap() as u32 * 128 + power as u32 + 64;
+            assert_eq!(unpack_callsign(n, &mut callsign), Ok(expected));
+            assert_eq!(unpack_grid_power(m, &mut grid), Ok((locator, power)));
+        }
+
+        assert_eq!(
+            unpack_callsign(0xFFF_FFFF, &mut callsign),
+            Err(Error::InvalidCallsign)
+        );
+        assert_eq!(
+            unpack_grid_power(180 * 180 * 128 + 64, &mut grid),
+            Err(Error::InvalidGrid)
+        );
+        assert_eq!(
+            unpack_grid_power(1 << 22 | 64, &mut grid),
+            Err(Error::InvalidGrid)
+        );
+        assert_eq!(
+            unpack_grid_power(64 + 1, &mut grid),
+            Err(Error::InvalidPower)
+        );
+    }
+
+    #[test]
+    fn test_validate_callsign() {
+        for callsign in [
+            "K1ABC",
+            "KA1BCD",
+            "G1ABC",
+            "9A1AA",
+            "E71A",
+            "2E0XYZ",
+            "K1A",
+            "PJ4/K1ABC",
+            "K1ABC/7",
+            "K1ABC/P",
+        ] {
+            assert_eq!(validate_callsign(callsign), Ok(()), "{callsign}");
+        }
+
+        for (callsign, error) in [
+            ("111", Error::InvalidPrefix),
+            ("1A1", Error::InvalidSuffix),
+            ("11A", Error::InvalidPrefix),
+            ("123ABC", Error::InvalidPrefix),
+            ("1ABC", Error::InvalidPrefix),
+            ("ABCD", Error::MissingNumeral),
+            ("", Error::MissingNumeral),
+            ("k1abc", Error::InvalidPrefix),
+            ("K1ABCD", Error::InvalidSuffix),
+            ("K1AB2", Error::InvalidPrefix),
+            ("ABCD1A", Error::InvalidPrefix),
+            ("PJ4/111", Error::InvalidPrefix),
+            ("PJ4/K1ABC/7", Error::InvalidCallsign),
+            ("PJ4/11AB", Error::InvalidPrefix),
+            ("3DA0RU", Error::InvalidCallsign),
+        ] {
+            assert_eq!(validate_callsign(callsign), Err(error), "{callsign}");
+        }
+    }
+
+    #[test]
+    fn test_normalize() {
+        let mut buffer = [0; 12];
+        for (input, expected) in [
+            ("  k1abc ", "K1ABC"),
+            ("k1abc/p", "K1ABC/P"),
+            ("K1ABC/QRP", "K1ABC"),
+            ("pj4/k1abc/mm", "PJ4/K1ABC"),
+            ("K1ABC/7", "K1ABC/7"),
+        ] {
+            assert_eq!(normalize_callsign(input, &mut buffer), Ok(expected));
+        }
+        for input in ["", "K1ABC/XYZ", "K1ABCDEFGHIJK", "KABC"] {
+            assert_eq!(
+                normalize_callsign(input, &mut buffer),
+                Err(Error::InvalidCallsign),
+                "{input}"
+            );
+        }
+
+        assert_eq!(strip_suffix("K1ABC/P"), "K1ABC");
+        assert_eq!(strip_suffix("K1ABC/QRP"), "K1ABC");
+        assert_eq!(strip_suffix("PJ4/K1ABC/M"), "PJ4/K1ABC");
+        assert_eq!(strip_suffix("K1ABC/7"), "K1ABC/7");
+        assert_eq!(strip_suffix("PJ4/K1ABC"), "PJ4/K1ABC");
+
+        let mut buffer = [0; 6];
+        assert_eq!(normalize_grid(" fn42 ", &mut buffer), Ok("FN42"));
+        assert_eq!(normalize_grid("fn42ax\n", &mut buffer), Ok("FN42AX"));
+        for input in ["", "FN4", "FN42A", "FN42AY", "SN42", "FN42AX1"] {
+            assert_eq!(
+                normalize_grid(input, &mut buffer),
+                Err(Error::InvalidGrid),
+                "{input}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_config_types() {
+        let callsign = Callsign::new(" pj4/k1abc ").unwrap();
+        assert_eq!(callsign.as_str(), "PJ4/K1ABC");
+        assert!(callsign.is_compound());
+        let parsed: Callsign = "K1ABC".parse().unwrap();
+        assert_eq!(std::format!("{parsed}"), "K1ABC");
+        assert_eq!(Callsign::new("K1ABC/XYZ"), Err(Error::InvalidCallsign));
+
+        let grid = Grid::new("fn42ax").unwrap();
+        assert_eq!(grid.as_str(), "FN42AX");
+        assert_eq!(grid.square(), "FN42");
+        assert!(grid.is_locator());
+        assert!(!Grid::new("FN42").unwrap().is_locator());
+        assert_eq!(Grid::new("FN42A"), Err(Error::InvalidGrid));
+
+        let power = Power::new(37).unwrap();
+        assert_eq!(power.dbm(), 37);
+        assert_eq!(" 37".parse(), Ok(power));
+        assert_eq!(Power::new(38), Err(Error::InvalidPower));
+        assert_eq!("x".parse::<Power>(), Err(Error::InvalidPower));
+
+        assert_eq!(
+            callsign.encode(&grid, power),
+            encode_auto("PJ4/K1ABC", "FN42AX", 37)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_config_types_serde() {
+        let callsign = Callsign::new("K1ABC").unwrap();
+        let json = serde_json::to_string(&callsign).unwrap();
+        assert_eq!(json, "\"K1ABC\"");
+        assert_eq!(serde_json::from_str::<Callsign>(&json).unwrap(), callsign);
+        assert!(serde_json::from_str::<Callsign>("\"K1ABC/XYZ\"").is_err());
+
+        let grid: Grid = serde_json::from_str("\"FN42\"").unwrap();
+        assert_eq!(grid.as_str(), "FN42");
+        assert!(serde_json::from_str::<Grid>("\"FN4\"").is_err());
+
+        let power: Power = serde_json::from_str("37").unwrap();
+        assert_eq!(serde_json::to_string(&power).unwrap(), "37");
+        assert!(serde_json::from_str::<Power>("38").is_err());
+    }
+
+    #[test]
+    fn test_encode_pair() {
+        let mut table = hash::Callsigns::<4>::new();
+        for (callsign, grid, resolved) in [
+            ("PJ4/K1ABC", "", "<PJ4/K1ABC>"),
+            ("K1ABC", "FK52", "<K1ABC>"),
+        ] {
+            let (first, second) = encode_pair(callsign, "FK52UD", 37).unwrap();
+            let mut first = decode(&first).unwrap();
+            let mut second = decode(&second).unwrap();
+            assert_eq!(first.callsign(), callsign);
+            assert_eq!(first.grid(), grid);
+            assert_eq!(first.power(), 37);
+
+            first.resolve(&mut table);
+            second.resolve(&mut table);
+            assert_eq!(second.callsign(), resolved);
+            assert_eq!(second.grid(), "FK52UD");
+            assert_eq!(second.power(), 37);
+        }
+
+        assert_eq!(encode_pair("K1ABC", "FK52", 37), Err(Error::InvalidGrid));
+        assert_eq!(
+            encode_pair("PJ4/K1ABC", "FK52UD", 38),
+            Err(Error::InvalidPower)
+        );
+    }
+
+    #[test]
+    fn test_unpack_type2() {
+        let call = encode_callsign("K1ABC").unwrap() as u64;
+        let pack = |n: u64, power: u64| {
+            let offset = n / 32768 + 1;
+            call << 22 | (n % 32768) << 7 | (64 + power + offset)
+        };
+
+        let message = unpack(pack(34932, 37)).unwrap();
+        assert_eq!(message.callsign(), "PJ4/K1ABC");
+        assert_eq!(message.grid(), "");
+        assert_eq!(message.power(), 37);
+
+        let message = unpack(pack(60007, 10)).unwrap();
+        assert_eq!(message.callsign(), "K1ABC/7");
+        assert_eq!(message.power(), 10);
+
+        let message = unpack(pack(60038, 60)).unwrap();
+        assert_eq!(message.callsign(), "K1ABC/12");
+        assert_eq!(message.power(), 60);
+
+        assert_eq!(
+            Message::from_parts("PJ4/K1ABC", "", 37, None),
+            unpack(pack(34932, 37))
+        );
+        assert!(Message::from_parts("K1ABC/PJ4", "", 37, None).is_err());
+        assert!(Message::from_parts("K1ABC", "", 37, None).is_err());
+    }
+
+    #[test]
+    fn test_unpack_type3() {
+        let hash = hash::hash("PJ4/K1ABC") as u64;
+        let pack = |locator: &str, power: u64| {
+            let call = encode_callsign(locator).unwrap() as u64;
+            call << 22 | hash << 7 | (63 - power)
+        };
+
+        // FK52UD, rotated.
+        let mut message = unpack(pack("K52UDF", 37)).unwrap();
+        assert_eq!(message.callsign(), "<...>");
+        assert_eq!(message.grid(), "FK52UD");
+        assert_eq!(message.power(), 37);
+        assert_eq!(message.hash(), Some(hash as u16));
+        assert_eq!(
+            Message::from_parts("<...>", "FK52UD", 37, Some(hash as u16)),
+            Ok(message)
+        );
+
+        // A type 2 message from the same station resolves the hash.
+        let call = encode_callsign("K1ABC").unwrap() as u64;
+        let mut type2 =
+            unpack(call << 22 | (34932 % 32768) << 7 | 103).unwrap();
+        let mut table = hash::Callsigns::<4>::new();
+        message.resolve(&mut table);
+        assert_eq!(message.callsign(), "<...>");
+        type2.resolve(&mut table);
+        message.resolve(&mut table);
+        assert_eq!(message.callsign(), "<PJ4/K1ABC>");
+
+        assert_eq!(unpack(pack("K52UDZ", 37)), Err(Error::InvalidGrid));
+        assert_eq!(unpack(pack("K52UDF", 38)), Err(Error::InvalidPower));
+    }
+
+    #[test]
+    fn test_decode_wspr() {
+        for (callsign, grid, power) in [
+            ("K1A", "FN34", 33),
+            ("N6AB", "CM87", 0),
+            ("G1ABC", "IO83", 37),
+            ("KA1BCD", "AA00", 33),
+            ("KA1BC", "RR99", 60),
+        ] {
+            let message = decode(&encode(callsign, grid, power).unwrap());
+            let message = message.unwrap();
+            assert_eq!(message.callsign(), callsign);
+            assert_eq!(message.grid(), grid);
+            assert_eq!(message.power(), power);
+        }
+
+        // A few corrupted symbols are corrected.
+        let mut symbols = encode("K1A", "FN34", 33).unwrap();
+        symbols[20] ^= 0x02;
+        symbols[100] ^= 0x02;
+        assert_eq!(decode(&symbols).unwrap().callsign(), "K1A");
+
+        // But not too many.
+        for symbol in symbols.iter_mut().step_by(3) {
+            *symbol ^= 0x02;
+        }
+        assert_eq!(decode(&symbols), Err(Error::DecodeFailed));
+
+        symbols[100] = 4;
+        assert_eq!(decode(&symbols), Err(Error::InvalidSymbol));
+    }
+
+    #[test]
+    fn test_verify() {
+        let mut symbols = encode("K1ABC", "FN42", 37).unwrap();
+        assert_eq!(verify("K1ABC", "FN42", 37, &symbols), Ok(true));
+        assert_eq!(verify_decode("k1abc", "fn42", 37, &symbols), Ok(true));
+        assert_eq!(verify("K1ABC", "FN42", 33, &symbols), Ok(false));
+        assert_eq!(verify_decode("K1ABC", "FN42", 33, &symbols), Ok(false));
+        assert_eq!(
+            verify("K1ABC", "FN42", 38, &symbols),
+            Err(Error::InvalidPower)
+        );
+        assert_eq!(
+            verify_decode("K1ABC", "FN4", 37, &symbols),
+            Err(Error::InvalidGrid)
+        );
+
+        // A corrupted symbol is caught by `verify`, but corrected by the
+        // decoder.
+        symbols[20] ^= 0x02;
+        assert_eq!(verify("K1ABC", "FN42", 37, &symbols), Ok(false));
+        assert_eq!(verify_decode("K1ABC", "FN42", 37, &symbols), Ok(true));
+    }
+
+    #[test]
+    fn test_decode_packed() {
+        let symbols = encode("K1ABC", "FN42", 37).unwrap();
+        let packed = pack_symbols(&symbols).unwrap();
+        assert_eq!(
+            packed[0],
+            symbols[0] << 6 | symbols[1] << 4 | symbols[2] << 2 | symbols[3]
+        );
+        assert_eq!(packed[40], symbols[160] << 6 | symbols[161] << 4);
+        assert_eq!(decode_packed(&packed), decode(&symbols));
+
+        let mut symbols = symbols;
+        symbols[10] = 4;
+        assert_eq!(pack_symbols(&symbols), Err(Error::InvalidSymbol));
+    }
+
+    #[test]
+    fn test_decode_soft() {
+        let symbols = encode("G1ABC", "IO83", 37).unwrap();
+
+        // Confident symbols, with a sprinkling of weak wrong ones.
+        let mut llrs = [0i8; 162];
+        for (i, (llr, symbol)) in llrs.iter_mut().zip(symbols).enumerate() {
+            let sign = if symbol >> 1 == 1 { 1 } else { -1 };
+            *llr = if i % 5 == 0 { -10 * sign } else { 60 * sign };
+        }
+        let message = decode_soft(&llrs).unwrap();
+        assert_eq!(message.callsign(), "G1ABC");
+        assert_eq!(message.grid(), "IO83");
+        assert_eq!(message.power(), 37);
+        assert_eq!(hard_errors(&llrs, &symbols), 33);
+
+        let (decoded, result) = decode_soft_metric(&llrs).unwrap();
+        assert_eq!(decoded, message);
+        assert!(result.metric > 0);
+
+        assert_eq!(decode_soft(&[0; 162]), Err(Error::DecodeFailed));
+
+        // Scratch memory may be reused between decodes.
+        let fano = decoder::Fano::default();
+        let mut scratch = decoder::FanoScratch::new();
+        assert_eq!(
+            decode_soft_in(&[0; 162], &fano, &mut scratch),
+            Err(Error::DecodeFailed)
+        );
+        assert_eq!(decode_soft_in(&llrs, &fano, &mut scratch), Ok(message));
+    }
+
+    #[test]
+    fn test_decode_deep() {
+        let mut noise = crate::demod::tests::Noise::new();
+        let known = [("K1ABC", "FN42"), ("G1ABC", "IO83"), ("N6AB", "CM87")];
+
+        // Soft symbols too noisy for the convolutional decoder.
+        let symbols = encode("G1ABC", "IO83", 37).unwrap();
+        let mut llrs = [0i8; 162];
+        for (llr, symbol) in llrs.iter_mut().zip(symbols) {
+            let sign = if symbol >> 1 == 1 { 1.0 } else { -1.0 };
+            let noise: f32 = (0..12).map(|_| noise.next()).sum::<f32>() / 2.0;
+            *llr = ((0.7 * sign + noise) * 40.0).clamp(-127.0, 127.0) as i8;
+        }
+        assert_eq!(decode_soft(&llrs), Err(Error::DecodeFailed));
+
+        let message = decode_deep(&llrs, &known).unwrap();
+        assert_eq!(message.callsign(), "G1ABC");
+        assert_eq!(message.grid(), "IO83");
+        assert_eq!(message.power(), 37);
+
+        assert_eq!(decode_deep(&llrs, &known[..1]), Err(Error::DecodeFailed));
+        assert_eq!(decode_deep(&[0; 162], &known), Err(Error::DecodeFailed));
+    }
+
+    #[test]
+    fn test_soft_symbols() {
+        let symbols = encode("G1ABC", "IO83", 37).unwrap();
+
+        let mut magnitudes = [[0.25f32; 4]; 162];
+        for (tones, &symbol) in magnitudes.iter_mut().zip(&symbols) {
+            tones[symbol as usize] = 1.0;
+        }
+        let llrs = soft_symbols(&magnitudes);
+        for (llr, symbol) in llrs.iter().zip(symbols) {
+            assert_eq!(*llr, if symbol >> 1 == 1 { 40 } else { -40 });
+        }
+        assert_eq!(decode_soft(&llrs).unwrap().callsign(), "G1ABC");
+
+        assert_eq!(soft_symbols(&[[0.0; 4]; 162]), [0; 162]);
+    }
+
     #[test]
     fn test_encode_wspr() {
         assert_eq!(
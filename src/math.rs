@@ -0,0 +1,248 @@
+// Floating point functions that aren't available in `core`. These favor
+// simplicity over speed, and are accurate to within a few ULP over the
+// ranges used by this crate. With the `libm` feature they call the `libm`
+// crate instead, which is faster and more accurate still.
+
+#[cfg(not(feature = "libm"))]
+use core::f64::consts::FRAC_PI_2;
+
+// Rounds half away from zero.
+#[cfg(feature = "libm")]
+pub(crate) fn round(x: f64) -> f64 {
+    libm::round(x)
+}
+
+// Rounds half away from zero.
+#[cfg(not(feature = "libm"))]
+pub(crate) fn round(x: f64) -> f64 {
+    if x >= 0.0 {
+        (x + 0.5) as i64 as f64
+    } else {
+        (x - 0.5) as i64 as f64
+    }
+}
+
+// Returns the sine and cosine of `x` (in radians).
+#[cfg(feature = "libm")]
+pub(crate) fn sin_cos(x: f64) -> (f64, f64) {
+    libm::sincos(x)
+}
+
+// Returns the sine and cosine of `x` (in radians).
+#[cfg(not(feature = "libm"))]
+pub(crate) fn sin_cos(x: f64) -> (f64, f64) {
+    // Reduce to the range [-π/4, π/4] and a quadrant, where the Taylor
+    // series converge quickly.
+    let quadrant = round(x / FRAC_PI_2);
+    let r = x - quadrant * FRAC_PI_2;
+    let r2 = r * r;
+
+    let mut sin = 0.0;
+    let mut cos = 0.0;
+    let mut term_sin = r;
+    let mut term_cos = 1.0;
+    for n in 1..=8 {
+        sin += term_sin;
+        cos += term_cos;
+        let n = n as f64;
+        term_sin *= -r2 / ((2.0 * n) * (2.0 * n + 1.0));
+        term_cos *= -r2 / ((2.0 * n - 1.0) * (2.0 * n));
+    }
+
+    match (quadrant as i64).rem_euclid(4) {
+        0 => (sin, cos),
+        1 => (cos, -sin),
+        2 => (-sin, -cos),
+        _ => (-cos, sin),
+    }
+}
+
+#[cfg(feature = "libm")]
+pub(crate) fn sqrt(x: f32) -> f32 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    libm::sqrtf(x)
+}
+
+#[cfg(not(feature = "libm"))]
+pub(crate) fn sqrt(x: f32) -> f32 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+
+    // An initial estimate from halving the exponent, refined with Newton's
+    // method.
+    let mut y = f32::from_bits((x.to_bits() >> 1) + 0x1FBD_1DF5);
+    for _ in 0..3 {
+        y = 0.5 * (y + x / y);
+    }
+    y
+}
+
+// Returns the base 10 logarithm of `x`, or negative infinity if `x` isn't
+// positive.
+#[cfg(feature = "libm")]
+pub(crate) fn log10(x: f32) -> f32 {
+    if x <= 0.0 || x.is_nan() {
+        return f32::NEG_INFINITY;
+    }
+    libm::log10f(x)
+}
+
+// Returns the base 10 logarithm of `x`, or negative infinity if `x` isn't
+// positive.
+#[cfg(not(feature = "libm"))]
+pub(crate) fn log10(x: f32) -> f32 {
+    if x <= 0.0 || x.is_nan() {
+        return f32::NEG_INFINITY;
+    }
+
+    // Split into a mantissa in [1, 2) and an exponent. Subnormals are scaled
+    // up first so the mantissa is normalized.
+    let (x, bias) = if x < f32::MIN_POSITIVE {
+        (x as f64 * (1u64 << 32) as f64, -32)
+    } else {
+        (x as f64, 0)
+    };
+    let bits = x.to_bits();
+    let exponent = ((bits >> 52) & 0x7FF) as i64 - 1023 + bias;
+    let mantissa =
+        f64::from_bits((bits & 0x000F_FFFF_FFFF_FFFF) | (1023 << 52));
+
+    // ln(m) = 2 atanh((m - 1) / (m + 1)), where the argument is at most 1/3.
+    let z = (mantissa - 1.0) / (mantissa + 1.0);
+    let z2 = z * z;
+    let mut ln = 0.0;
+    let mut term = z;
+    for n in 0..10 {
+        ln += term / (2 * n + 1) as f64;
+        term *= z2;
+    }
+    ln *= 2.0;
+
+    ((ln + exponent as f64 * core::f64::consts::LN_2)
+        * core::f64::consts::LOG10_E) as f32
+}
+
+// Returns 10 raised to the power `x`.
+#[cfg(feature = "libm")]
+pub(crate) fn exp10(x: f32) -> f32 {
+    libm::exp10f(x)
+}
+
+// Returns 10 raised to the power `x`, as 2^n e^r, where n is the nearest
+// integer to x log2(10) and |r| is at most ln(2) / 2.
+#[cfg(not(feature = "libm"))]
+pub(crate) fn exp10(x: f32) -> f32 {
+    let y = x as f64 * core::f64::consts::LOG2_10;
+    if y > 128.0 {
+        return f32::INFINITY;
+    } else if y < -150.0 {
+        return 0.0;
+    }
+    let n = round(y);
+    let r = (y - n) * core::f64::consts::LN_2;
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    for k in 1..14 {
+        term *= r / k as f64;
+        sum += term;
+    }
+    (sum * f64::from_bits(((n as i64 + 1023) as u64) << 52)) as f32
+}
+
+// Returns the error function of `x`.
+#[cfg(feature = "libm")]
+pub(crate) fn erf(x: f64) -> f64 {
+    libm::erf(x)
+}
+
+// Returns the error function of `x`, accurate to within 1e-8. The Taylor
+// series is summed up to |x| = 4, beyond which erf(x) is ±1 to within that.
+#[cfg(not(feature = "libm"))]
+pub(crate) fn erf(x: f64) -> f64 {
+    if x.abs() >= 4.0 {
+        return x.signum();
+    }
+    let x2 = x * x;
+    let mut sum = 0.0;
+    let mut term = x;
+    for n in 0..100 {
+        let value = term / (2 * n + 1) as f64;
+        sum += value;
+        if value.abs() < 1e-17 {
+            break;
+        }
+        term *= -x2 / (n + 1) as f64;
+    }
+    sum * core::f64::consts::FRAC_2_SQRT_PI
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::f64::consts::PI;
+
+    #[test]
+    fn test_sin_cos() {
+        for i in -1000..1000 {
+            let x = i as f64 * 0.0137;
+            let (sin, cos) = sin_cos(x);
+            assert!((sin - x.sin()).abs() < 1e-12, "sin({})", x);
+            assert!((cos - x.cos()).abs() < 1e-12, "cos({})", x);
+        }
+        assert_eq!(sin_cos(0.0), (0.0, 1.0));
+        assert!(sin_cos(PI).0.abs() < 1e-15);
+    }
+
+    #[test]
+    fn test_log10() {
+        for i in 1..1000 {
+            let x = i as f32 * 0.0371;
+            assert!((log10(x) - x.log10()).abs() < 1e-6, "log10({})", x);
+        }
+        assert_eq!(log10(1000.0), 3.0);
+        assert!((log10(1e-40) + 40.0).abs() < 1e-4);
+        assert_eq!(log10(0.0), f32::NEG_INFINITY);
+    }
+
+    #[test]
+    fn test_exp10() {
+        for i in -400..400 {
+            let x = i as f32 * 0.0917;
+            let expected = 10f32.powf(x);
+            assert!((exp10(x) / expected - 1.0).abs() < 1e-6, "exp10({})", x);
+        }
+        assert_eq!(exp10(0.0), 1.0);
+        assert_eq!(exp10(2.0), 100.0);
+        assert_eq!(exp10(-50.0), 0.0);
+        assert_eq!(exp10(50.0), f32::INFINITY);
+    }
+
+    #[test]
+    fn test_erf() {
+        for (x, expected) in [
+            (0.0, 0.0),
+            (0.5, 0.5204998778130465),
+            (1.0, 0.8427007929497149),
+            (2.0, 0.9953222650189527),
+            (3.0, 0.9999779095030014),
+            (3.9, 0.9999999652077514),
+            (5.0, 1.0),
+        ] {
+            assert!((erf(x) - expected).abs() < 1e-8, "erf({})", x);
+            assert!((erf(-x) + expected).abs() < 1e-8, "erf({})", -x);
+        }
+    }
+
+    #[test]
+    fn test_sqrt() {
+        for i in 1..1000 {
+            let x = i as f32 * 0.731;
+            assert!((sqrt(x) - x.sqrt()).abs() <= x.sqrt() * 1e-6);
+        }
+        assert_eq!(sqrt(0.0), 0.0);
+        assert_eq!(sqrt(-1.0), 0.0);
+    }
+}
@@ -0,0 +1,1506 @@
+//! Generating the signal a transmitter sends for 162 symbols.
+//!
+//! [`Symbols::tones`] gives the frequency of each tone, for transmitters that
+//! synthesize the signal themselves, such as an Si5351 clock generator, and
+//! [`rf_frequencies`] gives them in integer millihertz. [`Modulator`] generates
+//! the samples of the signal for a sound card, and [`FixedModulator`] generates
+//! them with integer arithmetic alone for a DAC or I2S peripheral of a
+//! microcontroller. [`IqModulator`] generates complex baseband samples for an
+//! SDR, and [`TestSignal`] a steady carrier or a two-tone test for calibrating
+//! and testing the transmitter.
+//!
+//! Every modulator here is continuous phase FSK (CPFSK): a single
+//! oscillator steps its frequency between tones, never its phase. Switching
+//! between free-running oscillators for each tone instead leaves a jump in
+//! phase at most symbol boundaries, which splatters energy well outside the
+//! 6 Hz the signal should occupy. [`Shaping`] smooths the steps between
+//! tones to reduce the sidebands further, and [`Timing`] lengthens the
+//! symbols or widens the tones for experimental variants of the mode.
+//!
+//! Any sample rate can be used, such as the 44100 or 48000 Hz of a sound
+//! card or whatever an I2S clock divides down to, without resampling. The
+//! first sample of each symbol is computed exactly from the sample rate,
+//! so the symbols never drift out of time even when they aren't a whole
+//! number of samples long, and the phase step of each tone is computed
+//! from the sample rate directly.
+//!
+//! Each modulator is an iterator computing one sample at a time from the
+//! symbols and a few words of state, so the waveform is never held in
+//! memory. A microcontroller can take each sample as its DAC asks for it:
+//!
+//! ```
+//! use wspr::modulate::FixedModulator;
+//!
+//! let symbols = wspr::encode("K1ABC", "FN42", 37).unwrap();
+//! let mut modulator = FixedModulator::new(&symbols, 8000, 1500.0).unwrap();
+//! let total = modulator.total();
+//!
+//! // The body of a 12-bit DAC's interrupt handler, run 8000 times a second.
+//! let mut on_interrupt = || {
+//!     let sample = modulator.next()?;
+//!     Some(((sample as i32 + 32768) >> 4) as u16)
+//! };
+//!
+//! let mut count = 0;
+//! while let Some(level) = on_interrupt() {
+//!     assert!(level < 4096);
+//!     count += 1;
+//! }
+//! assert_eq!(count, total);
+//! ```
+
+use core::f64::consts::{PI, TAU};
+
+use crate::dsp::Complex;
+use crate::morse::{Envelope, Identification};
+use crate::{math, nco, Error, SYMBOL_LENGTH, TONE_SPACING};
+
+/// The frequencies of the tones of a transmission.
+pub trait Symbols {
+    /// The frequency of each symbol's tone in turn, in Hz: `base`, the
+    /// frequency of tone 0, plus the symbol times the tone spacing of
+    /// 12000/8192 Hz. `base` may be an audio or an RF frequency.
+    fn tones(&self, base: f64) -> impl Iterator<Item = f64> + '_;
+
+    /// The frequency of each symbol's tone in turn in millihertz, as
+    /// [`tone_millihertz`] gives it, without any floating point.
+    fn tones_millihertz(&self, base: u64) -> impl Iterator<Item = u64> + '_;
+}
+
+impl Symbols for [u8; 162] {
+    fn tones(&self, base: f64) -> impl Iterator<Item = f64> + '_ {
+        self.iter()
+            .map(move |&symbol| base + symbol as f64 * TONE_SPACING as f64)
+    }
+
+    fn tones_millihertz(&self, base: u64) -> impl Iterator<Item = u64> + '_ {
+        self.iter()
+            .map(move |&symbol| tone_millihertz(base, symbol))
+    }
+}
+
+/// The spacing between adjacent tones in millihertz, 1464.84375 mHz, as a
+/// numerator and a denominator, so that tones can be placed exactly with
+/// integer arithmetic.
+pub const TONE_SPACING_MILLIHERTZ: (u64, u64) = (375_000, 256);
+
+/// The frequency of `symbol`'s tone in millihertz: `base`, the frequency of
+/// tone 0, plus the symbol times the tone spacing, rounded to the nearest
+/// millihertz.
+///
+/// Each tone is computed from the exact spacing, so the rounding error is
+/// never more than half a millihertz and doesn't build up, for DDS or PLL
+/// settings computed by firmware without a floating point unit.
+pub const fn tone_millihertz(base: u64, symbol: u8) -> u64 {
+    let (numerator, denominator) = TONE_SPACING_MILLIHERTZ;
+    let offset = (symbol & 3) as u64 * numerator;
+    base + (offset + denominator / 2) / denominator
+}
+
+/// The RF frequency of each of the 162 symbols in millihertz, for a
+/// transmitter that sets its oscillator directly, such as an Si5351 or a
+/// DDS, symbol by symbol.
+///
+/// Like an upper sideband transmitter tuned to `dial_hz`, the signal is
+/// centered `audio_offset_hz` above the dial, usually 1400 to 1600 Hz.
+/// Each frequency is rounded to the nearest millihertz from the exact
+/// tone spacing.
+pub fn rf_frequencies(
+    dial_hz: u64,
+    audio_offset_hz: u32,
+    symbols: &[u8; 162],
+) -> [u64; 162] {
+    // Tone 0 is 1.5 tones below the center, which is a fraction of a
+    // millihertz, so the whole sum is kept in 256ths of a millihertz.
+    let (numerator, denominator) = TONE_SPACING_MILLIHERTZ;
+    let center = (dial_hz + audio_offset_hz as u64) * 1000 * denominator;
+    let lowest = center - 3 * numerator / 2;
+    let mut frequencies = [0; 162];
+    for (frequency, &symbol) in frequencies.iter_mut().zip(symbols) {
+        let tone = lowest + (symbol & 3) as u64 * numerator;
+        *frequency = (tone + denominator / 2) / denominator;
+    }
+    frequencies
+}
+
+/// The length of each symbol and the spacing between tones.
+///
+/// WSPR's symbols are 8192 samples long at 12000 Hz, about 0.683 seconds,
+/// with the tones 1/0.683 Hz apart. Other timings generate slowed down or
+/// widened variants of the signal, with the same FEC and sync.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timing {
+    // Each symbol is `length` samples long at `rate` Hz.
+    length: u32,
+    rate: u32,
+    tone_spacing: f64,
+}
+
+impl Timing {
+    /// The timing of WSPR-2, the usual two minute mode.
+    pub const WSPR: Timing = Timing {
+        length: SYMBOL_LENGTH,
+        rate: 12000,
+        tone_spacing: TONE_SPACING as f64,
+    };
+
+    /// The timing of WSPR-15, with symbols eight times as long and tones
+    /// an eighth as far apart.
+    pub const WSPR_15: Timing = Timing {
+        length: 8 * SYMBOL_LENGTH,
+        rate: 12000,
+        tone_spacing: TONE_SPACING as f64 / 8.0,
+    };
+
+    /// Creates a timing with symbols `length` samples long at `rate` Hz,
+    /// and the tones spaced by the symbol rate, `rate / length` Hz.
+    ///
+    /// Returns [`Error::InvalidSampleRate`] if either is 0.
+    pub fn new(length: u32, rate: u32) -> Result<Self, Error> {
+        if length == 0 || rate == 0 {
+            return Err(Error::InvalidSampleRate);
+        }
+        Ok(Self {
+            length,
+            rate,
+            tone_spacing: rate as f64 / length as f64,
+        })
+    }
+
+    /// Spaces the tones `tone_spacing` Hz apart, rather than by the symbol
+    /// rate, such as a multiple of it for a wider signal.
+    pub fn with_tone_spacing(self, tone_spacing: f32) -> Self {
+        Self {
+            tone_spacing: tone_spacing as f64,
+            ..self
+        }
+    }
+
+    /// The length of each symbol in seconds.
+    pub fn symbol_period(&self) -> f64 {
+        self.length as f64 / self.rate as f64
+    }
+
+    /// The spacing between adjacent tones in Hz.
+    pub fn tone_spacing(&self) -> f64 {
+        self.tone_spacing
+    }
+
+    // The index of the first sample of symbol `i` at `sample_rate` Hz.
+    fn boundary(&self, sample_rate: u32, i: usize) -> usize {
+        let samples = i as u64 * self.length as u64 * sample_rate as u64;
+        (samples / self.rate as u64) as usize
+    }
+}
+
+impl Default for Timing {
+    fn default() -> Self {
+        Self::WSPR
+    }
+}
+
+// π√(2/ln 2), scaling a Gaussian filter's bandwidth-time product to the
+// steepness of its step response, as in FT8.
+const GAUSSIAN_SCALE: f64 = 5.336446256636997;
+
+/// How a modulator's frequency moves from one tone to the next.
+///
+/// Smoothing the steps between tones narrows the spectrum of the signal,
+/// keeping the keying sidebands out of the neighboring signals, at the
+/// cost of a little of each symbol's energy spilling into the next.
+/// [`Spectrum::occupied_bandwidth`](crate::dsp::Spectrum::occupied_bandwidth)
+/// measures the difference.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Shaping {
+    /// Steps straight to the next tone at the boundary between symbols.
+    #[default]
+    None,
+    /// Moves along a raised cosine over the given fraction of a symbol,
+    /// from 0.0 to 1.0, centered on the boundary between symbols.
+    RaisedCosine(f32),
+    /// Filters the frequency with a Gaussian filter, as GFSK does, with the
+    /// given bandwidth-time product of at least 1.0. FT8 uses 2.0; lower
+    /// values give smoother steps.
+    Gaussian(f32),
+}
+
+impl Shaping {
+    // Limits the parameters to their allowed ranges.
+    fn clamp(self) -> Self {
+        match self {
+            Shaping::None => Shaping::None,
+            Shaping::RaisedCosine(fraction) => {
+                Shaping::RaisedCosine(fraction.clamp(0.0, 1.0))
+            }
+            Shaping::Gaussian(bt) => Shaping::Gaussian(bt.max(1.0)),
+        }
+    }
+
+    // How far the frequency has moved to a new tone `x` symbols after the
+    // boundary where it begins, from 0.0 to 1.0. The step is spread over
+    // less than a symbol either side of the boundary.
+    fn rise(self, x: f64) -> f64 {
+        match self {
+            Shaping::RaisedCosine(fraction) if fraction > 0.0 => {
+                let fraction = fraction as f64;
+                let r = (x / fraction + 0.5).clamp(0.0, 1.0);
+                0.5 - 0.5 * math::sin_cos(PI * r).1
+            }
+            Shaping::Gaussian(bt) => {
+                0.5 + 0.5 * math::erf(GAUSSIAN_SCALE * bt as f64 * x)
+            }
+            _ => (x >= 0.0) as u8 as f64,
+        }
+    }
+}
+
+/// Generates the audio samples of a transmission, each from -1.0 to 1.0.
+///
+/// The tones are generated by a single oscillator whose frequency steps
+/// from symbol to symbol, so the signal's phase is continuous. Samples are
+/// produced one at a time as an iterator, or a block at a time with
+/// [`Modulator::fill`], so the whole transmission never needs to be held in
+/// memory.
+#[derive(Debug, Clone)]
+pub struct Modulator<'a> {
+    symbols: &'a [u8; 162],
+    sample_rate: u32,
+    timing: Timing,
+    // The frequency of tone 0 in Hz.
+    base: f64,
+    // The index of the next sample, and the symbol it belongs to.
+    index: usize,
+    symbol: usize,
+    phase: f64,
+    shaping: Shaping,
+    // The length of the ramps at either end in samples.
+    ramp: usize,
+    keyer: Option<Keyer<'a>>,
+    // The Doppler profile, and the point the next sample's time is after.
+    doppler: &'a [(f32, f32)],
+    point: usize,
+}
+
+impl<'a> Modulator<'a> {
+    /// Creates a modulator for `symbols` at `sample_rate` Hz, with the
+    /// center of the signal, midway between tones 1 and 2, at `frequency`
+    /// Hz, such as 1500 Hz.
+    ///
+    /// A sample rate of 0 is an [`Error::InvalidSampleRate`]. The tones
+    /// span 4.4 Hz around `frequency`, and if any of them would reach 0 Hz
+    /// or the Nyquist frequency, [`Error::InvalidFrequency`] is returned.
+    pub fn new(
+        symbols: &'a [u8; 162],
+        sample_rate: u32,
+        frequency: f32,
+    ) -> Result<Self, Error> {
+        let base = frequency as f64 - 1.5 * TONE_SPACING as f64;
+        let modulator = Self::with_base(symbols, sample_rate, base)?;
+        if base <= 0.0 {
+            return Err(Error::InvalidFrequency);
+        }
+        Ok(modulator)
+    }
+
+    // Creates a modulator with tone 0 at `base` Hz, which may be negative
+    // for complex samples.
+    fn with_base(
+        symbols: &'a [u8; 162],
+        sample_rate: u32,
+        base: f64,
+    ) -> Result<Self, Error> {
+        if sample_rate == 0 {
+            return Err(Error::InvalidSampleRate);
+        }
+        let nyquist = sample_rate as f64 / 2.0;
+        if base <= -nyquist || base + 3.0 * TONE_SPACING as f64 >= nyquist {
+            return Err(Error::InvalidFrequency);
+        }
+        Ok(Self {
+            symbols,
+            sample_rate,
+            timing: Timing::WSPR,
+            base,
+            index: 0,
+            symbol: 0,
+            phase: 0.0,
+            shaping: Shaping::None,
+            ramp: 0,
+            keyer: None,
+            doppler: &[],
+            point: 0,
+        })
+    }
+
+    /// Smooths the steps between tones with `shaping`.
+    pub fn with_shaping(self, shaping: Shaping) -> Self {
+        Self {
+            shaping: shaping.clamp(),
+            ..self
+        }
+    }
+
+    /// Generates the symbols with `timing` rather than WSPR's, keeping the
+    /// center of the signal where it is.
+    ///
+    /// Returns [`Error::InvalidFrequency`] if any tone would then be at or
+    /// above the Nyquist frequency or at or below 0 Hz.
+    pub fn with_timing(self, timing: Timing) -> Result<Self, Error> {
+        self.with_timing_above(timing, 0.0)
+    }
+
+    // Generates the symbols with `timing`, with every tone above `lowest`
+    // Hz.
+    fn with_timing_above(
+        self,
+        timing: Timing,
+        lowest: f64,
+    ) -> Result<Self, Error> {
+        let center = self.base + 1.5 * self.timing.tone_spacing;
+        let base = center - 1.5 * timing.tone_spacing;
+        let highest = base + 3.0 * timing.tone_spacing;
+        if base <= lowest || highest >= self.sample_rate as f64 / 2.0 {
+            return Err(Error::InvalidFrequency);
+        }
+        Ok(Self {
+            timing,
+            base,
+            ..self
+        })
+    }
+
+    /// Offsets the frequency over time by `profile`, pairs of a time in
+    /// seconds from the start of the transmission and an offset in Hz, in
+    /// order of time. The offset is interpolated linearly between points,
+    /// and held before the first and after the last.
+    ///
+    /// This simulates the Doppler shift of a balloon or satellite, or
+    /// pre-compensates for it with the offsets negated, so the signal
+    /// arrives where it should.
+    ///
+    /// Returns [`Error::InvalidFrequency`] if any tone would be shifted to
+    /// or above the Nyquist frequency or to or below 0 Hz.
+    pub fn with_doppler(
+        self,
+        profile: &'a [(f32, f32)],
+    ) -> Result<Self, Error> {
+        self.with_doppler_above(profile, 0.0)
+    }
+
+    // Offsets the frequency by `profile`, with every tone above `lowest` Hz.
+    fn with_doppler_above(
+        self,
+        profile: &'a [(f32, f32)],
+        lowest: f64,
+    ) -> Result<Self, Error> {
+        let nyquist = self.sample_rate as f64 / 2.0;
+        let highest = self.base + 3.0 * self.timing.tone_spacing;
+        for &(_, offset) in profile {
+            let offset = offset as f64;
+            if self.base + offset <= lowest || highest + offset >= nyquist {
+                return Err(Error::InvalidFrequency);
+            }
+        }
+        Ok(Self {
+            doppler: profile,
+            point: 0,
+            ..self
+        })
+    }
+
+    // The Doppler offset in Hz at the next sample.
+    fn doppler(&mut self) -> f64 {
+        let (first, last) = match (self.doppler.first(), self.doppler.last()) {
+            (Some(&first), Some(&last)) => (first, last),
+            _ => return 0.0,
+        };
+        let time = self.index as f64 / self.sample_rate as f64;
+        if time <= first.0 as f64 {
+            return first.1 as f64;
+        } else if time >= last.0 as f64 {
+            return last.1 as f64;
+        }
+        while self.doppler[self.point + 1].0 as f64 <= time {
+            self.point += 1;
+        }
+        let (t0, f0) = self.doppler[self.point];
+        let (t1, f1) = self.doppler[self.point + 1];
+        let x = (time - t0 as f64) / (t1 - t0) as f64;
+        f0 as f64 + x * (f1 - f0) as f64
+    }
+
+    /// Ramps the amplitude up along a raised cosine over the first
+    /// `milliseconds` of the transmission, and down over the last, to avoid
+    /// key clicks. The ramps are limited to half the transmission.
+    pub fn with_ramp(self, milliseconds: u32) -> Self {
+        let ramp = ramp_len(self.sample_rate, milliseconds, self.total());
+        Self { ramp, ..self }
+    }
+
+    /// Sends `id` in Morse code after the symbols, as a tone keyed with
+    /// 5 ms ramps.
+    ///
+    /// Returns [`Error::InvalidFrequency`] if the frequency of the
+    /// identification is at or above the Nyquist frequency or at or below
+    /// 0 Hz.
+    pub fn with_id(self, id: Identification<'a>) -> Result<Self, Error> {
+        self.with_keyer(id, 0.0)
+    }
+
+    // Sends `id` after the symbols, at a frequency above `lowest` Hz.
+    fn with_keyer(
+        self,
+        id: Identification<'a>,
+        lowest: f64,
+    ) -> Result<Self, Error> {
+        let frequency = match id.frequency() {
+            Some(frequency) => frequency as f64,
+            None => self.base + 1.5 * self.timing.tone_spacing,
+        };
+        if frequency <= lowest || frequency >= self.sample_rate as f64 / 2.0 {
+            return Err(Error::InvalidFrequency);
+        }
+        Ok(Self {
+            keyer: Some(Keyer {
+                envelope: id.envelope(self.sample_rate),
+                frequency,
+            }),
+            ..self
+        })
+    }
+
+    // The amplitude of sample `index` of the symbols, from 0.0 to 1.0.
+    fn envelope(&self, index: usize) -> f64 {
+        let end = self.timing.boundary(self.sample_rate, 162);
+        let edge = index.min(end.saturating_sub(index + 1));
+        if edge >= self.ramp {
+            return 1.0;
+        }
+        0.5 - 0.5 * math::sin_cos(PI * edge as f64 / self.ramp as f64).1
+    }
+
+    /// The number of samples in the whole transmission, about 110.6
+    /// seconds, and any identification after it. The number still to be
+    /// generated is [`Iterator::count`] or [`ExactSizeIterator::len`].
+    pub fn total(&self) -> usize {
+        let id = self.keyer.as_ref().map_or(0, |k| k.envelope.total());
+        self.timing.boundary(self.sample_rate, 162) + id
+    }
+
+    // Advances to the next sample, returning the oscillator's phase and the
+    // amplitude at it.
+    fn advance(&mut self) -> Option<(f64, f64)> {
+        let end = self.timing.boundary(self.sample_rate, 162);
+        let (frequency, amplitude) = if self.index < end {
+            let timing = self.timing;
+            while self.index
+                >= timing.boundary(self.sample_rate, self.symbol + 1)
+            {
+                self.symbol += 1;
+            }
+            let &symbol = self.symbols.get(self.symbol)?;
+            let tone = match self.shaping {
+                Shaping::None => symbol as f64,
+                shaping => self.shaped_tone(shaping),
+            };
+            let frequency = self.base + tone * self.timing.tone_spacing;
+            (frequency, self.envelope(self.index))
+        } else {
+            let keyer = self.keyer.as_mut()?;
+            (keyer.frequency, keyer.envelope.next()? as f64)
+        };
+        let frequency = frequency + self.doppler();
+
+        let phase = self.phase;
+        self.phase =
+            (self.phase + TAU * frequency / self.sample_rate as f64) % TAU;
+        self.index += 1;
+        Some((phase, amplitude))
+    }
+
+    // The tone at the next sample, moving from the previous symbol's tone
+    // and on to the next symbol's around the boundaries either side. The
+    // first and last tones are held before and after the transmission.
+    fn shaped_tone(&self, shaping: Shaping) -> f64 {
+        let tone = |i: usize| self.symbols[i.min(161)] as f64;
+        let length = self.timing.symbol_period() * self.sample_rate as f64;
+        let k = self.symbol;
+        let x = self.index as f64 / length - k as f64;
+        let previous = tone(k.saturating_sub(1));
+        previous
+            + (tone(k) - previous) * shaping.rise(x)
+            + (tone(k + 1) - tone(k)) * shaping.rise(x - 1.0)
+    }
+
+    /// Fills a sound card's buffer with the next samples, returning how
+    /// many there were. The buffer is only left part full once the
+    /// transmission, and any identification after it, is over.
+    pub fn fill(&mut self, samples: &mut [f32]) -> usize {
+        crate::fill(self.by_ref(), samples)
+    }
+}
+
+impl Iterator for Modulator<'_> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let (phase, amplitude) = self.advance()?;
+        Some((math::sin_cos(phase).0 * amplitude) as f32)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.total() - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for Modulator<'_> {}
+
+/// Generates the complex baseband samples of a transmission, for SDR
+/// transmitters such as the HackRF or PlutoSDR that take I/Q samples.
+///
+/// The signal is generated as [`Modulator`] generates it, each sample with
+/// a magnitude of 1.0.
+#[derive(Debug, Clone)]
+pub struct IqModulator<'a> {
+    modulator: Modulator<'a>,
+}
+
+impl<'a> IqModulator<'a> {
+    /// Creates a modulator for `symbols` at `sample_rate` Hz, with the
+    /// center of the signal at `offset` Hz from the center of the baseband,
+    /// which may be negative or 0.
+    ///
+    /// Every tone must lie within the baseband, between minus and plus
+    /// half of `sample_rate`, or [`Error::InvalidFrequency`] is returned;
+    /// a `sample_rate` of 0 gives [`Error::InvalidSampleRate`].
+    pub fn new(
+        symbols: &'a [u8; 162],
+        sample_rate: u32,
+        offset: f32,
+    ) -> Result<Self, Error> {
+        let base = offset as f64 - 1.5 * TONE_SPACING as f64;
+        Ok(Self {
+            modulator: Modulator::with_base(symbols, sample_rate, base)?,
+        })
+    }
+
+    /// Smooths the steps between tones with `shaping`.
+    pub fn with_shaping(self, shaping: Shaping) -> Self {
+        Self {
+            modulator: self.modulator.with_shaping(shaping),
+        }
+    }
+
+    /// Generates the symbols with `timing`, see [`Modulator::with_timing`].
+    /// The tones may be at negative frequencies.
+    pub fn with_timing(self, timing: Timing) -> Result<Self, Error> {
+        let nyquist = self.modulator.sample_rate as f64 / 2.0;
+        Ok(Self {
+            modulator: self.modulator.with_timing_above(timing, -nyquist)?,
+        })
+    }
+
+    /// Offsets the frequency over time by `profile`, see
+    /// [`Modulator::with_doppler`]. The tones may be shifted to negative
+    /// frequencies.
+    pub fn with_doppler(
+        self,
+        profile: &'a [(f32, f32)],
+    ) -> Result<Self, Error> {
+        let nyquist = self.modulator.sample_rate as f64 / 2.0;
+        Ok(Self {
+            modulator: self.modulator.with_doppler_above(profile, -nyquist)?,
+        })
+    }
+
+    /// Ramps the amplitude up and down at either end, see
+    /// [`Modulator::with_ramp`].
+    pub fn with_ramp(self, milliseconds: u32) -> Self {
+        Self {
+            modulator: self.modulator.with_ramp(milliseconds),
+        }
+    }
+
+    /// Sends `id` in Morse code after the symbols, see
+    /// [`Modulator::with_id`]. Its frequency is relative to the center of
+    /// the baseband, and may be negative.
+    pub fn with_id(self, id: Identification<'a>) -> Result<Self, Error> {
+        let nyquist = self.modulator.sample_rate as f64 / 2.0;
+        Ok(Self {
+            modulator: self.modulator.with_keyer(id, -nyquist)?,
+        })
+    }
+
+    /// The number of samples in the whole transmission, see
+    /// [`Modulator::total`].
+    pub fn total(&self) -> usize {
+        self.modulator.total()
+    }
+
+    /// Writes the next baseband samples to an SDR's transmit buffer, and
+    /// returns the number written, which is short of `samples.len()` only
+    /// for the last block.
+    pub fn fill(&mut self, samples: &mut [Complex]) -> usize {
+        crate::fill(self.by_ref(), samples)
+    }
+}
+
+impl Iterator for IqModulator<'_> {
+    type Item = Complex;
+
+    fn next(&mut self) -> Option<Complex> {
+        let (phase, amplitude) = self.modulator.advance()?;
+        let (sin, cos) = math::sin_cos(phase);
+        Some(Complex::new(
+            (cos * amplitude) as f32,
+            (sin * amplitude) as f32,
+        ))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.modulator.size_hint()
+    }
+}
+
+impl ExactSizeIterator for IqModulator<'_> {}
+
+// Keys a tone on and off to send an identification.
+#[derive(Debug, Clone)]
+struct Keyer<'a> {
+    envelope: Envelope<'a>,
+    frequency: f64,
+}
+
+// The length in samples of a ramp of `milliseconds`, limited to half of a
+// transmission of `total` samples.
+fn ramp_len(sample_rate: u32, milliseconds: u32, total: usize) -> usize {
+    let ramp = milliseconds as u64 * sample_rate as u64 / 1000;
+    (ramp as usize).min(total / 2)
+}
+
+/// Generates the samples of a transmission as [`Modulator`] does, as 16-bit
+/// (Q15) integers from a phase accumulator and a table of sines, for
+/// microcontrollers without a floating point unit.
+///
+/// Floating point is only used when the modulator is created. The table
+/// holds a quarter of a cycle, 514 bytes; with the `cordic` feature the
+/// sines are computed by CORDIC instead, taking no table at all.
+#[derive(Debug, Clone)]
+pub struct FixedModulator<'a> {
+    symbols: &'a [u8; 162],
+    sample_rate: u32,
+    timing: Timing,
+    // The frequency of the center of the signal in Hz.
+    frequency: f32,
+    // The phase step of each tone per sample, a full cycle being 2^32.
+    steps: [u32; 4],
+    index: usize,
+    symbol: usize,
+    // The index of the first sample of the next symbol.
+    next: usize,
+    phase: u32,
+    ramp: usize,
+}
+
+impl<'a> FixedModulator<'a> {
+    /// Creates a modulator for `symbols` at `sample_rate` Hz, with the
+    /// center of the signal at `frequency` Hz, see [`Modulator::new`].
+    pub fn new(
+        symbols: &'a [u8; 162],
+        sample_rate: u32,
+        frequency: f32,
+    ) -> Result<Self, Error> {
+        let modulator = Modulator::new(symbols, sample_rate, frequency)?;
+        Ok(Self {
+            symbols,
+            sample_rate,
+            timing: Timing::WSPR,
+            frequency,
+            steps: steps(&modulator),
+            index: 0,
+            symbol: 0,
+            next: Timing::WSPR.boundary(sample_rate, 1),
+            phase: 0,
+            ramp: 0,
+        })
+    }
+
+    /// Generates the symbols with `timing`, see [`Modulator::with_timing`].
+    pub fn with_timing(self, timing: Timing) -> Result<Self, Error> {
+        let modulator =
+            Modulator::new(self.symbols, self.sample_rate, self.frequency)?
+                .with_timing(timing)?;
+        Ok(Self {
+            timing,
+            steps: steps(&modulator),
+            next: timing.boundary(self.sample_rate, self.symbol + 1),
+            ..self
+        })
+    }
+
+    /// Ramps the amplitude up and down at either end, see
+    /// [`Modulator::with_ramp`].
+    pub fn with_ramp(self, milliseconds: u32) -> Self {
+        let ramp = ramp_len(self.sample_rate, milliseconds, self.total());
+        Self { ramp, ..self }
+    }
+
+    /// The number of samples in the whole transmission, see
+    /// [`Modulator::total`].
+    pub fn total(&self) -> usize {
+        self.timing.boundary(self.sample_rate, 162)
+    }
+
+    /// Copies the next Q15 samples into `samples` and returns how many were
+    /// copied, leaving the rest untouched after the last;
+    /// [`FixedModulator::fill_buffer`] pads them with silence instead.
+    pub fn fill(&mut self, samples: &mut [i16]) -> usize {
+        crate::fill(self.by_ref(), samples)
+    }
+
+    /// Fills the whole of `samples` with the next samples, padded with
+    /// silence after the end of the transmission, returning how many
+    /// samples remain after them; 0 once the block holds the end.
+    ///
+    /// This suits double buffered DMA to an I2S or DAC peripheral, refilling
+    /// each half of the buffer from the half and full complete interrupts
+    /// until 0 is returned.
+    pub fn fill_buffer(&mut self, samples: &mut [i16]) -> usize {
+        let count = self.fill(samples);
+        samples[count..].fill(0);
+        self.len()
+    }
+}
+
+impl Iterator for FixedModulator<'_> {
+    type Item = i16;
+
+    fn next(&mut self) -> Option<i16> {
+        // A symbol may be shorter than a sample, skipping some altogether.
+        while self.index >= self.next {
+            self.symbol += 1;
+            self.next = self.timing.boundary(self.sample_rate, self.symbol + 1);
+        }
+        let &symbol = self.symbols.get(self.symbol)?;
+
+        let mut sample = nco::sin_cos(self.phase).0;
+        if self.ramp > 0 {
+            let edge = self.index.min(self.total() - self.index - 1);
+            if edge < self.ramp {
+                // The raised cosine is the square of a sine over a quarter
+                // cycle.
+                let quarter = ((edge as u64) << 30) / self.ramp as u64;
+                let sine = nco::sin_cos(quarter as u32).0 as i32;
+                let gain = (sine * sine) >> 15;
+                sample = ((sample as i32 * gain) >> 15) as i16;
+            }
+        }
+        self.phase = self.phase.wrapping_add(self.steps[symbol as usize & 3]);
+        self.index += 1;
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.total().saturating_sub(self.index);
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for FixedModulator<'_> {}
+
+// The phase step of each of `modulator`'s tones per sample.
+fn steps(modulator: &Modulator) -> [u32; 4] {
+    let mut steps = [0u32; 4];
+    for (tone, step) in steps.iter_mut().enumerate() {
+        let spacing = modulator.timing.tone_spacing;
+        let frequency = modulator.base + tone as f64 * spacing;
+        let cycles = frequency / modulator.sample_rate as f64;
+        *step = math::round(cycles * (1u64 << 32) as f64) as u32;
+    }
+    steps
+}
+
+/// Generates a steady carrier or a two-tone test signal, for calibrating
+/// and testing a transmitter with the same sound card or DAC path as the
+/// WSPR signal itself.
+///
+/// Samples are from -1.0 to 1.0 as [`Modulator`] generates them, and the
+/// signal lasts as long as a transmission unless given another length.
+#[derive(Debug, Clone)]
+pub struct TestSignal {
+    sample_rate: u32,
+    // The frequencies of the tones in Hz. A carrier is two tones at the
+    // same frequency.
+    frequencies: [f64; 2],
+    phases: [f64; 2],
+    index: usize,
+    total: usize,
+}
+
+impl TestSignal {
+    /// Creates a steady carrier at the frequency of tone 0 of a signal
+    /// centered at `frequency` Hz, at `sample_rate` Hz, to measure with a
+    /// frequency counter.
+    ///
+    /// A carrier at 0 Hz or below, or at the Nyquist frequency or above,
+    /// is an [`Error::InvalidFrequency`], and a `sample_rate` of 0 an
+    /// [`Error::InvalidSampleRate`].
+    pub fn carrier(sample_rate: u32, frequency: f32) -> Result<Self, Error> {
+        let tone = frequency as f64 - 1.5 * TONE_SPACING as f64;
+        Self::with_tones(sample_rate, [tone, tone])
+    }
+
+    /// Creates a two-tone test signal of equal tones at `low` and `high` Hz,
+    /// at `sample_rate` Hz, to check the linearity of an amplifier. The
+    /// peaks of their sum reach 1.0.
+    ///
+    /// Both tones must be above 0 Hz and below the Nyquist frequency, as
+    /// for [`TestSignal::carrier`].
+    pub fn two_tone(
+        sample_rate: u32,
+        low: f32,
+        high: f32,
+    ) -> Result<Self, Error> {
+        Self::with_tones(sample_rate, [low as f64, high as f64])
+    }
+
+    // Creates a signal of two tones of half amplitude at `frequencies` Hz.
+    fn with_tones(
+        sample_rate: u32,
+        frequencies: [f64; 2],
+    ) -> Result<Self, Error> {
+        if sample_rate == 0 {
+            return Err(Error::InvalidSampleRate);
+        }
+        let nyquist = sample_rate as f64 / 2.0;
+        if frequencies.iter().any(|&f| f <= 0.0 || f >= nyquist) {
+            return Err(Error::InvalidFrequency);
+        }
+        Ok(Self {
+            sample_rate,
+            frequencies,
+            phases: [0.0; 2],
+            index: 0,
+            total: Timing::WSPR.boundary(sample_rate, 162),
+        })
+    }
+
+    /// Lasts `seconds` rather than the length of a transmission.
+    pub fn with_length(self, seconds: f32) -> Self {
+        let total = seconds.max(0.0) as f64 * self.sample_rate as f64;
+        Self {
+            total: math::round(total) as usize,
+            ..self
+        }
+    }
+
+    /// The frequencies of the two tones in Hz, which are the same for a
+    /// carrier.
+    pub fn frequencies(&self) -> [f64; 2] {
+        self.frequencies
+    }
+
+    /// The number of samples in the whole signal. The number still to be
+    /// generated is [`Iterator::count`] or [`ExactSizeIterator::len`].
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Writes as many samples of the tones as `samples` holds, or as
+    /// remain of the signal's length if that's fewer, and returns the
+    /// number written.
+    pub fn fill(&mut self, samples: &mut [f32]) -> usize {
+        crate::fill(self.by_ref(), samples)
+    }
+}
+
+impl Iterator for TestSignal {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.index >= self.total {
+            return None;
+        }
+        let mut sample = 0.0;
+        for (phase, &frequency) in self.phases.iter_mut().zip(&self.frequencies)
+        {
+            sample += 0.5 * math::sin_cos(*phase).0;
+            *phase = (*phase + TAU * frequency / self.sample_rate as f64) % TAU;
+        }
+        self.index += 1;
+        Some(sample as f32)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.total.saturating_sub(self.index);
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for TestSignal {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tones() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let base = 14_097_100.0 - 1.5 * 12000.0 / 8192.0;
+        let tones: std::vec::Vec<f64> = symbols.tones(base).collect();
+        assert_eq!(tones.len(), 162);
+        for (&tone, &symbol) in tones.iter().zip(&symbols) {
+            let expected = base + symbol as f64 * 12000.0 / 8192.0;
+            assert!((tone - expected).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_tones_millihertz() {
+        assert_eq!(tone_millihertz(14_097_000_000, 0), 14_097_000_000);
+        assert_eq!(tone_millihertz(14_097_000_000, 1), 14_097_001_465);
+        assert_eq!(tone_millihertz(14_097_000_000, 2), 14_097_002_930);
+        assert_eq!(tone_millihertz(14_097_000_000, 3), 14_097_004_395);
+
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let base = 1_497_802;
+        for (tone, exact) in symbols
+            .tones_millihertz(base)
+            .zip(symbols.tones(base as f64 / 1000.0))
+        {
+            assert!((tone as f64 - exact * 1000.0).abs() <= 0.5);
+        }
+    }
+
+    #[test]
+    fn test_rf_frequencies() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let frequencies = rf_frequencies(14_095_600, 1500, &symbols);
+        let center = 14_097_100.0;
+        for (&frequency, exact) in frequencies
+            .iter()
+            .zip(symbols.tones(center - 1.5 * 12000.0 / 8192.0))
+        {
+            assert!((frequency as f64 - exact * 1000.0).abs() <= 0.5);
+        }
+        assert_eq!(*frequencies.iter().min().unwrap(), 14_097_100_000 - 2197);
+        assert_eq!(*frequencies.iter().max().unwrap(), 14_097_100_000 + 2197);
+    }
+
+    #[test]
+    fn test_modulator() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        assert_eq!(
+            Modulator::new(&symbols, 0, 1500.0).err(),
+            Some(Error::InvalidSampleRate)
+        );
+        assert_eq!(
+            Modulator::new(&symbols, 3000, 1500.0).err(),
+            Some(Error::InvalidFrequency)
+        );
+        assert_eq!(
+            Modulator::new(&symbols, 12000, 1.0).err(),
+            Some(Error::InvalidFrequency)
+        );
+
+        // The samples decode back to the symbols.
+        let sample_rate = 8000;
+        let mut modulator =
+            Modulator::new(&symbols, sample_rate, 1500.0).unwrap();
+        assert_eq!(modulator.total(), 162 * 8192 * 8000 / 12000);
+        assert_eq!(modulator.len(), modulator.total());
+        let mut samples = std::vec![0f32; modulator.total() + 100];
+        let mut written = modulator.fill(&mut samples[..1000]);
+        written += modulator.fill(&mut samples[written..]);
+        assert_eq!(written, modulator.total());
+        assert_eq!(modulator.len(), 0);
+        assert_eq!(modulator.next(), None);
+        assert!(samples.iter().all(|sample| sample.abs() <= 1.0));
+
+        let lowest = 1500.0 - 1.5 * TONE_SPACING;
+        let demodulator =
+            crate::demod::Demodulator::new(sample_rate, lowest).unwrap();
+        let magnitudes = demodulator.magnitudes(&samples, 0).unwrap();
+        assert_eq!(crate::hard_symbols(&magnitudes), symbols);
+
+        // The iterator gives the same samples.
+        let modulator = Modulator::new(&symbols, sample_rate, 1500.0).unwrap();
+        assert_eq!(modulator.len(), written);
+        assert!(modulator.zip(&samples).all(|(a, &b)| a == b));
+    }
+
+    #[test]
+    fn test_sample_rates() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        for sample_rate in [8000, 11025, 22050, 44100, 48000, 96000] {
+            // The transmission lasts 162 * 8192 / 12000 seconds to within a
+            // sample, with every symbol within a sample of its length.
+            let modulator =
+                Modulator::new(&symbols, sample_rate, 1500.0).unwrap();
+            let seconds = 162.0 * 8192.0 / 12000.0;
+            let exact = seconds * sample_rate as f64;
+            assert!((modulator.total() as f64 - exact).abs() < 1.0);
+            let fixed =
+                FixedModulator::new(&symbols, sample_rate, 1500.0).unwrap();
+            assert_eq!(fixed.total(), modulator.total());
+            let length = 8192.0 * sample_rate as f64 / 12000.0;
+            for i in 0..162 {
+                let start = Timing::WSPR.boundary(sample_rate, i);
+                let end = Timing::WSPR.boundary(sample_rate, i + 1);
+                assert!(((end - start) as f64 - length).abs() < 1.0);
+            }
+        }
+
+        // The integer samples at 44100 Hz decode back to the symbols.
+        let samples: std::vec::Vec<i16> =
+            FixedModulator::new(&symbols, 44100, 1500.0)
+                .unwrap()
+                .collect();
+        let lowest = 1500.0 - 1.5 * TONE_SPACING;
+        let demodulator =
+            crate::demod::Demodulator::new(44100, lowest).unwrap();
+        let magnitudes = demodulator.magnitudes(&samples, 0).unwrap();
+        assert_eq!(crate::hard_symbols(&magnitudes), symbols);
+    }
+
+    #[test]
+    fn test_iq_modulator() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        assert_eq!(
+            IqModulator::new(&symbols, 375, 186.5).err(),
+            Some(Error::InvalidFrequency)
+        );
+        assert_eq!(
+            IqModulator::new(&symbols, 375, -186.0).err(),
+            Some(Error::InvalidFrequency)
+        );
+
+        // Signals on either side of the center of the baseband decode to the
+        // symbols.
+        for offset in [-20.0, 0.0, 35.0] {
+            let mut modulator =
+                IqModulator::new(&symbols, 375, offset).unwrap();
+            let mut samples = std::vec![Complex::default(); modulator.total()];
+            assert_eq!(modulator.fill(&mut samples), samples.len());
+            assert!(samples.iter().all(|s| (s.norm_sqr() - 1.0).abs() < 1e-4));
+
+            let lowest = offset - 1.5 * TONE_SPACING;
+            let demodulator =
+                crate::demod::Demodulator::new_iq(375, lowest).unwrap();
+            let magnitudes = demodulator.magnitudes(&samples, 0).unwrap();
+            assert_eq!(crate::hard_symbols(&magnitudes), symbols);
+        }
+    }
+
+    #[test]
+    fn test_phase_continuity() {
+        // The phase advances between every pair of samples, across symbol
+        // boundaries too, by exactly the step of the current or the next
+        // tone.
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let samples: std::vec::Vec<Complex> =
+            IqModulator::new(&symbols, 375, 10.0).unwrap().collect();
+        let base = 10.0 - 1.5 * TONE_SPACING;
+        for pair in samples.windows(2) {
+            let step = pair[1] * pair[0].conj();
+            let frequency = step.im.atan2(step.re) * 375.0 / TAU as f32;
+            let tone = (frequency - base) / TONE_SPACING;
+            assert!((tone - tone.round()).abs() < 1e-2, "{}", frequency);
+            assert!((0.0..=3.0).contains(&tone.round()));
+        }
+
+        // The real samples never jump by more than the highest tone's step.
+        let samples: std::vec::Vec<f32> =
+            Modulator::new(&symbols, 8000, 1500.0).unwrap().collect();
+        let highest = 1500.0 + 1.5 * TONE_SPACING;
+        let max_step = TAU as f32 * highest / 8000.0;
+        assert!(samples
+            .windows(2)
+            .all(|pair| (pair[1] - pair[0]).abs() <= max_step + 1e-4));
+    }
+
+    #[test]
+    fn test_timing() {
+        assert_eq!(Timing::new(0, 12000), Err(Error::InvalidSampleRate));
+        assert_eq!(Timing::default(), Timing::new(8192, 12000).unwrap());
+        assert!(
+            (Timing::WSPR_15.symbol_period() - 8.0 * 8192.0 / 12000.0).abs()
+                < 1e-9
+        );
+
+        // A widened signal, with the tones twice as far apart, each at the
+        // symbol's frequency around the same center.
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let timing = Timing::WSPR.with_tone_spacing(2.0 * TONE_SPACING);
+        let samples: std::vec::Vec<Complex> =
+            IqModulator::new(&symbols, 375, 10.0)
+                .unwrap()
+                .with_timing(timing)
+                .unwrap()
+                .collect();
+        assert_eq!(samples.len(), 162 * 256);
+        let base = 10.0 - 3.0 * TONE_SPACING;
+        for (i, pair) in samples.windows(2).enumerate() {
+            let step = pair[1] * pair[0].conj();
+            let frequency = step.im.atan2(step.re) * 375.0 / TAU as f32;
+            let expected = base + symbols[i / 256] as f32 * 2.0 * TONE_SPACING;
+            if i % 256 != 255 {
+                assert!((frequency - expected).abs() < 0.01, "{}", i);
+            }
+        }
+        assert_eq!(
+            Modulator::new(&symbols, 375, 185.0)
+                .unwrap()
+                .with_timing(timing)
+                .err(),
+            Some(Error::InvalidFrequency)
+        );
+
+        // WSPR-15, eight times as long, from the integer modulator too.
+        let modulator = Modulator::new(&symbols, 1000, 200.0)
+            .unwrap()
+            .with_timing(Timing::WSPR_15)
+            .unwrap();
+        let fixed = FixedModulator::new(&symbols, 1000, 200.0)
+            .unwrap()
+            .with_timing(Timing::WSPR_15)
+            .unwrap();
+        assert_eq!(modulator.total(), 162 * 65536 / 12);
+        assert_eq!(fixed.total(), modulator.total());
+        for (fixed, float) in fixed.zip(modulator) {
+            assert!((fixed as f32 / 32768.0 - float).abs() < 0.01);
+        }
+
+        // Symbols shorter than a sample still end the transmission on time.
+        let timing = Timing::new(1, 12000).unwrap().with_tone_spacing(1.4648);
+        let fixed = FixedModulator::new(&symbols, 8000, 1500.0)
+            .unwrap()
+            .with_timing(timing)
+            .unwrap();
+        assert_eq!(fixed.total(), 108);
+        assert_eq!(fixed.len(), 108);
+        assert_eq!(fixed.take(100_000).count(), 108);
+    }
+
+    #[test]
+    fn test_test_signal() {
+        assert_eq!(
+            TestSignal::carrier(0, 1500.0).err(),
+            Some(Error::InvalidSampleRate)
+        );
+        assert_eq!(
+            TestSignal::two_tone(8000, 700.0, 4000.0).err(),
+            Some(Error::InvalidFrequency)
+        );
+
+        // The carrier is a full scale tone at tone 0's frequency, lasting
+        // as long as a transmission.
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let carrier = TestSignal::carrier(8000, 1500.0).unwrap();
+        let lowest = 1500.0 - 1.5 * TONE_SPACING as f64;
+        assert_eq!(carrier.frequencies(), [lowest; 2]);
+        assert_eq!(
+            carrier.total(),
+            Modulator::new(&symbols, 8000, 1500.0).unwrap().total()
+        );
+        for (i, sample) in carrier.enumerate() {
+            let expected = (TAU * lowest * i as f64 / 8000.0).sin();
+            assert!((sample as f64 - expected).abs() < 1e-3, "{}", i);
+        }
+
+        // The two tones peak at full scale, with the power split between
+        // them.
+        let mut two_tone = TestSignal::two_tone(8000, 700.0, 1900.0)
+            .unwrap()
+            .with_length(1.0);
+        let mut samples = std::vec![0.0; 10000];
+        assert_eq!(two_tone.fill(&mut samples), 8000);
+        let samples = &samples[..8000];
+        let peak = samples.iter().fold(0.0f32, |peak, s| peak.max(s.abs()));
+        assert!((peak - 1.0).abs() < 1e-3);
+        let power = |frequency: f64| {
+            let (mut re, mut im) = (0.0, 0.0);
+            for (i, &sample) in samples.iter().enumerate() {
+                let (sin, cos) =
+                    (TAU * frequency * i as f64 / 8000.0).sin_cos();
+                re += sample as f64 * cos;
+                im += sample as f64 * sin;
+            }
+            (re * re + im * im) / 4000.0f64.powi(2)
+        };
+        assert!((power(700.0) - 0.25).abs() < 1e-3);
+        assert!((power(1900.0) - 0.25).abs() < 1e-3);
+        assert!(power(1300.0) < 1e-6);
+    }
+
+    // The fraction of the power of `samples` at 375 Hz more than 8 Hz from
+    // the center of a signal at 0 Hz.
+    fn sidebands(samples: &[Complex]) -> f64 {
+        let mut outside = 0.0;
+        for i in -160..=160 {
+            let frequency = i as f64 * 0.25;
+            if frequency.abs() <= 8.0 {
+                continue;
+            }
+            let step = TAU * frequency / 375.0;
+            let (mut re, mut im) = (0.0, 0.0);
+            for (n, sample) in samples.iter().enumerate() {
+                let (sin, cos) = (step * n as f64).sin_cos();
+                re += sample.re as f64 * cos + sample.im as f64 * sin;
+                im += sample.im as f64 * cos - sample.re as f64 * sin;
+            }
+            outside += re * re + im * im;
+        }
+        // Bins 0.25 Hz apart over samples lasting 4 times longer leave the
+        // power counted 4 times over, by Parseval's theorem.
+        outside / (samples.len() as f64 * samples.len() as f64) / 4.0
+    }
+
+    #[test]
+    fn test_shaping() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let generate = |shaping| -> std::vec::Vec<Complex> {
+            IqModulator::new(&symbols, 375, 0.0)
+                .unwrap()
+                .with_shaping(shaping)
+                .collect()
+        };
+        let unshaped = generate(Shaping::None);
+        let baseline = sidebands(&unshaped);
+
+        let lowest = -1.5 * TONE_SPACING;
+        let demodulator =
+            crate::demod::Demodulator::new_iq(375, lowest).unwrap();
+        for shaping in [Shaping::RaisedCosine(0.5), Shaping::Gaussian(2.0)] {
+            let samples = generate(shaping);
+            assert_eq!(samples.len(), unshaped.len());
+            assert!(sidebands(&samples) < baseline / 2.0, "{:?}", shaping);
+
+            // The frequency never steps from one sample to the next.
+            let frequencies: std::vec::Vec<f32> = samples
+                .windows(2)
+                .map(|pair| {
+                    let step = pair[1] * pair[0].conj();
+                    step.im.atan2(step.re) * 375.0 / TAU as f32
+                })
+                .collect();
+            assert!(frequencies
+                .windows(2)
+                .all(|pair| (pair[1] - pair[0]).abs() < 0.2));
+
+            let magnitudes = demodulator.magnitudes(&samples, 0).unwrap();
+            assert_eq!(crate::hard_symbols(&magnitudes), symbols);
+        }
+    }
+
+    #[test]
+    fn test_doppler() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let profile = [(10.0, 0.0), (60.0, -5.0), (100.0, 5.0)];
+        assert_eq!(
+            Modulator::new(&symbols, 375, 5.0)
+                .unwrap()
+                .with_doppler(&profile)
+                .err(),
+            Some(Error::InvalidFrequency)
+        );
+
+        // Each sample steps by the symbol's tone plus the interpolated
+        // offset.
+        let samples: std::vec::Vec<Complex> =
+            IqModulator::new(&symbols, 375, 0.0)
+                .unwrap()
+                .with_doppler(&profile)
+                .unwrap()
+                .collect();
+        let offset = |time: f32| match time {
+            t if t <= 10.0 => 0.0,
+            t if t <= 60.0 => -5.0 * (t - 10.0) / 50.0,
+            t if t <= 100.0 => -5.0 + 10.0 * (t - 60.0) / 40.0,
+            _ => 5.0,
+        };
+        for (i, pair) in samples.windows(2).enumerate() {
+            if i % 256 == 255 {
+                continue;
+            }
+            let step = pair[1] * pair[0].conj();
+            let frequency = step.im.atan2(step.re) * 375.0 / TAU as f32;
+            let tone = (symbols[i / 256] as f32 - 1.5) * TONE_SPACING;
+            let expected = tone + offset(i as f32 / 375.0);
+            assert!((frequency - expected).abs() < 0.01, "{}", i);
+        }
+
+        // Without a profile nothing changes.
+        let plain = IqModulator::new(&symbols, 375, 0.0).unwrap();
+        let empty = IqModulator::new(&symbols, 375, 0.0)
+            .unwrap()
+            .with_doppler(&[])
+            .unwrap();
+        assert!(plain.zip(empty).all(|(a, b)| a == b));
+    }
+
+    #[test]
+    fn test_fixed_modulator() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        assert_eq!(
+            FixedModulator::new(&symbols, 3000, 1500.0).err(),
+            Some(Error::InvalidFrequency)
+        );
+
+        // The samples match the floating point modulator's, and decode
+        // back to the symbols.
+        let sample_rate = 8000;
+        let mut fixed =
+            FixedModulator::new(&symbols, sample_rate, 1500.0).unwrap();
+        let modulator = Modulator::new(&symbols, sample_rate, 1500.0).unwrap();
+        assert_eq!(fixed.len(), modulator.total());
+        let mut samples = std::vec![0i16; fixed.total()];
+        assert_eq!(fixed.fill(&mut samples), samples.len());
+        assert_eq!(fixed.next(), None);
+        for (&fixed, float) in samples.iter().zip(modulator) {
+            assert!((fixed as f32 / 32768.0 - float).abs() < 0.01);
+        }
+
+        let lowest = 1500.0 - 1.5 * TONE_SPACING;
+        let mut demodulator =
+            crate::demod::FixedDemodulator::new(sample_rate, lowest).unwrap();
+        let mut magnitudes = [[0u32; 4]; 162];
+        for &sample in &samples {
+            let symbol = demodulator.symbol();
+            if let Some(tones) = demodulator.push(sample) {
+                magnitudes[symbol] = tones;
+            }
+        }
+        assert!(demodulator.is_complete());
+        assert_eq!(crate::demod::fixed_hard_symbols(&magnitudes), symbols);
+    }
+
+    #[test]
+    fn test_ramp() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let plain: std::vec::Vec<f32> =
+            Modulator::new(&symbols, 8000, 1500.0).unwrap().collect();
+        let ramped: std::vec::Vec<f32> = Modulator::new(&symbols, 8000, 1500.0)
+            .unwrap()
+            .with_ramp(10)
+            .collect();
+        let fixed: std::vec::Vec<i16> =
+            FixedModulator::new(&symbols, 8000, 1500.0)
+                .unwrap()
+                .with_ramp(10)
+                .collect();
+        assert_eq!(ramped.len(), plain.len());
+
+        // 80 samples ramp up at the start and down at the end, following a
+        // raised cosine, and the rest are untouched.
+        let total = plain.len();
+        for (i, (&ramped, &plain)) in ramped.iter().zip(&plain).enumerate() {
+            let edge = i.min(total - i - 1);
+            let gain = if edge < 80 {
+                0.5 - 0.5 * (core::f32::consts::PI * edge as f32 / 80.0).cos()
+            } else {
+                1.0
+            };
+            assert!((ramped - plain * gain).abs() < 1e-5, "{}", i);
+            assert!((fixed[i] as f32 / 32768.0 - ramped).abs() < 0.01, "{}", i);
+        }
+        assert_eq!(ramped[0], 0.0);
+        assert_eq!(fixed[0], 0);
+
+        // I/Q samples ramp the same way.
+        let iq: std::vec::Vec<Complex> = IqModulator::new(&symbols, 375, 0.0)
+            .unwrap()
+            .with_ramp(1000)
+            .collect();
+        assert_eq!(iq[0].norm(), 0.0);
+        assert!((iq[187].norm() - 0.5).abs() < 0.01);
+        assert!((iq[375].norm() - 1.0).abs() < 1e-4);
+        assert!(iq[iq.len() - 1].norm() < 1e-4);
+    }
+
+    #[test]
+    fn test_id() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let id = Identification::new("K1A").with_wpm(25);
+        assert_eq!(
+            Modulator::new(&symbols, 8000, 1500.0)
+                .unwrap()
+                .with_id(id.with_frequency(4000.0))
+                .err(),
+            Some(Error::InvalidFrequency)
+        );
+
+        // The identification follows the symbols, 384 samples to a unit.
+        let plain: std::vec::Vec<f32> =
+            Modulator::new(&symbols, 8000, 1500.0).unwrap().collect();
+        let modulator = Modulator::new(&symbols, 8000, 1500.0)
+            .unwrap()
+            .with_id(id)
+            .unwrap();
+        let keys: std::vec::Vec<bool> = id.keying().collect();
+        assert_eq!(modulator.total(), plain.len() + keys.len() * 384);
+        assert_eq!(modulator.len(), modulator.total());
+        let samples: std::vec::Vec<f32> = modulator.collect();
+        assert_eq!(samples[..plain.len()], plain[..]);
+
+        // Each unit is a tone while the key is down and silent otherwise,
+        // ramping up and down when the key changes.
+        for (unit, &key) in keys.iter().enumerate() {
+            let start = plain.len() + unit * 384;
+            let samples = &samples[start..start + 384];
+            let peak = samples[40..344].iter().fold(0f32, |a, &b| a.max(b));
+            if key {
+                assert!(peak > 0.99, "{}", unit);
+            } else {
+                assert!(samples.iter().all(|&sample| sample == 0.0));
+            }
+            if key && !keys[unit - 1] {
+                assert_eq!(samples[0], 0.0);
+            }
+        }
+
+        // I/Q samples may send it below the center of the baseband.
+        let modulator = IqModulator::new(&symbols, 375, 0.0).unwrap();
+        assert_eq!(
+            modulator.clone().with_id(id.with_frequency(190.0)).err(),
+            Some(Error::InvalidFrequency)
+        );
+        let samples: std::vec::Vec<Complex> = modulator
+            .with_id(id.with_frequency(-50.0))
+            .unwrap()
+            .skip(162 * 256 + 7 * 18 + 9)
+            .take(2)
+            .collect();
+        let step = samples[1] * samples[0].conj();
+        let frequency = step.im.atan2(step.re) * 375.0 / TAU as f32;
+        assert!((frequency + 50.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_fill_buffer() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let mut fixed = FixedModulator::new(&symbols, 8000, 1500.0).unwrap();
+        let total = fixed.total();
+        let expected: std::vec::Vec<i16> = fixed.clone().collect();
+
+        // Two halves of a DMA buffer, refilled in turn until the end.
+        let mut buffer = [[1i16; 500]; 2];
+        let mut samples = std::vec::Vec::new();
+        let mut half = 0;
+        loop {
+            let remaining = fixed.fill_buffer(&mut buffer[half]);
+            samples.extend_from_slice(&buffer[half]);
+            assert_eq!(remaining, total.saturating_sub(samples.len()));
+            if remaining == 0 {
+                break;
+            }
+            half ^= 1;
+        }
+        assert_eq!(samples.len(), total.div_ceil(500) * 500);
+        assert_eq!(samples[..total], expected[..]);
+        assert!(samples[total..].iter().all(|&sample| sample == 0));
+        assert_eq!(fixed.fill_buffer(&mut buffer[0]), 0);
+        assert_eq!(buffer[0], [0; 500]);
+    }
+}
@@ -0,0 +1,524 @@
+//! Morse code, for CW beacons and for an identification sent after a
+//! transmission where a license requires one.
+//!
+//! Text is sent in three stages, each available on its own:
+//!
+//! - [`code`] gives the dots and dashes of each character.
+//! - [`Elements`] gives the timing of a message as runs of units, the
+//!   length of a dot, with the key down or up, and [`Morse`] gives the
+//!   state of the key for each unit, for transmitters that key a carrier
+//!   themselves.
+//! - [`Envelope`] gives the amplitude of each sample, ramping as the key
+//!   goes down and up to avoid key clicks, and [`CwModulator`] generates the
+//!   samples of a keyed tone.
+//!
+//! An [`Identification`] added to a
+//! [`Modulator`](crate::modulate::Modulator) is sent as a keyed tone after
+//! the symbols.
+
+use core::f64::consts::{PI, TAU};
+use core::iter::{Chain, Repeat, Take};
+
+use crate::{math, Error};
+
+// The lengths, in units, of the gaps between the elements of a character,
+// between characters, and between words.
+const ELEMENT_GAP: u8 = 1;
+const CHARACTER_GAP: u8 = 3;
+const WORD_GAP: u8 = 7;
+
+// The length of the ramps as the key goes down and up, in milliseconds.
+const RAMP: u32 = 5;
+
+/// The elements of a character in Morse code, dots and dashes such as
+/// `"-.-"` for K, or `None` for a character without one.
+///
+/// Letters of either case, digits, and the characters `/?.,=` have codes.
+pub fn code(c: char) -> Option<&'static str> {
+    let c = u8::try_from(c).ok()?;
+    let code: &'static [u8] = match c.to_ascii_uppercase() {
+        b'A' => b".-",
+        b'B' => b"-...",
+        b'C' => b"-.-.",
+        b'D' => b"-..",
+        b'E' => b".",
+        b'F' => b"..-.",
+        b'G' => b"--.",
+        b'H' => b"....",
+        b'I' => b"..",
+        b'J' => b".---",
+        b'K' => b"-.-",
+        b'L' => b".-..",
+        b'M' => b"--",
+        b'N' => b"-.",
+        b'O' => b"---",
+        b'P' => b".--.",
+        b'Q' => b"--.-",
+        b'R' => b".-.",
+        b'S' => b"...",
+        b'T' => b"-",
+        b'U' => b"..-",
+        b'V' => b"...-",
+        b'W' => b".--",
+        b'X' => b"-..-",
+        b'Y' => b"-.--",
+        b'Z' => b"--..",
+        b'0' => b"-----",
+        b'1' => b".----",
+        b'2' => b"..---",
+        b'3' => b"...--",
+        b'4' => b"....-",
+        b'5' => b".....",
+        b'6' => b"-....",
+        b'7' => b"--...",
+        b'8' => b"---..",
+        b'9' => b"----.",
+        b'/' => b"-..-.",
+        b'?' => b"..--..",
+        b'.' => b".-.-.-",
+        b',' => b"--..--",
+        b'=' => b"-...-",
+        _ => return None,
+    };
+    core::str::from_utf8(code).ok()
+}
+
+/// A run of units, the length of a dot, with the key down or up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Element {
+    /// Whether the key is down.
+    pub key: bool,
+    /// The length of the run: 1 unit for a dot, 3 for a dash or the gap
+    /// between characters, and so on.
+    pub units: u8,
+}
+
+/// The timing of a message sent in Morse code, as runs of units with the
+/// key down or up.
+///
+/// Letters, digits, spaces, and the characters `/?.,=` are sent; anything
+/// else is skipped. The message ends with the last element, without a gap
+/// after it.
+#[derive(Debug, Clone)]
+pub struct Elements<'a> {
+    text: core::str::Chars<'a>,
+    // The elements of the character being sent, and the index of the next.
+    code: &'static [u8],
+    element: usize,
+    // The units of silence before the next element, and that element's
+    // length while they're sent.
+    gap: u8,
+    held: u8,
+}
+
+impl<'a> Elements<'a> {
+    /// Creates the timing of `text`.
+    pub fn new(text: &'a str) -> Self {
+        Self {
+            text: text.chars(),
+            code: &[],
+            element: 0,
+            gap: 0,
+            held: 0,
+        }
+    }
+}
+
+impl Iterator for Elements<'_> {
+    type Item = Element;
+
+    fn next(&mut self) -> Option<Element> {
+        if self.held > 0 {
+            let units = core::mem::take(&mut self.held);
+            return Some(Element { key: true, units });
+        }
+        while self.element == self.code.len() {
+            let c = self.text.next()?;
+            // Gaps are only sent between elements, not before the first.
+            if c == ' ' {
+                if self.gap > 0 {
+                    self.gap = WORD_GAP;
+                }
+            } else if let Some(code) = code(c) {
+                self.code = code.as_bytes();
+                self.element = 0;
+                if self.gap > 0 {
+                    self.gap = self.gap.max(CHARACTER_GAP);
+                }
+            }
+        }
+
+        let units = if self.code[self.element] == b'-' {
+            3
+        } else {
+            1
+        };
+        self.element += 1;
+        match core::mem::replace(&mut self.gap, ELEMENT_GAP) {
+            0 => Some(Element { key: true, units }),
+            gap => {
+                self.held = units;
+                Some(Element {
+                    key: false,
+                    units: gap,
+                })
+            }
+        }
+    }
+}
+
+/// The state of the key for each unit of a message sent in Morse code:
+/// `true` while the key is down. See [`Elements`].
+#[derive(Debug, Clone)]
+pub struct Morse<'a> {
+    elements: Elements<'a>,
+    // The state of the key and the units left with it so.
+    key: bool,
+    units: u8,
+}
+
+impl<'a> Morse<'a> {
+    /// Creates the keying of `text`.
+    pub fn new(text: &'a str) -> Self {
+        Self {
+            elements: Elements::new(text),
+            key: false,
+            units: 0,
+        }
+    }
+}
+
+impl Iterator for Morse<'_> {
+    type Item = bool;
+
+    fn next(&mut self) -> Option<bool> {
+        if self.units == 0 {
+            let element = self.elements.next()?;
+            (self.key, self.units) = (element.key, element.units);
+        }
+        self.units -= 1;
+        Some(self.key)
+    }
+}
+
+/// The amplitude of each sample of a message sent in Morse code, from 0.0
+/// to 1.0, ramping along a raised cosine over 5 ms as the key goes down
+/// and up.
+#[derive(Debug, Clone)]
+pub struct Envelope<'a> {
+    keys: Chain<Take<Repeat<bool>>, Morse<'a>>,
+    // The state of the key in the previous, current, and next units.
+    window: [bool; 3],
+    unit: usize,
+    units: usize,
+    // The length of a unit and of the ramps in samples, and the index of
+    // the next sample.
+    unit_len: usize,
+    ramp: usize,
+    index: usize,
+}
+
+impl<'a> Envelope<'a> {
+    /// Creates the envelope of `text` sent at `wpm` words per minute, with
+    /// samples at `sample_rate` Hz.
+    pub fn new(text: &'a str, sample_rate: u32, wpm: u8) -> Self {
+        Self::after_gap(text, sample_rate, wpm, 0)
+    }
+
+    // Creates the envelope of `text` after `gap` units of silence.
+    fn after_gap(text: &'a str, sample_rate: u32, wpm: u8, gap: usize) -> Self {
+        let mut keys =
+            core::iter::repeat(false).take(gap).chain(Morse::new(text));
+        let units = keys.clone().count();
+        let current = keys.next().unwrap_or(false);
+        let next = keys.next().unwrap_or(false);
+        let unit_len = unit_len(sample_rate, wpm);
+        let ramp = (sample_rate as u64 * RAMP as u64 / 1000) as usize;
+        Self {
+            keys,
+            window: [false, current, next],
+            unit: 0,
+            units,
+            unit_len,
+            ramp: ramp.min(unit_len / 2),
+            index: 0,
+        }
+    }
+
+    /// The number of samples in the whole message.
+    pub fn total(&self) -> usize {
+        self.units * self.unit_len
+    }
+
+    /// The length of a unit in samples.
+    pub fn unit_len(&self) -> usize {
+        self.unit_len
+    }
+}
+
+impl Iterator for Envelope<'_> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let unit = self.index / self.unit_len;
+        if unit >= self.units {
+            return None;
+        }
+        while self.unit < unit {
+            self.unit += 1;
+            let next = self.keys.next().unwrap_or(false);
+            self.window = [self.window[1], self.window[2], next];
+        }
+
+        let [previous, current, next] = self.window;
+        let mut amplitude = current as u8 as f64;
+        if current && self.ramp > 0 {
+            let rise = |n: usize| {
+                0.5 - 0.5 * math::sin_cos(PI * n as f64 / self.ramp as f64).1
+            };
+            let position = self.index % self.unit_len;
+            let remaining = self.unit_len - 1 - position;
+            if !previous && position < self.ramp {
+                amplitude = rise(position);
+            }
+            if !next && remaining < self.ramp {
+                amplitude = amplitude.min(rise(remaining));
+            }
+        }
+        self.index += 1;
+        Some(amplitude as f32)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.total().saturating_sub(self.index);
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for Envelope<'_> {}
+
+/// Generates the audio samples of a message sent in Morse code as a keyed
+/// tone, each from -1.0 to 1.0.
+#[derive(Debug, Clone)]
+pub struct CwModulator<'a> {
+    envelope: Envelope<'a>,
+    // The phase step of the tone per sample, and the phase of the next.
+    step: f64,
+    phase: f64,
+}
+
+impl<'a> CwModulator<'a> {
+    /// Creates a modulator sending `text` at `wpm` words per minute as a
+    /// tone of `frequency` Hz, with samples at `sample_rate` Hz.
+    ///
+    /// The sample rate and the tone are checked as
+    /// [`Modulator::new`](crate::modulate::Modulator::new) checks them,
+    /// returning [`Error::InvalidSampleRate`] or
+    /// [`Error::InvalidFrequency`].
+    pub fn new(
+        text: &'a str,
+        sample_rate: u32,
+        frequency: f32,
+        wpm: u8,
+    ) -> Result<Self, Error> {
+        if sample_rate == 0 {
+            return Err(Error::InvalidSampleRate);
+        }
+        if frequency <= 0.0 || frequency >= sample_rate as f32 / 2.0 {
+            return Err(Error::InvalidFrequency);
+        }
+        Ok(Self {
+            envelope: Envelope::new(text, sample_rate, wpm),
+            step: TAU * frequency as f64 / sample_rate as f64,
+            phase: 0.0,
+        })
+    }
+
+    /// The number of samples in the whole message.
+    pub fn total(&self) -> usize {
+        self.envelope.total()
+    }
+
+    /// Writes the next samples of the keyed tone to `samples` and returns
+    /// how many it wrote, which is less than the buffer holds only once the
+    /// last element of the message has been sent.
+    pub fn fill(&mut self, samples: &mut [f32]) -> usize {
+        crate::fill(self.by_ref(), samples)
+    }
+}
+
+impl Iterator for CwModulator<'_> {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let amplitude = self.envelope.next()?;
+        let sample = math::sin_cos(self.phase).0 as f32 * amplitude;
+        self.phase = (self.phase + self.step) % TAU;
+        Some(sample)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.envelope.size_hint()
+    }
+}
+
+impl ExactSizeIterator for CwModulator<'_> {}
+
+// The length of a unit in samples at `sample_rate` Hz and `wpm` words per
+// minute.
+fn unit_len(sample_rate: u32, wpm: u8) -> usize {
+    let samples = sample_rate as u64 * 6 / (5 * wpm.max(1) as u64);
+    (samples as usize).max(1)
+}
+
+/// A Morse identification sent after the symbols of a transmission, such as
+/// the callsign.
+///
+/// By default it's sent at 20 words per minute, at the frequency of the
+/// center of the transmission, after a gap the length of a space between
+/// words.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Identification<'a> {
+    text: &'a str,
+    wpm: u8,
+    frequency: Option<f32>,
+}
+
+impl<'a> Identification<'a> {
+    /// Creates an identification sending `text`.
+    pub fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            wpm: 20,
+            frequency: None,
+        }
+    }
+
+    /// Sends the identification at `wpm` words per minute, at least 1.
+    pub fn with_wpm(self, wpm: u8) -> Self {
+        Self {
+            wpm: wpm.max(1),
+            ..self
+        }
+    }
+
+    /// Sends the identification at `frequency` Hz, in the same terms as
+    /// the frequency of the transmission it follows.
+    pub fn with_frequency(self, frequency: f32) -> Self {
+        Self {
+            frequency: Some(frequency),
+            ..self
+        }
+    }
+
+    /// The frequency to send the identification at, if not that of the
+    /// transmission.
+    pub fn frequency(&self) -> Option<f32> {
+        self.frequency
+    }
+
+    /// The length of a unit, a dot, in seconds: 1.2 seconds divided by the
+    /// words per minute, as the word PARIS is 50 units long.
+    pub fn unit(&self) -> f32 {
+        1.2 / self.wpm as f32
+    }
+
+    /// The state of the key for each unit of the identification, beginning
+    /// with the gap before it.
+    pub fn keying(&self) -> impl Iterator<Item = bool> + Clone + 'a {
+        core::iter::repeat(false)
+            .take(WORD_GAP as usize)
+            .chain(Morse::new(self.text))
+    }
+
+    /// The amplitude of each sample of the identification at
+    /// `sample_rate` Hz, beginning with the gap before it.
+    pub fn envelope(&self, sample_rate: u32) -> Envelope<'a> {
+        let gap = WORD_GAP as usize;
+        Envelope::after_gap(self.text, sample_rate, self.wpm, gap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::String;
+
+    // Draws the keying of `text`, `=` for each unit the key is down.
+    fn draw(text: &str) -> String {
+        Morse::new(text)
+            .map(|key| if key { '=' } else { '.' })
+            .collect()
+    }
+
+    #[test]
+    fn test_morse() {
+        assert_eq!(draw("K1a"), "===.=.===...=.===.===.===.===...=.===");
+        assert_eq!(draw("e e"), "=.......=");
+        assert_eq!(draw("  E#  "), "=");
+        assert_eq!(draw(""), "");
+
+        // PARIS is 50 units long with the space after it.
+        assert_eq!(Morse::new("PARIS").count(), 50 - 7);
+        assert_eq!(Morse::new("PARIS PARIS").count(), 100 - 7);
+    }
+
+    #[test]
+    fn test_code() {
+        assert_eq!(code('K'), Some("-.-"));
+        assert_eq!(code('k'), Some("-.-"));
+        assert_eq!(code('/'), Some("-..-."));
+        assert_eq!(code(' '), None);
+        assert_eq!(code('é'), None);
+    }
+
+    #[test]
+    fn test_elements() {
+        let elements: std::vec::Vec<(bool, u8)> = Elements::new("E T")
+            .map(|element| (element.key, element.units))
+            .collect();
+        assert_eq!(elements, [(true, 1), (false, 7), (true, 3)]);
+    }
+
+    #[test]
+    fn test_cw_modulator() {
+        assert_eq!(
+            CwModulator::new("K1ABC", 8000, 4000.0, 20).err(),
+            Some(Error::InvalidFrequency)
+        );
+
+        // 20 WPM at 8000 Hz is 480 samples to a unit, the tone ramping up
+        // and down over 40 samples either side of each element.
+        let envelope = Envelope::new("EE", 8000, 20);
+        assert_eq!(envelope.unit_len(), 480);
+        assert_eq!(envelope.len(), 5 * 480);
+        let amplitudes: std::vec::Vec<f32> = envelope.collect();
+        assert_eq!(amplitudes[0], 0.0);
+        assert!((amplitudes[20] - 0.5).abs() < 1e-6);
+        assert!(amplitudes[40..440].iter().all(|&a| a == 1.0));
+        assert!(amplitudes[480..1920].iter().all(|&a| a == 0.0));
+        assert_eq!(amplitudes[1920..], amplitudes[..480]);
+
+        let mut modulator = CwModulator::new("EE", 8000, 700.0, 20).unwrap();
+        let mut samples = std::vec![0f32; modulator.total()];
+        assert_eq!(modulator.fill(&mut samples), samples.len());
+        assert_eq!(modulator.next(), None);
+        for (n, (&sample, &amplitude)) in
+            samples.iter().zip(&amplitudes).enumerate()
+        {
+            let tone = (TAU * 700.0 * n as f64 / 8000.0).sin() as f32;
+            assert!((sample - tone * amplitude).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_identification() {
+        let id = Identification::new("K1ABC/7").with_wpm(24);
+        assert_eq!(id.unit(), 0.05);
+        assert_eq!(id.envelope(12000).unit_len(), 600);
+        assert_eq!(id.frequency(), None);
+        assert_eq!(id.with_frequency(800.0).frequency(), Some(800.0));
+        assert!(id.keying().take(7).all(|key| !key));
+        assert_eq!(id.keying().count(), 7 + Morse::new("K1ABC/7").count());
+    }
+}
@@ -0,0 +1,122 @@
+// Integer sines for the numerically controlled oscillators of the fixed
+// point modulator and demodulator, which use neither floating point nor
+// libm. A phase is a fraction of a cycle, a full cycle being 2^32.
+//
+// By default the sine is looked up in a quarter cycle table, by the top 10
+// bits of the phase. With the `cordic` feature it's computed instead by
+// CORDIC, which takes no table and uses every bit of the phase, at the cost
+// of a couple of dozen shifts and adds.
+
+// The first quarter of a cycle of a sine wave in Q15, 256 entries to the
+// quarter and the peak.
+#[cfg(not(feature = "cordic"))]
+static QUARTER_SINE: [i16; 257] = quarter_sine();
+
+#[cfg(not(feature = "cordic"))]
+const fn quarter_sine() -> [i16; 257] {
+    let mut table = [0i16; 257];
+    let mut i = 0;
+    while i < 257 {
+        // A Taylor series about 0, where it's accurate to far better than
+        // the table's resolution up to π/2.
+        let x = core::f64::consts::TAU * i as f64 / 1024.0;
+        let mut term = x;
+        let mut sine = x;
+        let mut n = 1;
+        while n < 10 {
+            term *= -x * x / ((2 * n) * (2 * n + 1)) as f64;
+            sine += term;
+            n += 1;
+        }
+        table[i] = (sine * 32767.0 + 0.5) as i16;
+        i += 1;
+    }
+    table
+}
+
+// Returns the sine and cosine of `phase` in Q15.
+#[cfg(not(feature = "cordic"))]
+pub(crate) fn sin_cos(phase: u32) -> (i16, i16) {
+    (
+        sine((phase >> 22) as usize),
+        sine((phase >> 22) as usize + 256),
+    )
+}
+
+// The sine of `index` 1024ths of a cycle, from the quarter cycle table.
+#[cfg(not(feature = "cordic"))]
+fn sine(index: usize) -> i16 {
+    let index = index % 1024;
+    match index / 256 {
+        0 => QUARTER_SINE[index],
+        1 => QUARTER_SINE[512 - index],
+        2 => -QUARTER_SINE[index - 512],
+        _ => -QUARTER_SINE[1024 - index],
+    }
+}
+
+// atan(2^-i) for each iteration of CORDIC, as a phase.
+#[cfg(feature = "cordic")]
+const ATAN: [i32; 24] = [
+    536870912, 316933406, 167458907, 85004756, 42667331, 21354465, 10679838,
+    5340245, 2670163, 1335087, 667544, 333772, 166886, 83443, 41722, 20861,
+    10430, 5215, 2608, 1304, 652, 326, 163, 81,
+];
+
+// The reciprocal of the gain of the iterations of CORDIC, in Q30.
+#[cfg(feature = "cordic")]
+const GAIN: i32 = 652032874;
+
+// Returns the sine and cosine of `phase` in Q15.
+#[cfg(feature = "cordic")]
+pub(crate) fn sin_cos(phase: u32) -> (i16, i16) {
+    // CORDIC converges for angles within ±π/2, so angles beyond are turned
+    // half a cycle and the results negated.
+    let mut angle = phase as i32;
+    let mut sign = 1;
+    if angle.unsigned_abs() > 1 << 30 {
+        angle = angle.wrapping_sub(i32::MIN);
+        sign = -1;
+    }
+
+    // Rotate (1, 0), prescaled by the gain, by the angle in steps of
+    // ±atan(2^-i), driving the remaining angle to 0.
+    let (mut x, mut y) = (GAIN, 0i32);
+    for (i, &step) in ATAN.iter().enumerate() {
+        let (dx, dy) = (y >> i, x >> i);
+        if angle >= 0 {
+            (x, y) = (x - dx, y + dy);
+            angle -= step;
+        } else {
+            (x, y) = (x + dx, y - dy);
+            angle += step;
+        }
+    }
+
+    let q15 = |value: i32| {
+        let value = sign * ((value + (1 << 14)) >> 15);
+        value.clamp(-32767, 32767) as i16
+    };
+    (q15(y), q15(x))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sin_cos() {
+        for i in 0..4096u32 {
+            let phase = i.wrapping_mul(1_048_573);
+            let (sin, cos) = sin_cos(phase);
+            let x = core::f64::consts::TAU * phase as f64 / 4294967296.0;
+            // The table lookup is off by up to 1/1024th of a cycle.
+            assert!((sin as f64 / 32767.0 - x.sin()).abs() < 0.007, "{}", i);
+            assert!((cos as f64 / 32767.0 - x.cos()).abs() < 0.007, "{}", i);
+        }
+        assert_eq!(sin_cos(0), (0, 32767));
+        assert_eq!(sin_cos(1 << 30), (32767, 0));
+        assert_eq!(sin_cos(2 << 30), (0, -32767));
+        assert_eq!(sin_cos(3 << 30), (-32767, 0));
+    }
+}
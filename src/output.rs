@@ -0,0 +1,439 @@
+//! Driving outputs other than a 16-bit DAC, such as a GPIO pin, a PWM
+//! timer, or a DAC of fewer bits, from 16-bit samples like those of a
+//! [`FixedModulator`](crate::modulate::FixedModulator).
+
+use crate::Error;
+
+// Full scale of the 1-bit output of a `SigmaDelta`, twice the largest input
+// so that the loop stays stable.
+const FULL_SCALE: i32 = 1 << 16;
+
+/// Converts 16-bit samples into a noise shaped stream of bits, for a board
+/// without a DAC to play audio from a single GPIO pin.
+///
+/// The samples are interpolated up to the bit rate, typically 1 to 4 MHz,
+/// and quantized to one bit by a second order sigma-delta modulator, which
+/// pushes the quantization noise up in frequency, well clear of the audio.
+/// An RC low pass filter on the pin, with a corner around 3 kHz, recovers
+/// the audio at half of full scale.
+///
+/// Bits are produced one at a time with [`SigmaDelta::next_bit`], for a
+/// timer interrupt toggling the pin, or packed 32 to a word, most
+/// significant bit first, for SPI or a programmable I/O peripheral to shift
+/// out, the last word padded with low bits. A high bit drives the pin high.
+#[derive(Debug, Clone)]
+pub struct SigmaDelta<I> {
+    samples: I,
+    sample_rate: u32,
+    bit_rate: u32,
+    // The samples either side of the next bit, and how far the bit is from
+    // the first, in units of 1/`bit_rate` of a sample.
+    previous: i32,
+    next: Option<i32>,
+    fraction: u32,
+    integrators: [i32; 2],
+}
+
+impl<I: Iterator<Item = i16>> SigmaDelta<I> {
+    /// Creates a bit stream at `bit_rate` bits per second from `samples` at
+    /// `sample_rate` Hz.
+    ///
+    /// Returns [`Error::InvalidSampleRate`] if either rate is 0 or the bit
+    /// rate is below the sample rate.
+    pub fn new(
+        mut samples: I,
+        sample_rate: u32,
+        bit_rate: u32,
+    ) -> Result<Self, Error> {
+        if sample_rate == 0 || bit_rate < sample_rate {
+            return Err(Error::InvalidSampleRate);
+        }
+        Ok(Self {
+            next: samples.next().map(i32::from),
+            samples,
+            sample_rate,
+            bit_rate,
+            previous: 0,
+            fraction: 0,
+            integrators: [0; 2],
+        })
+    }
+
+    /// The next bit, or `None` after the last sample.
+    pub fn next_bit(&mut self) -> Option<bool> {
+        let next = self.next?;
+        let step = (next - self.previous) as i64 * self.fraction as i64
+            / self.bit_rate as i64;
+        let sample = self.previous + step as i32;
+
+        let bit = self.integrators[1] >= 0;
+        let feedback = if bit { FULL_SCALE } else { -FULL_SCALE };
+        self.integrators[0] += sample - feedback;
+        self.integrators[1] += self.integrators[0] - feedback;
+
+        self.fraction += self.sample_rate;
+        if self.fraction >= self.bit_rate {
+            self.fraction -= self.bit_rate;
+            self.previous = next;
+            self.next = self.samples.next().map(i32::from);
+        }
+        Some(bit)
+    }
+
+    /// Packs the next bits into `words`, 32 to a word, and returns the
+    /// number of words filled, which falls short of `words.len()` only when
+    /// the samples run out.
+    pub fn fill(&mut self, words: &mut [u32]) -> usize {
+        crate::fill(self.by_ref(), words)
+    }
+}
+
+impl<I: Iterator<Item = i16>> Iterator for SigmaDelta<I> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        let mut word = 0;
+        for i in 0..32 {
+            match self.next_bit() {
+                Some(bit) => word |= (bit as u32) << (31 - i),
+                None if i == 0 => return None,
+                None => break,
+            }
+        }
+        Some(word)
+    }
+}
+
+/// Converts 16-bit samples into compare values for a PWM timer, for a board
+/// without a DAC to play audio through an RC low pass filter on the timer's
+/// output pin.
+///
+/// The timer counts from 0 to [`Pwm::top`], so its PWM frequency is its
+/// clock divided by one more than that, and each sample is held for as many
+/// periods as fit in it. The compare values are best written to the timer
+/// by DMA at the start of each period. For example:
+///
+/// - An RP2040 at 125 MHz, with a PWM slice wrapping at 1999 for 62.5 kHz,
+///   playing 12500 Hz samples, 5 periods each.
+/// - An STM32F4 timer clocked at 84 MHz, with an auto reload value of 2099
+///   for 40 kHz, playing 8000 Hz samples, 5 periods each.
+///
+/// ```
+/// use wspr::modulate::FixedModulator;
+/// use wspr::output::Pwm;
+///
+/// let symbols = wspr::encode("K1ABC", "FN42", 37).unwrap();
+/// let modulator = FixedModulator::new(&symbols, 12500, 1500.0).unwrap();
+/// let pwm = Pwm::new(modulator, 12500, 125_000_000, 62500).unwrap();
+/// assert_eq!(pwm.top(), 1999);
+/// assert!(pwm.take(1000).all(|compare| compare <= 1999));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Pwm<I> {
+    samples: I,
+    sample_rate: u32,
+    frequency: u32,
+    top: u16,
+    // The compare value of the current sample, and how far through the
+    // sample the next period is, in units of 1/`frequency` of a sample.
+    compare: Option<u16>,
+    fraction: u32,
+}
+
+impl<I: Iterator<Item = i16>> Pwm<I> {
+    /// Creates compare values from `samples` at `sample_rate` Hz, for a
+    /// timer clocked at `clock` Hz producing PWM at `frequency` Hz.
+    ///
+    /// Returns [`Error::InvalidSampleRate`] if the sample rate is 0 or above
+    /// the PWM frequency, or [`Error::InvalidFrequency`] if the timer would
+    /// count to fewer than 2 or more than 65536 in each period.
+    pub fn new(
+        mut samples: I,
+        sample_rate: u32,
+        clock: u32,
+        frequency: u32,
+    ) -> Result<Self, Error> {
+        if sample_rate == 0 || frequency < sample_rate {
+            return Err(Error::InvalidSampleRate);
+        }
+        let top = (clock / frequency)
+            .checked_sub(1)
+            .and_then(|top| u16::try_from(top).ok())
+            .filter(|&top| top > 0)
+            .ok_or(Error::InvalidFrequency)?;
+        let compare = samples.next().map(|sample| scale(sample, top));
+        Ok(Self {
+            samples,
+            sample_rate,
+            frequency,
+            top,
+            compare,
+            fraction: 0,
+        })
+    }
+
+    /// The largest value the timer counts to, its auto reload or wrap value.
+    /// Compare values range from 0 to this.
+    pub fn top(&self) -> u16 {
+        self.top
+    }
+
+    /// Computes the compare values for the next samples into `values`,
+    /// ready for the timer's DMA, and returns how many there were; the last
+    /// block may be short.
+    pub fn fill(&mut self, values: &mut [u16]) -> usize {
+        crate::fill(self.by_ref(), values)
+    }
+}
+
+impl<I: Iterator<Item = i16>> Iterator for Pwm<I> {
+    type Item = u16;
+
+    fn next(&mut self) -> Option<u16> {
+        let compare = self.compare?;
+        self.fraction += self.sample_rate;
+        if self.fraction >= self.frequency {
+            self.fraction -= self.frequency;
+            let top = self.top;
+            self.compare = self.samples.next().map(|sample| scale(sample, top));
+        }
+        Some(compare)
+    }
+}
+
+// Scales a sample to a compare value from 0 to `top`.
+fn scale(sample: i16, top: u16) -> u16 {
+    (((sample as i32 + 32768) as u32 * (top as u32 + 1)) >> 16) as u16
+}
+
+/// Quantizes 16-bit samples for a DAC of fewer bits, such as the 8 to 12
+/// bit DACs of many microcontrollers, giving the codes to write to it from
+/// 0 for the lowest level.
+///
+/// Truncating a tone to a few bits leaves an error that repeats with the
+/// tone, landing as spurs on its harmonics and mixing products, some inside
+/// the 200 Hz WSPR window. [`Quantizer::with_dither`] adds triangular (TPDF)
+/// dither, turning the spurs into a little flat noise, and
+/// [`Quantizer::with_noise_shaping`] feeds back each sample's error so that
+/// the noise is pushed up in frequency, away from the audio.
+#[derive(Debug, Clone)]
+pub struct Quantizer<I> {
+    samples: I,
+    bits: u8,
+    dither: bool,
+    noise_shaping: bool,
+    // The error of the last sample, and the state of the random numbers
+    // for the dither.
+    error: i32,
+    random: u32,
+}
+
+impl<I: Iterator<Item = i16>> Quantizer<I> {
+    /// Creates codes for a DAC of `bits` bits, from 1 to 16, from
+    /// `samples`.
+    pub fn new(samples: I, bits: u8) -> Self {
+        Self {
+            samples,
+            bits: bits.clamp(1, 16),
+            dither: false,
+            noise_shaping: false,
+            error: 0,
+            random: 0x2545_F491,
+        }
+    }
+
+    /// Adds triangular dither of up to one level either side to each
+    /// sample.
+    pub fn with_dither(self) -> Self {
+        Self {
+            dither: true,
+            ..self
+        }
+    }
+
+    /// Shapes the quantization noise with first order error feedback.
+    pub fn with_noise_shaping(self) -> Self {
+        Self {
+            noise_shaping: true,
+            ..self
+        }
+    }
+
+    /// The largest code, for the highest level.
+    pub fn max_code(&self) -> u16 {
+        ((1u32 << self.bits) - 1) as u16
+    }
+
+    /// Quantizes the next samples into `codes` for the DAC, returning the
+    /// count, which only falls short of `codes.len()` once the samples are
+    /// used up.
+    pub fn fill(&mut self, codes: &mut [u16]) -> usize {
+        crate::fill(self.by_ref(), codes)
+    }
+
+    // A random number from 0 up to `level`, by xorshift.
+    fn uniform(&mut self, level: i32) -> i32 {
+        self.random ^= self.random << 13;
+        self.random ^= self.random >> 17;
+        self.random ^= self.random << 5;
+        ((self.random >> 16) as i32 * level) >> 16
+    }
+}
+
+impl<I: Iterator<Item = i16>> Iterator for Quantizer<I> {
+    type Item = u16;
+
+    fn next(&mut self) -> Option<u16> {
+        let sample = self.samples.next()? as i32 + 32768;
+        let level = 1 << (16 - self.bits);
+        let value = sample - self.error;
+        let dither = if self.dither {
+            self.uniform(level) + self.uniform(level) - level
+        } else {
+            0
+        };
+
+        let max = self.max_code() as i32;
+        let code =
+            ((value + dither + level / 2) >> (16 - self.bits)).clamp(0, max);
+        if self.noise_shaping {
+            // Limited so that clipping can't build up the error.
+            self.error = (code * level - value).clamp(-2 * level, 2 * level);
+        }
+        Some(code as u16)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulate::FixedModulator;
+    use crate::TONE_SPACING;
+
+    #[test]
+    fn test_sigma_delta() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let modulator = FixedModulator::new(&symbols, 4000, 1500.0).unwrap();
+        assert_eq!(
+            SigmaDelta::new(modulator.clone(), 4000, 2000).err(),
+            Some(Error::InvalidSampleRate)
+        );
+
+        // 16 bits to a sample, which averaged back down to 4000 Hz give the
+        // audio at half scale.
+        let total = modulator.total();
+        let mut bits = SigmaDelta::new(modulator, 4000, 64000).unwrap();
+        let mut words = std::vec![0u32; total / 2 + 1];
+        assert_eq!(bits.fill(&mut words), total / 2);
+        assert_eq!(bits.next(), None);
+
+        let audio: std::vec::Vec<f32> = words
+            .iter()
+            .flat_map(|word| [word >> 16, word & 0xFFFF])
+            .map(|half| half.count_ones() as f32 / 8.0 - 1.0)
+            .collect();
+        let lowest = 1500.0 - 1.5 * TONE_SPACING;
+        let demodulator = crate::demod::Demodulator::new(4000, lowest).unwrap();
+        let magnitudes = demodulator.magnitudes(&audio, 0).unwrap();
+        assert_eq!(crate::hard_symbols(&magnitudes), symbols);
+    }
+
+    #[test]
+    fn test_pwm() {
+        let samples = [i16::MIN, 0, i16::MAX];
+        assert_eq!(
+            Pwm::new(samples.into_iter(), 8000, 84_000_000, 4000).err(),
+            Some(Error::InvalidSampleRate)
+        );
+        assert_eq!(
+            Pwm::new(samples.into_iter(), 8000, 84_000_000, 1000).err(),
+            Some(Error::InvalidSampleRate)
+        );
+        assert_eq!(
+            Pwm::new(samples.into_iter(), 8000, 800_000_000, 8000).err(),
+            Some(Error::InvalidFrequency)
+        );
+
+        // 84 MHz at 40 kHz counts to 2099, 5 periods to a sample.
+        let mut pwm =
+            Pwm::new(samples.into_iter(), 8000, 84_000_000, 40_000).unwrap();
+        assert_eq!(pwm.top(), 2099);
+        let mut values = [0u16; 16];
+        assert_eq!(pwm.fill(&mut values), 15);
+        assert_eq!(values[..5], [0; 5]);
+        assert_eq!(values[5..10], [1050; 5]);
+        assert_eq!(values[10..15], [2099; 5]);
+
+        // A PWM frequency that isn't a multiple of the sample rate holds
+        // each sample for 2 or 3 periods.
+        let pwm = Pwm::new(samples.into_iter(), 8000, 84_000_000, 20_000);
+        assert_eq!(pwm.unwrap().count(), 8);
+    }
+
+    // The power of the error of `codes` of `bits` bits from `samples` at
+    // 48000 Hz, at each frequency from `low` to `high` Hz.
+    fn error_power(
+        samples: &[i16],
+        codes: &[u16],
+        bits: u8,
+        low: u32,
+        high: u32,
+    ) -> std::vec::Vec<f64> {
+        let level = 1 << (16 - bits);
+        let errors: std::vec::Vec<f64> = samples
+            .iter()
+            .zip(codes)
+            .map(|(&s, &c)| (c as i32 * level - 32768 - s as i32) as f64)
+            .collect();
+        (low..=high)
+            .map(|frequency| {
+                let step = core::f64::consts::TAU * frequency as f64 / 48000.0;
+                let (mut re, mut im) = (0.0, 0.0);
+                for (n, &error) in errors.iter().enumerate() {
+                    let (sin, cos) = (step * n as f64).sin_cos();
+                    re += error * cos;
+                    im -= error * sin;
+                }
+                (re * re + im * im) / errors.len() as f64
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_quantizer() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let modulator = FixedModulator::new(&symbols, 48000, 1500.0).unwrap();
+        // A quiet tone, a couple of levels of an 8-bit DAC.
+        let samples: std::vec::Vec<i16> =
+            modulator.take(12000).map(|sample| sample / 64).collect();
+        let quantize = |quantizer: Quantizer<_>| -> std::vec::Vec<u16> {
+            quantizer.collect()
+        };
+
+        let plain = quantize(Quantizer::new(samples.iter().copied(), 8));
+        assert_eq!(plain[0], 128);
+        assert!(plain.iter().all(|&code| code <= 255));
+        assert_eq!(Quantizer::new(samples.iter().copied(), 8).max_code(), 255);
+
+        // Plain quantization leaves a spur on the tone itself, which dither
+        // spreads out into noise.
+        let dithered =
+            quantize(Quantizer::new(samples.iter().copied(), 8).with_dither());
+        let spur = |codes: &[u16]| {
+            let power = error_power(&samples, codes, 8, 1450, 1550);
+            power.iter().fold(0f64, |a, &b| a.max(b))
+        };
+        assert!(spur(&dithered) < spur(&plain) / 10.0);
+
+        // Noise shaping moves the noise out of the WSPR window.
+        let shaped = quantize(
+            Quantizer::new(samples.iter().copied(), 8)
+                .with_dither()
+                .with_noise_shaping(),
+        );
+        let noise = |codes: &[u16]| -> f64 {
+            error_power(&samples, codes, 8, 1400, 1600).iter().sum()
+        };
+        assert!(noise(&shaped) < noise(&dithered) / 5.0);
+    }
+}
@@ -0,0 +1,125 @@
+//! Decoding on every core with `rayon`.
+//!
+//! [`decode`] works as [`search::decode`] does, but refines and decodes the
+//! candidates of each pass in parallel. [`decode_bands`] decodes the
+//! basebands of several bands at once, such as from a wideband receiver
+//! covering more than one WSPR segment.
+
+use std::vec;
+use std::vec::Vec;
+
+use rayon::prelude::*;
+
+use crate::dsp::{Complex, Fft};
+use crate::search::{
+    self, decode_candidate, subtract, Candidate, FFT_LEN, MAX_CANDIDATES,
+    PASSES,
+};
+use crate::spot::Spot;
+use crate::Error;
+
+/// Decodes every transmission in `samples`, a 375 Hz complex baseband, as
+/// [`search::decode_deep`] does, returning the spots found. Spot frequencies
+/// are relative to the center of the baseband.
+///
+/// Each pass searches the passband and then decodes all of its candidates in
+/// parallel, before subtracting the signals decoded. Unlike the sequential
+/// decoder, a signal isn't subtracted before the other candidates of the
+/// same pass are decoded, so the second pass matters more for finding weak
+/// signals beneath strong ones.
+pub fn decode<F: Fft<FFT_LEN>>(
+    samples: &mut [Complex],
+    fft: &mut F,
+    known: &[(&str, &str)],
+) -> Result<Vec<Spot>, Error> {
+    let mut workspace = vec![0f32; search::workspace_len(samples.len())];
+    let mut spots: Vec<Spot> = Vec::new();
+    for _ in 0..PASSES {
+        let mut candidates = [Candidate::default(); MAX_CANDIDATES];
+        let found =
+            search::search(samples, fft, &mut workspace, &mut candidates)?;
+
+        let view = &*samples;
+        let decodes: Vec<_> = candidates[..found]
+            .par_iter()
+            .filter_map(|candidate| decode_candidate(view, candidate, known))
+            .collect();
+
+        // A strong signal may produce several candidates, each of which
+        // decodes to the same message. It's only subtracted once.
+        let mut decoded = false;
+        let mut subtracted = Vec::with_capacity(decodes.len());
+        for (candidate, spot, symbols) in decodes {
+            if subtracted.contains(&spot.message) {
+                continue;
+            }
+            subtract(samples, &symbols, &candidate);
+            subtracted.push(spot.message);
+            decoded = true;
+            match spots.iter_mut().find(|s| s.is_duplicate(&spot)) {
+                Some(existing) if spot.snr > existing.snr => *existing = spot,
+                Some(_) => {}
+                None => spots.push(spot),
+            }
+        }
+
+        // Nothing new was removed, so another search won't find anything
+        // new either.
+        if !decoded {
+            break;
+        }
+    }
+
+    Ok(spots)
+}
+
+/// Decodes the basebands of several bands in parallel, as [`decode`] does,
+/// returning the spots found in each.
+pub fn decode_bands<F: Fft<FFT_LEN> + Default>(
+    bands: &mut [Vec<Complex>],
+    known: &[(&str, &str)],
+) -> Vec<Result<Vec<Spot>, Error>> {
+    bands
+        .par_iter_mut()
+        .map(|samples| decode(samples, &mut F::default(), known))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::Radix2;
+    use crate::search::tests::{noise, synthesize};
+
+    #[test]
+    fn test_decode_bands() {
+        let amplitude =
+            |snr: f32| (10f32.powf(snr / 10.0) * 2500.0 / 375.0).sqrt();
+
+        // Two signals whose tones overlap on one band, and one on another.
+        let mut first = noise(114 * 375);
+        let strong = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let weak = crate::encode("G1ABC", "IO83", 20).unwrap();
+        synthesize(&strong, 10.0, 0.0, 375, amplitude(-12.0), &mut first);
+        synthesize(&weak, 12.5, 0.0, 500, amplitude(-22.0), &mut first);
+
+        let mut second = noise(114 * 375);
+        let other = crate::encode("N6AB", "CM87", 0).unwrap();
+        synthesize(&other, -70.0, 1.0, 400, amplitude(-20.0), &mut second);
+
+        let mut bands = [first, second];
+        let spots = decode_bands::<Radix2<FFT_LEN>>(&mut bands, &[]);
+        fn callsigns(spots: &[Spot]) -> Vec<&str> {
+            let mut callsigns: Vec<_> =
+                spots.iter().map(|s| s.message.callsign()).collect();
+            callsigns.sort();
+            callsigns
+        }
+        assert_eq!(callsigns(spots[0].as_ref().unwrap()), ["G1ABC", "K1ABC"]);
+        assert_eq!(callsigns(spots[1].as_ref().unwrap()), ["N6AB"]);
+
+        let spot = &spots[1].as_ref().unwrap()[0];
+        assert!((spot.frequency + 70.0).abs() < 0.3);
+        assert!((spot.drift - 1.0).abs() <= 0.5);
+    }
+}
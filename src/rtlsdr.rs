@@ -0,0 +1,225 @@
+//! Converting the raw output of an RTL-SDR dongle into the 375 Hz complex
+//! baseband the [`search`](crate::search) works on.
+//!
+//! `rtl_sdr` writes interleaved unsigned 8-bit I/Q samples, centered on 127.5.
+//! [`RtlSdr`] mixes the WSPR passband down to DC and decimates it, a block at
+//! a time, so a dongle's output can be fed straight through as it arrives.
+
+use crate::dsp::{Complex, IqCorrector};
+use crate::search::SAMPLE_RATE;
+use crate::Error;
+
+/// A front end taking interleaved unsigned 8-bit I/Q samples, as written by
+/// `rtl_sdr`, to a 375 Hz complex baseband centered on the WSPR passband.
+///
+/// The sample rate must be a multiple of 375 Hz, such as 2.4 MS/s, 1.2 MS/s,
+/// or 240 kS/s. Each block of samples is mixed down and averaged, so
+/// frequencies away from the passband are attenuated but not removed
+/// entirely; tuning the dongle so that strong signals don't fall near a
+/// multiple of 375 Hz from the passband avoids them aliasing into it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RtlSdr {
+    decimation: usize,
+    // The phase step of the mixer per output sample.
+    block_step: f64,
+    // The phase of the mixer at the start of the current output sample.
+    phase: f64,
+    phasor: Complex,
+    rotation: Complex,
+    sum: Complex,
+    count: usize,
+    // The in-phase half of a sample split across blocks.
+    pending: Option<u8>,
+    corrector: Option<IqCorrector>,
+}
+
+impl RtlSdr {
+    /// Creates a front end for samples at `sample_rate` Hz, where the center
+    /// of the WSPR passband is `offset` Hz from the frequency the dongle is
+    /// tuned to, which may be negative. Returns
+    /// [`Error::InvalidSampleRate`] if the sample rate isn't a multiple of
+    /// 375 Hz, or [`Error::InvalidFrequency`] if the passband isn't within
+    /// the sampled bandwidth.
+    pub fn new(sample_rate: u32, offset: f32) -> Result<Self, Error> {
+        if sample_rate == 0 || sample_rate % SAMPLE_RATE != 0 {
+            return Err(Error::InvalidSampleRate);
+        }
+        let nyquist = sample_rate as f32 / 2.0;
+        if offset.abs() + 100.0 >= nyquist {
+            return Err(Error::InvalidFrequency);
+        }
+
+        let decimation = (sample_rate / SAMPLE_RATE) as usize;
+        let step = -core::f64::consts::TAU * offset as f64 / sample_rate as f64;
+        let block_step = (step * decimation as f64) % core::f64::consts::TAU;
+        Ok(Self {
+            decimation,
+            block_step,
+            phase: 0.0,
+            phasor: Complex::new(1.0, 0.0),
+            rotation: Complex::from_phase(step),
+            sum: Complex::default(),
+            count: 0,
+            pending: None,
+            corrector: None,
+        })
+    }
+
+    /// Removes the dongle's DC offset and I/Q imbalance with an
+    /// [`IqCorrector`] before mixing, which is worthwhile when the passband
+    /// is near the tuned frequency or its images fall in a busy band.
+    pub fn with_correction(self) -> Self {
+        let samples = self.decimation as u32 * SAMPLE_RATE / 4;
+        Self {
+            corrector: Some(IqCorrector::new(samples)),
+            ..self
+        }
+    }
+
+    /// The number of input samples averaged into each output sample.
+    pub fn decimation(&self) -> usize {
+        self.decimation
+    }
+
+    /// The most output samples [`RtlSdr::process`] may produce from a block
+    /// of `len` bytes.
+    pub fn output_len(&self, len: usize) -> usize {
+        let samples = (len + self.pending.is_some() as usize) / 2;
+        (self.count + samples) / self.decimation
+    }
+
+    /// Converts a block of interleaved I/Q bytes, writing the baseband
+    /// samples it completes to `out` and returning how many were written.
+    /// Blocks may be any length, a sample split between blocks is joined.
+    ///
+    /// `out` must hold at least [`RtlSdr::output_len`] samples, otherwise
+    /// [`Error::BufferTooSmall`] is returned and the block is ignored.
+    pub fn process(
+        &mut self,
+        block: &[u8],
+        out: &mut [Complex],
+    ) -> Result<usize, Error> {
+        if out.len() < self.output_len(block.len()) {
+            return Err(Error::BufferTooSmall);
+        }
+
+        let mut block = block;
+        let mut written = 0;
+        if let Some(i) = self.pending.take() {
+            match block.split_first() {
+                Some((&q, rest)) => {
+                    written += self.push(i, q, &mut out[written..]);
+                    block = rest;
+                }
+                None => self.pending = Some(i),
+            }
+        }
+
+        let pairs = block.chunks_exact(2);
+        let rest = pairs.remainder();
+        for pair in pairs {
+            written += self.push(pair[0], pair[1], &mut out[written..]);
+        }
+        if let [i] = rest {
+            self.pending = Some(*i);
+        }
+        Ok(written)
+    }
+
+    // Mixes one sample into the current output sample, writing it to `out`
+    // once complete. Returns the number of samples written.
+    fn push(&mut self, i: u8, q: u8, out: &mut [Complex]) -> usize {
+        let mut sample = Complex::new(i as f32 - 127.5, q as f32 - 127.5);
+        if let Some(corrector) = &mut self.corrector {
+            sample = corrector.correct(sample);
+        }
+        self.sum += sample * self.phasor;
+        self.phasor = self.phasor * self.rotation;
+        self.count += 1;
+        if self.count < self.decimation {
+            return 0;
+        }
+
+        // Restart the mixer from the exact phase, so rounding errors don't
+        // accumulate from one output sample to the next.
+        self.phase = (self.phase + self.block_step) % core::f64::consts::TAU;
+        self.phasor = Complex::from_phase(self.phase);
+        out[0] = self.sum * (1.0 / (127.5 * self.decimation as f32));
+        self.sum = Complex::default();
+        self.count = 0;
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec;
+    use std::vec::Vec;
+
+    // Generates `len` samples of a tone at `frequency` Hz.
+    fn tone(sample_rate: u32, frequency: f64, len: usize) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(2 * len);
+        for n in 0..len {
+            let phase = core::f64::consts::TAU * frequency * n as f64
+                / sample_rate as f64;
+            let (sin, cos) = crate::math::sin_cos(phase);
+            bytes.push(crate::math::round(127.5 + 100.0 * cos) as u8);
+            bytes.push(crate::math::round(127.5 + 100.0 * sin) as u8);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_new() {
+        assert_eq!(RtlSdr::new(2_048_000, 0.0), Err(Error::InvalidSampleRate));
+        assert_eq!(RtlSdr::new(0, 0.0), Err(Error::InvalidSampleRate));
+        assert_eq!(
+            RtlSdr::new(240_000, 120_000.0),
+            Err(Error::InvalidFrequency)
+        );
+        assert_eq!(RtlSdr::new(2_400_000, 0.0).unwrap().decimation(), 6400);
+    }
+
+    #[test]
+    fn test_process() {
+        // The passband 10 kHz above the tuned frequency, with a tone 20 Hz
+        // above its center.
+        let bytes = tone(240_000, 10_020.0, 240_000);
+        let mut rtlsdr = RtlSdr::new(240_000, 10_000.0).unwrap();
+        let mut out = vec![Complex::default(); 375];
+        assert_eq!(
+            rtlsdr.process(&bytes, &mut out[..374]),
+            Err(Error::BufferTooSmall)
+        );
+        assert_eq!(rtlsdr.process(&bytes, &mut out), Ok(375));
+
+        let expected =
+            Complex::from_phase(core::f64::consts::TAU * 20.0 / 375.0);
+        for pair in out.windows(2) {
+            let rotation = pair[1] * pair[0].conj();
+            let rotation = rotation * (1.0 / rotation.norm());
+            assert!((rotation - expected).norm() < 1e-3);
+            assert!((pair[1].norm() - 0.78).abs() < 0.02);
+        }
+
+        // Splitting the input, even mid-sample, makes no difference.
+        let mut split = RtlSdr::new(240_000, 10_000.0).unwrap();
+        let mut parts = vec![Complex::default(); 375];
+        let mut written = 0;
+        for chunk in bytes.chunks(1001) {
+            written += split.process(chunk, &mut parts[written..]).unwrap();
+        }
+        assert_eq!(written, 375);
+        assert_eq!(parts, out);
+
+        // Correction leaves a balanced signal much as it was.
+        let mut corrected =
+            RtlSdr::new(240_000, 10_000.0).unwrap().with_correction();
+        let mut parts = vec![Complex::default(); 375];
+        corrected.process(&bytes, &mut parts).unwrap();
+        for (part, out) in parts[100..].iter().zip(&out[100..]) {
+            assert!((*part - *out).norm() < 0.02);
+        }
+    }
+}
@@ -0,0 +1,833 @@
+//! Searching a complex baseband for WSPR transmissions.
+//!
+//! The search works on complex samples at 375 Hz (32 times slower than the
+//! 12000 Hz audio the tones are defined against), centered on the middle of
+//! the 200 Hz WSPR passband. At this rate each symbol is 256 samples long.
+//!
+//! The search runs in two stages. First a spectrogram is computed with an
+//! [`Fft`], in bins half a tone wide and frames half a symbol apart. Peaks in
+//! the average spectrum are then correlated against the sync vector over a
+//! range of frequency, time, and drift offsets, giving a list of
+//! [`Candidate`]s
+//! ranked by how well they match. Each candidate may then be refined with
+//! [`refine`] and demodulated with a [`Demodulator`].
+//!
+//! [`decode`] puts these pieces together to decode every transmission in a
+//! cycle.
+
+use crate::demod::{drift_offset, Demodulator};
+use crate::dsp::{Complex, Fft, Sample};
+use crate::spot::{self, Spot};
+use crate::{decoder, math, Error, SYNC, TONE_SPACING};
+
+/// The sample rate of the baseband, in Hz.
+pub const SAMPLE_RATE: u32 = 375;
+
+// The length of each FFT, covering two symbols.
+pub(crate) const FFT_LEN: usize = 512;
+
+// The distance between frames of the spectrogram, half a symbol.
+const HOP: usize = 128;
+
+// The width of each bin of the spectrogram in Hz, half a tone.
+const BIN_WIDTH: f32 = SAMPLE_RATE as f32 / FFT_LEN as f32;
+
+// Peaks are searched for within this many bins either side of the center of
+// the baseband, ±110 Hz.
+const SPAN: usize = 150;
+
+// Extra bins kept either side of the span, for the frequency search, the
+// tones either side of the center of a signal, and drift.
+const MARGIN: usize = 8;
+
+// The largest drift searched for, in Hz over the length of the transmission.
+const MAX_DRIFT: i32 = 4;
+
+// The number of bins kept from each frame of the spectrogram.
+const BINS: usize = 2 * (SPAN + MARGIN) + 1;
+
+// The maximum number of peaks considered.
+const MAX_PEAKS: usize = 200;
+
+// The minimum ratio of a peak's power to the noise, about -33 dB in a 2500
+// Hz bandwidth.
+const MIN_RATIO: f32 = 0.2;
+
+// The minimum sync correlation for a candidate to be kept.
+const MIN_SYNC: f32 = 0.1;
+
+// The sample at which a transmission nominally begins, 1 second into the
+// cycle.
+const NOMINAL_START: usize = SAMPLE_RATE as usize;
+
+// The latest start of a transmission searched for, in samples. This allows
+// clocks to be 1 second fast or 3 seconds slow.
+const MAX_START: usize = 4 * SAMPLE_RATE as usize;
+
+// The number of times the passband is searched by `decode`, with the signals
+// decoded so far removed before each search.
+pub(crate) const PASSES: usize = 2;
+
+// The maximum number of candidates considered on each pass of `decode`.
+pub(crate) const MAX_CANDIDATES: usize = 100;
+
+// Converts the ratio of the signal power in the 7 bins around a peak to the
+// noise in those bins into a signal to noise ratio in a 2500 Hz bandwidth.
+const SNR_SCALE: f32 = 26.3;
+
+/// A possible transmission found by [`search`].
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Candidate {
+    /// The frequency of the center of the signal, midway between tones 1
+    /// and 2, in Hz relative to the center of the baseband.
+    pub frequency: f32,
+    /// The index of the sample at which the transmission starts.
+    pub start: usize,
+    /// The change in frequency over the length of the transmission, in Hz.
+    /// The frequency is that of the middle of the transmission.
+    pub drift: f32,
+    /// The correlation with the sync vector, from -1.0 to 1.0.
+    pub sync: f32,
+    /// The estimated signal to noise ratio in dB, in a 2500 Hz bandwidth.
+    pub snr: f32,
+}
+
+impl Candidate {
+    /// The offset of the start of the transmission from its nominal start,
+    /// 1 second after the beginning of the cycle, in seconds. This is the DT
+    /// reported by wsprd; a positive value means the transmission began
+    /// late, usually because the transmitter's or receiver's clock is off.
+    ///
+    /// `samples` are assumed to begin at the start of the cycle.
+    pub fn dt(&self) -> f32 {
+        (self.start as f32 - NOMINAL_START as f32) / SAMPLE_RATE as f32
+    }
+}
+
+/// The range of times and frequencies searched for transmissions.
+///
+/// By default the whole ±110 Hz passband is searched, for transmissions
+/// starting from 1 second early to 3 seconds late. Narrowing the window
+/// speeds up the search, and avoids false candidates outside it, when the
+/// frequency and timing are roughly known, such as in a loopback test.
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Window {
+    min_dt: f32,
+    max_dt: f32,
+    frequency: f32,
+    span: f32,
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Window {
+    /// Creates a window covering the whole search range.
+    pub const fn new() -> Self {
+        Self {
+            min_dt: -(NOMINAL_START as f32) / SAMPLE_RATE as f32,
+            max_dt: (MAX_START - NOMINAL_START) as f32 / SAMPLE_RATE as f32,
+            frequency: 0.0,
+            span: SPAN as f32 * BIN_WIDTH,
+        }
+    }
+
+    /// Searches only for transmissions with a DT (see [`Candidate::dt`])
+    /// from `min` to `max` seconds, limited to the default range.
+    pub fn with_dt(self, min: f32, max: f32) -> Self {
+        Self {
+            min_dt: min,
+            max_dt: max,
+            ..self
+        }
+    }
+
+    /// Searches only for transmissions centered within `span` Hz either
+    /// side of `frequency`, relative to the center of the baseband, limited
+    /// to the default range.
+    pub fn with_frequency(self, frequency: f32, span: f32) -> Self {
+        Self {
+            frequency,
+            span,
+            ..self
+        }
+    }
+
+    // The range of starting samples searched, in multiples of `HOP`.
+    fn starts(&self) -> impl Iterator<Item = usize> {
+        let start = |dt: f32| NOMINAL_START as f32 + dt * SAMPLE_RATE as f32;
+        let first = (start(self.min_dt) / HOP as f32).max(0.0) as usize;
+        let last = (start(self.max_dt) / HOP as f32 + 0.999)
+            .clamp(0.0, (MAX_START / HOP) as f32) as usize;
+        (first.min(last) * HOP..=last * HOP).step_by(HOP)
+    }
+
+    // The range of spectrogram bins in which peaks are searched for.
+    fn bins(&self) -> core::ops::RangeInclusive<usize> {
+        let bin = |frequency: f32| {
+            let bin = frequency / BIN_WIDTH + (SPAN + MARGIN) as f32;
+            bin.clamp(MARGIN as f32, (BINS - MARGIN - 1) as f32)
+        };
+        let first = bin(self.frequency - self.span) as usize;
+        let last = (bin(self.frequency + self.span) + 0.999) as usize;
+        first..=last.min(BINS - MARGIN - 1)
+    }
+}
+
+// The number of frames in the spectrogram of `samples` samples.
+const fn frames(samples: usize) -> usize {
+    match samples.checked_sub(FFT_LEN) {
+        Some(remaining) => remaining / HOP + 1,
+        None => 0,
+    }
+}
+
+/// The number of `f32`s of workspace [`search`] needs for `samples`
+/// samples. For a full 2 minute cycle this is about 100,000 (400KB).
+pub const fn workspace_len(samples: usize) -> usize {
+    frames(samples) * BINS
+}
+
+/// Searches `samples`, a 375 Hz complex baseband, for transmissions.
+///
+/// Up to `candidates.len()` candidates are written to `candidates`, best
+/// first, and the number written is returned. `workspace` must hold at least
+/// [`workspace_len`] values, otherwise [`Error::BufferTooSmall`] is
+/// returned.
+pub fn search<F: Fft<FFT_LEN>>(
+    samples: &[Complex],
+    fft: &mut F,
+    workspace: &mut [f32],
+    candidates: &mut [Candidate],
+) -> Result<usize, Error> {
+    search_within(samples, fft, workspace, &Window::new(), candidates)
+}
+
+/// Searches `samples` as [`search`] does, only within `window`.
+pub fn search_within<F: Fft<FFT_LEN>>(
+    samples: &[Complex],
+    fft: &mut F,
+    workspace: &mut [f32],
+    window: &Window,
+    candidates: &mut [Candidate],
+) -> Result<usize, Error> {
+    let frames = frames(samples.len());
+    let spectrogram = workspace
+        .get_mut(..frames * BINS)
+        .ok_or(Error::BufferTooSmall)?;
+
+    // Compute the magnitude of each bin in each frame, using a sine window.
+    let mut sine = [0f32; FFT_LEN];
+    for (j, w) in sine.iter_mut().enumerate() {
+        let phase = core::f64::consts::PI * j as f64 / (FFT_LEN - 1) as f64;
+        *w = math::sin_cos(phase).0 as f32;
+    }
+
+    for (frame, row) in spectrogram.chunks_exact_mut(BINS).enumerate() {
+        let mut buffer = [Complex::default(); FFT_LEN];
+        let samples = &samples[frame * HOP..][..FFT_LEN];
+        for ((x, &sample), &w) in buffer.iter_mut().zip(samples).zip(&sine) {
+            *x = sample * w;
+        }
+        fft.forward(&mut buffer);
+
+        for (b, magnitude) in row.iter_mut().enumerate() {
+            let k = (b as isize - (SPAN + MARGIN) as isize)
+                .rem_euclid(FFT_LEN as isize);
+            *magnitude = buffer[k as usize].norm();
+        }
+    }
+
+    // Average the power in each bin, then smooth over the 7 bins a signal
+    // occupies.
+    let mut average = [0f32; BINS];
+    for row in spectrogram.chunks_exact(BINS) {
+        for (average, magnitude) in average.iter_mut().zip(row) {
+            *average += magnitude * magnitude;
+        }
+    }
+    let mut smoothed = [0f32; BINS];
+    for b in 3..BINS - 3 {
+        smoothed[b] = average[b - 3..=b + 3].iter().sum();
+    }
+
+    // Take the noise to be the 30th percentile of the smoothed spectrum.
+    let mut sorted = smoothed;
+    let sorted = &mut sorted[3..BINS - 3];
+    let percentile = sorted.len() * 3 / 10;
+    let (_, &mut noise, _) =
+        sorted.select_nth_unstable_by(percentile, |a, b| a.total_cmp(b));
+    if noise <= 0.0 {
+        return Ok(0);
+    }
+
+    // Correlate the peaks in the spectrum against the sync vector.
+    let mut found = [Candidate::default(); MAX_PEAKS];
+    let mut count = 0;
+    for b in window.bins() {
+        let ratio = smoothed[b] / noise - 1.0;
+        let peak =
+            smoothed[b] > smoothed[b - 1] && smoothed[b] >= smoothed[b + 1];
+        if !peak || ratio < MIN_RATIO || count == MAX_PEAKS {
+            continue;
+        }
+
+        let mut best = Candidate {
+            sync: f32::NEG_INFINITY,
+            snr: 10.0 * math::log10(ratio) - SNR_SCALE,
+            ..Candidate::default()
+        };
+        for center in b - 2..=b + 2 {
+            for start in window.starts() {
+                for drift in -MAX_DRIFT..=MAX_DRIFT {
+                    let drift = drift as f32;
+                    let sync = sync(spectrogram, center, start, drift);
+                    if sync > best.sync {
+                        best.sync = sync;
+                        best.start = start;
+                        best.drift = drift;
+                        best.frequency = (center as f32
+                            - (SPAN + MARGIN) as f32)
+                            * BIN_WIDTH;
+                    }
+                }
+            }
+        }
+
+        if best.sync >= MIN_SYNC {
+            found[count] = best;
+            count += 1;
+        }
+    }
+
+    let found = &mut found[..count];
+    found.sort_unstable_by(|a, b| b.sync.total_cmp(&a.sync));
+
+    let count = count.min(candidates.len());
+    candidates[..count].copy_from_slice(&found[..count]);
+    Ok(count)
+}
+
+// Correlates the sync vector against the spectrogram, for a signal centered
+// on bin `center` starting at sample `start`, drifting by `drift` Hz. The
+// tones of the signal are found 1 and 3 bins either side of the center.
+fn sync(spectrogram: &[f32], center: usize, start: usize, drift: f32) -> f32 {
+    let frames = spectrogram.len() / BINS;
+
+    // Frame `k` is centered on sample `k * HOP + FFT_LEN / 2`, so symbol 0 is
+    // centered in frame `start / HOP - 1` and each symbol is two frames on.
+    let first = (start / HOP) as isize - 1;
+    let mut magnitudes = [[0f32; 4]; 162];
+    for (k, magnitudes) in magnitudes.iter_mut().enumerate() {
+        let frame = first + 2 * k as isize;
+        if frame < 0 || frame as usize >= frames {
+            continue;
+        }
+        let row = &spectrogram[frame as usize * BINS..][..BINS];
+        let offset = drift_offset(drift, k) / BIN_WIDTH;
+        let center = (center as f32 + offset + 0.5) as usize;
+        *magnitudes = [
+            row[center - 3],
+            row[center - 1],
+            row[center + 1],
+            row[center + 3],
+        ];
+    }
+
+    correlate(&magnitudes)
+}
+
+// Correlates the tone magnitudes of each symbol against the sync vector. The
+// sync bit selects between tones 0 and 2 or tones 1 and 3, so the result is
+// 1.0 when all of the energy lies in the tones the sync vector expects, and
+// -1.0 when it all lies in the others.
+fn correlate(magnitudes: &[[f32; 4]; 162]) -> f32 {
+    let mut correlation = 0.0;
+    let mut total = 0.0;
+    for (tones, &sync) in magnitudes.iter().zip(&SYNC) {
+        let difference = (tones[1] + tones[3]) - (tones[0] + tones[2]);
+        correlation += if sync == 1 { difference } else { -difference };
+        total += tones.iter().sum::<f32>();
+    }
+
+    if total > 0.0 {
+        correlation / total
+    } else {
+        0.0
+    }
+}
+
+/// Refines the frequency, start, and drift of a candidate by demodulating it
+/// at a small grid of offsets, within the resolution of [`search`], and
+/// keeping the offset with the best sync correlation.
+pub fn refine<S: Sample>(samples: &[S], candidate: &Candidate) -> Candidate {
+    let mut best = *candidate;
+    best.sync = f32::NEG_INFINITY;
+
+    for (df, drift) in (-2..=2).flat_map(|df| (-1..=1).map(move |dd| (df, dd)))
+    {
+        let frequency = candidate.frequency + df as f32 * BIN_WIDTH / 4.0;
+        let drift = candidate.drift + drift as f32 * 0.5;
+        let lowest = frequency - 1.5 * TONE_SPACING;
+        let Ok(demodulator) = Demodulator::new_iq(SAMPLE_RATE, lowest) else {
+            continue;
+        };
+        let demodulator = demodulator.with_drift(drift);
+
+        for dt in -2..=2isize {
+            let Some(start) =
+                candidate.start.checked_add_signed(dt * HOP as isize / 4)
+            else {
+                continue;
+            };
+            let Ok(magnitudes) = demodulator.magnitudes(samples, start) else {
+                continue;
+            };
+
+            let sync = correlate(&magnitudes);
+            if sync > best.sync {
+                best.frequency = frequency;
+                best.start = start;
+                best.drift = drift;
+                best.sync = sync;
+            }
+        }
+    }
+
+    if best.sync == f32::NEG_INFINITY {
+        *candidate
+    } else {
+        best
+    }
+}
+
+/// Decodes every transmission in `samples`, a 375 Hz complex baseband,
+/// writing up to `spots.len()` spots to `spots`. The number of spots written
+/// is returned. Spot frequencies are relative to the center of the baseband.
+///
+/// The passband is searched, and each candidate refined and decoded in turn.
+/// Once a message has been decoded its signal is subtracted from `samples`,
+/// so weaker signals beneath it have a better chance on a second search.
+/// A transmission is only reported once, even if several candidates decode
+/// to it, see [`Spot::is_duplicate`].
+///
+/// `workspace` must hold at least [`workspace_len`] values.
+pub fn decode<F: Fft<FFT_LEN>>(
+    samples: &mut [Complex],
+    fft: &mut F,
+    workspace: &mut [f32],
+    spots: &mut [Spot],
+) -> Result<usize, Error> {
+    decode_deep(samples, fft, workspace, &[], spots)
+}
+
+/// Decodes every transmission in `samples` as [`decode`] does, falling back
+/// to a deep search for the callsigns and grids in `known` (see
+/// [`crate::decode_deep`]) when a candidate fails to decode.
+pub fn decode_deep<F: Fft<FFT_LEN>>(
+    samples: &mut [Complex],
+    fft: &mut F,
+    workspace: &mut [f32],
+    known: &[(&str, &str)],
+    spots: &mut [Spot],
+) -> Result<usize, Error> {
+    decode_within(samples, fft, workspace, &Window::new(), known, spots)
+}
+
+/// Decodes every transmission in `samples` as [`decode_deep`] does, only
+/// searching within `window`.
+pub fn decode_within<F: Fft<FFT_LEN>>(
+    samples: &mut [Complex],
+    fft: &mut F,
+    workspace: &mut [f32],
+    window: &Window,
+    known: &[(&str, &str)],
+    spots: &mut [Spot],
+) -> Result<usize, Error> {
+    let mut count = 0;
+    for _ in 0..PASSES {
+        let mut candidates = [Candidate::default(); MAX_CANDIDATES];
+        let found =
+            search_within(samples, fft, workspace, window, &mut candidates)?;
+
+        let mut decoded = false;
+        for candidate in &candidates[..found] {
+            if count == spots.len() {
+                return Ok(count);
+            }
+
+            // Signals decoded earlier in this pass have been subtracted, so
+            // any candidates they produced will no longer sync.
+            let Some((candidate, spot, symbols)) =
+                decode_candidate(&*samples, candidate, known)
+            else {
+                continue;
+            };
+
+            subtract(samples, &symbols, &candidate);
+            decoded = true;
+            count = spot::merge(spots, count, spot);
+        }
+
+        // Nothing new was removed, so another search won't find anything
+        // new either.
+        if !decoded {
+            break;
+        }
+    }
+
+    Ok(count)
+}
+
+// Refines a candidate and decodes it, returning the refined candidate, a
+// spot for the message, and the symbols it was sent as.
+pub(crate) fn decode_candidate(
+    samples: &[Complex],
+    candidate: &Candidate,
+    known: &[(&str, &str)],
+) -> Option<(Candidate, Spot, [u8; 162])> {
+    let candidate = refine(samples, candidate);
+    if candidate.sync < MIN_SYNC {
+        return None;
+    }
+
+    let lowest = candidate.frequency - 1.5 * TONE_SPACING;
+    let demodulator = Demodulator::new_iq(SAMPLE_RATE, lowest)
+        .ok()?
+        .with_drift(candidate.drift);
+    let llrs = demodulator.soft_symbols(samples, candidate.start).ok()?;
+    // Ordered statistics decoding recovers some of the signals too weak for
+    // the sequential decoder, before resorting to a deep search.
+    let decoded = crate::decode_soft_metric(&llrs).or_else(|_| {
+        crate::decode_soft_metric_with(&llrs, &decoder::Osd::default())
+    });
+    let (message, metric) = match decoded {
+        Ok((message, decoded)) => (message, Some(decoded.metric)),
+        Err(_) => (crate::decode_deep(&llrs, known).ok()?, None),
+    };
+    // A type 3 message encodes from the hash it carries, as its callsign
+    // is just `<...>`.
+    let symbols = message.encode().ok()?;
+
+    let spot = Spot {
+        errors: crate::hard_errors(&llrs, &symbols),
+        metric,
+        ..Spot::new(message, &candidate)
+    };
+    Some((candidate, spot, symbols))
+}
+
+// Removes a decoded transmission from `samples`. The amplitude and phase of
+// the tone sent in each symbol period are estimated by correlating against
+// it, and the estimated tone subtracted.
+pub(crate) fn subtract(
+    samples: &mut [Complex],
+    symbols: &[u8; 162],
+    candidate: &Candidate,
+) {
+    let length = FFT_LEN / 2;
+    let Some(samples) = samples.get_mut(candidate.start..) else {
+        return;
+    };
+
+    for ((i, &symbol), samples) in symbols
+        .iter()
+        .enumerate()
+        .zip(samples.chunks_exact_mut(length))
+    {
+        let frequency = candidate.frequency
+            + drift_offset(candidate.drift, i)
+            + (symbol as f32 - 1.5) * TONE_SPACING;
+        let phase =
+            core::f64::consts::TAU * frequency as f64 / SAMPLE_RATE as f64;
+        let step = Complex::from_phase(phase);
+
+        let mut phasor = Complex::new(1.0, 0.0);
+        let mut estimate = Complex::default();
+        for &sample in samples.iter() {
+            estimate += sample * phasor.conj();
+            phasor = phasor * step;
+        }
+        let estimate = estimate * (1.0 / length as f32);
+
+        let mut phasor = Complex::new(1.0, 0.0);
+        for sample in samples.iter_mut() {
+            *sample = *sample - estimate * phasor;
+            phasor = phasor * step;
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::demod::tests::Noise;
+    use crate::dsp::Radix2;
+    use std::vec;
+    use std::vec::Vec;
+
+    // Synthesizes a transmission at 375 Hz, centered on `frequency` and
+    // drifting by `drift`, and adds it to `samples` starting at `start`.
+    pub(crate) fn synthesize(
+        symbols: &[u8; 162],
+        frequency: f32,
+        drift: f32,
+        start: usize,
+        amplitude: f32,
+        samples: &mut [Complex],
+    ) {
+        let mut phase = 0f64;
+        for (i, &symbol) in symbols.iter().enumerate() {
+            let tone = frequency
+                + drift_offset(drift, i)
+                + (symbol as f32 - 1.5) * TONE_SPACING;
+            let step = core::f64::consts::TAU * tone as f64 / 375.0;
+            for sample in &mut samples[start + 256 * i..][..256] {
+                *sample += Complex::from_phase(phase) * amplitude;
+                phase += step;
+            }
+        }
+    }
+
+    // Complex white noise with a power of 1 per sample.
+    pub(crate) fn noise(len: usize) -> Vec<Complex> {
+        let mut noise = Noise::new();
+        let scale = 3f32.sqrt() / 2f32.sqrt();
+        (0..len)
+            .map(|_| Complex::new(noise.next(), noise.next()) * scale)
+            .collect()
+    }
+
+    #[test]
+    fn test_search() {
+        let mut samples = noise(114 * 375);
+
+        // Signals at -22 dB and -24 dB in a 2500 Hz bandwidth. The noise in
+        // 2500 Hz is 2500 / 375 times the noise per sample.
+        let amplitude =
+            |snr: f32| (10f32.powf(snr / 10.0) * 2500.0 / 375.0).sqrt();
+        let first = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let second = crate::encode("G1ABC", "IO83", 20).unwrap();
+        synthesize(&first, -40.3, 0.0, 375, amplitude(-22.0), &mut samples);
+        synthesize(&second, 61.0, -3.0, 700, amplitude(-24.0), &mut samples);
+
+        let mut workspace = vec![0f32; workspace_len(samples.len())];
+        let mut candidates = [Candidate::default(); 10];
+        let count = search(
+            &samples,
+            &mut Radix2::new(),
+            &mut workspace,
+            &mut candidates,
+        )
+        .unwrap();
+        assert!(count >= 2);
+
+        let mut candidates = candidates[..2].to_vec();
+        candidates.sort_by(|a, b| a.frequency.total_cmp(&b.frequency));
+        assert!((candidates[0].frequency + 40.3).abs() < 0.5);
+        assert!(candidates[0].start.abs_diff(375) <= 64);
+        assert!((candidates[0].snr + 22.0).abs() < 2.0);
+        assert!((candidates[1].frequency - 61.0).abs() < 0.5);
+        assert!(candidates[1].start.abs_diff(700) <= 64);
+        assert!((candidates[1].drift + 3.0).abs() <= 1.0);
+        assert!((candidates[1].snr + 24.0).abs() < 2.0);
+
+        // Refining brings the candidates closer, and they decode.
+        let refined = refine(&samples, &candidates[0]);
+        assert!((refined.frequency + 40.3).abs() < 0.2);
+        assert!(refined.start.abs_diff(375) <= 32);
+        let lowest = refined.frequency - 1.5 * TONE_SPACING;
+        let demodulator = Demodulator::new_iq(SAMPLE_RATE, lowest).unwrap();
+        let llrs = demodulator.soft_symbols(&samples, refined.start).unwrap();
+        assert_eq!(crate::decode_soft(&llrs).unwrap().callsign(), "K1ABC");
+
+        let refined = refine(&samples, &candidates[1]);
+        assert!((refined.drift + 3.0).abs() <= 0.5);
+        let lowest = refined.frequency - 1.5 * TONE_SPACING;
+        let demodulator = Demodulator::new_iq(SAMPLE_RATE, lowest)
+            .unwrap()
+            .with_drift(refined.drift);
+        let llrs = demodulator.soft_symbols(&samples, refined.start).unwrap();
+        assert_eq!(crate::decode_soft(&llrs).unwrap().callsign(), "G1ABC");
+
+        assert_eq!(
+            search(&samples, &mut Radix2::new(), &mut [], &mut candidates),
+            Err(Error::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn test_search_within() {
+        let mut samples = noise(114 * 375);
+        let amplitude =
+            |snr: f32| (10f32.powf(snr / 10.0) * 2500.0 / 375.0).sqrt();
+        let first = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let second = crate::encode("G1ABC", "IO83", 20).unwrap();
+        synthesize(&first, -40.3, 0.0, 375, amplitude(-22.0), &mut samples);
+        synthesize(&second, 61.0, 0.0, 700, amplitude(-22.0), &mut samples);
+
+        let mut workspace = vec![0f32; workspace_len(samples.len())];
+        let mut candidates = [Candidate::default(); 10];
+        let window = Window::new().with_frequency(60.0, 10.0).with_dt(0.5, 1.5);
+        let count = search_within(
+            &samples,
+            &mut Radix2::new(),
+            &mut workspace,
+            &window,
+            &mut candidates,
+        )
+        .unwrap();
+        assert!(count >= 1);
+        for candidate in &candidates[..count] {
+            assert!((candidate.frequency - 60.0).abs() <= 10.0 + BIN_WIDTH);
+            assert!((0.5 - 0.35..=1.5 + 0.35).contains(&candidate.dt()));
+        }
+        assert!((candidates[0].frequency - 61.0).abs() < 0.5);
+
+        // A loopback test only looking for the second signal.
+        let mut spots = [Spot::default(); 5];
+        let count = decode_within(
+            &mut samples,
+            &mut Radix2::new(),
+            &mut workspace,
+            &Window::new().with_frequency(61.0, 5.0).with_dt(-1.0, 1.0),
+            &[],
+            &mut spots,
+        )
+        .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(spots[0].message.callsign(), "G1ABC");
+
+        // The default window is the full search range.
+        assert_eq!(Window::default().bins(), MARGIN..=BINS - MARGIN - 1);
+        assert!(Window::new().starts().eq((0..=MAX_START).step_by(HOP)));
+    }
+
+    #[test]
+    fn test_subtract() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let mut samples = vec![Complex::default(); 114 * 375];
+        synthesize(&symbols, 20.0, 1.5, 375, 1.0, &mut samples);
+
+        let candidate = Candidate {
+            frequency: 20.0,
+            start: 375,
+            drift: 1.5,
+            ..Candidate::default()
+        };
+        subtract(&mut samples, &symbols, &candidate);
+        let residual: f32 = samples.iter().map(|x| x.norm_sqr()).sum();
+        assert!(residual < 1e-3 * (162 * 256) as f32);
+    }
+
+    #[test]
+    fn test_decode() {
+        let mut samples = noise(114 * 375);
+        let amplitude =
+            |snr: f32| (10f32.powf(snr / 10.0) * 2500.0 / 375.0).sqrt();
+
+        // Two signals whose tones overlap, and one on its own.
+        let strong = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let weak = crate::encode("G1ABC", "IO83", 20).unwrap();
+        let other = crate::encode("N6AB", "CM87", 0).unwrap();
+        synthesize(&strong, 10.0, 0.0, 375, amplitude(-12.0), &mut samples);
+        synthesize(&weak, 12.5, 0.0, 500, amplitude(-22.0), &mut samples);
+        synthesize(&other, -70.0, 1.0, 400, amplitude(-20.0), &mut samples);
+
+        let mut workspace = vec![0f32; workspace_len(samples.len())];
+        let mut spots = [Spot::default(); 5];
+        let count = decode(
+            &mut samples,
+            &mut Radix2::new(),
+            &mut workspace,
+            &mut spots,
+        )
+        .unwrap();
+
+        let mut callsigns: Vec<_> = spots[..count]
+            .iter()
+            .map(|s| s.message.callsign())
+            .collect();
+        callsigns.sort();
+        assert_eq!(callsigns, ["G1ABC", "K1ABC", "N6AB"]);
+
+        let spot = spots
+            .iter()
+            .find(|s| s.message.callsign() == "N6AB")
+            .unwrap();
+        assert!((spot.frequency + 70.0).abs() < 0.3);
+        assert!((spot.drift - 1.0).abs() <= 0.5);
+        assert!((spot.dt - 25.0 / 375.0).abs() < 0.1);
+        assert!(spot.sync > MIN_SYNC);
+
+        // The stronger signal decoded more cleanly.
+        let strong = spots
+            .iter()
+            .find(|s| s.message.callsign() == "K1ABC")
+            .unwrap();
+        assert!(strong.errors <= spot.errors);
+        assert!(strong.metric.unwrap() > spot.metric.unwrap());
+    }
+
+    #[test]
+    fn test_decode_type3() {
+        // A type 3 message is found and subtracted like any other, with
+        // its hash and locator.
+        let mut samples = noise(114 * 375);
+        let amplitude = (10f32.powf(-15.0 / 10.0) * 2500.0 / 375.0).sqrt();
+        let (_, symbols) = crate::encode_pair("K1ABC", "FN42AX", 37).unwrap();
+        synthesize(&symbols, 20.0, 0.0, 375, amplitude, &mut samples);
+
+        let mut workspace = vec![0f32; workspace_len(samples.len())];
+        let mut spots = [Spot::default(); 5];
+        let count = decode(
+            &mut samples,
+            &mut Radix2::new(),
+            &mut workspace,
+            &mut spots,
+        )
+        .unwrap();
+        assert_eq!(count, 1);
+        let message = spots[0].message;
+        assert_eq!(message.callsign(), "<...>");
+        assert_eq!(message.grid(), "FN42AX");
+        assert_eq!(message.hash(), Some(crate::hash::hash("K1ABC")));
+        assert_eq!(spots[0].errors, 0);
+    }
+
+    #[test]
+    fn test_decode_deep() {
+        let mut samples = noise(114 * 375);
+        let amplitude =
+            |snr: f32| (10f32.powf(snr / 10.0) * 2500.0 / 375.0).sqrt();
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        synthesize(&symbols, 30.0, 0.0, 375, amplitude(-30.0), &mut samples);
+
+        let mut workspace = vec![0f32; workspace_len(samples.len())];
+        let mut spots = [Spot::default(); 5];
+        let mut fft = Radix2::new();
+        let count =
+            decode(&mut samples.clone(), &mut fft, &mut workspace, &mut spots)
+                .unwrap();
+        assert_eq!(count, 0);
+
+        let known = [("G1ABC", "IO83"), ("K1ABC", "FN42")];
+        let count = decode_deep(
+            &mut samples,
+            &mut fft,
+            &mut workspace,
+            &known,
+            &mut spots,
+        )
+        .unwrap();
+        assert_eq!(count, 1);
+        assert_eq!(spots[0].message.callsign(), "K1ABC");
+        assert!((spots[0].frequency - 30.0).abs() < 0.3);
+        assert_eq!(spots[0].metric, None);
+    }
+}
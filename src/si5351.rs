@@ -0,0 +1,420 @@
+//! Frequency plans for the Si5351 clock generator, the usual oscillator of
+//! WSPR beacons and balloon trackers.
+//!
+//! The Si5351 multiplies its crystal up to a VCO of 600 to 900 MHz with a
+//! fractional PLL, then divides the VCO down with a MultiSynth and an R
+//! divider. A [`Plan`] fixes the MultiSynth at an even integer, as high as
+//! the VCO allows, and moves between tones with the PLL's fraction alone.
+//! Each fraction is the closest to the tone with a denominator the Si5351
+//! can hold, so that the 1.46 Hz steps between tones land within about a
+//! millihertz of where they should. Each tone's PLL registers can
+//! then be written in a single burst as each symbol begins.
+//!
+//! The register values are the eight bytes of a PLL (registers 26 to 33
+//! for PLL A) or a MultiSynth (registers 42 to 49 for MultiSynth 0) in
+//! order. The MultiSynth should also be put in integer mode, with bit 6 of
+//! its CLK control register.
+//!
+//! Crystals are rarely exactly on frequency. Measuring a tone of an
+//! uncorrected plan against where it should be gives the crystal's error
+//! with [`measured_correction`], and [`Plan::with_correction`] replans every
+//! tone for it.
+
+use crate::modulate::TONE_SPACING_MILLIHERTZ;
+use crate::Error;
+
+// The range of the VCO in Hz.
+const VCO_MIN: u64 = 600_000_000;
+const VCO_MAX: u64 = 900_000_000;
+
+// The lowest frequency a MultiSynth can produce without the R divider.
+const MULTISYNTH_MIN: u64 = 500_000;
+
+// The largest denominator of a fractional divider, 2^20 - 1.
+const MAX_DENOMINATOR: u128 = 1_048_575;
+
+// Parts per billion.
+const BILLION: u128 = 1_000_000_000;
+
+/// A divider or multiplier of `a + b / c`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Divider {
+    /// The integer part.
+    pub a: u32,
+    /// The numerator of the fractional part.
+    pub b: u32,
+    /// The denominator of the fractional part.
+    pub c: u32,
+}
+
+impl Divider {
+    /// The parameters P1, P2, and P3 the Si5351 encodes the divider as.
+    pub fn parameters(&self) -> (u32, u32, u32) {
+        let (a, b, c) = (self.a as u64, self.b as u64, self.c as u64);
+        let fraction = 128 * b / c;
+        let p1 = 128 * a + fraction - 512;
+        let p2 = 128 * b - c * fraction;
+        (p1 as u32, p2 as u32, c as u32)
+    }
+
+    /// The eight register bytes of the divider, in register order.
+    pub fn registers(&self) -> [u8; 8] {
+        let (p1, p2, p3) = self.parameters();
+        [
+            (p3 >> 8) as u8,
+            p3 as u8,
+            ((p1 >> 16) & 0x03) as u8,
+            (p1 >> 8) as u8,
+            p1 as u8,
+            (((p3 >> 16) & 0x0F) << 4 | ((p2 >> 16) & 0x0F)) as u8,
+            (p2 >> 8) as u8,
+            p2 as u8,
+        ]
+    }
+}
+
+/// The settings of an Si5351 for each of the four tones of a transmission.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Plan {
+    center: u64,
+    xtal: u32,
+    // How fast the crystal runs, in parts per billion.
+    correction: i32,
+    multisynth: u32,
+    // The R divider, as a power of two.
+    r: u8,
+    plls: [Divider; 4],
+}
+
+impl Plan {
+    /// Plans the tones of a transmission centered `audio_offset_hz` above
+    /// `dial_hz`, as [`rf_frequencies`](crate::modulate::rf_frequencies)
+    /// places them, from a crystal of `xtal_hz`, usually 25 or 27 MHz.
+    ///
+    /// Returns [`Error::InvalidFrequency`] if the tones are beyond the
+    /// Si5351's range, from about 8 kHz to 150 MHz, or the crystal can't
+    /// drive the PLL within it.
+    pub fn new(
+        dial_hz: u64,
+        audio_offset_hz: u32,
+        xtal_hz: u32,
+    ) -> Result<Self, Error> {
+        Self::with_crystal(dial_hz + audio_offset_hz as u64, xtal_hz, 0)
+    }
+
+    /// Replans the tones for a crystal running `ppb` parts per billion
+    /// fast, or slow if negative, so they land where they should. A
+    /// correction in parts per million is 1000 times as many parts per
+    /// billion.
+    ///
+    /// Returns [`Error::InvalidFrequency`] if the corrected crystal can't
+    /// drive the PLL within its range.
+    pub fn with_correction(self, ppb: i32) -> Result<Self, Error> {
+        Self::with_crystal(self.center, self.xtal, ppb)
+    }
+
+    // Plans the tones around `center` Hz from a crystal of `xtal_hz` nominal
+    // running `correction` parts per billion fast.
+    fn with_crystal(
+        center: u64,
+        xtal_hz: u32,
+        correction: i32,
+    ) -> Result<Self, Error> {
+        let ppb = BILLION as i128 + correction as i128;
+        if xtal_hz == 0 || center == 0 || ppb <= 0 {
+            return Err(Error::InvalidFrequency);
+        }
+        // The crystal's actual frequency is `xtal_hz * ppb` billionths of a
+        // Hz.
+        let ppb = ppb as u128;
+
+        // The R divider lets the MultiSynth run above its minimum, and the
+        // MultiSynth is the largest even divider keeping the VCO below its
+        // maximum at the highest tone.
+        let mut r = 0;
+        while center << r < MULTISYNTH_MIN && r < 7 {
+            r += 1;
+        }
+        let highest = (center + 3) << r;
+        let multisynth = (VCO_MAX / highest) as u32 & !1;
+        if !(6..=2048).contains(&multisynth) {
+            return Err(Error::InvalidFrequency);
+        }
+
+        let mut plls = [Divider { a: 0, b: 0, c: 1 }; 4];
+        for (tone, pll) in plls.iter_mut().enumerate() {
+            // In 512ths of a millihertz, where the tones are exact: tone 0
+            // is 1.5 tones below the center.
+            let (numerator, denominator) = TONE_SPACING_MILLIHERTZ;
+            let (numerator, unit) =
+                (numerator as u128, 2 * denominator as u128);
+            let frequency = center as u128 * 1000 * unit
+                + (2 * tone as u128) * numerator
+                - 3 * numerator;
+            let vco = frequency * ((multisynth as u128) << r) * BILLION;
+            let xtal = xtal_hz as u128 * ppb * 1000 * unit;
+
+            let (b, c) = approximate(vco % xtal, xtal, MAX_DENOMINATOR);
+            let a = vco / xtal + (b == c) as u128;
+            if !(15..=90).contains(&a) {
+                return Err(Error::InvalidFrequency);
+            }
+            *pll = if b == c {
+                Divider {
+                    a: a as u32,
+                    b: 0,
+                    c: 1,
+                }
+            } else {
+                Divider {
+                    a: a as u32,
+                    b: b as u32,
+                    c: c as u32,
+                }
+            };
+        }
+
+        let vco = |pll: &Divider| {
+            let c = pll.c as u128;
+            let vco =
+                xtal_hz as u128 * ppb * (pll.a as u128 * c + pll.b as u128);
+            (vco / (c * BILLION)) as u64
+        };
+        if vco(&plls[0]) < VCO_MIN || vco(&plls[3]) > VCO_MAX {
+            return Err(Error::InvalidFrequency);
+        }
+
+        Ok(Self {
+            center,
+            xtal: xtal_hz,
+            correction,
+            multisynth,
+            r,
+            plls,
+        })
+    }
+
+    /// The PLL multiplier for tone `tone`, from 0 to 3.
+    pub fn pll(&self, tone: u8) -> Divider {
+        self.plls[tone as usize & 3]
+    }
+
+    /// The PLL's register bytes for tone `tone`, from 0 to 3.
+    pub fn pll_registers(&self, tone: u8) -> [u8; 8] {
+        self.pll(tone).registers()
+    }
+
+    /// The MultiSynth's divider, an even integer.
+    pub fn multisynth(&self) -> Divider {
+        Divider {
+            a: self.multisynth,
+            b: 0,
+            c: 1,
+        }
+    }
+
+    /// The R divider's setting, the power of two it divides by, from 0 to
+    /// 7.
+    pub fn r_div(&self) -> u8 {
+        self.r
+    }
+
+    /// The MultiSynth's register bytes, including the R divider.
+    pub fn multisynth_registers(&self) -> [u8; 8] {
+        let mut registers = self.multisynth().registers();
+        registers[2] |= self.r << 4;
+        registers
+    }
+
+    /// The correction the plan was made for, in parts per billion.
+    pub fn correction(&self) -> i32 {
+        self.correction
+    }
+
+    /// The frequency of tone `tone`, from 0 to 3, in millihertz, as the
+    /// Si5351 will produce it from a crystal off by exactly
+    /// [`Plan::correction`].
+    pub fn frequency(&self, tone: u8) -> u64 {
+        let pll = self.pll(tone);
+        let c = pll.c as u128;
+        let ppb = (BILLION as i128 + self.correction as i128) as u128;
+        let numerator = self.xtal as u128
+            * ppb
+            * 1000
+            * (pll.a as u128 * c + pll.b as u128);
+        let denominator = c * ((self.multisynth as u128) << self.r) * BILLION;
+        ((numerator + denominator / 2) / denominator) as u64
+    }
+}
+
+/// The correction in parts per billion for a crystal that puts a tone at
+/// `measured` millihertz rather than `expected`, measured from a plan
+/// without a correction.
+pub fn measured_correction(expected: u64, measured: u64) -> i32 {
+    if expected == 0 {
+        return 0;
+    }
+    let error = (measured as i128 - expected as i128) * BILLION as i128;
+    let expected = expected as i128;
+    let ppb = (error + error.signum() * expected / 2) / expected;
+    ppb.clamp(i32::MIN as i128, i32::MAX as i128) as i32
+}
+
+// The fraction closest to `numerator / denominator`, less than 1, with a
+// denominator of at most `max`, from its continued fraction.
+pub(crate) fn approximate(
+    numerator: u128,
+    denominator: u128,
+    max: u128,
+) -> (u128, u128) {
+    let (mut p0, mut q0, mut p1, mut q1) = (0, 1, 1, 0);
+    let (mut n, mut d) = (numerator, denominator);
+    while d != 0 {
+        let a = n / d;
+        let q2 = q0 + a * q1;
+        if q2 > max {
+            // The last convergent, or the best of the fractions between it
+            // and the next, whichever is closer.
+            let k = (max - q0) / q1;
+            let (p, q) = (p0 + k * p1, q0 + k * q1);
+            let error =
+                |p: u128, q: u128| (p * denominator).abs_diff(q * numerator);
+            return if error(p, q) * q1 < error(p1, q1) * q {
+                (p, q)
+            } else {
+                (p1, q1)
+            };
+        }
+        (p0, q0, p1, q1) = (p1, q1, p0 + a * p1, q2);
+        (n, d) = (d, n - a * d);
+    }
+    (p1, q1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulate::tone_millihertz;
+
+    #[test]
+    fn test_divider() {
+        // A multiplier of 36 + 0/1, and the Si5351 datasheet's example of
+        // 32 + 3/4.
+        let divider = Divider { a: 36, b: 0, c: 1 };
+        assert_eq!(divider.parameters(), (4096, 0, 1));
+        assert_eq!(divider.registers(), [0, 1, 0, 0x10, 0x00, 0, 0, 0]);
+        let divider = Divider { a: 32, b: 3, c: 4 };
+        assert_eq!(divider.parameters(), (3680, 0, 4));
+
+        let divider = Divider {
+            a: 35,
+            b: 1_000_000,
+            c: MAX_DENOMINATOR as u32,
+        };
+        let (p1, p2, p3) = divider.parameters();
+        let registers = divider.registers();
+        assert_eq!(registers[0] as u32, (p3 >> 8) & 0xFF);
+        assert_eq!(registers[5] >> 4, (p3 >> 16) as u8);
+        assert_eq!(registers[2] as u32, p1 >> 16);
+        assert_eq!(
+            ((registers[5] as u32 & 0x0F) << 16)
+                | (registers[6] as u32) << 8
+                | registers[7] as u32,
+            p2
+        );
+    }
+
+    #[test]
+    fn test_approximate() {
+        assert_eq!(approximate(1, 3, 100), (1, 3));
+        assert_eq!(approximate(0, 7, 100), (0, 1));
+        // The fractional part of π, to within denominators of 100 and 1000.
+        assert_eq!(approximate(14_159_265, 100_000_000, 100), (14, 99));
+        assert_eq!(approximate(14_159_265, 100_000_000, 1000), (16, 113));
+        // 0.999... rounds up to 1 / 1 when no closer fraction fits.
+        assert_eq!(approximate(999_999, 1_000_000, 100), (1, 1));
+    }
+
+    #[test]
+    fn test_plan() {
+        assert_eq!(
+            Plan::new(14_095_600, 1500, 0),
+            Err(Error::InvalidFrequency)
+        );
+        assert_eq!(
+            Plan::new(200_000_000, 1500, 25_000_000),
+            Err(Error::InvalidFrequency)
+        );
+
+        for (dial, xtal) in [
+            (14_095_600, 25_000_000),
+            (7_038_600, 27_000_000),
+            (28_124_600, 25_000_000),
+            (136_000, 25_000_000),
+            (474_200, 25_000_000),
+            (50_293_000, 27_000_000),
+        ] {
+            let plan = Plan::new(dial, 1500, xtal).unwrap();
+            let divider = plan.multisynth().a as u64;
+            assert_eq!(divider % 2, 0);
+            let base = (dial + 1500) * 1000 - 2197;
+            for tone in 0..4 {
+                // Each tone is within a millihertz of where it should be.
+                let expected = tone_millihertz(base, tone);
+                let actual = plan.frequency(tone);
+                assert!(actual.abs_diff(expected) <= 1, "{}", dial);
+
+                let vco = (plan.frequency(tone) << plan.r_div()) * divider;
+                assert!((600_000_000_000..=900_000_000_000).contains(&vco));
+            }
+        }
+
+        // 20 m from 25 MHz, a MultiSynth of 62 and a VCO of 874 MHz.
+        let plan = Plan::new(14_095_600, 1500, 25_000_000).unwrap();
+        assert_eq!(plan.multisynth().a, 62);
+        assert_eq!(plan.r_div(), 0);
+        assert_eq!(plan.pll(0).a, 34);
+        assert_eq!(plan.multisynth_registers(), [0, 1, 0, 0x1D, 0x00, 0, 0, 0]);
+
+        // 2200 m needs the R divider.
+        let plan = Plan::new(136_000, 1500, 25_000_000).unwrap();
+        assert_eq!(plan.r_div(), 2);
+        assert_eq!(plan.multisynth_registers()[2] >> 4, 2);
+    }
+
+    #[test]
+    fn test_correction() {
+        assert_eq!(measured_correction(10_000_000, 10_000_000), 0);
+        assert_eq!(measured_correction(10_000_000, 10_000_001), 100);
+        assert_eq!(measured_correction(10_000_000, 9_999_999), -100);
+        assert_eq!(measured_correction(3_000_000, 3_000_001), 333);
+        assert_eq!(measured_correction(0, 1), 0);
+
+        // A crystal 12.5 ppm fast puts 20 m about 176 Hz high, which the
+        // measured correction takes back out.
+        let plan = Plan::new(14_095_600, 1500, 25_000_000).unwrap();
+        assert_eq!(plan.correction(), 0);
+        let expected = plan.frequency(0);
+        let measured = expected + expected / 80_000;
+        assert!(measured - expected > 176_000);
+        let ppb = measured_correction(expected, measured);
+        assert_eq!(ppb, 12_500);
+
+        let corrected = plan.with_correction(ppb).unwrap();
+        assert_eq!(corrected.correction(), 12_500);
+        let base = (14_095_600 + 1500) * 1000 - 2197;
+        for tone in 0..4 {
+            // The corrected tones are where they should be on the fast
+            // crystal, with the PLLs multiplying it less to get there.
+            let expected = tone_millihertz(base, tone);
+            assert!(corrected.frequency(tone).abs_diff(expected) <= 1);
+            assert!(corrected.pll(tone).a <= plan.pll(tone).a);
+            assert_ne!(corrected.pll(tone), plan.pll(tone));
+        }
+
+        assert_eq!(
+            plan.with_correction(-1_000_000_000),
+            Err(Error::InvalidFrequency)
+        );
+    }
+}
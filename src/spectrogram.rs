@@ -0,0 +1,282 @@
+//! Spectrograms of generated or received signals, to check by eye that a
+//! signal is where it should be and as narrow as it should be.
+//!
+//! A [`Spectrogram`] holds the power of each frequency bin of a series of
+//! overlapping FFTs, and can be written as a grayscale PNG image, with time
+//! running down and frequency to the right, for viewing as a waterfall.
+
+use std::io::{self, Write};
+use std::vec::Vec;
+
+use crate::dsp::{Complex, Fft, Radix2, Sample};
+use crate::math;
+
+/// The power of a signal over time and frequency.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Spectrogram {
+    sample_rate: u32,
+    size: usize,
+    step: usize,
+    // The frequency of the first bin, as an index into the FFT's bins from
+    // -N/2 to N/2 - 1.
+    first: isize,
+    bins: usize,
+    // The power of each bin in dB, a row at a time.
+    power: Vec<f32>,
+}
+
+impl Spectrogram {
+    /// Computes the spectrogram of `samples` at `sample_rate` Hz, from an
+    /// `N`-point FFT of Hann windowed samples every `step` samples.
+    ///
+    /// Every bin from -`sample_rate` / 2 to `sample_rate` / 2 is kept;
+    /// [`Spectrogram::crop`] keeps just the bins of interest. Power is in dB
+    /// relative to a complex tone of magnitude 1.0, which is 6 dB above a
+    /// real tone of amplitude 1.0.
+    pub fn new<const N: usize, S: Sample>(
+        samples: &[S],
+        sample_rate: u32,
+        step: usize,
+    ) -> Self {
+        let step = step.max(1);
+        let window: Vec<f32> = (0..N)
+            .map(|i| {
+                let x = core::f64::consts::TAU * i as f64 / N as f64;
+                (0.5 - 0.5 * math::sin_cos(x).1) as f32
+            })
+            .collect();
+        let gain: f32 = window.iter().sum();
+
+        let mut fft = Radix2::<N>::new();
+        let mut buffer = [Complex::default(); N];
+        let mut power = Vec::new();
+        for start in (0..samples.len().saturating_sub(N - 1)).step_by(step) {
+            for ((value, &sample), &w) in
+                buffer.iter_mut().zip(&samples[start..]).zip(&window)
+            {
+                let sample = sample.to_complex();
+                *value = Complex::new(sample.re * w, sample.im * w);
+            }
+            fft.forward(&mut buffer);
+            // Rotate the bins by half, so they run from the lowest
+            // frequency to the highest.
+            let (negative, positive) = buffer.split_at(N / 2);
+            for bin in positive.iter().chain(negative) {
+                let level = bin.norm_sqr() / (gain * gain);
+                power.push(10.0 * math::log10(level.max(1e-20)));
+            }
+        }
+
+        Self {
+            sample_rate,
+            size: N,
+            step,
+            first: -(N as isize / 2),
+            bins: N,
+            power,
+        }
+    }
+
+    /// Keeps just the bins from `low` to `high` Hz.
+    pub fn crop(&self, low: f32, high: f32) -> Self {
+        let bin = |frequency: f32| {
+            let bin = frequency * self.size as f32 / self.sample_rate as f32;
+            (math::round(bin as f64) as isize - self.first)
+                .clamp(0, self.bins as isize) as usize
+        };
+        let (start, end) = (bin(low), bin(high).max(bin(low)));
+        let power = self
+            .power
+            .chunks(self.bins)
+            .flat_map(|row| row[start..end].iter().copied())
+            .collect();
+        Self {
+            first: self.first + start as isize,
+            bins: end - start,
+            power,
+            ..*self
+        }
+    }
+
+    /// The number of rows, one for each FFT.
+    pub fn rows(&self) -> usize {
+        self.power.len().checked_div(self.bins).unwrap_or(0)
+    }
+
+    /// The number of frequency bins in each row.
+    pub fn bins(&self) -> usize {
+        self.bins
+    }
+
+    /// The power in dB of each bin of row `row`, from the lowest frequency
+    /// to the highest.
+    pub fn row(&self, row: usize) -> &[f32] {
+        let start = (row * self.bins).min(self.power.len());
+        let end = (start + self.bins).min(self.power.len());
+        &self.power[start..end]
+    }
+
+    /// The frequency of the center of bin `bin` in Hz.
+    pub fn frequency(&self, bin: usize) -> f32 {
+        let bin = self.first + bin as isize;
+        bin as f32 * self.sample_rate as f32 / self.size as f32
+    }
+
+    /// The time of the center of row `row` in seconds from the first
+    /// sample.
+    pub fn time(&self, row: usize) -> f32 {
+        (row * self.step + self.size / 2) as f32 / self.sample_rate as f32
+    }
+
+    /// Writes the spectrogram as an 8-bit grayscale PNG image, a pixel for
+    /// each bin of each row, with the strongest bin white and anything
+    /// `range` dB or more below it black.
+    pub fn write_png<W: Write>(
+        &self,
+        mut writer: W,
+        range: f32,
+    ) -> io::Result<()> {
+        let (width, height) = (self.bins as u32, self.rows() as u32);
+        let peak = self.power.iter().fold(f32::MIN, |a, &b| a.max(b));
+        let range = range.max(f32::EPSILON);
+
+        // Each line of pixels is preceded by its filter type, 0 for none.
+        let mut pixels = Vec::with_capacity(self.power.len() + self.rows());
+        for row in self.power.chunks(self.bins.max(1)) {
+            pixels.push(0);
+            pixels.extend(row.iter().map(|&power| {
+                let level = 1.0 - (peak - power) / range;
+                (level.clamp(0.0, 1.0) * 255.0 + 0.5) as u8
+            }));
+        }
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        // 8 bits, grayscale, deflate, adaptive filtering, not interlaced.
+        header.extend_from_slice(&[8, 0, 0, 0, 0]);
+
+        writer.write_all(b"\x89PNG\r\n\x1a\n")?;
+        write_chunk(&mut writer, b"IHDR", &header)?;
+        write_chunk(&mut writer, b"IDAT", &zlib(&pixels))?;
+        write_chunk(&mut writer, b"IEND", &[])?;
+        writer.flush()
+    }
+}
+
+// Writes a PNG chunk of `kind` holding `data`.
+fn write_chunk<W: Write>(
+    writer: &mut W,
+    kind: &[u8; 4],
+    data: &[u8],
+) -> io::Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    let crc = crc32(crc32(!0, kind), data);
+    writer.write_all(&(!crc).to_be_bytes())
+}
+
+// Wraps `data` in a zlib stream of uncompressed deflate blocks, which every
+// PNG reader accepts, avoiding a compression library.
+fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut stream = std::vec![0x78, 0x01];
+    let mut blocks = data.chunks(0xffff).peekable();
+    if blocks.peek().is_none() {
+        stream.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        let last = blocks.peek().is_none() as u8;
+        let length = block.len() as u16;
+        stream.push(last);
+        stream.extend_from_slice(&length.to_le_bytes());
+        stream.extend_from_slice(&(!length).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    stream.extend_from_slice(&((b << 16) | a).to_be_bytes());
+    stream
+}
+
+// Continues the CRC-32 `crc` over `data`, bit by bit.
+fn crc32(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb88320 & (crc & 1).wrapping_neg());
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::modulate::Modulator;
+
+    #[test]
+    fn test_spectrogram() {
+        // Each symbol of a transmission is in the bin of its tone.
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let samples: Vec<f32> =
+            Modulator::new(&symbols, 375, 100.0).unwrap().collect();
+        let spectrogram = Spectrogram::new::<256, _>(&samples, 375, 256);
+        assert_eq!(spectrogram.rows(), 162);
+        assert_eq!(spectrogram.bins(), 256);
+        assert_eq!(spectrogram.frequency(0), -187.5);
+        assert!((spectrogram.time(1) - 1.5 * 256.0 / 375.0).abs() < 1e-6);
+
+        let spectrogram = spectrogram.crop(95.0, 105.0);
+        assert_eq!(spectrogram.bins(), 7);
+        let lowest = 100.0 - 1.5 * crate::TONE_SPACING;
+        for (i, &symbol) in symbols.iter().enumerate() {
+            let row = spectrogram.row(i);
+            let peak = (0..row.len())
+                .max_by(|&a, &b| row[a].total_cmp(&row[b]))
+                .unwrap();
+            let tone = lowest + symbol as f32 * crate::TONE_SPACING;
+            assert!((spectrogram.frequency(peak) - tone).abs() < 0.75);
+            // A real tone of amplitude 1.0 is 6 dB below 0 dB.
+            assert!(row[peak] > -8.0 && row[peak] < -6.0);
+        }
+    }
+
+    #[test]
+    fn test_write_png() {
+        let samples: Vec<Complex> = (0..1024)
+            .map(|i| {
+                let (sin, cos) = (i as f32 * 0.5).sin_cos();
+                Complex::new(cos, sin)
+            })
+            .collect();
+        let spectrogram = Spectrogram::new::<64, _>(&samples, 8000, 32);
+        let mut png = Vec::new();
+        spectrogram.write_png(&mut png, 60.0).unwrap();
+
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(&png[16..20], &64u32.to_be_bytes());
+        assert_eq!(&png[20..24], &31u32.to_be_bytes());
+        // The CRC of the IHDR chunk.
+        let crc = !crc32(!0, &png[12..29]);
+        assert_eq!(&png[29..33], &crc.to_be_bytes());
+        assert_eq!(&png[png.len() - 12..], b"\0\0\0\0IEND\xae\x42\x60\x82");
+
+        // The pixels are stored whole, with the tone's bin white in every
+        // line.
+        let data = &png[41..png.len() - 16];
+        assert_eq!(&data[..2], &[0x78, 0x01]);
+        let pixels = &data[7..data.len() - 4];
+        assert_eq!(pixels.len(), 31 * 65);
+        let bin = (0.5 / core::f32::consts::TAU * 64.0).round() as usize;
+        for line in pixels.chunks(65) {
+            assert_eq!(line[0], 0);
+            assert_eq!(line[1 + 32 + bin], 255);
+        }
+    }
+}
@@ -0,0 +1,234 @@
+//! Decoded transmissions, and formatting them as the lines of text printed
+//! by wsprd, for tools such as wsprdaemon that parse its output.
+
+use core::fmt::{self, Write};
+
+use crate::search::Candidate;
+use crate::{math, Message};
+
+/// A decoded transmission.
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Spot {
+    /// The decoded message.
+    pub message: Message,
+    /// The frequency of the center of the signal, midway between tones 1 and
+    /// 2, in Hz. Whether this is relative to a baseband, an audio frequency,
+    /// or an RF frequency depends on where the spot came from.
+    pub frequency: f64,
+    /// The estimated signal to noise ratio in dB, in a 2500 Hz bandwidth.
+    pub snr: f32,
+    /// The offset of the start of the transmission from its nominal start, 1
+    /// second into the cycle, in seconds.
+    pub dt: f32,
+    /// The change in frequency over the length of the transmission, in Hz.
+    pub drift: f32,
+    /// The correlation with the sync vector, from -1.0 to 1.0.
+    pub sync: f32,
+    /// The number of soft symbols whose sign disagreed with the decoded
+    /// message, see [`crate::hard_errors`]. Marginal decodes have many.
+    pub errors: u32,
+    /// The metric of the channel decoder that decoded the message, larger
+    /// for cleaner decodes, or `None` if it was found by a deep search.
+    pub metric: Option<i32>,
+    /// The start of the cycle, in seconds since the Unix epoch (UTC), or 0
+    /// if it isn't known.
+    pub timestamp: u64,
+}
+
+// Spots of the same callsign less than this far apart, in Hz, are taken to
+// be the same transmission, about the width of a signal.
+const DUPLICATE_SPACING: f64 = 4.0;
+
+impl Spot {
+    /// Returns true if `self` and `other` are likely the same transmission
+    /// decoded twice, from a sidelobe or a second candidate: either they
+    /// carry the same message, or the same callsign at nearly the same
+    /// frequency.
+    pub fn is_duplicate(&self, other: &Spot) -> bool {
+        self.message == other.message
+            || (self.message.callsign() == other.message.callsign()
+                && (self.frequency - other.frequency).abs() < DUPLICATE_SPACING)
+    }
+
+    /// Creates a spot for a message decoded from `candidate`. The frequency
+    /// is relative to the center of the baseband that was searched and the
+    /// timestamp is unknown.
+    pub fn new(message: Message, candidate: &Candidate) -> Self {
+        Spot {
+            message,
+            frequency: candidate.frequency as f64,
+            snr: candidate.snr,
+            dt: candidate.dt(),
+            drift: candidate.drift,
+            sync: candidate.sync,
+            errors: 0,
+            metric: None,
+            timestamp: 0,
+        }
+    }
+
+    /// Writes the spot to `out` as a line in the format printed by wsprd,
+    /// without a trailing newline:
+    ///
+    /// ```text
+    /// ddhhmm snr   dt       freq drift  call grid pwr
+    /// 151802 -21  0.3  14.097056  0  K1ABC FN42 37
+    /// ```
+    ///
+    /// The frequency is printed in MHz, so should be an RF frequency.
+    pub fn write_line<W: Write>(&self, out: &mut W) -> fmt::Result {
+        let minutes = self.timestamp / 60;
+        write!(
+            out,
+            "{:02}{:02}{:02} {:3} {:4.1} {:10.6} {:2}  {} {} {}",
+            day_of_month(minutes / (24 * 60)),
+            minutes / 60 % 24,
+            minutes % 60,
+            math::round(self.snr as f64) as i32,
+            self.dt,
+            self.frequency / 1e6,
+            math::round(self.drift as f64) as i32,
+            self.message.callsign(),
+            self.message.grid(),
+            self.message.power(),
+        )
+    }
+}
+
+/// Removes duplicate spots (see [`Spot::is_duplicate`]) from `spots`,
+/// keeping the one with the best SNR of each set of duplicates in the
+/// position of the first. The remaining spots are moved to the front, and
+/// their number returned.
+pub fn dedup(spots: &mut [Spot]) -> usize {
+    let mut count = 0;
+    for i in 0..spots.len() {
+        let spot = spots[i];
+        count = merge(spots, count, spot);
+    }
+    count
+}
+
+/// Adds `spot` after the first `count` spots of `spots`, unless it
+/// duplicates one of them, in which case the one with the better SNR is
+/// kept. Returns the new count, which is unchanged if `spots` is full.
+pub fn merge(spots: &mut [Spot], count: usize, spot: Spot) -> usize {
+    if let Some(existing) =
+        spots[..count].iter_mut().find(|s| s.is_duplicate(&spot))
+    {
+        if spot.snr > existing.snr {
+            *existing = spot;
+        }
+        count
+    } else if count < spots.len() {
+        spots[count] = spot;
+        count + 1
+    } else {
+        count
+    }
+}
+
+// Returns the day of the month of `days` days since 1970-01-01. See
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn day_of_month(days: u64) -> u64 {
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524
+        - day_of_era / 146_096)
+        / 365;
+    let day_of_year =
+        day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    day_of_year - (153 * month + 2) / 5 + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::string::String;
+
+    #[test]
+    fn test_day_of_month() {
+        assert_eq!(day_of_month(0), 1);
+        assert_eq!(day_of_month(31), 1);
+        assert_eq!(day_of_month(59), 1);
+        // 2000-02-29 and 2024-12-31
+        assert_eq!(day_of_month(11_016), 29);
+        assert_eq!(day_of_month(20_088), 31);
+    }
+
+    #[test]
+    fn test_dedup() {
+        let spot = |callsign, grid, power, frequency, snr| {
+            let symbols = crate::encode(callsign, grid, power).unwrap();
+            Spot {
+                message: crate::decode(&symbols).unwrap(),
+                frequency,
+                snr,
+                ..Spot::default()
+            }
+        };
+
+        let mut spots = [
+            spot("K1ABC", "FN42", 37, 1500.0, -20.0),
+            spot("G1ABC", "IO83", 20, 1540.0, -15.0),
+            // A sidelobe that decoded with the wrong power.
+            spot("K1ABC", "FN42", 30, 1502.5, -25.0),
+            // The same message from a second, better candidate.
+            spot("G1ABC", "IO83", 20, 1540.3, -12.0),
+            // The same callsign, well away from the first.
+            spot("K1ABC", "FN42", 33, 1560.0, -22.0),
+        ];
+        assert_eq!(dedup(&mut spots), 3);
+        assert_eq!(spots[0].snr, -20.0);
+        assert_eq!(spots[0].message.power(), 37);
+        assert_eq!(spots[1].snr, -12.0);
+        assert_eq!(spots[2].message.power(), 33);
+    }
+
+    #[test]
+    fn test_write_line() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let message = crate::decode(&symbols).unwrap();
+
+        let spot = Spot {
+            message,
+            frequency: 14_097_056.2,
+            snr: -20.6,
+            dt: 0.31,
+            drift: -0.4,
+            sync: 0.8,
+            errors: 12,
+            metric: Some(2100),
+            // 2024-03-15 18:02 UTC
+            timestamp: 1_710_525_720,
+        };
+
+        let mut line = String::new();
+        spot.write_line(&mut line).unwrap();
+        assert_eq!(line, "151802 -21  0.3  14.097056  0  K1ABC FN42 37");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let spot = Spot {
+            message: crate::decode(&symbols).unwrap(),
+            frequency: 14_097_056.0,
+            timestamp: 1_710_525_720,
+            ..Spot::default()
+        };
+
+        let json = serde_json::to_string(&spot).unwrap();
+        assert!(json.contains(
+            r#""message":{"callsign":"K1ABC","grid":"FN42","power":37}"#
+        ));
+        assert_eq!(serde_json::from_str::<Spot>(&json).unwrap(), spot);
+
+        let json = json.replace("FN42", "FN4Z");
+        assert!(serde_json::from_str::<Spot>(&json).is_err());
+    }
+}
@@ -0,0 +1,120 @@
+//! Stacking the soft symbols of a transmitter over several cycles.
+//!
+//! A beacon sending the same message cycle after cycle can be decoded below
+//! the single cycle threshold by adding up its soft symbols: the signal adds
+//! coherently while the noise doesn't, so stacking `n` cycles gains up to
+//! 10·log10(`n`) dB. This is mostly useful for LF and MF experiments, where
+//! signals are weak but stable.
+
+use crate::{decode_deep, decode_soft, Error, Message};
+
+/// An accumulation of soft symbols (see [`decode_soft`]) from the same
+/// transmitter over several cycles.
+///
+/// Only cycles in which the transmitter is known to have sent the same
+/// message should be added, with its symbols demodulated at the same
+/// frequency and timing each time. Adding cycles without the signal only
+/// dilutes it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stack {
+    sums: [i32; 162],
+    cycles: u32,
+}
+
+impl Default for Stack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stack {
+    /// Creates an empty stack.
+    pub const fn new() -> Self {
+        Self {
+            sums: [0; 162],
+            cycles: 0,
+        }
+    }
+
+    /// The number of cycles added.
+    pub fn cycles(&self) -> u32 {
+        self.cycles
+    }
+
+    /// Adds the soft symbols of a cycle.
+    pub fn push(&mut self, llrs: &[i8; 162]) {
+        for (sum, &llr) in self.sums.iter_mut().zip(llrs) {
+            *sum += llr as i32;
+        }
+        self.cycles += 1;
+    }
+
+    /// Removes every cycle added.
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+
+    /// The combined soft symbols of every cycle added, normalized as
+    /// [`crate::soft_symbols`] does so that a typical soft symbol has a
+    /// magnitude of around 40.
+    pub fn llrs(&self) -> [i8; 162] {
+        let total: i64 = self.sums.iter().map(|&sum| sum.abs() as i64).sum();
+        let mut llrs = [0i8; 162];
+        if total > 0 {
+            let scale = 40.0 * 162.0 / total as f32;
+            for (llr, &sum) in llrs.iter_mut().zip(&self.sums) {
+                *llr = (sum as f32 * scale).clamp(-127.0, 127.0) as i8;
+            }
+        }
+        llrs
+    }
+
+    /// Decodes the combined soft symbols, see [`decode_soft`].
+    pub fn decode(&self) -> Result<Message, Error> {
+        decode_soft(&self.llrs())
+    }
+
+    /// Decodes the combined soft symbols with a deep search for the
+    /// callsigns and grids in `known`, see [`decode_deep`].
+    pub fn decode_deep(
+        &self,
+        known: &[(&str, &str)],
+    ) -> Result<Message, Error> {
+        decode_deep(&self.llrs(), known)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demod::tests::Noise;
+
+    #[test]
+    fn test_stack() {
+        let symbols = crate::encode("G1ABC", "IO83", 37).unwrap();
+        let mut noise = Noise::new();
+        let mut stack = Stack::new();
+        assert_eq!(stack.decode(), Err(Error::DecodeFailed));
+
+        // Each cycle alone is too weak to decode, but together they are.
+        for _ in 0..8 {
+            let mut llrs = [0i8; 162];
+            for (llr, &symbol) in llrs.iter_mut().zip(&symbols) {
+                let sign = if symbol >> 1 == 1 { 1.0 } else { -1.0 };
+                *llr = (25.0 * sign + 100.0 * noise.next()) as i8;
+            }
+            assert!(decode_soft(&llrs).is_err());
+            stack.push(&llrs);
+        }
+        assert_eq!(stack.cycles(), 8);
+
+        let message = stack.decode().unwrap();
+        assert_eq!(message.callsign(), "G1ABC");
+        assert_eq!(message.grid(), "IO83");
+        assert_eq!(message.power(), 37);
+
+        stack.clear();
+        assert_eq!(stack.cycles(), 0);
+        assert_eq!(stack.llrs(), [0; 162]);
+    }
+}
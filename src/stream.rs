@@ -0,0 +1,339 @@
+//! Decoding a continuous stream of samples as it arrives.
+//!
+//! A [`Decoder`] takes blocks of samples of any size straight from a sound
+//! card or SDR, keeping only the 375 Hz baseband of the current cycle, and
+//! decodes each cycle as soon as its 114 seconds have been received. The
+//! cycles are found from a [`Clock`], so the caller never needs to line up
+//! or assemble whole cycles themselves.
+
+use crate::dsp::{Complex, Downconverter, Fft, Sample};
+use crate::search::{self, Window, FFT_LEN, SAMPLE_RATE};
+use crate::spot::Spot;
+use crate::Error;
+
+// The length of a cycle in milliseconds.
+const CYCLE: u64 = 120_000;
+
+/// The number of baseband samples kept for each cycle, the first 114
+/// seconds.
+pub const BASEBAND_LEN: usize = 114 * SAMPLE_RATE as usize;
+
+/// The number of `f32`s of workspace a [`Decoder`] needs, about 400KB.
+pub const WORKSPACE_LEN: usize = search::workspace_len(BASEBAND_LEN);
+
+// How far the clock may disagree with the number of samples received
+// before the cycle being recorded is abandoned, in milliseconds. Samples
+// dropped by the audio driver show up this way.
+const MAX_SLIP: u64 = 500;
+
+/// A source of the current time, in milliseconds since the Unix epoch.
+///
+/// It only needs to be accurate to within a second or so of UTC, but
+/// shouldn't jump around; a real time clock set from GPS or NTP is ideal.
+pub trait Clock {
+    /// The current time.
+    fn now(&mut self) -> u64;
+}
+
+impl<F: FnMut() -> u64> Clock for F {
+    fn now(&mut self) -> u64 {
+        self()
+    }
+}
+
+/// The system clock.
+#[cfg(feature = "std")]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SystemClock;
+
+#[cfg(feature = "std")]
+impl Clock for SystemClock {
+    fn now(&mut self) -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|time| time.as_millis() as u64)
+            .unwrap_or_default()
+    }
+}
+
+/// A decoder fed a continuous stream of samples.
+///
+/// The stream is converted to the baseband as it arrives with a
+/// [`Downconverter`], into a buffer of [`BASEBAND_LEN`] samples provided by
+/// the caller, along with [`WORKSPACE_LEN`] `f32`s of workspace for the
+/// search. Together they take about 750KB, so on a microcontroller they're
+/// best kept in statics.
+pub struct Decoder<'a, C: Clock, F: Fft<FFT_LEN>> {
+    clock: C,
+    fft: F,
+    sample_rate: u32,
+    frequency: f64,
+    dial: f64,
+    window: Window,
+    template: Downconverter,
+    downconverter: Downconverter,
+    baseband: &'a mut [Complex],
+    workspace: &'a mut [f32],
+    len: usize,
+    // The start of the cycle being recorded in milliseconds, and the number
+    // of input samples received since.
+    cycle: Option<u64>,
+    received: u64,
+}
+
+impl<'a, C: Clock, F: Fft<FFT_LEN>> Decoder<'a, C, F> {
+    /// Creates a decoder for samples at `sample_rate` Hz, real or complex,
+    /// where the center of the WSPR passband is at `frequency` Hz, such as
+    /// 1500 Hz for audio (see [`Downconverter::new`]).
+    ///
+    /// Returns [`Error::BufferTooSmall`] if `baseband` holds fewer than
+    /// [`BASEBAND_LEN`] samples or `workspace` fewer than [`WORKSPACE_LEN`].
+    pub fn new(
+        sample_rate: u32,
+        frequency: f32,
+        clock: C,
+        fft: F,
+        baseband: &'a mut [Complex],
+        workspace: &'a mut [f32],
+    ) -> Result<Self, Error> {
+        if baseband.len() < BASEBAND_LEN || workspace.len() < WORKSPACE_LEN {
+            return Err(Error::BufferTooSmall);
+        }
+        let downconverter = Downconverter::new(sample_rate, frequency)?;
+        Ok(Self {
+            clock,
+            fft,
+            sample_rate,
+            frequency: frequency as f64,
+            dial: 0.0,
+            window: Window::new(),
+            template: downconverter.clone(),
+            downconverter,
+            baseband,
+            workspace,
+            len: 0,
+            cycle: None,
+            received: 0,
+        })
+    }
+
+    /// Reports spot frequencies as RF frequencies, for a receiver tuned to
+    /// `dial` Hz. Otherwise they are frequencies in the input.
+    pub fn with_dial(self, dial: f64) -> Self {
+        Self { dial, ..self }
+    }
+
+    /// Only searches for transmissions within `window`, see
+    /// [`search::search_within`].
+    pub fn with_window(self, window: Window) -> Self {
+        Self { window, ..self }
+    }
+
+    /// The start of the cycle being recorded, in seconds since the Unix
+    /// epoch, or `None` while waiting for the next one to begin.
+    pub fn cycle(&self) -> Option<u64> {
+        self.cycle.map(|start| start / 1000)
+    }
+
+    /// Takes the next block of samples, which are taken to have just been
+    /// received when this is called.
+    ///
+    /// When the block completes a cycle, the cycle is decoded and the spots
+    /// found are written to `spots`, returning how many were written.
+    /// Otherwise 0 is returned. Decoding takes a while, so the caller should
+    /// be able to buffer a few seconds of samples meanwhile.
+    ///
+    /// A cycle is only recorded if the stream is received from its start;
+    /// after a gap in the stream, decoding resumes at the next cycle.
+    pub fn push_samples<S: Sample>(
+        &mut self,
+        samples: &[S],
+        spots: &mut [Spot],
+    ) -> Result<usize, Error> {
+        // The time of the first sample in the block.
+        let now = self.clock.now();
+        let duration = self.duration(samples.len() as u64);
+        let time = now.saturating_sub(duration);
+
+        if let Some(start) = self.cycle {
+            let expected = start + self.duration(self.received);
+            if time.abs_diff(expected) > MAX_SLIP {
+                self.cycle = None;
+            }
+        }
+
+        let mut count = 0;
+        let mut index = 0;
+        while index < samples.len() {
+            let Some(start) = self.cycle else {
+                // Skip ahead to the start of the next cycle.
+                let time = time + self.duration(index as u64);
+                let start = time.div_ceil(CYCLE) * CYCLE;
+                let skip =
+                    ((start - time) * self.sample_rate as u64).div_ceil(1000);
+                index += skip as usize;
+                if index >= samples.len() {
+                    break;
+                }
+                self.cycle = Some(start);
+                self.received = 0;
+                self.len = 0;
+                self.downconverter = self.template.clone();
+                continue;
+            };
+
+            for &sample in &samples[index..] {
+                index += 1;
+                self.received += 1;
+                if let Some(sample) = self.downconverter.push(sample) {
+                    self.baseband[self.len] = sample;
+                    self.len += 1;
+                    if self.len == BASEBAND_LEN {
+                        break;
+                    }
+                }
+            }
+
+            if self.len == BASEBAND_LEN {
+                self.cycle = None;
+                count += self.decode(start, &mut spots[count..])?;
+            }
+        }
+        Ok(count)
+    }
+
+    // The time `samples` input samples take, in milliseconds.
+    fn duration(&self, samples: u64) -> u64 {
+        samples * 1000 / self.sample_rate as u64
+    }
+
+    // Decodes the cycle beginning at `start` milliseconds.
+    fn decode(
+        &mut self,
+        start: u64,
+        spots: &mut [Spot],
+    ) -> Result<usize, Error> {
+        let count = search::decode_within(
+            &mut self.baseband[..BASEBAND_LEN],
+            &mut self.fft,
+            &mut self.workspace[..WORKSPACE_LEN],
+            &self.window,
+            &[],
+            spots,
+        )?;
+        let delay = self.downconverter.delay() as f32;
+        for spot in spots[..count].iter_mut() {
+            spot.frequency += self.frequency + self.dial;
+            spot.dt -= delay;
+            spot.timestamp = start / 1000;
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demod::tests::{synthesize, Noise};
+    use crate::dsp::Radix2;
+    use std::cell::Cell;
+    use std::vec;
+    use std::vec::Vec;
+
+    #[test]
+    fn test_decoder() {
+        let mut baseband = vec![Complex::default(); BASEBAND_LEN];
+        let mut workspace = vec![0f32; WORKSPACE_LEN];
+        assert!(matches!(
+            Decoder::new(
+                8000,
+                1500.0,
+                || 0,
+                Radix2::new(),
+                &mut baseband[1..],
+                &mut workspace,
+            ),
+            Err(Error::BufferTooSmall)
+        ));
+
+        // A transmission 1 second into a cycle, at a 8000 Hz sample rate, in
+        // a stream beginning 5 seconds before the cycle.
+        let sample_rate = 8000u32;
+        let start = 1_700_000_040_000;
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let mut samples = vec![0f32; 122 * sample_rate as usize];
+        let lowest = 1520.0 - 1.5 * crate::TONE_SPACING;
+        synthesize(
+            &symbols,
+            sample_rate,
+            lowest,
+            6 * sample_rate as usize,
+            &mut samples,
+        );
+        let mut noise = Noise::new();
+        let samples: Vec<i16> = samples
+            .iter()
+            .map(|&sample| ((sample + 2.0 * noise.next()) * 3000.0) as i16)
+            .collect();
+
+        let clock = Cell::new(start - 5000);
+        let mut decoder = Decoder::new(
+            sample_rate,
+            1500.0,
+            || clock.get(),
+            Radix2::new(),
+            &mut baseband,
+            &mut workspace,
+        )
+        .unwrap()
+        .with_dial(14_095_600.0)
+        .with_window(Window::new().with_frequency(20.0, 10.0));
+
+        let mut spots = [Spot::default(); 8];
+        let mut decoded = Vec::new();
+        for block in samples.chunks(1000) {
+            clock.set(clock.get() + 125);
+            let count = decoder.push_samples(block, &mut spots).unwrap();
+            if count > 0 {
+                decoded.extend_from_slice(&spots[..count]);
+                assert_eq!(decoder.cycle(), None);
+            } else if clock.get() > start + 1000 && decoded.is_empty() {
+                assert_eq!(decoder.cycle(), Some(start / 1000));
+            }
+        }
+
+        assert_eq!(decoded.len(), 1);
+        let spot = &decoded[0];
+        assert_eq!(spot.message.callsign(), "K1ABC");
+        assert_eq!(spot.timestamp, start / 1000);
+        assert!((spot.frequency - 14_097_120.0).abs() < 1.0);
+        assert!(spot.dt.abs() < 0.15, "{}", spot.dt);
+    }
+
+    #[test]
+    fn test_decoder_gap() {
+        let mut baseband = vec![Complex::default(); BASEBAND_LEN];
+        let mut workspace = vec![0f32; WORKSPACE_LEN];
+        let clock = Cell::new(1_700_000_040_000);
+        let mut decoder = Decoder::new(
+            4000,
+            1500.0,
+            || clock.get(),
+            Radix2::new(),
+            &mut baseband,
+            &mut workspace,
+        )
+        .unwrap();
+
+        let block = [0i16; 400];
+        let mut spots = [Spot::default(); 8];
+        clock.set(clock.get() + 100);
+        decoder.push_samples(&block, &mut spots).unwrap();
+        assert_eq!(decoder.cycle(), Some(1_700_000_040));
+
+        // A second of samples goes missing.
+        clock.set(clock.get() + 1100);
+        decoder.push_samples(&block, &mut spots).unwrap();
+        assert_eq!(decoder.cycle(), None);
+    }
+}
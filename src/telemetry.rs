@@ -0,0 +1,16 @@
+//! Telemetry sent by pico balloons and other trackers in the fields of
+//! ordinary WSPR messages.
+//!
+//! Trackers alternate a regular message, with their callsign, grid square,
+//! and power, with messages whose fields carry measurements instead. Each
+//! scheme packs its measurements differently, in its own submodule.
+
+pub mod extended;
+pub mod u4b;
+pub mod wb8elk;
+pub mod zachtek;
+
+// The power levels a message can carry, in order.
+const POWERS: [u8; 19] = [
+    0, 3, 7, 10, 13, 17, 20, 23, 27, 30, 33, 37, 40, 43, 47, 50, 53, 57, 60,
+];
@@ -0,0 +1,210 @@
+//! Extended telemetry, packing measurements of the user's choosing into the
+//! same messages as U4B telemetry.
+//!
+//! A [`Schema`] lists the measurements, each a [`Field`] with a range and a
+//! resolution, and packs them into the callsign, grid, and power of a
+//! telemetry message on a U4B channel (see [`super::u4b`]). A bit of the
+//! grid and power distinguishes these messages from standard U4B
+//! telemetry, leaving room for about 37 bits of measurements.
+//!
+//! Schemas are best declared as constants, so that a schema whose fields
+//! don't fit is a compile error:
+//!
+//! ```
+//! use wspr::telemetry::extended::{Field, Schema};
+//!
+//! const SCHEMA: Schema<2> = Schema::new([
+//!     // Altitude, from 0 to 21340 meters in steps of 20.
+//!     Field::new(0.0, 21340.0, 20.0),
+//!     // Solar panel voltage, from 0 to 6 volts in steps of 0.01.
+//!     Field::new(0.0, 6.0, 0.01),
+//! ]);
+//!
+//! let message = SCHEMA.to_message(0, &[12060.0, 4.2]).unwrap();
+//! let values = SCHEMA.from_message(&message).unwrap();
+//! assert_eq!(values[0], 12060.0);
+//! ```
+
+use super::u4b::{self, CALLSIGN_VALUES, GRID_VALUES};
+use crate::{Error, Message};
+
+// The number of values the grid and power carry once the bit marking the
+// message as extended telemetry is taken.
+const GRID_HALF: u64 = GRID_VALUES as u64 / 2;
+
+/// The number of distinct values an extended telemetry message can carry,
+/// which the product of the number of values of each field can't exceed.
+pub const CAPACITY: u64 = CALLSIGN_VALUES as u64 * GRID_HALF;
+
+/// A measurement, from `min` to `max` in steps of `step`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Field {
+    /// The lowest value, which values below are clamped to.
+    pub min: f32,
+    /// The highest value, which values above are clamped to.
+    pub max: f32,
+    /// The resolution, which values are rounded to.
+    pub step: f32,
+}
+
+impl Field {
+    /// Creates a field. Panics if `step` isn't positive or `max` is below
+    /// `min`.
+    pub const fn new(min: f32, max: f32, step: f32) -> Self {
+        assert!(step > 0.0 && max >= min, "invalid field range");
+        Self { min, max, step }
+    }
+
+    /// The number of distinct values the field takes.
+    pub const fn values(&self) -> u64 {
+        ((self.max - self.min) / self.step + 0.5) as u64 + 1
+    }
+
+    // The index of the value nearest `value`.
+    fn index(&self, value: f32) -> u64 {
+        let value = value.clamp(self.min, self.max);
+        (((value - self.min) / self.step + 0.5) as u64).min(self.values() - 1)
+    }
+}
+
+/// The measurements carried by an extended telemetry message.
+///
+/// The fields are packed in order, the first taking the lowest place
+/// value, so a receiver must use the same schema as the transmitter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schema<const N: usize> {
+    fields: [Field; N],
+}
+
+impl<const N: usize> Schema<N> {
+    /// Creates a schema from its fields. Panics if the fields take more
+    /// than [`CAPACITY`] values together, which is a compile error when the
+    /// schema is a constant.
+    pub const fn new(fields: [Field; N]) -> Self {
+        let mut total = 1u64;
+        let mut i = 0;
+        while i < N {
+            total = total.saturating_mul(fields[i].values());
+            i += 1;
+        }
+        assert!(total <= CAPACITY, "the fields don't fit in a message");
+        Self { fields }
+    }
+
+    /// The fields of the schema.
+    pub fn fields(&self) -> &[Field; N] {
+        &self.fields
+    }
+
+    /// The telemetry message carrying `values` sent on `channel`, from 0 to
+    /// 599. Each value is rounded and clamped to its field.
+    ///
+    /// Returns [`Error::InvalidCallsign`] if `channel` is out of range.
+    pub fn to_message(
+        &self,
+        channel: u16,
+        values: &[f32; N],
+    ) -> Result<Message, Error> {
+        let id = u4b::id(channel)?;
+        let mut value = 0u64;
+        for (field, &v) in self.fields.iter().zip(values).rev() {
+            value = value * field.values() + field.index(v);
+        }
+        let callsign = (value / GRID_HALF) as u32;
+        let grid = (value % GRID_HALF) as u32 * 2;
+        u4b::pack(id, callsign, grid)
+    }
+
+    /// Encodes the telemetry message carrying `values` sent on `channel`
+    /// into 162 symbols, see [`Schema::to_message`].
+    pub fn encode(
+        &self,
+        channel: u16,
+        values: &[f32; N],
+    ) -> Result<[u8; 162], Error> {
+        self.to_message(channel, values)?.encode()
+    }
+
+    /// Unpacks the values from a received telemetry message, or returns
+    /// `None` if `message` isn't extended telemetry that fits the schema.
+    pub fn from_message(&self, message: &Message) -> Option<[f32; N]> {
+        let (callsign, grid) = u4b::unpack(message)?;
+        if grid % 2 != 0 {
+            return None;
+        }
+        let mut value = callsign as u64 * GRID_HALF + grid as u64 / 2;
+
+        let mut values = [0.0; N];
+        for (field, v) in self.fields.iter().zip(values.iter_mut()) {
+            *v = field.min + (value % field.values()) as f32 * field.step;
+            value /= field.values();
+        }
+        (value == 0).then_some(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::telemetry::u4b::Telemetry;
+
+    const SCHEMA: Schema<4> = Schema::new([
+        Field::new(0.0, 21340.0, 20.0),
+        Field::new(-50.0, 50.0, 1.0),
+        Field::new(0.0, 1.0, 1.0),
+        Field::new(3.0, 5.0, 0.01),
+    ]);
+
+    #[test]
+    fn test_extended() {
+        assert_eq!(SCHEMA.fields()[0].values(), 1068);
+        assert_eq!(SCHEMA.fields()[3].values(), 201);
+
+        for values in [
+            [0.0, -50.0, 0.0, 3.0],
+            [21340.0, 50.0, 1.0, 5.0],
+            [12060.0, -21.0, 1.0, 4.13],
+        ] {
+            let symbols = SCHEMA.encode(123, &values).unwrap();
+            let message = crate::decode(&symbols).unwrap();
+            let decoded = SCHEMA.from_message(&message).unwrap();
+            for (decoded, value) in decoded.iter().zip(values) {
+                assert!((decoded - value).abs() < 1e-3, "{decoded} {value}");
+            }
+            assert_eq!(Telemetry::from_message(&message), None);
+        }
+
+        // Values are rounded and clamped.
+        let message =
+            SCHEMA.to_message(0, &[12069.0, -60.0, 0.7, 9.0]).unwrap();
+        let decoded = SCHEMA.from_message(&message).unwrap();
+        assert_eq!(decoded[..3], [12060.0, -50.0, 1.0]);
+        assert!((decoded[3] - 5.0).abs() < 1e-3);
+
+        // Standard telemetry and other schemas aren't unpacked.
+        let telemetry = Telemetry {
+            subsquare: *b"AX",
+            altitude: 12060,
+            temperature: -25,
+            voltage: 4.1,
+            speed: 36,
+            gps_valid: true,
+        };
+        let message = telemetry.to_message(0).unwrap();
+        assert_eq!(SCHEMA.from_message(&message), None);
+        let small = Schema::new([Field::new(0.0, 9.0, 1.0)]);
+        let message = SCHEMA.to_message(0, &[20000.0, 0.0, 0.0, 4.0]).unwrap();
+        assert_eq!(small.from_message(&message), None);
+        assert_eq!(
+            SCHEMA.to_message(600, &[0.0; 4]),
+            Err(Error::InvalidCallsign)
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_capacity() {
+        let field = Field::new(0.0, 1e6, 1.0);
+        Schema::new([field, field]);
+    }
+}
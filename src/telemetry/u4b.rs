@@ -0,0 +1,446 @@
+//! The U4B telemetry scheme, used by U4B and Traquito pico balloon
+//! trackers.
+//!
+//! A tracker sends a regular type 1 message with its callsign, grid square,
+//! and power, then a telemetry message whose callsign, grid, and power
+//! carry the fifth and sixth characters of its locator, its altitude,
+//! temperature, battery voltage, speed, and GPS status. The telemetry
+//! callsign begins with `0`, `1`, or `Q` and has its third character set by
+//! the tracker's channel, so that receivers can tell trackers apart.
+//!
+//! [`Telemetry::to_message`] packs the measurements into a message, and
+//! [`Telemetry::from_message`] unpacks them from one that was received.
+//! [`Channel`] gives the rest of a channel's settings: which frequency and
+//! which minute it transmits in.
+
+use super::POWERS;
+use crate::spot::Spot;
+use crate::{math, Error, Message};
+
+// The number of channels, each with a distinct pair of callsign characters.
+const CHANNELS: u16 = 600;
+
+// The characters that begin a telemetry callsign, for channels 0-199,
+// 200-399, and 400-599.
+const IDS: [u8; 3] = [b'0', b'1', b'Q'];
+
+// The audio frequencies of the four lanes channels transmit in, avoiding
+// the middle of the band.
+const LANES: [f32; 4] = [1420.0, 1460.0, 1540.0, 1580.0];
+
+/// A band WSPR is used on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Band {
+    M2190,
+    M630,
+    M160,
+    M80,
+    M60,
+    M40,
+    M30,
+    M20,
+    M17,
+    M15,
+    M12,
+    M10,
+    M6,
+    M4,
+    M2,
+}
+
+impl Band {
+    /// The usual dial frequency of an upper sideband receiver or
+    /// transmitter, in Hz. WSPR signals are 1400-1600 Hz above it.
+    pub fn dial(self) -> f64 {
+        match self {
+            Band::M2190 => 136_000.0,
+            Band::M630 => 474_200.0,
+            Band::M160 => 1_836_600.0,
+            Band::M80 => 3_568_600.0,
+            Band::M60 => 5_287_200.0,
+            Band::M40 => 7_038_600.0,
+            Band::M30 => 10_138_700.0,
+            Band::M20 => 14_095_600.0,
+            Band::M17 => 18_104_600.0,
+            Band::M15 => 21_094_600.0,
+            Band::M12 => 24_924_600.0,
+            Band::M10 => 28_124_600.0,
+            Band::M6 => 50_293_000.0,
+            Band::M4 => 70_091_000.0,
+            Band::M2 => 144_489_000.0,
+        }
+    }
+
+    // The minute of the first of the five slots in each ten minutes, which
+    // rotates from band to band so a tracker hopping bands can use them all.
+    fn first_minute(self) -> u8 {
+        (self as u8 % 5) * 2
+    }
+}
+
+/// The settings of a U4B channel on a band.
+///
+/// The channel's 600 numbers are split into 30 groups of 20, each group with
+/// its own pair of callsign characters. Within a group, each channel has
+/// one of four frequency lanes and one of five slots in every ten minutes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Channel {
+    /// The first and third characters of the telemetry callsign.
+    pub id: [u8; 2],
+    /// The audio frequency of the center of the signal, in Hz above the
+    /// band's dial frequency.
+    pub frequency: f32,
+    /// The minute past the hour, and every ten minutes after, in which the
+    /// regular message is sent. The telemetry message is sent in the cycle
+    /// after it, two minutes later.
+    pub minute: u8,
+}
+
+impl Channel {
+    /// The settings of `channel`, from 0 to 599, on `band`.
+    ///
+    /// Returns [`Error::InvalidCallsign`] if `channel` is out of range.
+    pub fn new(channel: u16, band: Band) -> Result<Self, Error> {
+        Ok(Channel {
+            id: id(channel)?,
+            frequency: LANES[channel as usize % 20 / 5],
+            minute: (band.first_minute() + 2 * (channel % 5) as u8) % 10,
+        })
+    }
+
+    /// The RF frequency of the center of the signal, in Hz.
+    pub fn rf_frequency(&self, band: Band) -> f64 {
+        band.dial() + self.frequency as f64
+    }
+}
+
+// The first and third characters of the telemetry callsign of `channel`.
+pub(super) fn id(channel: u16) -> Result<[u8; 2], Error> {
+    if channel >= CHANNELS {
+        return Err(Error::InvalidCallsign);
+    }
+    Ok([
+        IDS[channel as usize / 200],
+        b'0' + (channel / 20 % 10) as u8,
+    ])
+}
+
+// The number of values the callsign of a telemetry message can carry, with
+// its second character from `0` to `Z` and the last three from `A` to `Z`.
+pub(super) const CALLSIGN_VALUES: u32 = 36 * 26 * 26 * 26;
+
+// The number of values the grid and power of a telemetry message can carry.
+pub(super) const GRID_VALUES: u32 = 18 * 18 * 10 * 10 * 19;
+
+// Packs a value below `CALLSIGN_VALUES` into the callsign of a telemetry
+// message, and one below `GRID_VALUES` into its grid and power.
+pub(super) fn pack(
+    id: [u8; 2],
+    mut callsign: u32,
+    mut grid: u32,
+) -> Result<Message, Error> {
+    let mut letters = [0u8; 6];
+    for c in letters[3..].iter_mut().rev() {
+        *c = b'A' + (callsign % 26) as u8;
+        callsign /= 26;
+    }
+    letters[0] = id[0];
+    letters[1] = crate::decode_callsign_char(callsign % 36);
+    letters[2] = id[1];
+
+    let power = POWERS[grid as usize % 19];
+    grid /= 19;
+    let mut square = [0u8; 4];
+    for (i, c) in square.iter_mut().enumerate().rev() {
+        let (base, radix) = if i < 2 { (b'A', 18) } else { (b'0', 10) };
+        *c = base + (grid % radix) as u8;
+        grid /= radix;
+    }
+
+    Message::from_parts(
+        crate::ascii(&letters),
+        crate::ascii(&square),
+        power,
+        None,
+    )
+}
+
+// Unpacks the values `pack` packed into the callsign, and the grid and
+// power, of a telemetry message, or returns `None` if `message` doesn't
+// have a telemetry callsign.
+pub(super) fn unpack(message: &Message) -> Option<(u32, u32)> {
+    let callsign = message.callsign().as_bytes();
+    let grid = message.grid().as_bytes();
+    if message.hash().is_some()
+        || callsign.len() != 6
+        || grid.len() != 4
+        || !IDS.contains(&callsign[0])
+        || !callsign[2].is_ascii_digit()
+    {
+        return None;
+    }
+
+    let mut value = crate::encode_callsign_char(callsign[1]).ok()?;
+    if value > 35 {
+        return None;
+    }
+    for &c in &callsign[3..] {
+        if !c.is_ascii_uppercase() {
+            return None;
+        }
+        value = value * 26 + (c - b'A') as u32;
+    }
+
+    let mut square = 0u32;
+    for (i, &c) in grid.iter().enumerate() {
+        square = if i < 2 {
+            square * 18 + (c - b'A') as u32
+        } else {
+            square * 10 + (c - b'0') as u32
+        };
+    }
+    let power = POWERS.iter().position(|&p| p == message.power())?;
+    Some((value, square * 19 + power as u32))
+}
+
+/// The measurements carried by a U4B telemetry message.
+///
+/// Each measurement is rounded to the resolution the message carries and
+/// clamped to its range when encoded.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Telemetry {
+    /// The fifth and sixth characters of the tracker's locator, such as
+    /// `*b"AX"` for `FN42AX`.
+    pub subsquare: [u8; 2],
+    /// The altitude in meters, from 0 to 21340 in steps of 20.
+    pub altitude: u32,
+    /// The temperature in degrees Celsius, from -50 to 39.
+    pub temperature: i32,
+    /// The battery voltage, from 3.0 to 4.95 in steps of 0.05.
+    pub voltage: f32,
+    /// The speed in knots, from 0 to 82 in steps of 2.
+    pub speed: u32,
+    /// Whether the GPS has a valid fix.
+    pub gps_valid: bool,
+}
+
+impl Telemetry {
+    /// The telemetry message sent on `channel`, from 0 to 599.
+    ///
+    /// Returns [`Error::InvalidCallsign`] if `channel` is out of range, or
+    /// [`Error::InvalidGrid`] if the subsquare isn't two letters from `A` to
+    /// `X`.
+    pub fn to_message(&self, channel: u16) -> Result<Message, Error> {
+        let id = id(channel)?;
+        let subsquare = self.subsquare.map(|c| c.to_ascii_uppercase());
+        if !subsquare.iter().all(|c| (b'A'..=b'X').contains(c)) {
+            return Err(Error::InvalidGrid);
+        }
+
+        // The subsquare and altitude are packed into the callsign.
+        let altitude = math::round(self.altitude.min(21340) as f64 / 20.0);
+        let mut value = (subsquare[0] - b'A') as u32;
+        value = value * 24 + (subsquare[1] - b'A') as u32;
+        value = value * 1068 + altitude as u32;
+
+        let callsign = value;
+
+        // The rest are packed into the grid and power, along with a bit
+        // marking this as a standard telemetry message.
+        let voltage =
+            math::round((self.voltage.clamp(3.0, 4.95) as f64 - 3.0) / 0.05);
+        let mut value = (self.temperature.clamp(-50, 39) + 50) as u32;
+        value = value * 40 + (voltage as u32 + 20) % 40;
+        value = value * 42 + self.speed.min(82) / 2;
+        value = value * 2 + self.gps_valid as u32;
+        value = value * 2 + 1;
+
+        pack(id, callsign, value)
+    }
+
+    /// Encodes the telemetry message sent on `channel` into 162 symbols, see
+    /// [`Telemetry::to_message`].
+    pub fn encode(&self, channel: u16) -> Result<[u8; 162], Error> {
+        self.to_message(channel)?.encode()
+    }
+
+    /// Unpacks the measurements from a received telemetry message, or
+    /// returns `None` if `message` isn't a U4B telemetry message.
+    ///
+    /// Any tracker's telemetry is unpacked. To follow a single tracker, check
+    /// that the first and third characters of the callsign match its
+    /// channel, and match the message to the regular messages it sends.
+    pub fn from_message(message: &Message) -> Option<Self> {
+        let (mut value, mut grid) = unpack(message)?;
+        let altitude = value % 1068 * 20;
+        value /= 1068;
+        if value / 24 >= 24 {
+            return None;
+        }
+        let subsquare = [b'A' + (value / 24) as u8, b'A' + (value % 24) as u8];
+
+        // Only standard telemetry is carried this way.
+        if grid % 2 != 1 {
+            return None;
+        }
+        grid /= 2;
+        let gps_valid = grid % 2 == 1;
+        grid /= 2;
+        let speed = grid % 42 * 2;
+        grid /= 42;
+        let voltage = ((grid % 40 + 20) % 40) as f32 * 0.05 + 3.0;
+        grid /= 40;
+        if grid >= 90 {
+            return None;
+        }
+
+        Some(Telemetry {
+            subsquare,
+            altitude,
+            temperature: grid as i32 - 50,
+            voltage,
+            speed,
+            gps_valid,
+        })
+    }
+
+    /// Unpacks the measurements from the message of a spot, see
+    /// [`Telemetry::from_message`].
+    pub fn from_spot(spot: &Spot) -> Option<Self> {
+        Self::from_message(&spot.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let telemetry = Telemetry {
+            subsquare: *b"AX",
+            altitude: 12060,
+            temperature: -25,
+            voltage: 4.1,
+            speed: 36,
+            gps_valid: true,
+        };
+        let message = telemetry.to_message(0).unwrap();
+        assert_eq!(message.callsign(), "010LFZ");
+        assert_eq!(message.grid(), "EQ63");
+        assert_eq!(message.power(), 47);
+        assert_eq!(telemetry.encode(0), crate::encode("010LFZ", "EQ63", 47));
+
+        // The channel sets the first and third characters.
+        for (channel, id) in
+            [(19, "010"), (20, "011"), (210, "110"), (599, "Q19")]
+        {
+            let message = telemetry.to_message(channel).unwrap();
+            assert_eq!(&message.callsign()[..3], id);
+        }
+        assert_eq!(telemetry.to_message(600), Err(Error::InvalidCallsign));
+
+        let invalid = Telemetry {
+            subsquare: *b"AY",
+            ..telemetry
+        };
+        assert_eq!(invalid.to_message(0), Err(Error::InvalidGrid));
+    }
+
+    #[test]
+    fn test_channel() {
+        let channel = Channel::new(0, Band::M20).unwrap();
+        assert_eq!(channel.id, *b"00");
+        assert_eq!(channel.frequency, 1420.0);
+        assert_eq!(channel.minute, 4);
+        assert_eq!(channel.rf_frequency(Band::M20), 14_097_020.0);
+
+        for (number, band, id, frequency, minute) in [
+            (4, Band::M20, *b"00", 1420.0, 2),
+            (5, Band::M20, *b"00", 1460.0, 4),
+            (19, Band::M40, *b"00", 1580.0, 8),
+            (20, Band::M40, *b"01", 1420.0, 0),
+            (233, Band::M2190, *b"11", 1540.0, 6),
+            (599, Band::M10, *b"Q9", 1580.0, 0),
+        ] {
+            let channel = Channel::new(number, band).unwrap();
+            assert_eq!(channel.id, id);
+            assert_eq!(channel.frequency, frequency);
+            assert_eq!(channel.minute, minute);
+        }
+        assert_eq!(Channel::new(600, Band::M20), Err(Error::InvalidCallsign));
+
+        // The telemetry callsign carries the channel's characters.
+        let telemetry = Telemetry {
+            subsquare: *b"AA",
+            altitude: 0,
+            temperature: 0,
+            voltage: 4.0,
+            speed: 0,
+            gps_valid: false,
+        };
+        let message = telemetry.to_message(233).unwrap();
+        let callsign = message.callsign().as_bytes();
+        assert_eq!([callsign[0], callsign[2]], *b"11");
+    }
+
+    #[test]
+    fn test_decode() {
+        let telemetry = Telemetry {
+            subsquare: *b"AX",
+            altitude: 12060,
+            temperature: -25,
+            voltage: 4.1,
+            speed: 36,
+            gps_valid: true,
+        };
+        for channel in [0, 219, 599] {
+            let symbols = telemetry.encode(channel).unwrap();
+            let spot = Spot {
+                message: crate::decode(&symbols).unwrap(),
+                ..Spot::default()
+            };
+            let decoded = Telemetry::from_spot(&spot).unwrap();
+            assert_eq!(decoded.subsquare, telemetry.subsquare);
+            assert_eq!(decoded.altitude, telemetry.altitude);
+            assert_eq!(decoded.temperature, telemetry.temperature);
+            assert!((decoded.voltage - telemetry.voltage).abs() < 1e-3);
+            assert_eq!(decoded.speed, telemetry.speed);
+            assert_eq!(decoded.gps_valid, telemetry.gps_valid);
+        }
+
+        // Every value in range survives the round trip.
+        for (temperature, voltage, speed, gps_valid) in [
+            (-50, 3.0, 0, false),
+            (39, 4.95, 82, true),
+            (0, 3.95, 41, false),
+        ] {
+            let telemetry = Telemetry {
+                subsquare: *b"XA",
+                altitude: 21340,
+                temperature,
+                voltage,
+                speed,
+                gps_valid,
+            };
+            let message = telemetry.to_message(300).unwrap();
+            let decoded = Telemetry::from_message(&message).unwrap();
+            assert_eq!(decoded.subsquare, telemetry.subsquare);
+            assert_eq!(decoded.altitude, telemetry.altitude);
+            assert_eq!(decoded.temperature, temperature);
+            assert!((decoded.voltage - voltage).abs() < 1e-3);
+            assert_eq!(decoded.speed, speed / 2 * 2);
+            assert_eq!(decoded.gps_valid, gps_valid);
+        }
+
+        // Regular messages aren't telemetry.
+        for (callsign, grid) in [("K1ABC", "FN42"), ("Q1ABC", "FN42")] {
+            let message: Message =
+                std::format!("{callsign} {grid} 37").parse().unwrap();
+            assert_eq!(Telemetry::from_message(&message), None);
+        }
+        let message =
+            crate::decode(&crate::encode("K1ABC", "FN42AX", 37).unwrap());
+        assert_eq!(Telemetry::from_message(&message.unwrap()), None);
+    }
+}
@@ -0,0 +1,140 @@
+//! The scheme used by WB8ELK trackers, which send their altitude and
+//! battery voltage in the grid and power of a follow-up message.
+//!
+//! A tracker sends a regular message with its callsign, grid square, and
+//! power, then a second type 1 message with the same callsign. The grid of
+//! the second message is the altitude in meters, counting from `AA00` as
+//! grid squares are packed, and the power is the battery voltage in tenths
+//! of a volt, so `37` is 3.7 volts.
+
+use super::POWERS;
+use crate::spot::Spot;
+use crate::{Error, Message};
+
+// The number of grid squares, and so the highest altitude plus one.
+const SQUARES: u32 = 18 * 18 * 10 * 10;
+
+/// The measurements carried by a WB8ELK follow-up message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Telemetry {
+    /// The altitude in meters, from 0 to 32399.
+    pub altitude: u32,
+    /// The battery voltage, from 0.0 to 6.0, sent rounded to the nearest
+    /// power level, which is within 0.2 volts.
+    pub voltage: f32,
+}
+
+impl Telemetry {
+    /// The follow-up message sent by the tracker with `callsign`, which must
+    /// be a standard callsign.
+    pub fn to_message(&self, callsign: &str) -> Result<Message, Error> {
+        let mut value = self.altitude.min(SQUARES - 1);
+        let mut grid = [0u8; 4];
+        for (i, c) in grid.iter_mut().enumerate().rev() {
+            let (base, radix) = if i < 2 { (b'A', 18) } else { (b'0', 10) };
+            *c = base + (value % radix) as u8;
+            value /= radix;
+        }
+
+        let tenths = self.voltage.clamp(0.0, 6.0) * 10.0;
+        let power = POWERS
+            .iter()
+            .copied()
+            .min_by(|&a, &b| {
+                (a as f32 - tenths)
+                    .abs()
+                    .total_cmp(&(b as f32 - tenths).abs())
+            })
+            .unwrap_or_default();
+
+        Message::from_parts(callsign, crate::ascii(&grid), power, None)
+    }
+
+    /// Encodes the follow-up message sent by the tracker with `callsign`
+    /// into 162 symbols, see [`Telemetry::to_message`].
+    pub fn encode(&self, callsign: &str) -> Result<[u8; 162], Error> {
+        self.to_message(callsign)?.encode()
+    }
+
+    /// Unpacks the measurements from a follow-up message, or returns `None`
+    /// if `message` isn't a type 1 message.
+    ///
+    /// Any type 1 message unpacks to some measurement, so only follow-up
+    /// messages, from a tracker's callsign in the cycle after its regular
+    /// message, should be unpacked.
+    pub fn from_message(message: &Message) -> Option<Self> {
+        let grid = message.grid().as_bytes();
+        if message.hash().is_some() || grid.len() != 4 {
+            return None;
+        }
+        let mut altitude = 0;
+        for (i, &c) in grid.iter().enumerate() {
+            altitude = if i < 2 {
+                altitude * 18 + (c - b'A') as u32
+            } else {
+                altitude * 10 + (c - b'0') as u32
+            };
+        }
+        Some(Telemetry {
+            altitude,
+            voltage: message.power() as f32 / 10.0,
+        })
+    }
+
+    /// Unpacks the measurements from the message of a spot, see
+    /// [`Telemetry::from_message`].
+    pub fn from_spot(spot: &Spot) -> Option<Self> {
+        Self::from_message(&spot.message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wb8elk() {
+        let telemetry = Telemetry {
+            altitude: 12345,
+            voltage: 3.68,
+        };
+        let message = telemetry.to_message("K1ABC").unwrap();
+        assert_eq!(message.callsign(), "K1ABC");
+        assert_eq!(message.grid(), "GP45");
+        assert_eq!(message.power(), 37);
+        assert_eq!(
+            telemetry.encode("K1ABC"),
+            crate::encode("K1ABC", "GP45", 37)
+        );
+
+        let symbols = telemetry.encode("K1ABC").unwrap();
+        let spot = Spot {
+            message: crate::decode(&symbols).unwrap(),
+            ..Spot::default()
+        };
+        assert_eq!(
+            Telemetry::from_spot(&spot),
+            Some(Telemetry {
+                altitude: 12345,
+                voltage: 3.7,
+            })
+        );
+
+        // Out of range measurements are clamped.
+        let telemetry = Telemetry {
+            altitude: 40000,
+            voltage: 9.0,
+        };
+        let message = telemetry.to_message("K1ABC").unwrap();
+        assert_eq!(message.grid(), "RR99");
+        assert_eq!(message.power(), 60);
+
+        assert_eq!(
+            telemetry.to_message("PJ4/K1ABC"),
+            Err(Error::InvalidCallsign)
+        );
+        let message =
+            crate::decode(&crate::encode("K1ABC", "FN42AX", 37).unwrap());
+        assert_eq!(Telemetry::from_message(&message.unwrap()), None);
+    }
+}
@@ -0,0 +1,85 @@
+//! The scheme used by Zachtek WSPR-TX trackers, which send their altitude
+//! in the power field of a second message.
+//!
+//! A tracker sends a regular message with its callsign, grid square, and
+//! power, then in the next cycle a type 3 message with its six character
+//! locator, whose power field carries its altitude instead, each dBm
+//! counting for 300 meters.
+
+use super::POWERS;
+use crate::{encode_pair, Error, Message};
+
+/// The time from the start of the first message to the start of the
+/// second, one cycle later, in seconds.
+pub const SECOND_DELAY: u32 = 120;
+
+// The altitude each dBm of the second message's power field stands for, in
+// meters.
+const METERS_PER_DBM: u32 = 300;
+
+/// The pair of messages a tracker sends.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sequence {
+    /// The regular message, with the tracker's callsign and grid square.
+    pub first: [u8; 162],
+    /// The type 3 message with the tracker's locator and altitude, sent
+    /// [`SECOND_DELAY`] seconds after the first begins.
+    pub second: [u8; 162],
+}
+
+/// Encodes the messages sent by a tracker with `callsign` at `locator`,
+/// transmitting `power` dBm at `altitude` meters.
+///
+/// The altitude is rounded to the nearest power level that can be sent, so
+/// its resolution ranges from 900 to 1200 meters, up to 18000 meters.
+pub fn encode(
+    callsign: &str,
+    locator: &str,
+    power: u8,
+    altitude: u32,
+) -> Result<Sequence, Error> {
+    let (first, _) = encode_pair(callsign, locator, power)?;
+    let dbm = altitude.min(60 * METERS_PER_DBM) as f32 / METERS_PER_DBM as f32;
+    let power = POWERS
+        .iter()
+        .copied()
+        .min_by(|&a, &b| {
+            (a as f32 - dbm).abs().total_cmp(&(b as f32 - dbm).abs())
+        })
+        .unwrap_or_default();
+    let second = crate::encode(callsign, locator, power)?;
+    Ok(Sequence { first, second })
+}
+
+/// The altitude in meters carried by the second message of a tracker, or
+/// `None` if `message` isn't a type 3 message.
+pub fn altitude(message: &Message) -> Option<u32> {
+    message.hash()?;
+    Some(message.power() as u32 * METERS_PER_DBM)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zachtek() {
+        let sequence = encode("K1ABC", "FN42AX", 10, 11900).unwrap();
+        assert_eq!(Ok(sequence.first), crate::encode("K1ABC", "FN42", 10));
+        let second = crate::decode(&sequence.second).unwrap();
+        assert_eq!(second.grid(), "FN42AX");
+        assert_eq!(second.hash(), Some(crate::hash::hash("K1ABC")));
+        assert_eq!(altitude(&second), Some(12000));
+
+        let first = crate::decode(&sequence.first).unwrap();
+        assert_eq!(altitude(&first), None);
+
+        // Altitudes beyond the highest power level are clamped.
+        let sequence = encode("PJ4/K1ABC", "FK52UD", 10, 30000).unwrap();
+        assert_eq!(Ok(sequence.first), crate::encode("PJ4/K1ABC", "", 10));
+        let second = crate::decode(&sequence.second).unwrap();
+        assert_eq!(altitude(&second), Some(18000));
+
+        assert_eq!(encode("K1ABC", "FN42", 10, 0), Err(Error::InvalidGrid));
+    }
+}
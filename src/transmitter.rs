@@ -0,0 +1,1004 @@
+//! Driving any radio that can be set to a frequency through a
+//! transmission, symbol by symbol.
+//!
+//! A [`WsprTransmitter`] is anything that can be tuned and keyed, such as
+//! an Si5351 or DDS driver, or an SDR. A [`Player`] walks it through the 162
+//! symbols of a message, setting each symbol's frequency and saying how long
+//! to wait before the next, so the same loop drives every backend:
+//!
+//! ```
+//! use wspr::transmitter::{Player, WsprTransmitter};
+//!
+//! struct Radio;
+//!
+//! impl WsprTransmitter for Radio {
+//!     fn set_frequency(&mut self, hz_millis: u64) {}
+//!     fn enable(&mut self) {}
+//!     fn disable(&mut self) {}
+//! }
+//!
+//! let symbols = wspr::encode("K1ABC", "FN42", 37).unwrap();
+//! let mut player = Player::new(&symbols, 14_095_600, 1500);
+//! let mut radio = Radio;
+//! while let Some(nanoseconds) = player.step(&mut radio) {
+//!     // Wait `nanoseconds` before the next step.
+//! }
+//! ```
+//!
+//! With the `embedded-hal` feature, [`play`] runs that loop with an
+//! embedded-hal `DelayNs`, blocking for the whole transmission. With the
+//! `embassy` feature, [`play_async`] runs it with embassy's timers instead,
+//! so other tasks can run while the transmission is sent.
+//!
+//! Firmware without either can call [`SymbolStepper::tick`] from a periodic
+//! timer interrupt instead, and act on the [`Step`] it returns, if any.
+//!
+//! A [`TxProgress`] shares how far a transmission has got between the
+//! interrupt stepping it and the main loop, which can also abort it, such
+//! as on a geofence or a low battery.
+//!
+//! A [`SymbolClock`] says which symbol should be on the air at a time in
+//! the transmit window, and how far into it, so firmware that missed ticks
+//! or stalled can get back in step.
+
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use crate::modulate::rf_frequencies;
+use crate::{Error, SYMBOL_LENGTH};
+
+// The length of each symbol in nanoseconds is `SYMBOL_LENGTH` samples at
+// this rate.
+const RATE: u64 = 12000;
+
+// The states of a `TxProgress` other than a symbol on the air.
+const IDLE: u8 = 0xFF;
+const FINISHED: u8 = 162;
+const ABORTED: u8 = 163;
+
+/// A radio that can be tuned and keyed, to send a transmission with a
+/// [`Player`].
+pub trait WsprTransmitter {
+    /// Tunes the radio to `hz_millis` millihertz.
+    fn set_frequency(&mut self, hz_millis: u64);
+
+    /// Starts transmitting.
+    fn enable(&mut self);
+
+    /// Stops transmitting.
+    fn disable(&mut self);
+
+    /// Readies the radio before the first symbol, such as by keying a PA,
+    /// switching an antenna relay, or powering up a TCXO. Does nothing by
+    /// default.
+    fn before_transmission(&mut self) {}
+
+    /// Puts the radio back after the last symbol, such as by unkeying a PA
+    /// or switching an antenna relay back. Does nothing by default.
+    fn after_transmission(&mut self) {}
+}
+
+impl<T: WsprTransmitter + ?Sized> WsprTransmitter for &mut T {
+    fn set_frequency(&mut self, hz_millis: u64) {
+        (**self).set_frequency(hz_millis)
+    }
+
+    fn enable(&mut self) {
+        (**self).enable()
+    }
+
+    fn disable(&mut self) {
+        (**self).disable()
+    }
+
+    fn before_transmission(&mut self) {
+        (**self).before_transmission()
+    }
+
+    fn after_transmission(&mut self) {
+        (**self).after_transmission()
+    }
+}
+
+/// Steps a [`WsprTransmitter`] through the symbols of a transmission.
+///
+/// The transmitter's [`WsprTransmitter::before_transmission`] is called on
+/// the first step, and its [`WsprTransmitter::after_transmission`] after it's
+/// disabled, each separated from the symbols by a guard time set with
+/// [`Player::with_guards`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Player {
+    frequencies: [u64; 162],
+    // The next symbol to send, 162 to stop, 163 after the guard time, or
+    // 164 once over.
+    symbol: usize,
+    // The nanoseconds into the transmission the player starts, and whether
+    // it has readied the transmitter and enabled it.
+    elapsed: u64,
+    prepared: bool,
+    started: bool,
+    // The guard times before the first symbol and after the last, in
+    // nanoseconds.
+    before: u32,
+    after: u32,
+}
+
+impl Player {
+    /// Creates a player for `symbols`, centered `audio_offset_hz` above
+    /// `dial_hz` as [`rf_frequencies`] places them.
+    pub fn new(
+        symbols: &[u8; 162],
+        dial_hz: u64,
+        audio_offset_hz: u32,
+    ) -> Self {
+        Self {
+            frequencies: rf_frequencies(dial_hz, audio_offset_hz, symbols),
+            symbol: 0,
+            elapsed: 0,
+            prepared: false,
+            started: false,
+            before: 0,
+            after: 0,
+        }
+    }
+
+    /// Waits `before` nanoseconds between readying the transmitter and the
+    /// first symbol, and `after` nanoseconds between the last symbol and
+    /// putting it back, such as for a relay to settle. The guard before
+    /// comes out of the second before a transmission normally starts.
+    pub fn with_guards(self, before: u32, after: u32) -> Self {
+        Self {
+            before,
+            after,
+            ..self
+        }
+    }
+
+    /// Starts the player at symbol `symbol`, such as to resume a
+    /// transmission cut short by a reset, as if the symbols before had been
+    /// sent.
+    pub fn with_symbol(self, symbol: usize) -> Self {
+        self.with_elapsed(boundary(symbol.min(162)))
+    }
+
+    /// Starts the player `nanoseconds` into the transmission, partway
+    /// through a symbol if need be, holding that symbol only for the rest
+    /// of its time, so the symbols after are on time. A [`SymbolClock`]
+    /// can tell how far in that is, to decide whether to resume at all.
+    pub fn with_elapsed(self, nanoseconds: u64) -> Self {
+        let symbol = (0..=162)
+            .rev()
+            .find(|&i| boundary(i) <= nanoseconds)
+            .unwrap_or(0);
+        Self {
+            symbol,
+            elapsed: nanoseconds,
+            prepared: false,
+            started: false,
+            ..self
+        }
+    }
+
+    /// The frequency of each symbol in millihertz.
+    pub fn frequencies(&self) -> &[u64; 162] {
+        &self.frequencies
+    }
+
+    /// The index of the next symbol to send, or 162 once they've all been
+    /// sent.
+    pub fn symbol(&self) -> usize {
+        self.symbol.min(162)
+    }
+
+    /// Whether the transmission is over and the transmitter put back.
+    pub fn is_finished(&self) -> bool {
+        self.symbol > 163
+    }
+
+    /// Sets `transmitter` to the next symbol's frequency, enabling it on the
+    /// first step, and returns the nanoseconds until the next step. After
+    /// the last symbol, disables `transmitter` and returns `None`.
+    ///
+    /// With guard times, the first step readies `transmitter` and returns
+    /// the guard time before, and the step after the last symbol disables
+    /// it and returns the guard time after, before the last step puts it
+    /// back.
+    ///
+    /// The symbols are 8192 / 12000 seconds long, so the durations vary
+    /// by a nanosecond to keep the transmission exactly 110.592 seconds
+    /// long.
+    pub fn step<T: WsprTransmitter + ?Sized>(
+        &mut self,
+        transmitter: &mut T,
+    ) -> Option<u32> {
+        if !self.prepared {
+            self.prepared = true;
+            transmitter.before_transmission();
+            if self.before > 0 && self.symbol < 162 {
+                return Some(self.before);
+            }
+        }
+        match self.symbol {
+            i @ 0..=161 => {
+                transmitter.set_frequency(self.frequencies[i]);
+                if !self.started {
+                    transmitter.enable();
+                    self.started = true;
+                }
+                self.symbol += 1;
+                Some((boundary(i + 1) - boundary(i).max(self.elapsed)) as u32)
+            }
+            162 => {
+                transmitter.disable();
+                self.symbol += 1;
+                if self.after > 0 {
+                    return Some(self.after);
+                }
+                self.step(transmitter)
+            }
+            163 => {
+                transmitter.after_transmission();
+                self.symbol += 1;
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Sends the whole transmission to `transmitter`, calling `wait` with
+    /// the nanoseconds to wait after each symbol.
+    pub fn play<T, F>(&mut self, transmitter: &mut T, mut wait: F)
+    where
+        T: WsprTransmitter + ?Sized,
+        F: FnMut(u32),
+    {
+        while let Some(nanoseconds) = self.step(transmitter) {
+            wait(nanoseconds);
+        }
+    }
+}
+
+/// What to do to a transmitter on a tick of a [`SymbolStepper`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum Step {
+    /// Ready the transmitter, with
+    /// [`WsprTransmitter::before_transmission`].
+    Prepare,
+    /// Set the frequency to the first symbol's, in millihertz, and start
+    /// transmitting.
+    Start(u64),
+    /// Set the frequency to the next symbol's, in millihertz.
+    Frequency(u64),
+    /// Stop transmitting.
+    Stop,
+    /// Put the transmitter back, with
+    /// [`WsprTransmitter::after_transmission`].
+    Finish,
+}
+
+impl Step {
+    /// Does what the step says to `transmitter`.
+    pub fn apply<T: WsprTransmitter + ?Sized>(self, transmitter: &mut T) {
+        match self {
+            Step::Prepare => transmitter.before_transmission(),
+            Step::Start(frequency) => {
+                transmitter.set_frequency(frequency);
+                transmitter.enable();
+            }
+            Step::Frequency(frequency) => transmitter.set_frequency(frequency),
+            Step::Stop => transmitter.disable(),
+            Step::Finish => transmitter.after_transmission(),
+        }
+    }
+}
+
+/// Steps through the symbols of a transmission from a periodic timer
+/// interrupt, without blocking or an async runtime.
+///
+/// [`SymbolStepper::tick`] is called once a tick, and says when to ready
+/// the transmitter, start, change frequency, stop, and put it back. Symbols
+/// start on the first tick at or after their time, so they're late by up to
+/// a tick, but the transmission doesn't drift however long it is.
+///
+/// As a tick does one thing at most, the first symbol starts a tick after
+/// the transmitter is readied, and it's put back a tick after it stops,
+/// unless [`SymbolStepper::with_guards`] sets longer guard times.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolStepper {
+    frequencies: [u64; 162],
+    tick_hz: u32,
+    // The ticks since the start of the first symbol, negative during the
+    // guard time before it, the tick a transmission is resumed from, and
+    // the first tick a symbol can start on.
+    ticks: i64,
+    resume: Option<u64>,
+    ready: i64,
+    // The next symbol to send, 162 to stop, 163 to put the transmitter
+    // back, or 164 once over.
+    symbol: usize,
+    prepared: bool,
+    started: bool,
+    // The guard times in ticks, and the tick the transmitter is put back
+    // on.
+    before: u32,
+    after: u32,
+    finish: i64,
+}
+
+impl SymbolStepper {
+    /// Creates a stepper for `symbols`, centered `audio_offset_hz` above
+    /// `dial_hz` as [`rf_frequencies`] places them, ticked `tick_hz` times
+    /// a second.
+    ///
+    /// Returns [`Error::InvalidSampleRate`] if `tick_hz` is 0.
+    pub fn new(
+        symbols: &[u8; 162],
+        dial_hz: u64,
+        audio_offset_hz: u32,
+        tick_hz: u32,
+    ) -> Result<Self, Error> {
+        if tick_hz == 0 {
+            return Err(Error::InvalidSampleRate);
+        }
+        Ok(Self {
+            frequencies: rf_frequencies(dial_hz, audio_offset_hz, symbols),
+            tick_hz,
+            ticks: 0,
+            resume: None,
+            ready: 0,
+            symbol: 0,
+            prepared: false,
+            started: false,
+            before: 1,
+            after: 1,
+            finish: 0,
+        })
+    }
+
+    /// Waits `before` ticks between readying the transmitter and the first
+    /// symbol, and `after` ticks between the last symbol and putting it
+    /// back, as [`Player::with_guards`] does, at least a tick each.
+    pub fn with_guards(self, before: u32, after: u32) -> Self {
+        Self {
+            before: before.max(1),
+            after: after.max(1),
+            ..self
+        }
+    }
+
+    /// Starts the stepper `ticks` ticks into the transmission, such as to
+    /// resume it after a reset, so it readies the transmitter on the next
+    /// tick and starts with the symbol due once the guard time before is
+    /// over. The transmission carries on through the guard time, so the
+    /// symbols after are on time.
+    pub fn with_ticks(self, ticks: u64) -> Self {
+        Self {
+            resume: Some(ticks),
+            symbol: 0,
+            prepared: false,
+            started: false,
+            ..self
+        }
+    }
+
+    /// Counts a tick, returning what to do on it, if anything. The first
+    /// tick readies the transmitter.
+    pub fn tick(&mut self) -> Option<Step> {
+        if !self.prepared {
+            // A new transmission starts after the guard time before, and
+            // a resumed one carries on through it.
+            let before = self.before as i64;
+            (self.ticks, self.ready) = match self.resume {
+                Some(ticks) => (ticks as i64, ticks as i64 + before),
+                None => (-before, 0),
+            };
+            self.prepared = true;
+            self.ticks += 1;
+            return Some(Step::Prepare);
+        }
+        let ticks = self.ticks;
+        self.ticks += 1;
+        if self.symbol == 163 && ticks >= self.finish {
+            self.symbol = 164;
+            return Some(Step::Finish);
+        }
+        if self.symbol > 162
+            || ticks < self.ready
+            || ticks < self.start(self.symbol)
+        {
+            return None;
+        }
+
+        // With ticks longer than a symbol, skip to the latest symbol due.
+        let mut symbol = self.symbol;
+        while symbol < 162 && ticks >= self.start(symbol + 1) {
+            symbol += 1;
+        }
+        let step = match symbol {
+            162 => {
+                self.finish = ticks + self.after as i64;
+                Step::Stop
+            }
+            _ if !self.started => Step::Start(self.frequencies[symbol]),
+            _ => Step::Frequency(self.frequencies[symbol]),
+        };
+        self.started = true;
+        self.symbol = symbol + 1;
+        Some(step)
+    }
+
+    /// Counts a tick like [`SymbolStepper::tick`], and publishes the
+    /// symbol on the air to `progress`. If `progress` has been aborted,
+    /// the transmission is over instead: stopped if it had started, and
+    /// the transmitter put back once the guard time after is over.
+    pub fn tick_with(&mut self, progress: &TxProgress) -> Option<Step> {
+        if progress.is_abort_requested() && self.symbol < 163 {
+            progress.state.store(ABORTED, Ordering::Release);
+            if !self.prepared {
+                self.symbol = 164;
+                return None;
+            }
+            if !self.started {
+                self.symbol = 164;
+                return Some(Step::Finish);
+            }
+            self.finish = self.ticks + self.after as i64;
+            self.ticks += 1;
+            self.symbol = 163;
+            return Some(Step::Stop);
+        }
+        let step = self.tick();
+        match step {
+            Some(Step::Start(_) | Step::Frequency(_)) => {
+                let symbol = (self.symbol - 1) as u8;
+                progress.state.store(symbol, Ordering::Release);
+            }
+            Some(Step::Stop) => {
+                progress.state.store(FINISHED, Ordering::Release)
+            }
+            _ => {}
+        }
+        step
+    }
+
+    /// The index of the next symbol to send, or 162 once they've all been
+    /// sent.
+    pub fn symbol(&self) -> usize {
+        self.symbol.min(162)
+    }
+
+    /// Whether the transmission is over and the transmitter put back.
+    pub fn is_finished(&self) -> bool {
+        self.symbol > 163
+    }
+
+    // The first tick at or after the start of symbol `i`.
+    fn start(&self, i: usize) -> i64 {
+        first_tick(i, self.tick_hz) as i64
+    }
+}
+
+/// The progress of a transmission, shared between the code stepping it,
+/// such as a timer interrupt, and the code watching it, which can abort
+/// it.
+///
+/// It's made of atomics loaded and stored, not read and modified, so it
+/// can be a `static` without a critical section, even on cores without
+/// atomic read-modify-write instructions.
+#[derive(Debug)]
+pub struct TxProgress {
+    // The symbol on the air, or one of the states above.
+    state: AtomicU8,
+    abort: AtomicBool,
+}
+
+impl TxProgress {
+    /// Creates the progress of a transmission yet to start.
+    pub const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(IDLE),
+            abort: AtomicBool::new(false),
+        }
+    }
+
+    /// Asks for the transmission to stop at the next tick.
+    pub fn abort(&self) {
+        self.abort.store(true, Ordering::Release);
+    }
+
+    /// Whether [`TxProgress::abort`] has been called since the last reset.
+    pub fn is_abort_requested(&self) -> bool {
+        self.abort.load(Ordering::Acquire)
+    }
+
+    /// The index of the symbol on the air, or `None` before the
+    /// transmission starts and once it's over.
+    pub fn symbol(&self) -> Option<usize> {
+        match self.state.load(Ordering::Acquire) {
+            symbol @ 0..=161 => Some(symbol as usize),
+            _ => None,
+        }
+    }
+
+    /// Whether the transmission is on the air.
+    pub fn is_active(&self) -> bool {
+        self.symbol().is_some()
+    }
+
+    /// Whether the transmission is over, sent in full or aborted.
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state.load(Ordering::Acquire), FINISHED | ABORTED)
+    }
+
+    /// Whether the transmission was stopped by [`TxProgress::abort`].
+    pub fn was_aborted(&self) -> bool {
+        self.state.load(Ordering::Acquire) == ABORTED
+    }
+
+    /// Readies the progress for the next transmission, clearing any abort.
+    pub fn reset(&self) {
+        self.abort.store(false, Ordering::Release);
+        self.state.store(IDLE, Ordering::Release);
+    }
+}
+
+impl Default for TxProgress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Maps the time since the start of a transmit window to the symbol on the
+/// air.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SymbolClock {
+    tick_hz: u32,
+    // The ticks from the start of the window to the first symbol.
+    delay: u64,
+}
+
+impl SymbolClock {
+    /// A clock counting milliseconds, with the first symbol a second into
+    /// the window.
+    pub const MILLISECONDS: SymbolClock = SymbolClock {
+        tick_hz: 1000,
+        delay: 1000,
+    };
+
+    /// Creates a clock counting `tick_hz` ticks a second, with the first
+    /// symbol a second into the window, as WSPR transmissions start.
+    ///
+    /// Returns [`Error::InvalidSampleRate`] if `tick_hz` is 0.
+    pub fn new(tick_hz: u32) -> Result<Self, Error> {
+        if tick_hz == 0 {
+            return Err(Error::InvalidSampleRate);
+        }
+        Ok(Self {
+            tick_hz,
+            delay: tick_hz as u64,
+        })
+    }
+
+    /// Starts the first symbol `delay` ticks into the window, such as 0
+    /// when counting from the first symbol.
+    pub fn with_delay(self, delay: u64) -> Self {
+        Self { delay, ..self }
+    }
+
+    /// The symbol on the air `ticks` into the window, and the fraction of
+    /// it that's passed, from 0.0 up to 1.0. Returns `None` before the
+    /// first symbol or after the last.
+    pub fn at(&self, ticks: u64) -> Option<(usize, f32)> {
+        let elapsed = ticks.checked_sub(self.delay)? as u128 * RATE as u128;
+        let length = self.tick_hz as u128 * SYMBOL_LENGTH as u128;
+        let symbol = elapsed / length;
+        if symbol >= 162 {
+            return None;
+        }
+        let phase = (elapsed % length) as f64 / length as f64;
+        Some((symbol as usize, phase as f32))
+    }
+
+    /// The first tick at or after the start of symbol `symbol`, or of the
+    /// end of the transmission for 162.
+    pub fn start(&self, symbol: usize) -> u64 {
+        self.delay + first_tick(symbol, self.tick_hz)
+    }
+}
+
+impl Default for SymbolClock {
+    fn default() -> Self {
+        Self::MILLISECONDS
+    }
+}
+
+/// Sends `symbols` centered `audio_offset_hz` above `dial_hz` to
+/// `transmitter`, holding each symbol for 8192 / 12000 seconds with
+/// `delay`, and returns once the transmission is over, nearly two minutes
+/// later.
+#[cfg(feature = "embedded-hal")]
+pub fn play<T, D>(
+    symbols: &[u8; 162],
+    dial_hz: u64,
+    audio_offset_hz: u32,
+    transmitter: &mut T,
+    delay: &mut D,
+) where
+    T: WsprTransmitter + ?Sized,
+    D: embedded_hal::delay::DelayNs + ?Sized,
+{
+    Player::new(symbols, dial_hz, audio_offset_hz)
+        .play(transmitter, |nanoseconds| delay.delay_ns(nanoseconds));
+}
+
+/// Sends `symbols` centered `audio_offset_hz` above `dial_hz` to
+/// `transmitter`, awaiting an embassy timer between symbols, and returns
+/// once the transmission is over.
+///
+/// Each symbol starts at a fixed time from the first, rather than a fixed
+/// time after the last, so time spent setting the frequency or by other
+/// tasks doesn't accumulate.
+#[cfg(feature = "embassy")]
+pub async fn play_async<T>(
+    symbols: &[u8; 162],
+    dial_hz: u64,
+    audio_offset_hz: u32,
+    transmitter: &mut T,
+) where
+    T: WsprTransmitter + ?Sized,
+{
+    use embassy_time::{Duration, Instant, Timer};
+
+    let mut player = Player::new(symbols, dial_hz, audio_offset_hz);
+    let start = Instant::now();
+    let mut elapsed = 0;
+    while let Some(nanoseconds) = player.step(transmitter) {
+        elapsed += nanoseconds as u64;
+        Timer::at(start + Duration::from_nanos(elapsed)).await;
+    }
+}
+
+// The first of `tick_hz` ticks at or after the start of symbol `i`.
+fn first_tick(i: usize, tick_hz: u32) -> u64 {
+    let ticks = boundary(i) as u128 * tick_hz as u128;
+    ticks.div_ceil(1_000_000_000) as u64
+}
+
+// The start of symbol `i` in nanoseconds from the start of the
+// transmission.
+fn boundary(i: usize) -> u64 {
+    i as u64 * SYMBOL_LENGTH as u64 * 1_000_000_000 / RATE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Frequency(u64),
+        Enable,
+        Disable,
+        Before,
+        After,
+    }
+
+    #[derive(Default)]
+    struct Recorder(Vec<Event>);
+
+    impl WsprTransmitter for Recorder {
+        fn set_frequency(&mut self, hz_millis: u64) {
+            self.0.push(Event::Frequency(hz_millis));
+        }
+
+        fn enable(&mut self) {
+            self.0.push(Event::Enable);
+        }
+
+        fn disable(&mut self) {
+            self.0.push(Event::Disable);
+        }
+
+        fn before_transmission(&mut self) {
+            self.0.push(Event::Before);
+        }
+
+        fn after_transmission(&mut self) {
+            self.0.push(Event::After);
+        }
+    }
+
+    #[test]
+    fn test_player() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let mut player = Player::new(&symbols, 14_095_600, 1500);
+        let frequencies = *player.frequencies();
+        assert_eq!(frequencies, rf_frequencies(14_095_600, 1500, &symbols));
+
+        let mut recorder = Recorder::default();
+        let mut durations = Vec::new();
+        player.play(&mut recorder, |nanoseconds| durations.push(nanoseconds));
+        assert!(player.is_finished());
+        assert_eq!(player.symbol(), 162);
+        assert_eq!(player.step(&mut recorder), None);
+
+        // The transmitter is readied, then the first frequency set before
+        // enabling it, and it's disabled once the last symbol is over, then
+        // put back.
+        let events = &recorder.0;
+        assert_eq!(events.len(), 166);
+        assert_eq!(events[0], Event::Before);
+        assert_eq!(events[1], Event::Frequency(frequencies[0]));
+        assert_eq!(events[2], Event::Enable);
+        for (event, &frequency) in events[3..164].iter().zip(&frequencies[1..])
+        {
+            assert_eq!(*event, Event::Frequency(frequency));
+        }
+        assert_eq!(events[164], Event::Disable);
+        assert_eq!(events[165], Event::After);
+
+        // Each symbol is 682.67 ms, adding up to exactly 110.592 s.
+        assert_eq!(durations.len(), 162);
+        assert!(durations
+            .iter()
+            .all(|&d| d == 682_666_666 || d == 682_666_667));
+        let total: u64 = durations.iter().map(|&d| d as u64).sum();
+        assert_eq!(total, 110_592_000_000);
+    }
+
+    #[test]
+    fn test_symbol_stepper() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let frequencies = rf_frequencies(14_095_600, 1500, &symbols);
+        assert_eq!(
+            SymbolStepper::new(&symbols, 14_095_600, 1500, 0),
+            Err(Error::InvalidSampleRate)
+        );
+
+        // A millisecond tick readies the transmitter, starts a tick later,
+        // starts symbol 1 683 ms after that, stops 110.592 s in, and puts
+        // the transmitter back a tick later.
+        let mut stepper =
+            SymbolStepper::new(&symbols, 14_095_600, 1500, 1000).unwrap();
+        let mut steps = Vec::new();
+        for tick in 0..120_000 {
+            if let Some(step) = stepper.tick() {
+                steps.push((tick, step));
+            }
+        }
+        assert!(stepper.is_finished());
+        assert_eq!(steps.len(), 165);
+        assert_eq!(steps[0], (0, Step::Prepare));
+        assert_eq!(steps[1], (1, Step::Start(frequencies[0])));
+        assert_eq!(steps[2], (684, Step::Frequency(frequencies[1])));
+        assert_eq!(steps[4], (2049, Step::Frequency(frequencies[3])));
+        assert_eq!(steps[163], (110_593, Step::Stop));
+        assert_eq!(steps[164], (110_594, Step::Finish));
+
+        // Applying the steps drives a transmitter as a player does.
+        let mut recorder = Recorder::default();
+        for (_, step) in steps {
+            step.apply(&mut recorder);
+        }
+        let mut expected = Recorder::default();
+        Player::new(&symbols, 14_095_600, 1500).play(&mut expected, |_| {});
+        assert_eq!(recorder.0, expected.0);
+
+        // A tick of a second skips symbols that are over by the next tick.
+        let mut stepper =
+            SymbolStepper::new(&symbols, 14_095_600, 1500, 1).unwrap();
+        assert_eq!(stepper.tick(), Some(Step::Prepare));
+        assert_eq!(stepper.tick(), Some(Step::Start(frequencies[0])));
+        assert_eq!(stepper.tick(), Some(Step::Frequency(frequencies[1])));
+        assert_eq!(stepper.tick(), Some(Step::Frequency(frequencies[2])));
+        assert_eq!(stepper.symbol(), 3);
+        let steps = (0..200).filter_map(|_| stepper.tick()).count();
+        assert_eq!(steps, 110);
+        assert!(stepper.is_finished());
+    }
+
+    #[test]
+    fn test_stepper_guards() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let frequencies = rf_frequencies(14_095_600, 1500, &symbols);
+
+        // Guards of 50 and 20 ms at a millisecond tick.
+        let mut stepper = SymbolStepper::new(&symbols, 14_095_600, 1500, 1000)
+            .unwrap()
+            .with_guards(50, 20);
+        let mut steps = Vec::new();
+        for tick in 0..120_000 {
+            if let Some(step) = stepper.tick() {
+                steps.push((tick, step));
+            }
+        }
+        assert_eq!(steps.len(), 165);
+        assert_eq!(steps[0], (0, Step::Prepare));
+        assert_eq!(steps[1], (50, Step::Start(frequencies[0])));
+        assert_eq!(steps[2], (733, Step::Frequency(frequencies[1])));
+        assert_eq!(steps[163], (110_642, Step::Stop));
+        assert_eq!(steps[164], (110_662, Step::Finish));
+
+        // Resumed, the guard before still comes first.
+        let mut stepper = SymbolStepper::new(&symbols, 14_095_600, 1500, 1000)
+            .unwrap()
+            .with_ticks(70_000)
+            .with_guards(50, 20);
+        assert_eq!(stepper.tick(), Some(Step::Prepare));
+        let start = (1..100).find_map(|tick| Some(tick).zip(stepper.tick()));
+        assert_eq!(start, Some((50, Step::Start(frequencies[102]))));
+    }
+
+    #[test]
+    fn test_guards() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let mut player = Player::new(&symbols, 14_095_600, 1500)
+            .with_guards(50_000_000, 20_000_000);
+        let mut recorder = Recorder::default();
+
+        // The guard before follows readying the transmitter.
+        assert_eq!(player.step(&mut recorder), Some(50_000_000));
+        assert_eq!(recorder.0, [Event::Before]);
+        assert_eq!(player.step(&mut recorder), Some(682_666_666));
+        assert_eq!(recorder.0[2], Event::Enable);
+
+        // The guard after follows disabling it, then it's put back.
+        let mut durations = Vec::new();
+        player.play(&mut recorder, |nanoseconds| durations.push(nanoseconds));
+        assert_eq!(durations.len(), 162);
+        assert_eq!(durations[161], 20_000_000);
+        let events = &recorder.0;
+        assert_eq!(events[events.len() - 2], Event::Disable);
+        assert_eq!(events[events.len() - 1], Event::After);
+        assert!(player.is_finished());
+    }
+
+    #[test]
+    fn test_resume() {
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let frequencies = rf_frequencies(14_095_600, 1500, &symbols);
+
+        // Resuming at symbol 100 enables the transmitter with its
+        // frequency, and sends the rest on time.
+        let player = Player::new(&symbols, 14_095_600, 1500);
+        let mut resumed = player.clone().with_symbol(100);
+        assert_eq!(resumed.symbol(), 100);
+        let mut recorder = Recorder::default();
+        let mut total = 0u64;
+        resumed.play(&mut recorder, |nanoseconds| total += nanoseconds as u64);
+        assert_eq!(total, 110_592_000_000 - boundary(100));
+        assert_eq!(recorder.0[1], Event::Frequency(frequencies[100]));
+        assert_eq!(recorder.0[2], Event::Enable);
+        assert_eq!(recorder.0.len(), 66);
+
+        // Partway through symbol 10, it's held only for the rest of its
+        // time.
+        let mut resumed = player.clone().with_elapsed(boundary(10) + 1000);
+        assert_eq!(resumed.symbol(), 10);
+        let mut recorder = Recorder::default();
+        let first = resumed.step(&mut recorder).unwrap();
+        assert_eq!(first as u64, boundary(11) - boundary(10) - 1000);
+        assert_eq!(resumed.step(&mut recorder), Some(682_666_667));
+
+        // Past the end, there's nothing left but to disable and put back.
+        let mut resumed = player.with_elapsed(120_000_000_000);
+        let mut recorder = Recorder::default();
+        assert_eq!(resumed.step(&mut recorder), None);
+        assert_eq!(recorder.0, [Event::Before, Event::Disable, Event::After]);
+
+        // A stepper resumed 70 s in starts with symbol 102, once the
+        // transmitter is ready.
+        let mut stepper = SymbolStepper::new(&symbols, 14_095_600, 1500, 1000)
+            .unwrap()
+            .with_ticks(70_000);
+        assert_eq!(stepper.tick(), Some(Step::Prepare));
+        assert_eq!(stepper.tick(), Some(Step::Start(frequencies[102])));
+        assert_eq!(stepper.tick(), None);
+        assert_eq!(stepper.symbol(), 103);
+        let steps = (0..50_000).filter_map(|_| stepper.tick()).count();
+        assert_eq!(steps, 61);
+    }
+
+    #[test]
+    fn test_tx_progress() {
+        static PROGRESS: TxProgress = TxProgress::new();
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let mut stepper =
+            SymbolStepper::new(&symbols, 14_095_600, 1500, 10).unwrap();
+        assert!(!PROGRESS.is_active() && !PROGRESS.is_finished());
+
+        // A whole transmission, symbol by symbol.
+        let mut steps = 0;
+        for _ in 0..2000 {
+            if stepper.tick_with(&PROGRESS).is_some() {
+                steps += 1;
+            }
+            if let Some(symbol) = PROGRESS.symbol() {
+                assert_eq!(symbol + 1, stepper.symbol());
+            }
+        }
+        assert_eq!(steps, 165);
+        assert!(PROGRESS.is_finished() && !PROGRESS.was_aborted());
+
+        // Aborted 50 ticks in, the next tick stops it, and the one after
+        // puts the transmitter back.
+        PROGRESS.reset();
+        let mut stepper =
+            SymbolStepper::new(&symbols, 14_095_600, 1500, 10).unwrap();
+        for _ in 0..50 {
+            stepper.tick_with(&PROGRESS);
+        }
+        assert_eq!(PROGRESS.symbol(), Some(7));
+        PROGRESS.abort();
+        assert!(PROGRESS.is_abort_requested() && PROGRESS.is_active());
+        assert_eq!(stepper.tick_with(&PROGRESS), Some(Step::Stop));
+        assert!(PROGRESS.is_finished() && PROGRESS.was_aborted());
+        assert_eq!(PROGRESS.symbol(), None);
+        assert_eq!(stepper.tick_with(&PROGRESS), Some(Step::Finish));
+        assert!(stepper.is_finished() && PROGRESS.was_aborted());
+        assert_eq!(stepper.tick_with(&PROGRESS), None);
+
+        // Aborted before it starts, it never does.
+        PROGRESS.reset();
+        PROGRESS.abort();
+        let mut stepper =
+            SymbolStepper::new(&symbols, 14_095_600, 1500, 10).unwrap();
+        assert_eq!(stepper.tick_with(&PROGRESS), None);
+        assert!(PROGRESS.was_aborted());
+    }
+
+    #[test]
+    fn test_symbol_clock() {
+        assert_eq!(SymbolClock::new(0), Err(Error::InvalidSampleRate));
+        assert_eq!(SymbolClock::new(1000), Ok(SymbolClock::default()));
+
+        // Milliseconds from the start of the window, with the first symbol
+        // at 1 s and the last over at 111.592 s.
+        let clock = SymbolClock::MILLISECONDS;
+        assert_eq!(clock.at(999), None);
+        assert_eq!(clock.at(1000), Some((0, 0.0)));
+        let (symbol, phase) = clock.at(1000 + 1024).unwrap();
+        assert_eq!(symbol, 1);
+        assert!((phase - 0.5).abs() < 1e-6);
+        assert_eq!(clock.at(1000 + 2048), Some((3, 0.0)));
+        assert_eq!(clock.at(111_591).map(|(symbol, _)| symbol), Some(161));
+        assert_eq!(clock.at(111_592), None);
+        assert_eq!(clock.start(0), 1000);
+        assert_eq!(clock.start(1), 1683);
+        assert_eq!(clock.start(162), 111_592);
+
+        // Each symbol's start maps back to it, and the tick before to the
+        // symbol before, at a 32768 Hz RTC tick.
+        let clock = SymbolClock::new(32768).unwrap().with_delay(0);
+        for symbol in 1..162 {
+            let start = clock.start(symbol);
+            let (at, phase) = clock.at(start).unwrap();
+            assert_eq!(at, symbol);
+            assert!(phase < 1.0 / 22369.0);
+            assert_eq!(clock.at(start - 1).unwrap().0, symbol - 1);
+        }
+    }
+
+    #[cfg(feature = "embedded-hal")]
+    #[test]
+    fn test_play() {
+        // A delay that only adds up how long it was asked to wait.
+        struct Clock(u64);
+
+        impl embedded_hal::delay::DelayNs for Clock {
+            fn delay_ns(&mut self, ns: u32) {
+                self.0 += ns as u64;
+            }
+        }
+
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let mut recorder = Recorder::default();
+        let mut clock = Clock(0);
+        play(&symbols, 14_095_600, 1500, &mut recorder, &mut clock);
+        assert_eq!(clock.0, 110_592_000_000);
+        assert_eq!(recorder.0.len(), 166);
+        assert_eq!(recorder.0[2], Event::Enable);
+        assert_eq!(recorder.0[164], Event::Disable);
+    }
+}
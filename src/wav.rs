@@ -0,0 +1,474 @@
+//! Reading WAV recordings, such as those saved by WSJT-X, and decoding the
+//! WSPR transmissions within them, and writing WAV files of transmissions.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::vec;
+use std::vec::Vec;
+
+use crate::dsp::{Complex, Downconverter, Radix2};
+use crate::modulate::Modulator;
+use crate::spot::Spot;
+
+// The sample rate recordings are expected to use.
+const SAMPLE_RATE: u32 = 12000;
+
+// The audio frequency at the center of the 200 Hz WSPR passband.
+const CENTER: f64 = 1500.0;
+
+// The maximum number of messages decoded from a cycle.
+const MAX_DECODES: usize = 100;
+
+pub(crate) fn invalid(message: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Reads a WAV file, returning the sample rate and the samples of the first
+/// channel scaled to the range [-1, 1). 8, 16, 24, and 32-bit integer PCM
+/// and 32-bit floating point samples are supported.
+pub fn read<R: Read>(mut reader: R) -> io::Result<(u32, Vec<f32>)> {
+    let mut header = [0u8; 12];
+    reader.read_exact(&mut header)?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WAVE" {
+        return Err(invalid("not a WAV file"));
+    }
+
+    let mut format = None;
+    loop {
+        let mut chunk = [0u8; 8];
+        reader.read_exact(&mut chunk)?;
+        let id = [chunk[0], chunk[1], chunk[2], chunk[3]];
+        let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+
+        // Chunks are padded to an even length. The body grows as it's read,
+        // so a malformed size fails at the end of the file rather than
+        // allocating up to 4 GiB first.
+        let padded = size as u64 + (size as u64 & 1);
+        let mut body = Vec::new();
+        reader.by_ref().take(padded).read_to_end(&mut body)?;
+        if (body.len() as u64) < padded {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "truncated chunk",
+            ));
+        }
+
+        match &id {
+            b"fmt " if body.len() >= 16 => {
+                let tag = u16::from_le_bytes([body[0], body[1]]);
+                let channels = u16::from_le_bytes([body[2], body[3]]);
+                let rate =
+                    u32::from_le_bytes([body[4], body[5], body[6], body[7]]);
+                let bits = u16::from_le_bytes([body[14], body[15]]);
+                format = Some((tag, channels, rate, bits));
+            }
+            b"data" => {
+                let (tag, channels, rate, bits) =
+                    format.ok_or(invalid("missing fmt chunk"))?;
+                body.truncate(size as usize);
+                let samples = convert(&body, tag, channels, bits)?;
+                return Ok((rate, samples));
+            }
+            _ => {}
+        }
+    }
+}
+
+// Converts the raw bytes of a data chunk into samples of the first channel.
+fn convert(
+    data: &[u8],
+    tag: u16,
+    channels: u16,
+    bits: u16,
+) -> io::Result<Vec<f32>> {
+    const PCM: u16 = 1;
+    const FLOAT: u16 = 3;
+    const EXTENSIBLE: u16 = 0xFFFE;
+
+    let width = bits as usize / 8;
+    let frame = width * channels as usize;
+    if frame == 0 {
+        return Err(invalid("unsupported sample format"));
+    }
+
+    let convert: fn(&[u8]) -> f32 = match (tag, bits) {
+        (PCM | EXTENSIBLE, 8) => |b| (b[0] as f32 - 128.0) / 128.0,
+        (PCM | EXTENSIBLE, 16) => {
+            |b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0
+        }
+        (PCM | EXTENSIBLE, 24) => {
+            |b| i32::from_le_bytes([0, b[0], b[1], b[2]]) as f32 / 2147483648.0
+        }
+        (PCM | EXTENSIBLE, 32) => |b| {
+            i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2147483648.0
+        },
+        (FLOAT, 32) => |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        _ => return Err(invalid("unsupported sample format")),
+    };
+
+    Ok(data.chunks_exact(frame).map(convert).collect())
+}
+
+/// Writes a 16-bit mono WAV file of `samples` at `sample_rate` Hz.
+pub fn write<W: Write>(
+    mut writer: W,
+    sample_rate: u32,
+    samples: &[i16],
+) -> io::Result<()> {
+    let size = u32::try_from(2 * samples.len())
+        .map_err(|_| invalid("too many samples"))?;
+    let mut header = Vec::with_capacity(44);
+    header.extend_from_slice(b"RIFF");
+    header.extend_from_slice(&(36 + size).to_le_bytes());
+    header.extend_from_slice(b"WAVEfmt ");
+    header.extend_from_slice(&16u32.to_le_bytes());
+    // PCM, 1 channel.
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&1u16.to_le_bytes());
+    header.extend_from_slice(&sample_rate.to_le_bytes());
+    header.extend_from_slice(&(2 * sample_rate).to_le_bytes());
+    header.extend_from_slice(&2u16.to_le_bytes());
+    header.extend_from_slice(&16u16.to_le_bytes());
+    header.extend_from_slice(b"data");
+    header.extend_from_slice(&size.to_le_bytes());
+    writer.write_all(&header)?;
+
+    for sample in samples {
+        writer.write_all(&sample.to_le_bytes())?;
+    }
+    writer.flush()
+}
+
+/// How [`write_wav`] generates a transmission.
+///
+/// By default the signal is centered on 1500 Hz at 12000 Hz, at half of
+/// full scale, and begins at the start of the file, which ends with the
+/// transmission. [`WavOptions::wsjtx`] matches the files WSJT-X records.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WavOptions {
+    sample_rate: u32,
+    frequency: f32,
+    amplitude: f32,
+    delay: f32,
+    length: Option<f32>,
+}
+
+impl Default for WavOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WavOptions {
+    /// Creates the default options.
+    pub const fn new() -> Self {
+        Self {
+            sample_rate: SAMPLE_RATE,
+            frequency: CENTER as f32,
+            amplitude: 0.5,
+            delay: 0.0,
+            length: None,
+        }
+    }
+
+    /// The options matching a cycle recorded by WSJT-X: 114 seconds at
+    /// 12000 Hz, with the transmission centered on 1500 Hz beginning 1
+    /// second in, as a transmitter following the protocol does.
+    pub const fn wsjtx() -> Self {
+        Self {
+            delay: 1.0,
+            length: Some(114.0),
+            ..Self::new()
+        }
+    }
+
+    /// Generates samples at `sample_rate` Hz.
+    pub fn with_sample_rate(self, sample_rate: u32) -> Self {
+        Self {
+            sample_rate,
+            ..self
+        }
+    }
+
+    /// Centers the signal, midway between tones 1 and 2, at `frequency` Hz.
+    pub fn with_frequency(self, frequency: f32) -> Self {
+        Self { frequency, ..self }
+    }
+
+    /// Scales the signal to peak at `amplitude`, from 0.0 to 1.0 of full
+    /// scale.
+    pub fn with_amplitude(self, amplitude: f32) -> Self {
+        Self {
+            amplitude: amplitude.clamp(0.0, 1.0),
+            ..self
+        }
+    }
+
+    /// Begins the transmission after `delay` seconds of silence.
+    pub fn with_delay(self, delay: f32) -> Self {
+        Self {
+            delay: delay.max(0.0),
+            ..self
+        }
+    }
+
+    /// Makes the file `length` seconds long, padding it with silence or
+    /// cutting the transmission short.
+    pub fn with_length(self, length: f32) -> Self {
+        Self {
+            length: Some(length.max(0.0)),
+            ..self
+        }
+    }
+}
+
+/// Writes a WAV file of the transmission of a message, see [`crate::encode`],
+/// to play into WSJT-X or a transmitter.
+///
+/// Returns an error of kind [`io::ErrorKind::InvalidInput`] if the message
+/// can't be encoded or the options don't allow the signal to be generated.
+pub fn write_wav<P: AsRef<Path>>(
+    path: P,
+    callsign: &str,
+    grid: &str,
+    power: u8,
+    options: &WavOptions,
+) -> io::Result<()> {
+    let invalid_input =
+        |_| io::Error::new(io::ErrorKind::InvalidInput, "invalid message");
+    let symbols =
+        crate::encode(callsign, grid, power).map_err(invalid_input)?;
+    let modulator =
+        Modulator::new(&symbols, options.sample_rate, options.frequency)
+            .map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidInput, "invalid options")
+            })?;
+
+    let delay = (options.delay * options.sample_rate as f32) as usize;
+    let mut samples = vec![0i16; delay];
+    samples.extend(
+        modulator.map(|sample| (sample * options.amplitude * 32767.0) as i16),
+    );
+    if let Some(length) = options.length {
+        samples.resize((length * options.sample_rate as f32) as usize, 0);
+    }
+    write(
+        BufWriter::new(File::create(path)?),
+        options.sample_rate,
+        &samples,
+    )
+}
+
+/// Decodes every WSPR transmission found in a WAV file holding a 2 minute
+/// cycle, as recorded by WSJT-X: 12000 Hz, mono, beginning at the start of
+/// the even minute. See [`decode_reader`].
+pub fn decode_wav<P: AsRef<Path>>(path: P) -> io::Result<Vec<Spot>> {
+    decode_reader(BufReader::new(File::open(path)?))
+}
+
+/// Decodes every WSPR transmission found in a WAV recording of a 2 minute
+/// cycle. The frequency of each spot is an audio frequency, and the
+/// timestamp is left unknown.
+///
+/// The 200 Hz passband centered on 1500 Hz is searched, for transmissions
+/// beginning up to 1 second early or 3 seconds late.
+pub fn decode_reader<R: Read>(reader: R) -> io::Result<Vec<Spot>> {
+    let (sample_rate, samples) = read(reader)?;
+    if sample_rate != SAMPLE_RATE {
+        return Err(invalid("sample rate must be 12000 Hz"));
+    }
+    decode_audio(&samples)
+}
+
+// Decodes every WSPR transmission in a 2 minute cycle of 12000 Hz audio, with
+// spot frequencies as audio frequencies.
+pub(crate) fn decode_audio(samples: &[f32]) -> io::Result<Vec<Spot>> {
+    // Mix the center of the passband down to DC and filter and decimate it
+    // to 375 Hz, so nothing from outside the passband aliases into it.
+    let mut downconverter = Downconverter::new(SAMPLE_RATE, CENTER as f32)
+        .map_err(|_| invalid("unsupported sample rate"))?;
+    // The filters lag the input, so silence flushes the end of it through.
+    let flush = (downconverter.delay() * SAMPLE_RATE as f64) as usize + 1;
+    let len = samples.len() + flush;
+    let mut baseband = vec![Complex::default(); downconverter.output_len(len)];
+    let mut written = 0;
+    let silence = vec![0f32; flush];
+    for &sample in samples.iter().chain(&silence) {
+        if let Some(sample) = downconverter.push(sample) {
+            baseband[written] = sample;
+            written += 1;
+        }
+    }
+    baseband.truncate(written);
+
+    let mut spots = decode_baseband(&mut baseband)?;
+    let delay = downconverter.delay() as f32;
+    for spot in spots.iter_mut() {
+        spot.frequency += CENTER;
+        spot.dt -= delay;
+    }
+    Ok(spots)
+}
+
+// Decodes every WSPR transmission in a 375 Hz complex baseband, with spot
+// frequencies relative to the center of the baseband.
+#[cfg(not(feature = "rayon"))]
+pub(crate) fn decode_baseband(
+    baseband: &mut [Complex],
+) -> io::Result<Vec<Spot>> {
+    let mut workspace =
+        vec![0f32; crate::search::workspace_len(baseband.len())];
+    let mut spots = vec![Spot::default(); MAX_DECODES];
+    let count = crate::search::decode(
+        baseband,
+        &mut Radix2::new(),
+        &mut workspace,
+        &mut spots,
+    )
+    .map_err(|_| invalid("search failed"))?;
+
+    spots.truncate(count);
+    Ok(spots)
+}
+
+// Decodes every WSPR transmission in a 375 Hz complex baseband, with spot
+// frequencies relative to the center of the baseband.
+#[cfg(feature = "rayon")]
+pub(crate) fn decode_baseband(
+    baseband: &mut [Complex],
+) -> io::Result<Vec<Spot>> {
+    let mut spots = crate::parallel::decode(baseband, &mut Radix2::new(), &[])
+        .map_err(|_| invalid("search failed"))?;
+    spots.truncate(MAX_DECODES);
+    Ok(spots)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::demod::tests::{synthesize, Noise};
+    use crate::TONE_SPACING;
+
+    // Builds a 16-bit mono WAV file in memory.
+    fn wav(sample_rate: u32, samples: &[f32]) -> Vec<u8> {
+        let mut wav = Vec::new();
+        let size = 2 * samples.len() as u32;
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + size).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes());
+        wav.extend_from_slice(&sample_rate.to_le_bytes());
+        wav.extend_from_slice(&(2 * sample_rate).to_le_bytes());
+        wav.extend_from_slice(&2u16.to_le_bytes());
+        wav.extend_from_slice(&16u16.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&size.to_le_bytes());
+        for sample in samples {
+            wav.extend_from_slice(&((sample * 32767.0) as i16).to_le_bytes());
+        }
+        wav
+    }
+
+    #[test]
+    fn test_read() {
+        let (rate, samples) = read(&wav(8000, &[0.0, 0.5, -0.5])[..]).unwrap();
+        assert_eq!(rate, 8000);
+        assert_eq!(samples.len(), 3);
+        assert!((samples[1] - 0.5).abs() < 1e-4);
+
+        assert!(read(&b"RIFF\0\0\0\0WAVX"[..]).is_err());
+
+        // A chunk claiming to be longer than the file is truncated.
+        let mut truncated = wav(8000, &[0.0, 0.5, -0.5]);
+        truncated[40..44].copy_from_slice(&u32::MAX.to_le_bytes());
+        let error = read(&truncated[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+        let mut written = Vec::new();
+        write(&mut written, 8000, &[0, 16383, -16383]).unwrap();
+        assert_eq!(written, wav(8000, &[0.0, 0.5, -0.5]));
+    }
+
+    #[test]
+    fn test_write_wav() {
+        let path = std::env::temp_dir().join("wspr_test_write_wav.wav");
+        let options = WavOptions::new().with_frequency(1480.0).with_delay(1.0);
+        write_wav(&path, "K1ABC", "FN42", 37, &options).unwrap();
+        let decodes = decode_wav(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(decodes.len(), 1);
+        assert_eq!(decodes[0].message.callsign(), "K1ABC");
+        assert!((decodes[0].frequency - 1480.0).abs() < 1.0);
+        assert!(decodes[0].dt.abs() < 0.1);
+
+        let error = write_wav(&path, "K1ABC", "FN4", 37, &options).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_write_wav_wsjtx() {
+        let path = std::env::temp_dir().join("wspr_test_write_wav_wsjtx.wav");
+        write_wav(&path, "K1ABC", "FN42", 37, &WavOptions::wsjtx()).unwrap();
+        let (sample_rate, samples) =
+            read(BufReader::new(File::open(&path).unwrap())).unwrap();
+        let decodes = decode_wav(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(sample_rate, 12000);
+        assert_eq!(samples.len(), 114 * 12000);
+        assert!(samples[..12000].iter().all(|&sample| sample == 0.0));
+        assert_ne!(samples[12001], 0.0);
+        assert_eq!(decodes.len(), 1);
+        assert!((decodes[0].frequency - 1500.0).abs() < 1.0);
+        assert!(decodes[0].dt.abs() < 0.1);
+    }
+
+    #[test]
+    fn test_decode_reader() {
+        let mut samples = vec![0f32; 114 * 12000];
+        let mut noise = Noise::new();
+        for sample in samples.iter_mut() {
+            *sample = 0.15 * noise.next();
+        }
+
+        let first = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let second = crate::encode("G1ABC", "IO83", 20).unwrap();
+        let low = 1450.0 - 1.5 * TONE_SPACING;
+        let high = 1540.0 - 1.5 * TONE_SPACING;
+        let mut signal = vec![0f32; samples.len()];
+        synthesize(&first, 12000, low, 12000, &mut signal);
+        synthesize(&second, 12000, high, 18000, &mut signal);
+        for (sample, signal) in samples.iter_mut().zip(&signal) {
+            *sample += 0.02 * signal;
+        }
+
+        let mut decodes = decode_reader(&wav(12000, &samples)[..]).unwrap();
+        decodes.sort_by(|a, b| a.frequency.total_cmp(&b.frequency));
+        assert_eq!(decodes.len(), 2);
+        assert_eq!(decodes[0].message.callsign(), "K1ABC");
+        assert!((decodes[0].frequency - 1450.0).abs() < 1.0);
+        assert!(decodes[0].dt.abs() < 0.1);
+        assert!(decodes[0].drift.abs() <= 1.0);
+        assert_eq!(decodes[1].message.callsign(), "G1ABC");
+        assert!((decodes[1].frequency - 1540.0).abs() < 1.0);
+        assert!((decodes[1].dt - 0.5).abs() < 0.1);
+
+        assert!(decode_reader(&wav(8000, &samples)[..]).is_err());
+    }
+
+    #[test]
+    fn test_decode_audio_rejects_aliases() {
+        // A transmission 300 Hz above the passband would alias to 1425 Hz
+        // were the audio decimated without filtering it first.
+        let symbols = crate::encode("K1ABC", "FN42", 37).unwrap();
+        let mut samples = vec![0f32; 114 * 12000];
+        let lowest = 1800.0 - 1.5 * TONE_SPACING;
+        synthesize(&symbols, 12000, lowest, 12000, &mut samples);
+        assert!(decode_audio(&samples).unwrap().is_empty());
+    }
+}